@@ -0,0 +1,178 @@
+use std::{
+    ffi::OsString,
+    io::{Read as _, Seek as _},
+    os::unix::process::CommandExt as _,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bstr::ByteSlice as _;
+use eyre::{Context as _, OptionExt as _};
+
+/// The operation letters accepted by `ar`. The first non-option argument
+/// containing one of these is the operation key (e.g. `rcs`).
+const OPERATIONS: &[u8] = b"dmpqrstx";
+
+fn main() -> ExitCode {
+    let result = run();
+
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("brioche-ar error: {:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> eyre::Result<ExitCode> {
+    let wrapper = brioche_toolwrap::Wrapper::new("brioche-ar")?;
+    let ar = wrapper.tool("ar");
+
+    // If deterministic mode is disabled, call the original `ar` binary
+    if !brioche_toolwrap::env_toggle("BRIOCHE_AR_DETERMINISTIC", true) {
+        let error = std::process::Command::new(&ar)
+            .args(std::env::args_os().skip(1))
+            .exec();
+        return Err(error).context("failed to exec ar");
+    }
+
+    // Expand `@file` args, since the operation key and members may need
+    // to be rewritten
+    let mut args = brioche_toolwrap::expand_response_files(std::env::args_os().skip(1))?;
+
+    // Temporary directory holding unpacked copies of packed members. This
+    // needs to stay alive until `ar` finishes
+    let members_dir = tempfile::TempDir::new()?;
+
+    if let Some(key_index) = find_key_index(&args)? {
+        let key = brioche_toolwrap::arg_bytes(&args[key_index])?;
+        let key = deterministic_key(key);
+
+        // Members are only read from disk when adding to the archive
+        let adds_members = key.contains(&b'q') || key.contains(&b'r');
+        let full_paths = key.contains(&b'P');
+        if adds_members {
+            let member_start_index = key_index + 1 + positional_params(&key) + 1;
+            for (n, arg) in args.iter_mut().enumerate().skip(member_start_index) {
+                let arg_bytes = brioche_toolwrap::arg_bytes(arg)?;
+                let member_path = brioche_toolwrap::arg_path(arg_bytes)?;
+
+                let unpacked = unpack_member(member_path, &members_dir.path().join(n.to_string()))
+                    .with_context(|| {
+                        format!("failed to unpack member {}", member_path.display())
+                    })?;
+                if let Some(unpacked) = unpacked {
+                    // With `P`, members are stored by their full path, so
+                    // we can't swap in a temporary copy
+                    eyre::ensure!(
+                        !full_paths,
+                        "cannot add packed member {} with full path names",
+                        member_path.display()
+                    );
+                    *arg = unpacked.into_os_string();
+                }
+            }
+        }
+
+        args[key_index] = key.to_os_str()?.to_owned();
+    }
+
+    let mut command = std::process::Command::new(&ar);
+    command.args(&args);
+
+    // Used by some `ar` implementations (e.g. on macOS) instead of the `D`
+    // modifier
+    command.env("ZERO_AR_DATE", "1");
+
+    let status = command.status().context("failed to run ar")?;
+
+    Ok(brioche_toolwrap::exit_code(status))
+}
+
+/// Find the index of the operation key argument, skipping over long
+/// options (and their values) that can appear before it.
+fn find_key_index(args: &[OsString]) -> eyre::Result<Option<usize>> {
+    let mut n = 0;
+    while let Some(arg) = args.get(n) {
+        let arg_bytes = brioche_toolwrap::arg_bytes(arg)?;
+        match &**arg_bytes {
+            b"--plugin" | b"--target" | b"--output" => {
+                // These options take a separate value
+                n += 2;
+            }
+            _ if arg_bytes.starts_with(b"--") || arg_bytes.starts_with(b"-X") => {
+                n += 1;
+            }
+            _ => {
+                let has_operation = arg_bytes.iter().any(|c| OPERATIONS.contains(c));
+                return Ok(has_operation.then_some(n));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Add the `D` (deterministic) modifier to an operation key, replacing
+/// `U` if it was given. This zeroes out timestamps, UIDs, and GIDs, and
+/// uses consistent file modes for each member.
+fn deterministic_key(key: &[u8]) -> Vec<u8> {
+    let mut key = key.to_vec();
+    key.retain(|&c| c != b'U');
+    if !key.contains(&b'D') {
+        key.push(b'D');
+    }
+    key
+}
+
+/// Get the number of positional parameters between the operation key and
+/// the archive name. `a`, `b`, and `i` take a member name, and `N` takes a
+/// count.
+fn positional_params(key: &[u8]) -> usize {
+    let relpos = key.iter().any(|c| b"abi".contains(c));
+    let count = key.contains(&b'N');
+    usize::from(relpos) + usize::from(count)
+}
+
+/// If the member at `path` is packed, write the unpacked contents to a new
+/// file in `temp_dir` (using the same file name) and return its path.
+fn unpack_member(path: &Path, temp_dir: &Path) -> eyre::Result<Option<PathBuf>> {
+    let mut input = match std::fs::File::open(path) {
+        Ok(input) => input,
+        Err(_) => {
+            // Let `ar` report missing members
+            return Ok(None);
+        }
+    };
+    let Ok(extracted) = brioche_pack::extract_pack(&mut input) else {
+        return Ok(None);
+    };
+
+    let all_resource_dirs = brioche_resources::find_resource_dirs(path, true)
+        .with_context(|| format!("failed to get resource dirs for {}", path.display()))?;
+    let source = brioche_autopack::pack_source(path, &extracted.pack, &all_resource_dirs)
+        .with_context(|| format!("failed to get source path for {}", path.display()))?;
+
+    let file_name = path.file_name().ok_or_eyre("could not get member name")?;
+    std::fs::create_dir_all(temp_dir)?;
+    let unpacked_path = temp_dir.join(file_name);
+    let mut unpacked = std::fs::File::create(&unpacked_path)?;
+
+    match source {
+        brioche_autopack::PackSource::This => {
+            // Only keep the part of the file before the pack
+            input.rewind()?;
+            let mut unpacked_input = input.take(extracted.unpacked_len.try_into()?);
+            std::io::copy(&mut unpacked_input, &mut unpacked)?;
+        }
+        brioche_autopack::PackSource::Path(source_path) => {
+            // The file is a packed executable, so use the original
+            // program it points to
+            let mut source = std::fs::File::open(&source_path)?;
+            std::io::copy(&mut source, &mut unpacked)?;
+        }
+    }
+
+    Ok(Some(unpacked_path))
+}
@@ -0,0 +1,126 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+/// Why a file is being added to the resource dir.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlobRole {
+    /// The program being packed, such as a dynamic binary or WebAssembly
+    /// module.
+    Program,
+
+    /// The program used to run the program being packed, such as a
+    /// dynamic linker, script command, or WebAssembly runtime.
+    Interpreter,
+
+    /// A shared library (or Windows DLL) needed by the program.
+    Library,
+
+    /// The script being packed.
+    Script,
+}
+
+/// A file about to be added to the resource dir as a blob.
+#[derive(Debug, Clone, Copy)]
+pub struct BlobRequest<'a> {
+    /// The path of the file being packed that needs the blob.
+    pub packed_path: &'a Path,
+
+    /// The file the blob will be read from.
+    pub source_path: &'a Path,
+
+    pub role: BlobRole,
+
+    /// The name the blob will be added with.
+    pub alias_name: &'a Path,
+}
+
+/// What to do with a blob, returned by [`AutopackHook::before_add_blob`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobDecision {
+    /// Add the blob as-is.
+    Add,
+
+    /// Add the blob with a different alias name.
+    Rename { alias_name: PathBuf },
+
+    /// Add a different file instead, optionally with a different alias
+    /// name. The file is added with its own permissions.
+    Substitute {
+        source_path: PathBuf,
+        alias_name: Option<PathBuf>,
+    },
+
+    /// Don't pack the path that needs the blob. The path is skipped, or
+    /// autopacking fails if the path can't be skipped.
+    Veto,
+}
+
+/// A hook called while autopacking, set with
+/// [`crate::AutopackConfig::hooks`]. This lets callers implement their own
+/// policies for resources, such as stripping libraries before they're
+/// added.
+pub trait AutopackHook: std::fmt::Debug + Send + Sync {
+    /// Called before a file is added to the resource dir. Runnable
+    /// metadata and other generated blobs don't go through this hook.
+    fn before_add_blob(&self, request: &BlobRequest) -> eyre::Result<BlobDecision>;
+}
+
+/// The error returned when a hook vetoes a blob, which stops the path
+/// needing it from being packed.
+#[derive(Debug, thiserror::Error)]
+#[error("hook vetoed adding {source_path:?} as {role:?}")]
+pub struct BlobVetoed {
+    pub source_path: PathBuf,
+    pub role: BlobRole,
+}
+
+/// Run each hook in order, returning the file and alias name to add. Each
+/// hook sees the file and alias name chosen by the hooks before it.
+pub fn apply_blob_hooks<'a>(
+    hooks: &[std::sync::Arc<dyn AutopackHook>],
+    packed_path: &Path,
+    source_path: &'a Path,
+    role: BlobRole,
+    alias_name: &'a Path,
+) -> eyre::Result<(Cow<'a, Path>, Cow<'a, Path>)> {
+    let mut source_path = Cow::Borrowed(source_path);
+    let mut alias_name = Cow::Borrowed(alias_name);
+
+    for hook in hooks {
+        let request = BlobRequest {
+            packed_path,
+            source_path: &source_path,
+            role,
+            alias_name: &alias_name,
+        };
+        match hook.before_add_blob(&request)? {
+            BlobDecision::Add => {}
+            BlobDecision::Rename {
+                alias_name: new_alias_name,
+            } => {
+                alias_name = Cow::Owned(new_alias_name);
+            }
+            BlobDecision::Substitute {
+                source_path: new_source_path,
+                alias_name: new_alias_name,
+            } => {
+                source_path = Cow::Owned(new_source_path);
+                if let Some(new_alias_name) = new_alias_name {
+                    alias_name = Cow::Owned(new_alias_name);
+                }
+            }
+            BlobDecision::Veto => {
+                return Err(BlobVetoed {
+                    source_path: source_path.into_owned(),
+                    role,
+                }
+                .into());
+            }
+        }
+    }
+
+    Ok((source_path, alias_name))
+}
@@ -0,0 +1,72 @@
+use std::path::Path;
+
+/// Matches files by their contents rather than their path, so files like
+/// Go binaries or kernel modules can be skipped wherever they appear. A
+/// file matches if it meets every condition set in the signature.
+#[derive(Debug, Clone, Default)]
+pub struct ContentSignature {
+    /// A name for the signature, shown when a file is skipped.
+    pub name: String,
+
+    /// Bytes the file must start with, such as a magic number.
+    pub prefix: Option<Vec<u8>>,
+
+    /// Section names an ELF file must have all of, such as
+    /// `.go.buildinfo` for Go binaries or `.modinfo` for kernel modules.
+    /// Files that aren't ELF files never match if this is set.
+    pub elf_sections: Vec<String>,
+}
+
+impl ContentSignature {
+    fn matches(&self, contents: &[u8], elf: Option<&goblin::elf::Elf>) -> bool {
+        if let Some(prefix) = &self.prefix {
+            if !contents.starts_with(prefix) {
+                return false;
+            }
+        }
+
+        if !self.elf_sections.is_empty() {
+            let Some(elf) = elf else {
+                return false;
+            };
+            let has_all_sections = self.elf_sections.iter().all(|name| {
+                elf.section_headers.iter().any(|section_header| {
+                    elf.shdr_strtab.get_at(section_header.sh_name) == Some(name.as_str())
+                })
+            });
+            if !has_all_sections {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Return the name of the first signature matching the file at `path`, if
+/// any.
+pub fn matching_signature(
+    signatures: &[ContentSignature],
+    path: &Path,
+) -> eyre::Result<Option<String>> {
+    if signatures.is_empty() {
+        return Ok(None);
+    }
+
+    let contents = crate::mapped_file::MappedFile::open(path)?;
+
+    // Only parse the file as ELF if a signature checks its sections
+    let needs_elf = signatures
+        .iter()
+        .any(|signature| !signature.elf_sections.is_empty());
+    let elf = if needs_elf {
+        goblin::elf::Elf::parse(&contents).ok()
+    } else {
+        None
+    };
+
+    let signature = signatures
+        .iter()
+        .find(|signature| signature.matches(&contents, elf.as_ref()));
+    Ok(signature.map(|signature| signature.name.clone()))
+}
@@ -0,0 +1,219 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use eyre::Context as _;
+
+/// The name of the config files that can be placed in an input tree to
+/// override settings for files within the same directory (recursively).
+pub const DIRECTORY_CONFIG_FILENAME: &str = ".brioche-autopack.toml";
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct DirectoryConfigFile {
+    /// Extra libraries to skip for dynamic binaries and shared libraries.
+    #[serde(default)]
+    skip_libraries: Vec<String>,
+
+    /// Globs of paths to exclude from autopacking, relative to the dir
+    /// containing the config file.
+    #[serde(default)]
+    exclude: Vec<String>,
+
+    #[serde(default)]
+    script: ScriptDirectoryConfigFile,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ScriptDirectoryConfigFile {
+    /// Commands to use in place of script interpreters, keyed by the
+    /// command name from the shebang (e.g. `python = "python3"`).
+    #[serde(default)]
+    interpreters: HashMap<String, String>,
+
+    /// Extra args to pass to script interpreters, keyed by the command
+    /// name from the shebang (e.g. `python3 = ["-I", "-B"]`).
+    #[serde(default)]
+    interpreter_args: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug)]
+struct DirectoryConfig {
+    dir: PathBuf,
+    skip_libraries: Vec<String>,
+    exclude: globset::GlobSet,
+    script_interpreters: HashMap<String, String>,
+    script_interpreter_args: HashMap<String, Vec<String>>,
+}
+
+/// Settings that apply to a single input path, merged from the directory
+/// config files above it.
+#[derive(Debug, Clone, Default)]
+pub struct PathOverrides {
+    pub skip_libraries: HashSet<String>,
+    pub skip_unknown_libraries: Option<bool>,
+    pub packed_executable: Option<PathBuf>,
+    pub script_interpreters: HashMap<String, String>,
+    pub script_interpreter_args: HashMap<String, Vec<String>>,
+
+    /// Set when repacking with [`crate::RepackConfig::refresh_libraries`],
+    /// so libraries are only resolved from the link dependencies.
+    pub refresh_libraries: bool,
+}
+
+impl PathOverrides {
+    /// Apply the overrides to a dynamic linking config.
+    pub fn dynamic_linking<'a>(
+        &self,
+        config: &'a crate::DynamicLinkingConfig,
+    ) -> Cow<'a, crate::DynamicLinkingConfig> {
+        if self.skip_libraries.is_empty()
+            && self.skip_unknown_libraries.is_none()
+            && !self.refresh_libraries
+        {
+            return Cow::Borrowed(config);
+        }
+
+        let mut config = config.clone();
+        config
+            .skip_libraries
+            .extend(self.skip_libraries.iter().cloned());
+        if let Some(skip_unknown_libraries) = self.skip_unknown_libraries {
+            config.skip_unknown_libraries = skip_unknown_libraries;
+        }
+        if self.refresh_libraries {
+            config.library_paths.clear();
+            config.resolve_rpaths = false;
+            config.resolve_nix_store_paths = false;
+        }
+        Cow::Owned(config)
+    }
+
+    /// Merge in a path override from the autopack config.
+    pub fn apply(&mut self, path_override: &crate::PathConfigOverride) {
+        self.skip_libraries
+            .extend(path_override.skip_libraries.iter().cloned());
+        if let Some(skip_unknown_libraries) = path_override.skip_unknown_libraries {
+            self.skip_unknown_libraries = Some(skip_unknown_libraries);
+        }
+        if let Some(packed_executable) = &path_override.packed_executable {
+            self.packed_executable = Some(packed_executable.clone());
+        }
+        self.script_interpreters.extend(
+            path_override
+                .script_interpreters
+                .iter()
+                .map(|(name, command)| (name.clone(), command.clone())),
+        );
+        self.script_interpreter_args.extend(
+            path_override
+                .script_interpreter_args
+                .iter()
+                .map(|(name, args)| (name.clone(), args.clone())),
+        );
+    }
+}
+
+/// Loads and caches directory config files within an input tree.
+pub struct DirectoryConfigs {
+    base_path: PathBuf,
+    configs: HashMap<PathBuf, Option<Rc<DirectoryConfig>>>,
+}
+
+impl DirectoryConfigs {
+    pub fn new(base_path: &Path) -> Self {
+        Self {
+            base_path: base_path.to_owned(),
+            configs: HashMap::new(),
+        }
+    }
+
+    /// Get the overrides for a path within the input tree, from each
+    /// config file between the base path and the path's dir. Deeper config
+    /// files take precedence. Returns `None` if the path is excluded.
+    pub fn path_overrides(&mut self, path: &Path) -> eyre::Result<Option<PathOverrides>> {
+        let Some(parent) = path.parent() else {
+            return Ok(Some(PathOverrides::default()));
+        };
+
+        let mut dirs = parent
+            .ancestors()
+            .take_while(|dir| dir.starts_with(&self.base_path))
+            .collect::<Vec<_>>();
+        dirs.reverse();
+
+        let mut overrides = PathOverrides::default();
+        for dir in dirs {
+            let Some(config) = self.config_for_dir(dir)? else {
+                continue;
+            };
+
+            let relative_path = path.strip_prefix(&config.dir)?;
+            if config.exclude.is_match(relative_path) {
+                return Ok(None);
+            }
+
+            overrides
+                .skip_libraries
+                .extend(config.skip_libraries.iter().cloned());
+            overrides.script_interpreters.extend(
+                config
+                    .script_interpreters
+                    .iter()
+                    .map(|(name, command)| (name.clone(), command.clone())),
+            );
+            overrides.script_interpreter_args.extend(
+                config
+                    .script_interpreter_args
+                    .iter()
+                    .map(|(name, args)| (name.clone(), args.clone())),
+            );
+        }
+
+        Ok(Some(overrides))
+    }
+
+    fn config_for_dir(&mut self, dir: &Path) -> eyre::Result<Option<Rc<DirectoryConfig>>> {
+        if let Some(config) = self.configs.get(dir) {
+            return Ok(config.clone());
+        }
+
+        let config_path = dir.join(DIRECTORY_CONFIG_FILENAME);
+        let config = match std::fs::read_to_string(&config_path) {
+            Ok(config) => {
+                let config = load_directory_config(dir, &config)
+                    .with_context(|| format!("failed to load {}", config_path.display()))?;
+                Some(Rc::new(config))
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to read {}", config_path.display()));
+            }
+        };
+
+        self.configs.insert(dir.to_owned(), config.clone());
+        Ok(config)
+    }
+}
+
+fn load_directory_config(dir: &Path, config: &str) -> eyre::Result<DirectoryConfig> {
+    let config: DirectoryConfigFile = toml::from_str(config)?;
+
+    let mut exclude = globset::GlobSetBuilder::new();
+    for pattern in &config.exclude {
+        exclude.add(globset::Glob::new(pattern)?);
+    }
+
+    Ok(DirectoryConfig {
+        dir: dir.to_owned(),
+        skip_libraries: config.skip_libraries,
+        exclude: exclude.build()?,
+        script_interpreters: config.script.interpreters,
+        script_interpreter_args: config.script.interpreter_args,
+    })
+}
@@ -0,0 +1,92 @@
+use std::{io::Read as _, path::Path};
+
+use goblin::elf::header::{
+    EI_CLASS, EI_DATA, ELFCLASS32, ELFCLASS64, ELFDATA2LSB, ELFDATA2MSB, ELFMAG, EM_386,
+    EM_AARCH64, EM_ARM, EM_PPC64, EM_RISCV, EM_S390, EM_X86_64,
+};
+
+/// `e_machine` for LoongArch, which isn't included in goblin's constants.
+const EM_LOONGARCH: u16 = 258;
+
+/// The architecture of an ELF file, based on its `EI_CLASS` and
+/// `e_machine` fields. The dynamic linker only loads libraries with the
+/// same architecture as the program, so this is used to pick the right
+/// library when multiple variants share the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElfArch {
+    pub is_64: bool,
+    pub machine: u16,
+}
+
+impl ElfArch {
+    pub fn from_elf(elf: &goblin::elf::Elf) -> Self {
+        Self {
+            is_64: elf.is_64,
+            machine: elf.header.e_machine,
+        }
+    }
+
+    /// Read the architecture from an ELF file's header without reading
+    /// the rest of the file. Returns `None` if the file isn't an ELF file.
+    pub fn read(path: &Path) -> std::io::Result<Option<Self>> {
+        let file = std::fs::File::open(path)?;
+        let mut header = [0; 20];
+        let mut header_len = 0;
+        let mut file = file.take(header.len() as u64);
+        while header_len < header.len() {
+            let n = file.read(&mut header[header_len..])?;
+            if n == 0 {
+                break;
+            }
+            header_len += n;
+        }
+
+        Ok(Self::from_header(&header[..header_len]))
+    }
+
+    fn from_header(header: &[u8]) -> Option<Self> {
+        if !header.starts_with(ELFMAG) || header.len() < 20 {
+            return None;
+        }
+
+        let is_64 = match header[EI_CLASS] {
+            ELFCLASS32 => false,
+            ELFCLASS64 => true,
+            _ => return None,
+        };
+        let machine_bytes = [header[18], header[19]];
+        let machine = match header[EI_DATA] {
+            ELFDATA2LSB => u16::from_le_bytes(machine_bytes),
+            ELFDATA2MSB => u16::from_be_bytes(machine_bytes),
+            _ => return None,
+        };
+
+        Some(Self { is_64, machine })
+    }
+
+    /// Get the architecture for a target triple, such as
+    /// `x86_64-unknown-linux-gnu` or `aarch64-linux-musl`. Only the
+    /// architecture component of the triple is used.
+    pub fn from_target_triple(target: &str) -> eyre::Result<Self> {
+        let arch = target.split('-').next().unwrap_or_default();
+        let (is_64, machine) = match arch {
+            "x86_64" | "amd64" => (true, EM_X86_64),
+            "i386" | "i486" | "i586" | "i686" | "x86" => (false, EM_386),
+            "aarch64" | "arm64" => (true, EM_AARCH64),
+            _ if arch.starts_with("arm") || arch.starts_with("thumb") => (false, EM_ARM),
+            "riscv64" | "riscv64gc" => (true, EM_RISCV),
+            "riscv32" | "riscv32gc" => (false, EM_RISCV),
+            "powerpc64" | "powerpc64le" | "ppc64" | "ppc64le" => (true, EM_PPC64),
+            "s390x" => (true, EM_S390),
+            "loongarch64" => (true, EM_LOONGARCH),
+            _ => eyre::bail!("unsupported architecture in target {target:?}"),
+        };
+
+        Ok(Self { is_64, machine })
+    }
+
+    /// Check if a file at the path is an ELF file with this architecture.
+    pub fn matches_file(&self, path: &Path) -> bool {
+        matches!(Self::read(path), Ok(Some(arch)) if arch == *self)
+    }
+}
@@ -0,0 +1,401 @@
+use goblin::{
+    container::{Container, Ctx},
+    elf::{
+        dynamic::{Dyn, DT_NULL, DT_RPATH, DT_RUNPATH, DT_STRSZ, DT_STRTAB},
+        program_header::{ProgramHeader, PF_R, PF_W, PT_DYNAMIC, PT_INTERP, PT_LOAD, PT_PHDR},
+        section_header::{SectionHeader, SHT_DYNAMIC},
+    },
+};
+use scroll::Pwrite as _;
+
+/// The minimum alignment used for the segment added when rewriting.
+const MIN_PAGE_SIZE: u64 = 0x1000;
+
+/// Changes to make to an ELF file's dynamic linking info.
+#[derive(Debug, Clone, Default)]
+pub struct ElfRewrite {
+    /// The new program interpreter (`PT_INTERP`).
+    pub interpreter: Option<Vec<u8>>,
+
+    /// The new `DT_RUNPATH`. Any existing `DT_RPATH` is removed, since it
+    /// would otherwise take precedence over `LD_LIBRARY_PATH`.
+    pub runpath: Option<Vec<u8>>,
+}
+
+/// Rewrite the interpreter and runpath of an ELF file, similar to
+/// `patchelf --set-interpreter --set-rpath`.
+///
+/// The new values usually don't fit in place, so a new loadable segment is
+/// appended to the file containing a copy of the program headers, the new
+/// interpreter, and new copies of the dynamic string table and dynamic
+/// section. The originals are left in place but are no longer referenced.
+///
+/// The new segment is placed so its file offset and virtual address have
+/// the same difference as the first loadable segment, since older kernels
+/// assume this when passing the program headers' address to the
+/// interpreter. This means the file is padded up to the end of the
+/// existing segments in memory, which includes any `.bss` section.
+pub fn rewrite_elf(contents: &[u8], rewrite: &ElfRewrite) -> eyre::Result<Vec<u8>> {
+    let elf = goblin::elf::Elf::parse(contents)?;
+    let container = if elf.is_64 {
+        Container::Big
+    } else {
+        Container::Little
+    };
+    let endian = if elf.little_endian {
+        scroll::Endian::Little
+    } else {
+        scroll::Endian::Big
+    };
+    let ctx = Ctx::new(container, endian);
+    let word_size = ctx.size() as u64;
+
+    let load_segments = elf
+        .program_headers
+        .iter()
+        .filter(|phdr| phdr.p_type == PT_LOAD);
+    let first_load = load_segments
+        .clone()
+        .min_by_key(|phdr| phdr.p_vaddr)
+        .ok_or_else(|| eyre::eyre!("ELF file has no loadable segments"))?;
+    let load_delta = first_load.p_vaddr.wrapping_sub(first_load.p_offset);
+    let page_size = load_segments
+        .clone()
+        .map(|phdr| phdr.p_align)
+        .max()
+        .unwrap_or_default()
+        .max(MIN_PAGE_SIZE);
+    let max_vaddr_end = load_segments
+        .map(|phdr| phdr.p_vaddr + phdr.p_memsz)
+        .max()
+        .unwrap_or_default();
+
+    let segment_offset = (contents.len() as u64).max(max_vaddr_end.wrapping_sub(load_delta));
+    let segment_offset = segment_offset.next_multiple_of(page_size);
+    let segment_vaddr = segment_offset.wrapping_add(load_delta);
+
+    let mut segment = SegmentBuilder::default();
+
+    // Reserve space for the program headers, plus one for the new segment
+    let phdr_size = ProgramHeader::size(ctx);
+    let phdrs_len = phdr_size * (elf.program_headers.len() + 1);
+    let phdrs_position = segment.append(&vec![0; phdrs_len], word_size);
+
+    let interpreter_position = rewrite.interpreter.as_ref().map(|interpreter| {
+        let mut interpreter = interpreter.clone();
+        interpreter.push(0);
+        let position = segment.append(&interpreter, 1);
+        (position, interpreter.len() as u64)
+    });
+
+    let dynamic_position = match &rewrite.runpath {
+        Some(runpath) => {
+            let dynamic = elf.dynamic.as_ref().ok_or_else(|| {
+                eyre::eyre!("cannot set runpath for ELF file without a dynamic section")
+            })?;
+            let old_dynstr = contents
+                .get(dynamic.info.strtab..dynamic.info.strtab + dynamic.info.strsz)
+                .ok_or_else(|| eyre::eyre!("dynamic string table is out of bounds"))?;
+
+            let mut dynstr = old_dynstr.to_vec();
+            let runpath_index = dynstr.len() as u64;
+            dynstr.extend_from_slice(runpath);
+            dynstr.push(0);
+            let dynstr_position = segment.append(&dynstr, 1);
+
+            let mut dyns = dynamic
+                .dyns
+                .iter()
+                .filter(|dyn_| dyn_.d_tag != DT_NULL && dyn_.d_tag != DT_RPATH)
+                .cloned()
+                .collect::<Vec<_>>();
+            if !dyns.iter().any(|dyn_| dyn_.d_tag == DT_RUNPATH) {
+                dyns.push(Dyn {
+                    d_tag: DT_RUNPATH,
+                    d_val: 0,
+                });
+            }
+            for dyn_ in &mut dyns {
+                match dyn_.d_tag {
+                    DT_STRTAB => dyn_.d_val = segment_vaddr + dynstr_position,
+                    DT_STRSZ => dyn_.d_val = dynstr.len() as u64,
+                    DT_RUNPATH => dyn_.d_val = runpath_index,
+                    _ => {}
+                }
+            }
+            dyns.push(Dyn {
+                d_tag: DT_NULL,
+                d_val: 0,
+            });
+
+            let dyn_size = Dyn::size(container);
+            let mut dynamic_bytes = vec![0; dyn_size * dyns.len()];
+            for (n, dyn_) in dyns.into_iter().enumerate() {
+                dynamic_bytes.pwrite_with(dyn_, n * dyn_size, ctx)?;
+            }
+            let dynamic_position = segment.append(&dynamic_bytes, word_size);
+
+            Some((
+                dynamic_position,
+                dynamic_bytes.len() as u64,
+                dynstr_position,
+                dynstr.len() as u64,
+            ))
+        }
+        None => None,
+    };
+
+    // Update the program headers to point into the new segment
+    let mut phdrs = elf.program_headers.clone();
+    for phdr in &mut phdrs {
+        let new_location = match phdr.p_type {
+            PT_PHDR => Some((phdrs_position, phdrs_len as u64)),
+            PT_INTERP => interpreter_position,
+            PT_DYNAMIC => dynamic_position.map(|(position, len, _, _)| (position, len)),
+            _ => None,
+        };
+        if let Some((position, len)) = new_location {
+            phdr.p_offset = segment_offset + position;
+            phdr.p_vaddr = segment_vaddr + position;
+            phdr.p_paddr = segment_vaddr + position;
+            phdr.p_filesz = len;
+            phdr.p_memsz = len;
+        }
+    }
+    eyre::ensure!(
+        interpreter_position.is_none() || phdrs.iter().any(|phdr| phdr.p_type == PT_INTERP),
+        "cannot set interpreter for ELF file without an interpreter"
+    );
+
+    // Loadable segments must be sorted by address, so the new segment
+    // goes after the last one
+    let last_load_index = phdrs
+        .iter()
+        .rposition(|phdr| phdr.p_type == PT_LOAD)
+        .unwrap_or_default();
+    let segment_len = segment.contents.len() as u64;
+    phdrs.insert(
+        last_load_index + 1,
+        ProgramHeader {
+            p_type: PT_LOAD,
+            p_flags: PF_R | PF_W,
+            p_offset: segment_offset,
+            p_vaddr: segment_vaddr,
+            p_paddr: segment_vaddr,
+            p_filesz: segment_len,
+            p_memsz: segment_len,
+            p_align: page_size,
+        },
+    );
+    for (n, phdr) in phdrs.into_iter().enumerate() {
+        segment
+            .contents
+            .pwrite_with(phdr, phdrs_position as usize + n * phdr_size, ctx)?;
+    }
+
+    let mut output = contents.to_vec();
+
+    // Update the section headers so tools that read them see the new
+    // locations too
+    let section_header_size = SectionHeader::size(ctx);
+    for (n, section_header) in elf.section_headers.iter().enumerate() {
+        let section_name = elf.shdr_strtab.get_at(section_header.sh_name);
+        let new_location = match section_name {
+            Some(".interp") => interpreter_position,
+            Some(".dynstr") => dynamic_position.map(|(_, _, position, len)| (position, len)),
+            _ if section_header.sh_type == SHT_DYNAMIC => {
+                dynamic_position.map(|(position, len, _, _)| (position, len))
+            }
+            _ => None,
+        };
+        let Some((position, len)) = new_location else {
+            continue;
+        };
+
+        let mut section_header = section_header.clone();
+        section_header.sh_offset = segment_offset + position;
+        section_header.sh_addr = segment_vaddr + position;
+        section_header.sh_size = len;
+        let section_header_offset = elf.header.e_shoff as usize + n * section_header_size;
+        output.pwrite_with(section_header, section_header_offset, ctx)?;
+    }
+
+    let mut header = elf.header;
+    header.e_phoff = segment_offset + phdrs_position;
+    header.e_phnum = header
+        .e_phnum
+        .checked_add(1)
+        .ok_or_else(|| eyre::eyre!("too many program headers"))?;
+    output.pwrite_with(header, 0, endian)?;
+
+    output.resize(segment_offset as usize, 0);
+    output.extend_from_slice(&segment.contents);
+
+    Ok(output)
+}
+
+#[derive(Default)]
+struct SegmentBuilder {
+    contents: Vec<u8>,
+}
+
+impl SegmentBuilder {
+    /// Append bytes to the segment with the given alignment, returning
+    /// their position within the segment.
+    fn append(&mut self, bytes: &[u8], align: u64) -> u64 {
+        let position = (self.contents.len() as u64).next_multiple_of(align);
+        self.contents.resize(position as usize, 0);
+        self.contents.extend_from_slice(bytes);
+        position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brioche_test_fixtures::ElfBuilder;
+    use goblin::elf::{dynamic::DT_NEEDED, Elf};
+
+    use super::*;
+
+    /// Check that the rewritten file has a valid program header table and
+    /// dynamic section within the new segment, and that the original
+    /// segments are unchanged.
+    fn check_rewritten(original: &[u8], rewritten: &[u8]) -> ProgramHeader {
+        let original_elf = Elf::parse(original).unwrap();
+        let elf = Elf::parse(rewritten).unwrap();
+
+        // The new segment is the last loadable segment, at the end of the
+        // file
+        let load_segments = elf
+            .program_headers
+            .iter()
+            .filter(|phdr| phdr.p_type == PT_LOAD)
+            .collect::<Vec<_>>();
+        assert!(load_segments.is_sorted_by_key(|phdr| phdr.p_vaddr));
+        let new_segment = (*load_segments.last().unwrap()).clone();
+        assert_eq!(
+            new_segment.p_offset + new_segment.p_filesz,
+            rewritten.len() as u64
+        );
+        assert_eq!(new_segment.p_offset % new_segment.p_align, 0);
+        assert_eq!(
+            new_segment.p_vaddr - new_segment.p_offset,
+            original_elf.program_headers[0].p_vaddr - original_elf.program_headers[0].p_offset,
+        );
+
+        // The program headers are copied into the new segment, with one
+        // more for the new segment itself
+        assert_eq!(
+            elf.header.e_phnum as usize,
+            original_elf.program_headers.len() + 1
+        );
+        let phdrs_len = elf.header.e_phnum as u64 * elf.header.e_phentsize as u64;
+        assert!(elf.header.e_phoff >= new_segment.p_offset);
+        assert!(elf.header.e_phoff + phdrs_len <= new_segment.p_offset + new_segment.p_filesz);
+
+        // The original segments and their contents are left in place
+        let original_segments = elf
+            .program_headers
+            .iter()
+            .filter(|phdr| !matches!(phdr.p_type, PT_INTERP | PT_DYNAMIC))
+            .filter(|phdr| **phdr != new_segment)
+            .cloned()
+            .collect::<Vec<_>>();
+        let expected_segments = original_elf
+            .program_headers
+            .iter()
+            .filter(|phdr| !matches!(phdr.p_type, PT_INTERP | PT_DYNAMIC))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(original_segments, expected_segments);
+        let header_len = elf.header.e_ehsize as usize;
+        assert_eq!(
+            rewritten[header_len..original.len()],
+            original[header_len..]
+        );
+
+        // The dynamic section and string table are read from the new
+        // segment
+        let new_segment_vaddrs = new_segment.p_vaddr..new_segment.p_vaddr + new_segment.p_memsz;
+        let dynamic_phdr = elf
+            .program_headers
+            .iter()
+            .find(|phdr| phdr.p_type == PT_DYNAMIC)
+            .unwrap();
+        assert!(new_segment_vaddrs.contains(&dynamic_phdr.p_vaddr));
+        let dynamic = elf.dynamic.as_ref().unwrap();
+        let strtab = dynamic
+            .dyns
+            .iter()
+            .find(|dyn_| dyn_.d_tag == DT_STRTAB)
+            .unwrap();
+        assert!(new_segment_vaddrs.contains(&strtab.d_val));
+
+        new_segment
+    }
+
+    #[test]
+    fn test_rewrite_executable() {
+        let original = ElfBuilder::executable("/lib64/ld-linux-x86-64.so.2")
+            .needed("libfoo.so.1")
+            .runpath(Some("/old/lib"))
+            .build();
+        let rewrite = ElfRewrite {
+            interpreter: Some(b"/new/ld-linux.so".to_vec()),
+            runpath: Some(b"$ORIGIN/../lib".to_vec()),
+        };
+        let rewritten = rewrite_elf(&original, &rewrite).unwrap();
+
+        let new_segment = check_rewritten(&original, &rewritten);
+        let elf = Elf::parse(&rewritten).unwrap();
+        assert_eq!(elf.interpreter, Some("/new/ld-linux.so"));
+        assert_eq!(elf.runpaths, ["$ORIGIN/../lib"]);
+        assert_eq!(elf.libraries, ["libfoo.so.1"]);
+
+        let interp_phdr = elf
+            .program_headers
+            .iter()
+            .find(|phdr| phdr.p_type == PT_INTERP)
+            .unwrap();
+        assert!(interp_phdr.p_offset >= new_segment.p_offset);
+
+        // The runpath replaces the existing entry instead of adding another
+        let dynamic = elf.dynamic.as_ref().unwrap();
+        let tags = dynamic
+            .dyns
+            .iter()
+            .map(|dyn_| dyn_.d_tag)
+            .collect::<Vec<_>>();
+        assert_eq!(tags.iter().filter(|tag| **tag == DT_RUNPATH).count(), 1);
+        assert_eq!(tags.iter().filter(|tag| **tag == DT_NEEDED).count(), 1);
+    }
+
+    #[test]
+    fn test_rewrite_shared_library() {
+        let original = ElfBuilder::shared_library("libfoo.so.1")
+            .needed("libbar.so.1")
+            .build();
+        let rewrite = ElfRewrite {
+            interpreter: None,
+            runpath: Some(b"$ORIGIN".to_vec()),
+        };
+        let rewritten = rewrite_elf(&original, &rewrite).unwrap();
+
+        check_rewritten(&original, &rewritten);
+        let elf = Elf::parse(&rewritten).unwrap();
+        assert_eq!(elf.interpreter, None);
+        assert_eq!(elf.soname, Some("libfoo.so.1"));
+        assert_eq!(elf.runpaths, ["$ORIGIN"]);
+        assert_eq!(elf.libraries, ["libbar.so.1"]);
+    }
+
+    #[test]
+    fn test_rewrite_interpreter_without_interp() {
+        let original = ElfBuilder::shared_library("libfoo.so.1").build();
+        let rewrite = ElfRewrite {
+            interpreter: Some(b"/new/ld-linux.so".to_vec()),
+            runpath: None,
+        };
+        assert!(rewrite_elf(&original, &rewrite).is_err());
+    }
+}
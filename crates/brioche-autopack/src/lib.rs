@@ -1,12 +1,56 @@
 use std::{
     collections::{BTreeMap, HashMap, HashSet, VecDeque},
     io::{BufRead as _, Read as _, Write as _},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use bstr::{ByteSlice as _, ByteVec as _};
 use eyre::{Context as _, ContextCompat as _, OptionExt as _};
 
+pub use blob_hook::{AutopackHook, BlobDecision, BlobRequest, BlobRole, BlobVetoed};
+pub use content_signature::ContentSignature;
+pub use directory_config::DIRECTORY_CONFIG_FILENAME;
+pub use elf_arch::ElfArch;
+pub use library_index::LibraryIndex;
+pub use manifest::{build_manifest, AutopackManifest, AutopackManifestResource};
+pub use object_inspector::{ElfInspector, ObjectInfo, ObjectInspector, PeInspector};
+pub use pack_resources::{pack_resources, ResourceKind};
+pub use privileged::PrivilegedFilePolicy;
+pub use progress::{
+    ProgressEvent, ProgressReporter, SilentProgressReporter, SkipReason, StdoutProgressReporter,
+};
+pub use symbol_versions::{SymbolVersionCheck, VersionRequirement};
+pub use trailing_payload::{
+    detach_trailing_payload, elf_trailing_payload, restore_trailing_payload,
+};
+pub use windows::{WindowsDllDirs, WINDOWS_DLL_DIRS_FORMAT};
+
+use pending_paths::PendingPaths;
+
+mod blob_hook;
+mod content_signature;
+mod directory_config;
+mod elf_arch;
+mod elf_rewrite;
+mod library_cache;
+mod library_filter;
+mod library_index;
+mod manifest;
+mod mapped_file;
+mod object_inspector;
+mod pack_resources;
+mod pending_paths;
+mod privileged;
+mod progress;
+mod shebang;
+mod state;
+mod symbol_versions;
+mod trailing_payload;
+mod windows;
+
 pub fn pack_source(
     source_path: &Path,
     pack: &brioche_pack::Pack,
@@ -28,11 +72,9 @@ pub fn pack_source(
             metadata,
             resource_paths: _,
         } => {
-            if format == runnable_core::FORMAT {
-                let metadata: runnable_core::Runnable = serde_json::from_slice(metadata)
-                    .with_context(|| {
-                        format!("failed to deserialize runnable metadata: {metadata:?}")
-                    })?;
+            if runnable_core::is_runnable_format(format) {
+                let metadata = runnable_core::load_runnable(format, metadata, all_resource_dirs)
+                    .with_context(|| format!("failed to load runnable metadata: {metadata:?}"))?;
                 let Some(runnable_source) = metadata.source else {
                     eyre::bail!("no source path in metadata");
                 };
@@ -62,6 +104,8 @@ pub fn pack_source(
                 };
 
                 PackSource::Path(runnable_source_path)
+            } else if format == WINDOWS_DLL_DIRS_FORMAT {
+                PackSource::This
             } else {
                 eyre::bail!("unknown metadata format: {format:?}");
             }
@@ -82,12 +126,93 @@ pub struct AutopackConfig {
     pub resource_dir: PathBuf,
     pub all_resource_dirs: Vec<PathBuf>,
     pub inputs: AutopackInputs,
-    pub quiet: bool,
+
+    /// Receives events as paths are autopacked, such as
+    /// [`StdoutProgressReporter`] to print progress.
+    pub progress: Arc<dyn ProgressReporter>,
+
     pub link_dependencies: Vec<PathBuf>,
     pub dynamic_binary: Option<DynamicBinaryConfig>,
+    pub loader_binary: Option<LoaderBinaryConfig>,
     pub shared_library: Option<SharedLibraryConfig>,
+    pub windows_binary: Option<WindowsBinaryConfig>,
     pub script: Option<ScriptConfig>,
+    pub wasm: Option<WasmConfig>,
     pub repack: Option<RepackConfig>,
+
+    /// The number of paths to autopack at the same time. Defaults to the
+    /// available parallelism.
+    pub concurrency: Option<NonZeroUsize>,
+
+    /// Resolve everything needed to autopack each path without writing
+    /// any outputs or resources. The results list what would be packed.
+    pub dry_run: bool,
+
+    /// Write the results as JSON to this path. The report is also written
+    /// if autopacking fails, with the error recorded for the failed path.
+    pub report_path: Option<PathBuf>,
+
+    /// After autopacking succeeds, write a JSON manifest of the resources
+    /// referenced by each packed path to this path. See
+    /// [`AutopackManifest`].
+    pub manifest_path: Option<PathBuf>,
+
+    /// Settings that apply to paths matching a glob. These take precedence
+    /// over `.brioche-autopack.toml` files, and later overrides take
+    /// precedence over earlier ones.
+    pub path_overrides: Vec<PathConfigOverride>,
+
+    /// Only pack ELF files with this architecture. Other ELF files are
+    /// skipped, e.g. so 32-bit libraries in a recipe are left unpacked.
+    pub target_arch: Option<ElfArch>,
+
+    /// What to do with setuid, setgid, or capability-bearing files.
+    pub privileged_files: PrivilegedFilePolicy,
+
+    /// How files are added to the resource dir. Hard links are only used
+    /// for interpreters and libraries.
+    pub blob_link_mode: brioche_resources::BlobLinkMode,
+
+    /// Skip input paths matching any of these signatures, even if they
+    /// could be packed.
+    pub skip_signatures: Vec<ContentSignature>,
+
+    /// Inspectors for object formats besides ELF and PE. These are tried
+    /// in order before the built-in [`PeInspector`] and [`ElfInspector`].
+    pub object_inspectors: Vec<Arc<dyn ObjectInspector>>,
+
+    /// Hooks called before each file is added to the resource dir, in
+    /// order. See [`AutopackHook`].
+    pub hooks: Vec<Arc<dyn AutopackHook>>,
+
+    /// Record the hashes of each autopacked path in this file. Paths that
+    /// still match what was recorded by a previous run are skipped.
+    pub state_file: Option<PathBuf>,
+}
+
+/// Settings for paths matching `glob`. For glob inputs, the glob is matched
+/// against the path relative to the base path. For path inputs, it's
+/// matched against the path as given.
+#[derive(Debug, Clone, Default)]
+pub struct PathConfigOverride {
+    pub glob: String,
+
+    /// Extra libraries to skip, in addition to the config's libraries.
+    pub skip_libraries: HashSet<String>,
+
+    pub skip_unknown_libraries: Option<bool>,
+
+    /// The packed executable to use for dynamic binaries and scripts.
+    pub packed_executable: Option<PathBuf>,
+
+    /// Commands to use in place of script interpreters, keyed by the
+    /// command name from the shebang.
+    pub script_interpreters: HashMap<String, String>,
+
+    /// Extra args to pass to script interpreters, keyed by the command
+    /// name from the shebang. These go after any args from the shebang,
+    /// just before the script path.
+    pub script_interpreter_args: HashMap<String, Vec<String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -97,6 +222,13 @@ pub enum AutopackInputs {
         base_path: PathBuf,
         patterns: Vec<String>,
         exclude_patterns: Vec<String>,
+
+        /// Follow symlinks to files and directories within the base path.
+        /// Globs are matched against the symlink's path, but the target
+        /// is what gets packed, and each target is only packed once no
+        /// matter how many symlinks point to it. Dangling symlinks and
+        /// symlinks that point outside the base path are skipped.
+        follow_symlinks: bool,
     },
 }
 
@@ -104,8 +236,38 @@ pub enum AutopackInputs {
 pub struct DynamicLinkingConfig {
     pub library_paths: Vec<PathBuf>,
     pub skip_libraries: HashSet<String>,
+
+    /// Skip libraries with names matching any of these globs, such as
+    /// `libGL*.so*`. Like `skip_libraries`, skipped libraries are still
+    /// resolved so their dependencies are included.
+    pub skip_library_globs: Vec<String>,
+
+    /// If not empty, skip any library whose name doesn't match one of
+    /// these globs.
+    pub only_library_globs: Vec<String>,
+
     pub extra_libraries: Vec<String>,
     pub skip_unknown_libraries: bool,
+
+    /// Resolve interpreters and RPATH/RUNPATH entries under `/nix/store`
+    /// from the host, so pre-built binaries that were linked against Nix
+    /// packages can be packed. The resolved files are copied into the
+    /// resource dir like any other dependency.
+    pub resolve_nix_store_paths: bool,
+
+    /// Search the RPATH and RUNPATH entries embedded in each ELF file for
+    /// its libraries, with `$ORIGIN` expanded relative to the file. These
+    /// are searched after `library_paths` and the link dependencies.
+    pub resolve_rpaths: bool,
+
+    /// Check that each resolved library defines the symbol versions
+    /// required by the files that depend on it.
+    pub symbol_version_check: SymbolVersionCheck,
+
+    /// Add the symlinks around each library (such as `libfoo.so` and
+    /// `libfoo.so.1.2.3` for `libfoo.so.1`) to its library dir, so the
+    /// library can also be loaded by those names.
+    pub preserve_symlink_chains: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -113,6 +275,40 @@ pub struct DynamicBinaryConfig {
     pub packed_executable: PathBuf,
     pub extra_runtime_library_paths: Vec<PathBuf>,
     pub dynamic_linking: DynamicLinkingConfig,
+    pub mode: DynamicBinaryMode,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum DynamicBinaryMode {
+    /// Replace the binary with a packed executable, which runs the
+    /// program from the resource dir with its interpreter and libraries.
+    #[default]
+    Pack,
+
+    /// Rewrite the binary's `PT_INTERP` and `DT_RUNPATH` in place (like
+    /// `patchelf`), for programs that break when wrapped, such as ones
+    /// that re-exec `/proc/self/exe`. The runpath uses `$ORIGIN`, but the
+    /// interpreter must be an absolute path, so it defaults to the
+    /// absolute path of the interpreter in the resource dir.
+    ///
+    /// This means that by default, rewritten binaries are not relocatable:
+    /// they fail to start once the resource dir is moved or the output is
+    /// copied to another machine, unlike packed binaries. Set
+    /// `interpreter` to the path the interpreter will have at runtime if
+    /// the output will be moved.
+    RewriteInPlace { interpreter: Option<PathBuf> },
+}
+
+/// Config for ELF executables that are dynamically linked but have no
+/// `PT_INTERP`, such as static-PIE binaries or binaries meant to be started
+/// through an explicit loader. These are packed like dynamic binaries,
+/// using `loader` as the interpreter.
+#[derive(Debug, Clone)]
+pub struct LoaderBinaryConfig {
+    pub packed_executable: PathBuf,
+    pub loader: PathBuf,
+    pub extra_runtime_library_paths: Vec<PathBuf>,
+    pub dynamic_linking: DynamicLinkingConfig,
 }
 
 #[derive(Debug, Clone)]
@@ -121,12 +317,25 @@ pub struct SharedLibraryConfig {
     pub allow_empty: bool,
 }
 
+/// Config for Windows PE binaries (both executables and DLLs). Imported
+/// DLLs are found in `dynamic_linking.library_paths`, matching names
+/// case-insensitively. `resolve_nix_store_paths` is ignored.
+#[derive(Debug, Clone)]
+pub struct WindowsBinaryConfig {
+    pub dynamic_linking: DynamicLinkingConfig,
+}
+
 #[derive(Debug, Clone)]
 pub struct ScriptConfig {
     pub packed_executable: PathBuf,
     pub base_path: Option<PathBuf>,
-    pub env: HashMap<String, runnable_core::EnvValue>,
+    /// Env vars to set for packed scripts. These are sorted by name so
+    /// packed scripts are reproducible.
+    pub env: BTreeMap<String, runnable_core::EnvValue>,
     pub clear_env: bool,
+    /// Env vars to keep when `clear_env` is set. See
+    /// [`runnable_core::Runnable::clear_env_except`].
+    pub clear_env_except: Vec<String>,
 }
 
 impl ScriptConfig {
@@ -153,18 +362,45 @@ impl ScriptConfig {
                     let value = relative_template(value, self.base_path.as_deref(), output_path)?;
                     runnable_core::EnvValue::Fallback { value }
                 }
-                runnable_core::EnvValue::Prepend { value, separator } => {
+                runnable_core::EnvValue::Prepend {
+                    value,
+                    separator,
+                    if_exists,
+                } => {
                     let value = relative_template(value, self.base_path.as_deref(), output_path)?;
                     runnable_core::EnvValue::Prepend {
                         value,
                         separator: separator.clone(),
+                        if_exists: *if_exists,
                     }
                 }
-                runnable_core::EnvValue::Append { value, separator } => {
+                runnable_core::EnvValue::Append {
+                    value,
+                    separator,
+                    if_exists,
+                } => {
                     let value = relative_template(value, self.base_path.as_deref(), output_path)?;
                     runnable_core::EnvValue::Append {
                         value,
                         separator: separator.clone(),
+                        if_exists: *if_exists,
+                    }
+                }
+                runnable_core::EnvValue::MergedView { values } => {
+                    let values = values
+                        .iter()
+                        .map(|value| {
+                            relative_template(value, self.base_path.as_deref(), output_path)
+                        })
+                        .collect::<eyre::Result<_>>()?;
+                    runnable_core::EnvValue::MergedView { values }
+                }
+                runnable_core::EnvValue::PrependPath { path, subpath } => {
+                    let path =
+                        relative_runnable_path(path, self.base_path.as_deref(), output_path)?;
+                    runnable_core::EnvValue::PrependPath {
+                        path,
+                        subpath: subpath.clone(),
                     }
                 }
             };
@@ -192,24 +428,11 @@ fn relative_template(
         .map(|component| -> eyre::Result<_> {
             match component {
                 runnable_core::TemplateComponent::Literal { .. }
-                | runnable_core::TemplateComponent::Resource { .. } => eyre::Ok(component.clone()),
+                | runnable_core::TemplateComponent::Resource { .. }
+                | runnable_core::TemplateComponent::EnvVar { .. }
+                | runnable_core::TemplateComponent::ProgramDir => eyre::Ok(component.clone()),
                 runnable_core::TemplateComponent::RelativePath { path } => {
-                    // TODO: Handle path resolution in a cross-platform way.
-                    // This could change based on the host platform
-
-                    let path = path
-                        .to_path()
-                        .with_context(|| format!("failed to parse path {path:?}"))?;
-
-                    let full_path = base_path.join(path);
-                    let new_relative_path = pathdiff::diff_paths(full_path, output_dir)
-                        .context("failed to get path relative to output dir")?;
-                    let new_relative_path = <Vec<u8>>::from_path_buf(new_relative_path).map_err(
-                        |new_relative_path| {
-                            eyre::eyre!("failed to convert path {new_relative_path:?}")
-                        },
-                    )?;
-
+                    let new_relative_path = relative_path(path, base_path, output_dir)?;
                     eyre::Ok(runnable_core::TemplateComponent::RelativePath {
                         path: new_relative_path,
                     })
@@ -220,29 +443,246 @@ fn relative_template(
     Ok(runnable_core::Template { components })
 }
 
+fn relative_runnable_path(
+    path: &runnable_core::RunnablePath,
+    base_path: Option<&Path>,
+    output_path: &Path,
+) -> eyre::Result<runnable_core::RunnablePath> {
+    let Some(base_path) = base_path else {
+        return Ok(path.clone());
+    };
+    let output_path = base_path.join(output_path);
+    let output_dir = output_path
+        .parent()
+        .ok_or_else(|| eyre::eyre!("failed to get parent of output path"))?;
+
+    match path {
+        runnable_core::RunnablePath::RelativePath { path } => {
+            let new_relative_path = relative_path(path, base_path, output_dir)?;
+            Ok(runnable_core::RunnablePath::RelativePath {
+                path: new_relative_path,
+            })
+        }
+        runnable_core::RunnablePath::Resource { .. } => Ok(path.clone()),
+    }
+}
+
+/// Re-resolve `path` (relative to `base_path`) so that it's relative to
+/// `output_dir` instead.
+fn relative_path(path: &[u8], base_path: &Path, output_dir: &Path) -> eyre::Result<Vec<u8>> {
+    // TODO: Handle path resolution in a cross-platform way.
+    // This could change based on the host platform
+
+    let path = path
+        .to_path()
+        .with_context(|| format!("failed to parse path {path:?}"))?;
+
+    let full_path = base_path.join(path);
+    let new_relative_path = pathdiff::diff_paths(full_path, output_dir)
+        .context("failed to get path relative to output dir")?;
+    let new_relative_path = <Vec<u8>>::from_path_buf(new_relative_path)
+        .map_err(|new_relative_path| eyre::eyre!("failed to convert path {new_relative_path:?}"))?;
+    Ok(new_relative_path)
+}
+
+/// Config for WebAssembly modules. Like scripts, modules are packed to run
+/// with a command from the link dependencies' `$PATH`, in this case a wasm
+/// runtime such as `wasmtime` or `wasmer`.
 #[derive(Debug, Clone)]
-pub struct RepackConfig {}
+pub struct WasmConfig {
+    pub packed_executable: PathBuf,
+
+    /// The name of the runtime command.
+    pub runtime: String,
+
+    /// Args passed to the runtime before the module, such as `run`. The
+    /// args the packed module is called with are passed after the module.
+    pub runtime_args: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RepackConfig {
+    /// Resolve the interpreter and libraries of repacked paths only from
+    /// `link_dependencies`, ignoring the configured library paths, rpaths,
+    /// and Nix store paths. The program from the existing pack is kept,
+    /// and its library dirs are replaced with the newly resolved ones.
+    /// This is useful for repacking a tree against upgraded libraries.
+    pub refresh_libraries: bool,
+}
+
+/// The results from a call to [`autopack`], with one entry per path,
+/// sorted by path.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutopackResults {
+    pub paths: Vec<AutopackPathResult>,
+}
+
+impl AutopackResults {
+    /// Get the sum of the phase timings across all paths.
+    pub fn total_timings(&self) -> PhaseTimings {
+        let mut total = PhaseTimings::default();
+        for path in &self.paths {
+            total.add(&path.timings);
+        }
+        total
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutopackPathResult {
+    pub path: PathBuf,
+    pub packed: bool,
+
+    /// The kind of file detected, or `None` if the path couldn't be packed.
+    /// For repacked files, this is always [`AutopackKind::Repack`].
+    pub kind: Option<AutopackKind>,
+
+    /// The interpreter for a dynamic binary, or the command for a script.
+    pub interpreter: Option<AutopackDependencyResult>,
+
+    /// The libraries (or DLLs) needed by the path, including transitive
+    /// dependencies.
+    pub libraries: Vec<AutopackLibraryResult>,
+
+    pub timings: PhaseTimings,
+
+    /// The resources added for the path, relative to the resource dir.
+    /// For a dry run, these are the resources that would be added.
+    pub resources: Vec<PathBuf>,
+
+    /// The error that stopped the path from being packed, if any.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutopackDependencyResult {
+    pub source_path: PathBuf,
+    pub resource_path: PathBuf,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutopackLibraryResult {
+    pub name: String,
+
+    /// Where the library was found, or `None` if it wasn't found and
+    /// unknown libraries are skipped.
+    pub source_path: Option<PathBuf>,
+
+    /// The resource path for the library, or `None` if it was skipped.
+    pub resource_path: Option<PathBuf>,
+}
+
+/// Time spent in each phase while autopacking a path. Time spent
+/// autopacking a pending dependency is recorded against the dependency,
+/// not the path that depends on it.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseTimings {
+    /// Reading the file to determine what kind of file it is.
+    pub kind_detection: Duration,
+
+    /// Parsing ELF files, including needed libraries.
+    pub elf_parsing: Duration,
+
+    /// Finding interpreters, libraries, and script commands.
+    pub library_resolution: Duration,
+
+    /// Hashing and adding files to the resource dir.
+    pub resource_insertion: Duration,
+
+    /// Writing the packed executable and injecting the pack.
+    pub pack_injection: Duration,
+}
+
+impl PhaseTimings {
+    pub fn total(&self) -> Duration {
+        self.kind_detection
+            + self.elf_parsing
+            + self.library_resolution
+            + self.resource_insertion
+            + self.pack_injection
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        self.kind_detection += other.kind_detection;
+        self.elf_parsing += other.elf_parsing;
+        self.library_resolution += other.library_resolution;
+        self.resource_insertion += other.resource_insertion;
+        self.pack_injection += other.pack_injection;
+    }
+
+    fn time<T>(phase: &mut Duration, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        *phase += start.elapsed();
+        result
+    }
+}
 
 struct AutopackPathConfig {
     can_skip: bool,
+    overrides: directory_config::PathOverrides,
 }
 
-pub fn autopack(config: &AutopackConfig) -> eyre::Result<()> {
-    let ctx = autopack_context(config)?;
+pub fn autopack(config: &AutopackConfig) -> eyre::Result<AutopackResults> {
+    let preparation = prepare_autopack(config)?;
+    autopack_prepared(config, preparation)
+}
+
+/// Autopack using work done ahead of time by [`prepare_autopack`]. The
+/// preparation must have been created from the same config.
+pub fn autopack_prepared(
+    config: &AutopackConfig,
+    preparation: AutopackPreparation,
+) -> eyre::Result<AutopackResults> {
+    let mut ctx = AutopackContext {
+        config,
+        link_dependency_library_paths: preparation.link_dependency_library_paths,
+        link_dependency_paths: preparation.link_dependency_paths,
+        library_indexes: preparation.library_indexes,
+        library_cache: library_cache::LibraryCache::default(),
+        results: Mutex::new(vec![]),
+        state: None,
+    };
     let mut pending_paths = BTreeMap::<PathBuf, AutopackPathConfig>::new();
+    let path_config_overrides = config
+        .path_overrides
+        .iter()
+        .map(|path_override| {
+            let glob = globset::Glob::new(&path_override.glob)?.compile_matcher();
+            eyre::Ok((glob, path_override))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let apply_path_config_overrides = |overrides: &mut directory_config::PathOverrides,
+                                       path: &Path| {
+        for (glob, path_override) in &path_config_overrides {
+            if glob.is_match(path) {
+                overrides.apply(path_override);
+            }
+        }
+    };
 
     match &config.inputs {
         AutopackInputs::Paths(paths) => {
-            pending_paths.extend(
-                paths
-                    .iter()
-                    .map(|path| (path.clone(), AutopackPathConfig { can_skip: true })),
-            );
+            pending_paths.extend(paths.iter().map(|path| {
+                let mut overrides = directory_config::PathOverrides::default();
+                apply_path_config_overrides(&mut overrides, path);
+                let path_config = AutopackPathConfig {
+                    can_skip: true,
+                    overrides,
+                };
+                (path.clone(), path_config)
+            }));
         }
         AutopackInputs::Globs {
             base_path,
             patterns,
             exclude_patterns,
+            follow_symlinks,
         } => {
             let mut globs = globset::GlobSetBuilder::new();
             for pattern in patterns {
@@ -256,11 +696,28 @@ pub fn autopack(config: &AutopackConfig) -> eyre::Result<()> {
 
             let globs = globs.build()?;
             let exclude_globs = exclude_globs.build()?;
+            let mut directory_configs = directory_config::DirectoryConfigs::new(base_path);
 
-            let walkdir = walkdir::WalkDir::new(base_path);
+            let canonical_base_path = if *follow_symlinks {
+                Some(base_path.canonicalize().with_context(|| {
+                    format!("failed to canonicalize base path {}", base_path.display())
+                })?)
+            } else {
+                None
+            };
+
+            let walkdir = walkdir::WalkDir::new(base_path).follow_links(*follow_symlinks);
             for entry in walkdir {
-                let entry = entry?;
-                if !entry.file_type().is_file() {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(error) if *follow_symlinks && is_unfollowable_symlink(&error) => {
+                        continue;
+                    }
+                    Err(error) => {
+                        return Err(error.into());
+                    }
+                };
+                if !entry.file_type().is_file() || entry.file_name() == DIRECTORY_CONFIG_FILENAME {
                     continue;
                 }
 
@@ -273,95 +730,314 @@ pub fn autopack(config: &AutopackConfig) -> eyre::Result<()> {
                         )
                     })?;
 
-                if globs.is_match(&relative_entry_path)
-                    && !exclude_globs.is_match(&relative_entry_path)
+                if !globs.is_match(&relative_entry_path)
+                    || exclude_globs.is_match(&relative_entry_path)
                 {
-                    pending_paths.insert(
-                        entry.path().to_owned(),
-                        AutopackPathConfig { can_skip: false },
-                    );
+                    continue;
+                }
+
+                // When following symlinks, pack the file at its real
+                // location within the base path. Files reachable through
+                // several symlinks end up with the same path, so they're
+                // only packed once
+                let (entry_path, relative_entry_path) = match &canonical_base_path {
+                    Some(canonical_base_path) => {
+                        let canonical_entry_path = entry.path().canonicalize()?;
+                        let Ok(relative_target_path) =
+                            canonical_entry_path.strip_prefix(canonical_base_path)
+                        else {
+                            continue;
+                        };
+                        if exclude_globs.is_match(relative_target_path) {
+                            continue;
+                        }
+
+                        (
+                            base_path.join(relative_target_path),
+                            relative_target_path.to_owned(),
+                        )
+                    }
+                    None => (entry.path().to_owned(), relative_entry_path),
+                };
+                if pending_paths.contains_key(&entry_path) {
+                    continue;
                 }
+
+                // Apply settings from `.brioche-autopack.toml` files in
+                // the input tree
+                let Some(mut overrides) = directory_configs.path_overrides(&entry_path)? else {
+                    continue;
+                };
+                apply_path_config_overrides(&mut overrides, &relative_entry_path);
+
+                pending_paths.insert(
+                    entry_path,
+                    AutopackPathConfig {
+                        can_skip: false,
+                        overrides,
+                    },
+                );
             }
         }
     }
 
-    while let Some((path, path_config)) = pending_paths.pop_first() {
-        autopack_path(&ctx, &path, &path_config, &mut pending_paths)?;
+    let state = config
+        .state_file
+        .as_deref()
+        .map(state::AutopackState::load)
+        .transpose()?;
+    let mut unchanged_paths = vec![];
+    if let Some(state) = &state {
+        let mut file_hashes = HashMap::new();
+        for (path, path_config) in &pending_paths {
+            let config_hash = state::config_hash(config, &path_config.overrides);
+            if state.is_unchanged(path, &config_hash, &config.resource_dir, &mut file_hashes)? {
+                unchanged_paths.push(path.clone());
+            }
+        }
+
+        for path in &unchanged_paths {
+            pending_paths.remove(path);
+            config.progress.report(&ProgressEvent::Unchanged { path });
+        }
     }
+    ctx.state = state.map(Mutex::new);
+
+    let concurrency = config
+        .concurrency
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let workers = concurrency.min(pending_paths.len()).max(1);
+    let pending_paths = PendingPaths::new(pending_paths);
+
+    let result = std::thread::scope(|scope| {
+        let worker_handles = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    while let Some((path, path_config, _in_progress)) = pending_paths.next() {
+                        let result = autopack_path(&ctx, &path, &path_config, &pending_paths);
+                        if let Err(error) = result {
+                            pending_paths.fail();
+                            return Err(error);
+                        }
+                    }
 
-    Ok(())
+                    eyre::Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut result = Ok(());
+        for handle in worker_handles {
+            let worker_result = handle
+                .join()
+                .map_err(|_| eyre::eyre!("autopack worker panicked"))
+                .and_then(|result| result);
+            if result.is_ok() {
+                result = worker_result;
+            }
+        }
+        result
+    });
+
+    // Paths finish in a different order each run, so sort the results to
+    // keep reports reproducible
+    let mut results = AutopackResults {
+        paths: ctx
+            .results
+            .into_inner()
+            .map_err(|_| eyre::eyre!("autopack results lock poisoned"))?,
+    };
+    results.paths.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if let Some(report_path) = &config.report_path {
+        let report = serde_json::to_vec_pretty(&results)?;
+        std::fs::write(report_path, report)
+            .with_context(|| format!("failed to write report to {}", report_path.display()))?;
+    }
+
+    // The state is saved even if autopacking failed, so paths that were
+    // packed successfully don't need to be packed again
+    if let (Some(state_file), Some(state)) = (&config.state_file, ctx.state) {
+        if !config.dry_run {
+            let state = state
+                .into_inner()
+                .map_err(|_| eyre::eyre!("autopack state lock poisoned"))?;
+            state.save(state_file)?;
+        }
+    }
+
+    result?;
+
+    if let Some(manifest_path) = &config.manifest_path {
+        if !config.dry_run {
+            // Unchanged paths were packed by a previous run, so they're
+            // included in the manifest too
+            let output_paths = results
+                .paths
+                .iter()
+                .filter(|path_result| path_result.packed)
+                .map(|path_result| &*path_result.path)
+                .chain(unchanged_paths.iter().map(|path| &**path));
+            let manifest = build_manifest(output_paths, &config.all_resource_dirs)?;
+            let manifest = serde_json::to_vec_pretty(&manifest)?;
+            std::fs::write(manifest_path, manifest).with_context(|| {
+                format!("failed to write manifest to {}", manifest_path.display())
+            })?;
+        }
+    }
+
+    Ok(results)
+}
+
+/// Check if a walkdir error is from a symlink that can't be followed,
+/// either because it's dangling or because it's part of a loop.
+fn is_unfollowable_symlink(error: &walkdir::Error) -> bool {
+    if error.loop_ancestor().is_some() {
+        return true;
+    }
+
+    error
+        .io_error()
+        .is_some_and(|error| error.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Get the paths a dependency adds to an env var. Each entry under
+/// `brioche-env.d/env/<env_var>` within the dependency is a symlink to a
+/// path that should be added. Returns an empty list if the dependency
+/// doesn't set the env var.
+pub fn dependency_env_var_paths(dependency: &Path, env_var: &str) -> eyre::Result<Vec<PathBuf>> {
+    let env_dir = dependency.join("brioche-env.d").join("env").join(env_var);
+    let env_dir_entries = match std::fs::read_dir(&env_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(vec![]);
+        }
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to read directory {:?}", env_dir));
+        }
+    };
+
+    let mut entries = env_dir_entries.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut paths = vec![];
+    for entry in entries {
+        eyre::ensure!(
+            entry.metadata()?.is_symlink(),
+            "expected {:?} to be a symlink",
+            entry.path()
+        );
+
+        let entry_path = entry
+            .path()
+            .canonicalize()
+            .with_context(|| format!("failed to canonicalize path {:?}", entry.path()))?;
+        paths.push(entry_path);
+    }
+
+    Ok(paths)
+}
+
+/// Get the names of all env vars set by a dependency through
+/// `brioche-env.d/env`.
+pub fn dependency_env_vars(dependency: &Path) -> eyre::Result<Vec<String>> {
+    let env_dir = dependency.join("brioche-env.d").join("env");
+    let env_dir_entries = match std::fs::read_dir(&env_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(vec![]);
+        }
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to read directory {:?}", env_dir));
+        }
+    };
+
+    let mut env_vars = vec![];
+    for entry in env_dir_entries {
+        let entry = entry?;
+        let env_var = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| eyre::eyre!("invalid env var name: {name:?}"))?;
+        env_vars.push(env_var);
+    }
+
+    env_vars.sort();
+    Ok(env_vars)
 }
 
 struct AutopackContext<'a> {
     config: &'a AutopackConfig,
     link_dependency_library_paths: Vec<PathBuf>,
     link_dependency_paths: Vec<PathBuf>,
+    library_indexes: Vec<(Vec<PathBuf>, LibraryIndex)>,
+    library_cache: library_cache::LibraryCache,
+    results: Mutex<Vec<AutopackPathResult>>,
+    state: Option<Mutex<state::AutopackState>>,
+}
+
+impl AutopackContext<'_> {
+    /// Whether objects for `arch` should be packed with this config.
+    fn is_target_arch(&self, arch: ElfArch) -> bool {
+        self.config
+            .target_arch
+            .is_none_or(|target_arch| target_arch == arch)
+    }
+
+    /// Inspect an object file with the configured object inspectors,
+    /// falling back to the built-in PE and ELF inspectors.
+    fn inspect_object(&self, contents: &[u8]) -> Option<ObjectInfo> {
+        self.config
+            .object_inspectors
+            .iter()
+            .map(|inspector| &**inspector)
+            .chain([&PeInspector as &dyn ObjectInspector, &ElfInspector])
+            .find_map(|inspector| inspector.inspect(contents))
+    }
+
+    /// Build a library index for the given library paths, followed by
+    /// the link dependencies' library paths. Reuses an index from the
+    /// preparation if one was built for the same library paths.
+    fn library_index(&self, library_paths: &[PathBuf]) -> eyre::Result<LibraryIndex> {
+        let prepared_index = self
+            .library_indexes
+            .iter()
+            .find(|(paths, _)| paths == library_paths);
+        if let Some((_, library_index)) = prepared_index {
+            return Ok(library_index.clone());
+        }
+
+        LibraryIndex::from_search_paths(
+            &[library_paths, &self.link_dependency_library_paths[..]].concat(),
+        )
+    }
+}
+
+/// Work for autopacking that only depends on the config, such as reading
+/// link dependencies and indexing library search paths. This can be done
+/// while the inputs are still being written, e.g. while a linker runs.
+///
+/// Library search paths are only scanned once, so libraries added to them
+/// after preparing won't be found.
+pub struct AutopackPreparation {
+    link_dependency_library_paths: Vec<PathBuf>,
+    link_dependency_paths: Vec<PathBuf>,
+    library_indexes: Vec<(Vec<PathBuf>, LibraryIndex)>,
 }
 
-fn autopack_context(config: &AutopackConfig) -> eyre::Result<AutopackContext> {
+pub fn prepare_autopack(config: &AutopackConfig) -> eyre::Result<AutopackPreparation> {
     let mut link_dependency_library_paths = vec![];
     let mut link_dependency_paths = vec![];
     for link_dep in &config.link_dependencies {
         // Add $LIBRARY_PATH directories from symlinks under
         // brioche-env.d/env/LIBRARY_PATH
-        let library_path_env_dir = link_dep
-            .join("brioche-env.d")
-            .join("env")
-            .join("LIBRARY_PATH");
-        let library_path_env_dir_entries = match std::fs::read_dir(&library_path_env_dir) {
-            Ok(entries) => entries,
-            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                continue;
-            }
-            Err(error) => {
-                return Err(error).with_context(|| {
-                    format!("failed to read directory {:?}", library_path_env_dir)
-                });
-            }
-        };
-        for entry in library_path_env_dir_entries {
-            let entry = entry?;
-            eyre::ensure!(
-                entry.metadata()?.is_symlink(),
-                "expected {:?} to be a symlink",
-                entry.path()
-            );
-
-            let entry_path = entry
-                .path()
-                .canonicalize()
-                .with_context(|| format!("failed to canonicalize path {:?}", entry.path()))?;
-            link_dependency_library_paths.push(entry_path);
-        }
+        link_dependency_library_paths.extend(dependency_env_var_paths(link_dep, "LIBRARY_PATH")?);
     }
 
     for link_dep in &config.link_dependencies {
         // Add $PATH directories from symlinks under brioche-env.d/env/PATH
-        let path_env_dir = link_dep.join("brioche-env.d").join("env").join("PATH");
-        let path_env_dir_entries = match std::fs::read_dir(&path_env_dir) {
-            Ok(entries) => entries,
-            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                continue;
-            }
-            Err(error) => {
-                return Err(error)
-                    .with_context(|| format!("failed to read directory {:?}", path_env_dir));
-            }
-        };
-        for entry in path_env_dir_entries {
-            let entry = entry?;
-            eyre::ensure!(
-                entry.metadata()?.is_symlink(),
-                "expected {:?} to be a symlink",
-                entry.path()
-            );
-
-            let entry_path = entry
-                .path()
-                .canonicalize()
-                .with_context(|| format!("failed to canonicalize path {:?}", entry.path()))?;
-            link_dependency_paths.push(entry_path);
-        }
+        link_dependency_paths.extend(dependency_env_var_paths(link_dep, "PATH")?);
     }
 
     for link_dep in &config.link_dependencies {
@@ -372,10 +1048,54 @@ fn autopack_context(config: &AutopackConfig) -> eyre::Result<AutopackContext> {
         }
     }
 
-    Ok(AutopackContext {
-        config,
+    let mut library_indexes = vec![];
+    let dynamic_linking_configs = config
+        .dynamic_binary
+        .iter()
+        .map(|config| &config.dynamic_linking)
+        .chain(
+            config
+                .loader_binary
+                .iter()
+                .map(|config| &config.dynamic_linking),
+        )
+        .chain(
+            config
+                .shared_library
+                .iter()
+                .map(|config| &config.dynamic_linking),
+        );
+    for dynamic_linking_config in dynamic_linking_configs {
+        let library_paths = &dynamic_linking_config.library_paths;
+        let is_indexed = library_indexes
+            .iter()
+            .any(|(paths, _)| paths == library_paths);
+        if is_indexed {
+            continue;
+        }
+
+        let library_index = LibraryIndex::from_search_paths(
+            &[&library_paths[..], &link_dependency_library_paths[..]].concat(),
+        )?;
+        library_indexes.push((library_paths.clone(), library_index));
+    }
+
+    // Repacking with refreshed libraries only searches the link
+    // dependencies, so index them on their own too
+    let refreshes_libraries = config
+        .repack
+        .as_ref()
+        .is_some_and(|repack| repack.refresh_libraries);
+    let is_indexed = library_indexes.iter().any(|(paths, _)| paths.is_empty());
+    if refreshes_libraries && !is_indexed {
+        let library_index = LibraryIndex::from_search_paths(&link_dependency_library_paths)?;
+        library_indexes.push((vec![], library_index));
+    }
+
+    Ok(AutopackPreparation {
         link_dependency_library_paths,
         link_dependency_paths,
+        library_indexes,
     })
 }
 
@@ -383,17 +1103,117 @@ fn autopack_path(
     ctx: &AutopackContext,
     path: &Path,
     path_config: &AutopackPathConfig,
-    pending_paths: &mut BTreeMap<PathBuf, AutopackPathConfig>,
+    pending_paths: &PendingPaths,
 ) -> eyre::Result<()> {
-    let did_pack = try_autopack_path(ctx, path, path, pending_paths)?;
-    if did_pack {
-        if !ctx.config.quiet {
-            println!("autopacked {}", path.display());
+    let mut path_result = AutopackPathResult {
+        path: path.to_owned(),
+        packed: false,
+        kind: None,
+        interpreter: None,
+        libraries: vec![],
+        timings: PhaseTimings::default(),
+        resources: vec![],
+        error: None,
+    };
+    ctx.config.progress.report(&ProgressEvent::Started { path });
+
+    // Hash the path before packing it, so the state records what it was
+    // packed from
+    let input_hash = match &ctx.state {
+        Some(_) if !ctx.config.dry_run => Some(
+            state::hash_file(path).with_context(|| format!("failed to hash {}", path.display()))?,
+        ),
+        _ => None,
+    };
+
+    let mut skipped_signature = None;
+    let mut skipped_privileges = None;
+    let signature = content_signature::matching_signature(&ctx.config.skip_signatures, path);
+    let did_pack = match signature {
+        Ok(Some(signature)) => {
+            skipped_signature = Some(signature);
+            Ok(false)
+        }
+        Ok(None) => privileged::file_privileges(path).and_then(|privileges| {
+            match (privileges, ctx.config.privileged_files) {
+                (Some(privileges), PrivilegedFilePolicy::Skip) => {
+                    skipped_privileges = Some(privileges);
+                    Ok(false)
+                }
+                (Some(privileges), PrivilegedFilePolicy::Error) => {
+                    eyre::bail!("refusing to autopack {} with {privileges}", path.display());
+                }
+                (privileges, _) => {
+                    let did_pack = try_autopack_path(
+                        ctx,
+                        path,
+                        path,
+                        &path_config.overrides,
+                        pending_paths,
+                        &mut path_result,
+                    )?;
+                    if let Some(privileges) = privileges.filter(|_| did_pack) {
+                        let message =
+                            format!("autopacked {} without its {privileges}", path.display());
+                        ctx.config.progress.report(&ProgressEvent::Warning {
+                            path,
+                            message: &message,
+                        });
+                    }
+                    Ok(did_pack)
+                }
+            }
+        }),
+        Err(error) => Err(error),
+    };
+    match &did_pack {
+        Ok(did_pack) => {
+            path_result.packed = *did_pack;
+
+            // Paths that must be packed aren't recorded unless they were,
+            // so a failure isn't skipped as unchanged on the next run
+            let is_done = *did_pack
+                || !path_config.can_skip
+                || skipped_signature.is_some()
+                || skipped_privileges.is_some();
+            let state = ctx.state.as_ref().filter(|_| is_done);
+            if let (Some(state), Some(input_hash)) = (state, &input_hash) {
+                let config_hash = state::config_hash(ctx.config, &path_config.overrides);
+                state
+                    .lock()
+                    .map_err(|_| eyre::eyre!("autopack state lock poisoned"))?
+                    .record(config_hash, input_hash.clone(), &path_result)?;
+            }
         }
-    } else if !path_config.can_skip {
-        if !ctx.config.quiet {
-            println!("skipped {}", path.display());
+        Err(error) => {
+            let message = format!("{error:#}");
+            ctx.config.progress.report(&ProgressEvent::Error {
+                path,
+                message: &message,
+            });
+            path_result.error = Some(message);
         }
+    }
+    ctx.results
+        .lock()
+        .map_err(|_| eyre::eyre!("autopack results lock poisoned"))?
+        .push(path_result);
+    let did_pack = did_pack?;
+
+    let skip_reason = if let Some(signature) = &skipped_signature {
+        SkipReason::Signature { name: signature }
+    } else if let Some(privileges) = &skipped_privileges {
+        SkipReason::Privileges { privileges }
+    } else {
+        SkipReason::Unpackable
+    };
+    if did_pack {
+        ctx.config.progress.report(&ProgressEvent::Packed { path });
+    } else if !matches!(skip_reason, SkipReason::Unpackable) || !path_config.can_skip {
+        ctx.config.progress.report(&ProgressEvent::Skipped {
+            path,
+            reason: skip_reason,
+        });
     } else {
         eyre::bail!("failed to autopack path: {path:?}");
     }
@@ -405,26 +1225,90 @@ fn try_autopack_path(
     ctx: &AutopackContext,
     source_path: &Path,
     output_path: &Path,
-    pending_paths: &mut BTreeMap<PathBuf, AutopackPathConfig>,
+    overrides: &directory_config::PathOverrides,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
 ) -> eyre::Result<bool> {
-    let Some(kind) = autopack_kind(source_path)? else {
+    let kind = PhaseTimings::time(&mut path_result.timings.kind_detection, || {
+        autopack_kind(ctx, source_path)
+    })?;
+    let Some(kind) = kind else {
         return Ok(false);
     };
+    path_result.kind.get_or_insert(kind);
+
+    let did_pack = match kind {
+        AutopackKind::DynamicBinary => autopack_dynamic_binary(
+            ctx,
+            source_path,
+            output_path,
+            overrides,
+            pending_paths,
+            path_result,
+        ),
+        AutopackKind::LoaderBinary => autopack_loader_binary(
+            ctx,
+            source_path,
+            output_path,
+            overrides,
+            pending_paths,
+            path_result,
+        ),
+        AutopackKind::SharedLibrary => autopack_shared_library(
+            ctx,
+            source_path,
+            output_path,
+            overrides,
+            pending_paths,
+            path_result,
+        ),
+        AutopackKind::WindowsBinary => windows::autopack_windows_binary(
+            ctx,
+            source_path,
+            output_path,
+            overrides,
+            pending_paths,
+            path_result,
+        ),
+        AutopackKind::Script => autopack_script(
+            ctx,
+            source_path,
+            output_path,
+            overrides,
+            pending_paths,
+            path_result,
+        ),
+        AutopackKind::Wasm => autopack_wasm(
+            ctx,
+            source_path,
+            output_path,
+            overrides,
+            pending_paths,
+            path_result,
+        ),
+        AutopackKind::Repack => autopack_repack(
+            ctx,
+            source_path,
+            output_path,
+            overrides,
+            pending_paths,
+            path_result,
+        ),
+    };
 
-    match kind {
-        AutopackKind::DynamicBinary => {
-            autopack_dynamic_binary(ctx, source_path, output_path, pending_paths)
-        }
-        AutopackKind::SharedLibrary => {
-            autopack_shared_library(ctx, source_path, output_path, pending_paths)
+    // A hook vetoing one of the path's blobs means the path shouldn't be
+    // packed, so drop any resources that were already added for it
+    match did_pack {
+        Err(error) if error.downcast_ref::<BlobVetoed>().is_some() => {
+            path_result.resources.clear();
+            Ok(false)
         }
-        AutopackKind::Script => autopack_script(ctx, source_path, output_path, pending_paths),
-        AutopackKind::Repack => autopack_repack(ctx, source_path, output_path, pending_paths),
+        did_pack => did_pack,
     }
 }
 
-fn autopack_kind(path: &Path) -> eyre::Result<Option<AutopackKind>> {
-    let contents = std::fs::read(path)?;
+fn autopack_kind(ctx: &AutopackContext, path: &Path) -> eyre::Result<Option<AutopackKind>> {
+    let contents = mapped_file::MappedFile::open(path)?;
 
     let contents_cursor = std::io::Cursor::new(&contents[..]);
     let pack = brioche_pack::extract_pack(contents_cursor);
@@ -433,56 +1317,78 @@ fn autopack_kind(path: &Path) -> eyre::Result<Option<AutopackKind>> {
         Ok(Some(AutopackKind::Repack))
     } else if contents.starts_with(b"#!") {
         Ok(Some(AutopackKind::Script))
+    } else if contents.starts_with(WASM_MAGIC) {
+        Ok(Some(AutopackKind::Wasm))
     } else {
-        let program_object = goblin::Object::parse(&contents);
-
-        let Ok(goblin::Object::Elf(program_object)) = program_object else {
+        let Some(program_object) = ctx.inspect_object(&contents) else {
             return Ok(None);
         };
 
-        if program_object.interpreter.is_some() {
+        if program_object.is_windows {
+            Ok(Some(AutopackKind::WindowsBinary))
+        } else if program_object.interpreter.is_some() {
             Ok(Some(AutopackKind::DynamicBinary))
         } else if program_object.is_lib {
             Ok(Some(AutopackKind::SharedLibrary))
+        } else if program_object.is_dynamic {
+            Ok(Some(AutopackKind::LoaderBinary))
         } else {
             Ok(None)
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-enum AutopackKind {
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutopackKind {
     DynamicBinary,
+
+    /// A dynamically linked ELF executable without an interpreter, see
+    /// [`LoaderBinaryConfig`].
+    LoaderBinary,
+
     SharedLibrary,
+    WindowsBinary,
     Script,
+    Wasm,
     Repack,
 }
 
+/// The magic bytes at the start of a WebAssembly module.
+const WASM_MAGIC: &[u8] = b"\0asm";
+
 fn autopack_dynamic_binary(
     ctx: &AutopackContext,
     source_path: &Path,
     output_path: &Path,
-    pending_paths: &mut BTreeMap<PathBuf, AutopackPathConfig>,
+    overrides: &directory_config::PathOverrides,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
 ) -> eyre::Result<bool> {
     let Some(dynamic_binary_config) = &ctx.config.dynamic_binary else {
         return Ok(false);
     };
+    let dynamic_linking = overrides.dynamic_linking(&dynamic_binary_config.dynamic_linking);
 
-    let output_path_parent = output_path
-        .parent()
-        .ok_or_eyre("could not get parent of output path")?;
-
-    let contents = std::fs::read(source_path)?;
-    let program_object = goblin::Object::parse(&contents)?;
+    let contents = mapped_file::MappedFile::open(source_path)?;
+    let program_object = PhaseTimings::time(&mut path_result.timings.elf_parsing, || {
+        ctx.inspect_object(&contents)
+    });
 
-    let goblin::Object::Elf(program_object) = program_object else {
+    let Some(program_object) = program_object else {
         eyre::bail!(
-            "tried to autopack non-ELF dynamic binary: {}",
+            "tried to autopack unrecognized dynamic binary: {}",
             source_path.display()
         );
     };
+    if !program_object
+        .arch
+        .is_none_or(|arch| ctx.is_target_arch(arch))
+    {
+        return Ok(false);
+    }
 
-    let Some(interpreter) = program_object.interpreter else {
+    let Some(interpreter) = &program_object.interpreter else {
         eyre::bail!(
             "tried to autopack dynamic binary without an interpreter: {}",
             source_path.display()
@@ -492,48 +1398,217 @@ fn autopack_dynamic_binary(
         eyre::eyre!("expected program interpreter to start with '/': {interpreter:?}")
     })?;
 
-    let mut interpreter_path = None;
-    for dependency in &ctx.config.link_dependencies {
-        let dependency_path = dependency.join(relative_interpreter);
-        if dependency_path.exists() {
-            interpreter_path = Some(dependency_path);
-            break;
+    let interpreter_path = PhaseTimings::time(&mut path_result.timings.library_resolution, || {
+        for dependency in &ctx.config.link_dependencies {
+            let dependency_path = dependency.join(relative_interpreter);
+            if dependency_path.exists() {
+                return Some(dependency_path);
+            }
         }
-    }
+
+        if dynamic_linking.resolve_nix_store_paths && interpreter.starts_with(NIX_STORE_DIR) {
+            let nix_interpreter_path = PathBuf::from(interpreter);
+            if nix_interpreter_path.is_file() {
+                return Some(nix_interpreter_path);
+            }
+        }
+
+        None
+    });
 
     let interpreter_path = interpreter_path.ok_or_else(|| {
         eyre::eyre!("could not find interpreter for dynamic binary: {source_path:?}")
     })?;
 
     // Autopack the interpreter if it's pending
-    try_autopack_dependency(ctx, &interpreter_path, pending_paths)?;
-
-    let interpreter_resource_path = add_named_blob_from(ctx, &interpreter_path, None)
+    let canonical_interpreter_path =
+        try_autopack_dependency(ctx, &interpreter_path, pending_paths)?;
+
+    let interpreter_resource_path =
+        PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+            add_cached_blob_from(
+                ctx,
+                &mut path_result.resources,
+                &path_result.path,
+                &interpreter_path,
+                canonical_interpreter_path.as_deref(),
+                BlobRole::Interpreter,
+                None,
+            )
+        })
         .with_context(|| format!("failed to add resource for interpreter {interpreter_path:?}"))?;
-    let program_resource_path = add_named_blob_from(ctx, source_path, None)
-        .with_context(|| format!("failed to add resource for program {source_path:?}"))?;
+    path_result.interpreter = Some(AutopackDependencyResult {
+        source_path: interpreter_path.clone(),
+        resource_path: interpreter_resource_path.clone(),
+    });
 
     let needed_libraries: VecDeque<_> = program_object
-        .libraries
+        .needed_libraries
         .iter()
-        .copied()
-        .chain(
-            dynamic_binary_config
-                .dynamic_linking
-                .extra_libraries
-                .iter()
-                .map(|lib| &**lib),
-        )
-        .map(|lib| lib.to_string())
+        .chain(&dynamic_linking.extra_libraries)
+        .cloned()
         .collect();
 
     let library_dir_resource_paths = collect_all_library_dirs(
         ctx,
-        &dynamic_binary_config.dynamic_linking,
+        &dynamic_linking,
+        &program_object,
+        source_path,
         needed_libraries,
         pending_paths,
+        path_result,
     )?;
 
+    if let DynamicBinaryMode::RewriteInPlace { interpreter } = &dynamic_binary_config.mode {
+        let interpreter = match interpreter {
+            Some(interpreter) => interpreter.clone(),
+            None => std::path::absolute(ctx.config.resource_dir.join(&interpreter_resource_path))?,
+        };
+        return rewrite_dynamic_binary(
+            ctx,
+            &contents,
+            source_path,
+            output_path,
+            &interpreter,
+            &dynamic_binary_config.extra_runtime_library_paths,
+            &library_dir_resource_paths,
+            path_result,
+        );
+    }
+
+    let packed_executable = overrides
+        .packed_executable
+        .as_deref()
+        .unwrap_or(&dynamic_binary_config.packed_executable);
+    pack_ld_linux(
+        ctx,
+        source_path,
+        output_path,
+        packed_executable,
+        interpreter_resource_path,
+        library_dir_resource_paths,
+        &dynamic_binary_config.extra_runtime_library_paths,
+        path_result,
+    )
+}
+
+fn autopack_loader_binary(
+    ctx: &AutopackContext,
+    source_path: &Path,
+    output_path: &Path,
+    overrides: &directory_config::PathOverrides,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
+) -> eyre::Result<bool> {
+    let Some(loader_binary_config) = &ctx.config.loader_binary else {
+        return Ok(false);
+    };
+    let dynamic_linking = overrides.dynamic_linking(&loader_binary_config.dynamic_linking);
+
+    let contents = mapped_file::MappedFile::open(source_path)?;
+    let program_object = PhaseTimings::time(&mut path_result.timings.elf_parsing, || {
+        ctx.inspect_object(&contents)
+    });
+
+    let Some(program_object) = program_object else {
+        eyre::bail!(
+            "tried to autopack unrecognized loader binary: {}",
+            source_path.display()
+        );
+    };
+    if !program_object
+        .arch
+        .is_none_or(|arch| ctx.is_target_arch(arch))
+    {
+        return Ok(false);
+    }
+
+    // The binary doesn't name an interpreter, so use the configured
+    // loader. Autopack it first if it's pending
+    let loader_path = &loader_binary_config.loader;
+    let canonical_loader_path = try_autopack_dependency(ctx, loader_path, pending_paths)?;
+
+    let loader_resource_path =
+        PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+            add_cached_blob_from(
+                ctx,
+                &mut path_result.resources,
+                &path_result.path,
+                loader_path,
+                canonical_loader_path.as_deref(),
+                BlobRole::Interpreter,
+                None,
+            )
+        })
+        .with_context(|| format!("failed to add resource for loader {loader_path:?}"))?;
+    path_result.interpreter = Some(AutopackDependencyResult {
+        source_path: loader_path.clone(),
+        resource_path: loader_resource_path.clone(),
+    });
+
+    let needed_libraries: VecDeque<_> = program_object
+        .needed_libraries
+        .iter()
+        .chain(&dynamic_linking.extra_libraries)
+        .cloned()
+        .collect();
+
+    let library_dir_resource_paths = collect_all_library_dirs(
+        ctx,
+        &dynamic_linking,
+        &program_object,
+        source_path,
+        needed_libraries,
+        pending_paths,
+        path_result,
+    )?;
+
+    let packed_executable = overrides
+        .packed_executable
+        .as_deref()
+        .unwrap_or(&loader_binary_config.packed_executable);
+    pack_ld_linux(
+        ctx,
+        source_path,
+        output_path,
+        packed_executable,
+        loader_resource_path,
+        library_dir_resource_paths,
+        &loader_binary_config.extra_runtime_library_paths,
+        path_result,
+    )
+}
+
+/// Add a program to the resource dir and replace it with a packed
+/// executable that runs it with the given interpreter and library dirs.
+#[allow(clippy::too_many_arguments)]
+fn pack_ld_linux(
+    ctx: &AutopackContext,
+    source_path: &Path,
+    output_path: &Path,
+    packed_executable: &Path,
+    interpreter_resource_path: PathBuf,
+    library_dir_resource_paths: Vec<PathBuf>,
+    extra_runtime_library_paths: &[PathBuf],
+    path_result: &mut AutopackPathResult,
+) -> eyre::Result<bool> {
+    let output_path_parent = output_path
+        .parent()
+        .ok_or_eyre("could not get parent of output path")?;
+
+    let program_resource_path =
+        PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+            add_named_blob_from(
+                ctx,
+                &mut path_result.resources,
+                &path_result.path,
+                source_path,
+                BlobRole::Program,
+                None,
+            )
+        })
+        .with_context(|| format!("failed to add resource for program {source_path:?}"))?;
+
     let program = <Vec<u8>>::from_path_buf(program_resource_path)
         .map_err(|_| eyre::eyre!("invalid UTF-8 in path"))?;
     let interpreter = <Vec<u8>>::from_path_buf(interpreter_resource_path)
@@ -545,8 +1620,7 @@ fn autopack_dynamic_binary(
                 .map_err(|_| eyre::eyre!("invalid UTF-8 in path"))
         })
         .collect::<eyre::Result<Vec<_>>>()?;
-    let runtime_library_dirs = dynamic_binary_config
-        .extra_runtime_library_paths
+    let runtime_library_dirs = extra_runtime_library_paths
         .iter()
         .map(|path| {
             let path = pathdiff::diff_paths(path, output_path_parent).ok_or_else(|| eyre::eyre!("failed to get relative path from output path {output_path_parent:?} to runtime library path {path:?}"))?;
@@ -562,15 +1636,84 @@ fn autopack_dynamic_binary(
         runtime_library_dirs,
     };
 
-    let packed_exec_path = &dynamic_binary_config.packed_executable;
-    let mut packed_exec = std::fs::File::open(packed_exec_path)
-        .with_context(|| format!("failed to open packed executable {packed_exec_path:?}"))?;
-    let mut output = std::fs::File::create(output_path)
-        .with_context(|| format!("failed to create file {output_path:?}"))?;
-    std::io::copy(&mut packed_exec, &mut output)
-        .with_context(|| format!("failed to copy packed executable to {output_path:?}"))?;
-    brioche_pack::inject_pack(output, &pack)
-        .with_context(|| format!("failed to inject pack into {output_path:?}"))?;
+    if ctx.config.dry_run {
+        return Ok(true);
+    }
+
+    PhaseTimings::time(&mut path_result.timings.pack_injection, || {
+        write_packed_executable(packed_executable, output_path, &pack)
+    })?;
+
+    Ok(true)
+}
+
+/// Rewrite a dynamic binary's interpreter and runpath for
+/// [`DynamicBinaryMode::RewriteInPlace`]. The runpath contains the runtime
+/// library paths followed by the library dirs, relative to `$ORIGIN`.
+#[allow(clippy::too_many_arguments)]
+fn rewrite_dynamic_binary(
+    ctx: &AutopackContext,
+    contents: &[u8],
+    source_path: &Path,
+    output_path: &Path,
+    interpreter: &Path,
+    runtime_library_paths: &[PathBuf],
+    library_dir_resource_paths: &[PathBuf],
+    path_result: &mut AutopackPathResult,
+) -> eyre::Result<bool> {
+    let output_path = std::path::absolute(output_path)?;
+    let output_path_parent = output_path
+        .parent()
+        .ok_or_eyre("could not get parent of output path")?;
+    let resource_dir = std::path::absolute(&ctx.config.resource_dir)?;
+
+    let library_paths = runtime_library_paths.iter().map(std::path::absolute).chain(
+        library_dir_resource_paths
+            .iter()
+            .map(|resource_path| Ok(resource_dir.join(resource_path))),
+    );
+    let mut runpath = vec![];
+    for library_path in library_paths {
+        let library_path = library_path?;
+        let relative_path = pathdiff::diff_paths(&library_path, output_path_parent)
+            .ok_or_else(|| {
+                eyre::eyre!("failed to get relative path from output path {output_path_parent:?} to library path {library_path:?}")
+            })?;
+        let relative_path = <Vec<u8>>::from_path_buf(relative_path)
+            .map_err(|_| eyre::eyre!("invalid UTF-8 in path"))?;
+
+        if !runpath.is_empty() {
+            runpath.push(b':');
+        }
+        runpath.extend_from_slice(b"$ORIGIN");
+        if !relative_path.is_empty() {
+            runpath.push(b'/');
+            runpath.extend_from_slice(&relative_path);
+        }
+    }
+
+    let interpreter = <Vec<u8>>::from_path_buf(interpreter.to_owned())
+        .map_err(|_| eyre::eyre!("invalid UTF-8 in path"))?;
+    let rewrite = elf_rewrite::ElfRewrite {
+        interpreter: Some(interpreter),
+        runpath: Some(runpath),
+    };
+    let new_contents = PhaseTimings::time(&mut path_result.timings.pack_injection, || {
+        elf_rewrite::rewrite_elf(contents, &rewrite)
+    })
+    .with_context(|| format!("failed to rewrite ELF file {source_path:?}"))?;
+
+    if ctx.config.dry_run {
+        return Ok(true);
+    }
+
+    PhaseTimings::time(&mut path_result.timings.pack_injection, || {
+        let permissions = std::fs::metadata(source_path)?.permissions();
+        std::fs::write(&output_path, new_contents)
+            .with_context(|| format!("failed to write {output_path:?}"))?;
+        std::fs::set_permissions(&output_path, permissions)?;
+        eyre::Ok(())
+    })?;
 
     Ok(true)
 }
@@ -579,47 +1722,50 @@ fn autopack_shared_library(
     ctx: &AutopackContext,
     source_path: &Path,
     output_path: &Path,
-    pending_paths: &mut BTreeMap<PathBuf, AutopackPathConfig>,
+    overrides: &directory_config::PathOverrides,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
 ) -> eyre::Result<bool> {
     let Some(shared_library_config) = &ctx.config.shared_library else {
         return Ok(false);
     };
+    let dynamic_linking = overrides.dynamic_linking(&shared_library_config.dynamic_linking);
 
-    let contents = std::fs::read(source_path)?;
-    let program_object = goblin::Object::parse(&contents)?;
+    let contents = mapped_file::MappedFile::open(source_path)?;
+    let program_object = PhaseTimings::time(&mut path_result.timings.elf_parsing, || {
+        ctx.inspect_object(&contents)
+    });
 
-    let goblin::Object::Elf(program_object) = program_object else {
+    let Some(program_object) = program_object else {
         eyre::bail!(
-            "tried to autopack non-ELF dynamic binary: {}",
+            "tried to autopack unrecognized dynamic binary: {}",
             source_path.display()
         );
     };
+    if !program_object
+        .arch
+        .is_none_or(|arch| ctx.is_target_arch(arch))
+    {
+        return Ok(false);
+    }
 
+    let library_filter = library_filter::LibraryFilter::new(&dynamic_linking, false)?;
     let needed_libraries: VecDeque<_> = program_object
-        .libraries
+        .needed_libraries
         .iter()
-        .copied()
-        .filter(|library| {
-            !shared_library_config
-                .dynamic_linking
-                .skip_libraries
-                .contains(*library)
-        })
-        .chain(
-            shared_library_config
-                .dynamic_linking
-                .extra_libraries
-                .iter()
-                .map(|lib| &**lib),
-        )
-        .map(|lib| lib.to_string())
+        .filter(|library| !library_filter.is_skipped(library))
+        .chain(&dynamic_linking.extra_libraries)
+        .cloned()
         .collect();
 
     let library_dir_resource_paths = collect_all_library_dirs(
         ctx,
-        &shared_library_config.dynamic_linking,
+        &dynamic_linking,
+        &program_object,
+        source_path,
         needed_libraries,
         pending_paths,
+        path_result,
     )?;
 
     let library_dirs = library_dir_resource_paths
@@ -635,14 +1781,21 @@ fn autopack_shared_library(
         return Ok(false);
     }
 
-    let file = if source_path == output_path {
-        std::fs::OpenOptions::new().append(true).open(output_path)?
-    } else {
-        let mut new_file = std::fs::File::create(output_path)?;
-        new_file.write_all(&contents)?;
-        new_file
-    };
-    brioche_pack::inject_pack(file, &pack)?;
+    if ctx.config.dry_run {
+        return Ok(true);
+    }
+
+    PhaseTimings::time(&mut path_result.timings.pack_injection, || {
+        let file = if source_path == output_path {
+            std::fs::OpenOptions::new().append(true).open(output_path)?
+        } else {
+            let mut new_file = std::fs::File::create(output_path)?;
+            new_file.write_all(&contents)?;
+            new_file
+        };
+        brioche_pack::inject_pack(file, &pack)?;
+        eyre::Ok(())
+    })?;
 
     Ok(true)
 }
@@ -651,7 +1804,9 @@ fn autopack_script(
     ctx: &AutopackContext,
     source_path: &Path,
     output_path: &Path,
-    pending_paths: &mut BTreeMap<PathBuf, AutopackPathConfig>,
+    overrides: &directory_config::PathOverrides,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
 ) -> eyre::Result<bool> {
     let Some(script_config) = &ctx.config.script else {
         return Ok(false);
@@ -670,65 +1825,70 @@ fn autopack_script(
     let mut shebang_line = String::new();
     script_file.read_line(&mut shebang_line)?;
 
-    let shebang_line = shebang_line.trim();
-    let shebang_parts = shebang_line.split_once(|c: char| c.is_ascii_whitespace());
-    let (command_path, arg) = match shebang_parts {
-        Some((command_path, arg)) => (command_path.trim(), arg.trim()),
-        None => (shebang_line, ""),
-    };
-
-    let mut arg = Some(arg).filter(|arg| !arg.is_empty());
-    let mut command_name = command_path
-        .split(['/', '\\'])
-        .last()
-        .unwrap_or(command_path);
-
-    if command_name == "env" {
-        command_name = arg.ok_or_eyre("expected argument for env script")?;
-        arg = None;
-    }
-    let mut command = None;
-    for link_dependency_path in &ctx.link_dependency_paths {
-        if link_dependency_path.join(command_name).is_file() {
-            command = Some(link_dependency_path.join(command_name));
-            break;
-        }
-    }
+    let shebang = shebang::parse_shebang(&shebang_line)?;
+    let command_name = overrides
+        .script_interpreters
+        .get(&shebang.command_name)
+        .unwrap_or(&shebang.command_name);
+    let command = PhaseTimings::time(&mut path_result.timings.library_resolution, || {
+        ctx.link_dependency_paths
+            .iter()
+            .map(|link_dependency_path| link_dependency_path.join(command_name))
+            .find(|command| command.is_file())
+    });
 
     let command = command.ok_or_else(|| eyre::eyre!("could not find command {command_name:?}"))?;
 
     // Autopack the command if it's pending
     try_autopack_dependency(ctx, &command, pending_paths)?;
 
-    let command_resource = add_named_blob_from(ctx, &command, None)?;
-    let script_resource = add_named_blob_from(ctx, source_path, None)?;
+    let (command_resource, script_resource) =
+        PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+            let command_resource = add_named_blob_from(
+                ctx,
+                &mut path_result.resources,
+                &path_result.path,
+                &command,
+                BlobRole::Interpreter,
+                None,
+            )?;
+            let script_resource = add_named_blob_from(
+                ctx,
+                &mut path_result.resources,
+                &path_result.path,
+                source_path,
+                BlobRole::Script,
+                None,
+            )?;
+            eyre::Ok((command_resource, script_resource))
+        })?;
+    path_result.interpreter = Some(AutopackDependencyResult {
+        source_path: command.clone(),
+        resource_path: command_resource.clone(),
+    });
 
-    let env_resource_paths = script_config
+    let env_template_resources = script_config
         .env
         .values()
-        .filter_map(|value| match value {
-            runnable_core::EnvValue::Clear => None,
-            runnable_core::EnvValue::Inherit => None,
-            runnable_core::EnvValue::Set { value } => Some(value),
-            runnable_core::EnvValue::Fallback { value } => Some(value),
-            runnable_core::EnvValue::Prepend {
-                value,
-                separator: _,
-            } => Some(value),
-            runnable_core::EnvValue::Append {
-                value,
-                separator: _,
-            } => Some(value),
-        })
+        .flat_map(|value| value.templates())
         .flat_map(|template| &template.components)
         .filter_map(|component| match component {
             runnable_core::TemplateComponent::Literal { .. }
-            | runnable_core::TemplateComponent::RelativePath { .. } => None,
-            runnable_core::TemplateComponent::Resource { resource } => Some(
-                resource
-                    .to_path()
-                    .map_err(|_| eyre::eyre!("invalid resource path")),
-            ),
+            | runnable_core::TemplateComponent::RelativePath { .. }
+            | runnable_core::TemplateComponent::EnvVar { .. }
+            | runnable_core::TemplateComponent::ProgramDir => None,
+            runnable_core::TemplateComponent::Resource { resource } => Some(resource),
+        });
+    let env_path_resources = script_config.env.values().filter_map(|value| match value {
+        runnable_core::EnvValue::PrependPath { path, .. } => path.resource(),
+        _ => None,
+    });
+    let env_resource_paths = env_template_resources
+        .chain(env_path_resources)
+        .map(|resource| {
+            resource
+                .to_path()
+                .map_err(|_| eyre::eyre!("invalid resource path"))
         })
         .collect::<eyre::Result<Vec<_>>>()?;
 
@@ -742,12 +1902,20 @@ fn autopack_script(
 
     let command = runnable_core::Template::from_resource_path(command_resource)?;
 
-    let mut args = vec![];
-    if let Some(arg) = arg {
-        args.push(runnable_core::ArgValue::Arg {
+    let extra_args = overrides
+        .script_interpreter_args
+        .get(&shebang.command_name)
+        .into_iter()
+        .flatten()
+        .cloned();
+    let mut args = shebang
+        .args
+        .into_iter()
+        .chain(extra_args)
+        .map(|arg| runnable_core::ArgValue::Arg {
             value: runnable_core::Template::from_literal(arg.into()),
-        });
-    }
+        })
+        .collect::<Vec<_>>();
     args.push(runnable_core::ArgValue::Arg {
         value: runnable_core::Template::from_resource_path(script_resource.clone())?,
     });
@@ -762,40 +1930,265 @@ fn autopack_script(
         args,
         env,
         clear_env: script_config.clear_env,
+        clear_env_except: script_config.clear_env_except.clone(),
+        umask: None,
+        nice: None,
+        rlimits: vec![],
+        cwd: None,
+        platforms: vec![],
+        pre_commands: vec![],
         source: Some(runnable_core::RunnableSource {
             path: runnable_core::RunnablePath::from_resource_path(script_resource)?,
         }),
     };
-    let pack = brioche_pack::Pack::Metadata {
-        resource_paths,
-        format: runnable_core::FORMAT.to_string(),
-        metadata: serde_json::to_vec(&runnable_pack)?,
+    let script_name = source_path
+        .file_name()
+        .ok_or_eyre("failed to get filename from script path")?;
+    let mut metadata_name = script_name.to_owned();
+    metadata_name.push(".runnable.json");
+    let pack = PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+        build_runnable_metadata_pack(&runnable_pack, resource_paths, |metadata| {
+            add_named_blob(
+                ctx,
+                &mut path_result.resources,
+                metadata,
+                false,
+                Path::new(&metadata_name),
+            )
+            .with_context(|| format!("failed to add runnable metadata resource {metadata_name:?}"))
+        })
+    })?;
+
+    if ctx.config.dry_run {
+        return Ok(true);
+    }
+
+    PhaseTimings::time(&mut path_result.timings.pack_injection, || {
+        let packed_executable = overrides
+            .packed_executable
+            .as_deref()
+            .unwrap_or(&script_config.packed_executable);
+        write_packed_executable(packed_executable, output_path, &pack)
+    })?;
+
+    Ok(true)
+}
+
+fn autopack_wasm(
+    ctx: &AutopackContext,
+    source_path: &Path,
+    output_path: &Path,
+    overrides: &directory_config::PathOverrides,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
+) -> eyre::Result<bool> {
+    let Some(wasm_config) = &ctx.config.wasm else {
+        return Ok(false);
+    };
+
+    let runtime_name = &wasm_config.runtime;
+    let runtime = PhaseTimings::time(&mut path_result.timings.library_resolution, || {
+        ctx.link_dependency_paths
+            .iter()
+            .map(|link_dependency_path| link_dependency_path.join(runtime_name))
+            .find(|runtime| runtime.is_file())
+    });
+    let runtime =
+        runtime.ok_or_else(|| eyre::eyre!("could not find wasm runtime {runtime_name:?}"))?;
+
+    // Autopack the runtime if it's pending
+    try_autopack_dependency(ctx, &runtime, pending_paths)?;
+
+    let (runtime_resource, module_resource) =
+        PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+            let runtime_resource = add_named_blob_from(
+                ctx,
+                &mut path_result.resources,
+                &path_result.path,
+                &runtime,
+                BlobRole::Interpreter,
+                None,
+            )?;
+            let module_resource = add_named_blob_from(
+                ctx,
+                &mut path_result.resources,
+                &path_result.path,
+                source_path,
+                BlobRole::Program,
+                None,
+            )?;
+            eyre::Ok((runtime_resource, module_resource))
+        })?;
+    path_result.interpreter = Some(AutopackDependencyResult {
+        source_path: runtime.clone(),
+        resource_path: runtime_resource.clone(),
+    });
+
+    let resource_paths = [runtime_resource.clone(), module_resource.clone()]
+        .into_iter()
+        .map(|path| {
+            Vec::<u8>::from_path_buf(path).map_err(|_| eyre::eyre!("invalid resource path"))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    let command = runnable_core::Template::from_resource_path(runtime_resource)?;
+
+    let mut args = wasm_config
+        .runtime_args
+        .iter()
+        .map(|arg| runnable_core::ArgValue::Arg {
+            value: runnable_core::Template::from_literal(arg.clone().into()),
+        })
+        .collect::<Vec<_>>();
+    args.push(runnable_core::ArgValue::Arg {
+        value: runnable_core::Template::from_resource_path(module_resource.clone())?,
+    });
+    args.push(runnable_core::ArgValue::Rest);
+
+    let runnable_pack = runnable_core::Runnable {
+        command,
+        args,
+        env: vec![],
+        clear_env: false,
+        clear_env_except: vec![],
+        umask: None,
+        nice: None,
+        rlimits: vec![],
+        cwd: None,
+        platforms: vec![],
+        pre_commands: vec![],
+        source: Some(runnable_core::RunnableSource {
+            path: runnable_core::RunnablePath::from_resource_path(module_resource)?,
+        }),
     };
+    let module_name = source_path
+        .file_name()
+        .ok_or_eyre("failed to get filename from wasm module path")?;
+    let mut metadata_name = module_name.to_owned();
+    metadata_name.push(".runnable.json");
+    let pack = PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+        build_runnable_metadata_pack(&runnable_pack, resource_paths, |metadata| {
+            add_named_blob(
+                ctx,
+                &mut path_result.resources,
+                metadata,
+                false,
+                Path::new(&metadata_name),
+            )
+            .with_context(|| format!("failed to add runnable metadata resource {metadata_name:?}"))
+        })
+    })?;
+
+    if ctx.config.dry_run {
+        return Ok(true);
+    }
+
+    PhaseTimings::time(&mut path_result.timings.pack_injection, || {
+        let packed_executable = overrides
+            .packed_executable
+            .as_deref()
+            .unwrap_or(&wasm_config.packed_executable);
+        write_packed_executable(packed_executable, output_path, &pack)
+    })?;
+
+    Ok(true)
+}
 
-    let packed_exec_path = &script_config.packed_executable;
+/// Write a copy of a packed executable to `output_path`, then inject
+/// `pack` into it.
+fn write_packed_executable(
+    packed_exec_path: &Path,
+    output_path: &Path,
+    pack: &brioche_pack::Pack,
+) -> eyre::Result<()> {
     let mut packed_exec = std::fs::File::open(packed_exec_path)
         .with_context(|| format!("failed to open packed executable {packed_exec_path:?}"))?;
-
     let mut output = std::fs::File::create(output_path)
         .with_context(|| format!("failed to create file {output_path:?}"))?;
     std::io::copy(&mut packed_exec, &mut output)
         .with_context(|| format!("failed to copy packed executable to {output_path:?}"))?;
-    brioche_pack::inject_pack(output, &pack)
+    brioche_pack::inject_pack(output, pack)
         .with_context(|| format!("failed to inject pack into {output_path:?}"))?;
 
-    Ok(true)
+    Ok(())
+}
+
+/// Runnable metadata larger than this is stored in a resource instead of
+/// directly in the pack. See [`runnable_core::REFERENCE_FORMAT`].
+pub const MAX_INLINE_RUNNABLE_METADATA_LEN: usize = 16 * 1024;
+
+/// Build a metadata pack for a runnable. Small runnables are embedded
+/// directly, but large runnables are written to `resource_dir` as a blob
+/// named `name`, so that the pack only contains a reference to it.
+pub fn runnable_metadata_pack(
+    resource_dir: &Path,
+    runnable: &runnable_core::Runnable,
+    resource_paths: Vec<Vec<u8>>,
+    name: &Path,
+) -> eyre::Result<brioche_pack::Pack> {
+    build_runnable_metadata_pack(runnable, resource_paths, |metadata| {
+        brioche_resources::add_named_blob(resource_dir, std::io::Cursor::new(metadata), false, name)
+            .with_context(|| format!("failed to add runnable metadata resource {name:?}"))
+    })
+}
+
+/// Build a metadata pack for a runnable, calling `add_metadata_blob` to
+/// add the metadata as a resource if it's too large to embed. Resource
+/// paths are sorted and deduplicated so the pack doesn't depend on the
+/// order resources were added in.
+fn build_runnable_metadata_pack(
+    runnable: &runnable_core::Runnable,
+    mut resource_paths: Vec<Vec<u8>>,
+    add_metadata_blob: impl FnOnce(Vec<u8>) -> eyre::Result<PathBuf>,
+) -> eyre::Result<brioche_pack::Pack> {
+    resource_paths.sort();
+    resource_paths.dedup();
+
+    let metadata = serde_json::to_vec(runnable)?;
+    if metadata.len() <= MAX_INLINE_RUNNABLE_METADATA_LEN {
+        return Ok(brioche_pack::Pack::Metadata {
+            resource_paths,
+            format: runnable_core::FORMAT.to_string(),
+            metadata,
+        });
+    }
+
+    let metadata_resource = add_metadata_blob(metadata)?;
+    let resource = <Vec<u8>>::from_path_buf(metadata_resource)
+        .map_err(|_| eyre::eyre!("invalid UTF-8 in path"))?;
+    resource_paths.push(resource.clone());
+
+    let reference = runnable_core::RunnableReference { resource };
+    Ok(brioche_pack::Pack::Metadata {
+        resource_paths,
+        format: runnable_core::REFERENCE_FORMAT.to_string(),
+        metadata: serde_json::to_vec(&reference)?,
+    })
 }
 
 fn autopack_repack(
     ctx: &AutopackContext,
     source_path: &Path,
     output_path: &Path,
-    pending_paths: &mut BTreeMap<PathBuf, AutopackPathConfig>,
+    overrides: &directory_config::PathOverrides,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
 ) -> eyre::Result<bool> {
-    let Some(_) = &ctx.config.repack else {
+    let Some(repack_config) = &ctx.config.repack else {
         return Ok(false);
     };
 
+    let refreshed_overrides;
+    let overrides = if repack_config.refresh_libraries {
+        refreshed_overrides = directory_config::PathOverrides {
+            refresh_libraries: true,
+            ..overrides.clone()
+        };
+        &refreshed_overrides
+    } else {
+        overrides
+    };
+
     let contents = std::fs::read(source_path)?;
     let extracted = brioche_pack::extract_pack(std::io::Cursor::new(&contents))?;
 
@@ -805,6 +2198,29 @@ fn autopack_repack(
     let unpacked_source_path;
     let unpacked_output_path;
     match repack_source {
+        PackSource::This if ctx.config.dry_run => {
+            // Write the unpacked contents to a temporary file instead, so
+            // the output path is left unchanged
+            let unpacked_contents = &contents[..extracted.unpacked_len];
+            let temp_path = dry_run_temp_path();
+            std::fs::write(&temp_path, unpacked_contents).with_context(|| {
+                format!(
+                    "failed to write unpacked contents to {}",
+                    temp_path.display()
+                )
+            })?;
+
+            let result = try_autopack_path(
+                ctx,
+                &temp_path,
+                output_path,
+                overrides,
+                pending_paths,
+                path_result,
+            );
+            let _ = std::fs::remove_file(&temp_path);
+            return result;
+        }
         PackSource::This => {
             // Write the unpacked contents to the output path
             let unpacked_contents = &contents[..extracted.unpacked_len];
@@ -830,24 +2246,50 @@ fn autopack_repack(
         ctx,
         &unpacked_source_path,
         &unpacked_output_path,
+        overrides,
         pending_paths,
+        path_result,
     )?;
     Ok(result)
 }
 
+fn dry_run_temp_path() -> PathBuf {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    std::env::temp_dir().join(format!(
+        "brioche-autopack-dry-run-{}-{n}",
+        std::process::id()
+    ))
+}
+
 fn collect_all_library_dirs(
     ctx: &AutopackContext,
     dynamic_linking_config: &DynamicLinkingConfig,
+    program: &ObjectInfo,
+    program_path: &Path,
     mut needed_libraries: VecDeque<String>,
-    pending_paths: &mut BTreeMap<PathBuf, AutopackPathConfig>,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
 ) -> eyre::Result<Vec<PathBuf>> {
-    let mut library_search_paths = vec![];
     let mut resource_library_dirs = vec![];
     let mut found_libraries = HashSet::new();
     let mut found_library_dirs = HashSet::new();
-
-    library_search_paths.extend_from_slice(&dynamic_linking_config.library_paths);
-    library_search_paths.extend_from_slice(&ctx.link_dependency_library_paths);
+    let program_arch = program.arch;
+
+    let library_filter = library_filter::LibraryFilter::new(dynamic_linking_config, false)?;
+    let mut required_versions = symbol_versions::RequiredVersions::default();
+    let mut packed_library_versions = vec![];
+    required_versions.add(&program.version_requirements, program_path);
+
+    let mut library_index =
+        PhaseTimings::time(&mut path_result.timings.library_resolution, || {
+            let mut library_index = ctx.library_index(&dynamic_linking_config.library_paths)?;
+            let search_entries = program.library_search_entries.iter().map(|entry| &**entry);
+            for path in rpath_library_paths(dynamic_linking_config, search_entries, program_path) {
+                library_index.add_search_path(&path)?;
+            }
+            eyre::Ok(library_index)
+        })?;
 
     while let Some(library_name) = needed_libraries.pop_front() {
         // If we've already found this library, then skip it
@@ -855,10 +2297,21 @@ fn collect_all_library_dirs(
             continue;
         }
 
-        // Find the path to the library
-        let library_path = find_library(&library_search_paths, &library_name)?;
+        // Find the path to the library, skipping libraries for other
+        // architectures
+        let library_path = PhaseTimings::time(&mut path_result.timings.library_resolution, || {
+            library_index
+                .find_for_arch(&library_name, program_arch)
+                .map(Path::to_owned)
+        });
         let Some(library_path) = library_path else {
             if dynamic_linking_config.skip_unknown_libraries {
+                found_libraries.insert(library_name.clone());
+                path_result.libraries.push(AutopackLibraryResult {
+                    name: library_name,
+                    source_path: None,
+                    resource_path: None,
+                });
                 continue;
             } else {
                 eyre::bail!("library not found: {library_name:?}");
@@ -866,23 +2319,48 @@ fn collect_all_library_dirs(
         };
 
         // Autopack the library if it's pending
-        try_autopack_dependency(ctx, &library_path, pending_paths)?;
+        let canonical_library_path = try_autopack_dependency(ctx, &library_path, pending_paths)?;
 
         found_libraries.insert(library_name.clone());
 
         // Don't add the library if it's been skipped. We still do everything
         // else so we can add transitive dependencies even if a library has
         // been skipped
-        if !dynamic_linking_config
-            .skip_libraries
-            .contains(&*library_name)
-        {
+        let mut library_result = AutopackLibraryResult {
+            name: library_name.clone(),
+            source_path: Some(library_path.clone()),
+            resource_path: None,
+        };
+        let is_skipped = library_filter.is_skipped(&library_name);
+        if !is_skipped {
             // Add the library to the resource directory
             let library_alias = Path::new(&library_name);
             let library_resource_path =
-                add_named_blob_from(ctx, &library_path, Some(library_alias)).with_context(
-                    || format!("failed to add resource for library {library_path:?}"),
-                )?;
+                PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+                    add_cached_blob_from(
+                        ctx,
+                        &mut path_result.resources,
+                        &path_result.path,
+                        &library_path,
+                        canonical_library_path.as_deref(),
+                        BlobRole::Library,
+                        Some(library_alias),
+                    )
+                })
+                .with_context(|| format!("failed to add resource for library {library_path:?}"))?;
+            if dynamic_linking_config.preserve_symlink_chains && !ctx.config.dry_run {
+                PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+                    let chain = brioche_resources::symlink_chain(&library_path)?;
+                    brioche_resources::add_symlink_chain(
+                        &ctx.config.resource_dir,
+                        &library_resource_path,
+                        &chain,
+                    )?;
+                    eyre::Ok(())
+                })
+                .with_context(|| format!("failed to add symlinks for library {library_path:?}"))?;
+            }
+            library_result.resource_path = Some(library_resource_path.clone());
 
             // Add the parent dir to the list of library directories. Note
             // that this directory is guaranteed to only contain just this
@@ -897,46 +2375,88 @@ fn collect_all_library_dirs(
                 resource_library_dirs.push(library_resource_dir.clone());
             }
         }
+        ctx.config.progress.report(&ProgressEvent::LibraryResolved {
+            path: &path_result.path,
+            library: &library_result,
+        });
+        path_result.libraries.push(library_result);
 
         // Try to get the dynamic dependencies from the library itself
-        let Ok(library_file) = std::fs::read(&library_path) else {
-            continue;
+        let cached_library = canonical_library_path
+            .as_deref()
+            .and_then(|path| ctx.library_cache.library(path));
+        let library = match cached_library {
+            Some(library) => library,
+            None => {
+                let Ok(library_file) = mapped_file::MappedFile::open(&library_path) else {
+                    continue;
+                };
+                let library =
+                    library_cache::LibraryInfo::read(ctx, &library_file, &mut path_result.timings);
+                match canonical_library_path {
+                    Some(canonical_library_path) => ctx
+                        .library_cache
+                        .insert_library(canonical_library_path, library),
+                    None => Arc::new(library),
+                }
+            }
         };
-        let Ok(library_object) = goblin::Object::parse(&library_file) else {
+        let Some(library_info) = &*library else {
             continue;
         };
 
-        // TODO: Support other object files
-        let library_elf = match library_object {
-            goblin::Object::Elf(elf) => elf,
-            _ => {
-                continue;
+        needed_libraries.extend(library_info.object.needed_libraries.iter().cloned());
+        required_versions.add(&library_info.object.version_requirements, &library_path);
+        PhaseTimings::time(&mut path_result.timings.library_resolution, || {
+            let rpath_entries = library_info
+                .object
+                .library_search_entries
+                .iter()
+                .map(|entry| &**entry);
+            for path in rpath_library_paths(dynamic_linking_config, rpath_entries, &library_path) {
+                library_index.add_search_path(&path)?;
             }
-        };
-        needed_libraries.extend(library_elf.libraries.iter().map(|lib| lib.to_string()));
-
-        // If the library has a Brioche pack, then use the included resources
-        // for additional search directories
-        let library_file_cursor = std::io::Cursor::new(&library_file[..]);
-        if let Ok(extracted_library) = brioche_pack::extract_pack(library_file_cursor) {
-            let library_dirs = match &extracted_library.pack {
-                brioche_pack::Pack::LdLinux { library_dirs, .. } => &library_dirs[..],
-                brioche_pack::Pack::Static { library_dirs } => &library_dirs[..],
-                brioche_pack::Pack::Metadata { .. } => &[],
-            };
+            for library_dir_path in &library_info.pack_library_dirs {
+                library_index.add_search_path(library_dir_path)?;
+            }
+            eyre::Ok(())
+        })?;
 
-            for library_dir in library_dirs {
-                let Ok(library_dir) = library_dir.to_path() else {
-                    continue;
-                };
-                let Some(library_dir_path) = brioche_resources::find_in_resource_dirs(
-                    &ctx.config.all_resource_dirs,
-                    library_dir,
-                ) else {
-                    continue;
-                };
+        if !is_skipped {
+            packed_library_versions.push((library_name, library_path, library.clone()));
+        }
+    }
 
-                library_search_paths.push(library_dir_path);
+    // Check the symbol versions once all the libraries have been found,
+    // since libraries found later can require versions from libraries
+    // found earlier
+    if dynamic_linking_config.symbol_version_check != SymbolVersionCheck::Disabled {
+        let missing_versions = packed_library_versions
+            .iter()
+            .flat_map(|(library_name, library_path, library)| {
+                let defined_versions = library
+                    .as_ref()
+                    .as_ref()
+                    .and_then(|library| library.object.defined_versions.as_ref());
+                required_versions.missing_versions(library_name, library_path, defined_versions)
+            })
+            .collect::<Vec<_>>();
+        match dynamic_linking_config.symbol_version_check {
+            SymbolVersionCheck::Disabled => {}
+            SymbolVersionCheck::Warn => {
+                for missing_version in &missing_versions {
+                    ctx.config.progress.report(&ProgressEvent::Warning {
+                        path: &path_result.path,
+                        message: missing_version,
+                    });
+                }
+            }
+            SymbolVersionCheck::Error => {
+                eyre::ensure!(
+                    missing_versions.is_empty(),
+                    "libraries are missing required symbol versions:\n{}",
+                    missing_versions.join("\n")
+                );
             }
         }
     }
@@ -944,10 +2464,83 @@ fn collect_all_library_dirs(
     Ok(resource_library_dirs)
 }
 
-fn find_library(
+const NIX_STORE_DIR: &str = "/nix/store/";
+
+/// Get the library search paths from the RPATH and RUNPATH entries of an
+/// object file. Entries pointing into the Nix store are included if
+/// `resolve_nix_store_paths` is enabled, and all other absolute or
+/// `$ORIGIN`-relative entries are included if `resolve_rpaths` is enabled.
+fn rpath_library_paths<'a>(
+    dynamic_linking_config: &DynamicLinkingConfig,
+    rpath_entries: impl IntoIterator<Item = &'a str>,
+    elf_path: &Path,
+) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    let entries = rpath_entries.into_iter().flat_map(|paths| paths.split(':'));
+    for entry in entries {
+        let path = if entry.starts_with(NIX_STORE_DIR) {
+            if !dynamic_linking_config.resolve_nix_store_paths {
+                continue;
+            }
+            PathBuf::from(entry)
+        } else {
+            if !dynamic_linking_config.resolve_rpaths {
+                continue;
+            }
+            let Some(path) = expand_rpath_entry(entry, elf_path.parent()) else {
+                continue;
+            };
+            path
+        };
+
+        if path.is_dir() && !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+
+    paths
+}
+
+/// Expand `$ORIGIN` in an RPATH or RUNPATH entry. Returns `None` for
+/// entries that can't be resolved ahead of time, such as relative paths
+/// (which are relative to the working directory at runtime) or other
+/// tokens like `$LIB`.
+fn expand_rpath_entry(entry: &str, origin: Option<&Path>) -> Option<PathBuf> {
+    let expanded = if entry.contains("$ORIGIN") || entry.contains("${ORIGIN}") {
+        let origin = origin?.to_str()?;
+        entry
+            .replace("${ORIGIN}", origin)
+            .replace("$ORIGIN", origin)
+    } else {
+        entry.to_string()
+    };
+
+    if expanded.contains('$') || !expanded.starts_with('/') {
+        return None;
+    }
+
+    Some(PathBuf::from(expanded))
+}
+
+/// Find a library by name from a list of search paths. Each search path
+/// can either be a directory containing the library, or a library file
+/// whose filename or `DT_SONAME` matches.
+pub fn find_library(
     library_search_paths: &[PathBuf],
     library_name: &str,
 ) -> eyre::Result<Option<PathBuf>> {
+    find_library_for_arch(library_search_paths, library_name, None)
+}
+
+/// Find a library by name like [`find_library`]. If `arch` is set,
+/// libraries that aren't ELF files with the same architecture are skipped.
+pub fn find_library_for_arch(
+    library_search_paths: &[PathBuf],
+    library_name: &str,
+    arch: Option<ElfArch>,
+) -> eyre::Result<Option<PathBuf>> {
+    let is_arch_match = |path: &Path| arch.is_none_or(|arch| arch.matches_file(path));
+
     let mut library_search_path_files = vec![];
 
     // Try to find a direct filename match from the search paths
@@ -956,7 +2549,7 @@ fn find_library(
             // Check if the search path is a directory and contains a file
             // matching the library name
             let lib_path = path.join(library_name);
-            if lib_path.is_file() {
+            if lib_path.is_file() && is_arch_match(&lib_path) {
                 return Ok(Some(lib_path));
             }
         } else if path.is_file() {
@@ -965,7 +2558,7 @@ fn find_library(
             let path_filename = path
                 .file_name()
                 .ok_or_eyre("failed to get filename from path")?;
-            if path_filename.to_str() == Some(library_name) {
+            if path_filename.to_str() == Some(library_name) && is_arch_match(path) {
                 return Ok(Some(path.to_owned()));
             }
 
@@ -978,7 +2571,7 @@ fn find_library(
     // Try to find a library file that matches based on its `DT_SONAME` field
     // as a fallback
     for &path in &library_search_path_files {
-        let Ok(contents) = std::fs::read(path) else {
+        let Ok(contents) = mapped_file::MappedFile::open(path) else {
             continue;
         };
 
@@ -986,7 +2579,7 @@ fn find_library(
             continue;
         };
 
-        if elf.soname == Some(library_name) {
+        if elf.soname == Some(library_name) && is_arch_match(path) {
             return Ok(Some(path.to_owned()));
         }
     }
@@ -994,22 +2587,48 @@ fn find_library(
     Ok(None)
 }
 
+/// Add a file as a blob, after running it through the configured
+/// [`AutopackHook`]s. `packed_path` is the path being packed that needs
+/// the blob. The alias name defaults to the file's name.
 fn add_named_blob_from(
     ctx: &AutopackContext,
+    resources: &mut Vec<PathBuf>,
+    packed_path: &Path,
     path: &Path,
+    role: BlobRole,
     alias_name: Option<&Path>,
 ) -> eyre::Result<PathBuf> {
-    use std::os::unix::prelude::PermissionsExt as _;
+    let alias_name = default_alias_name(path, alias_name)?;
+    let (path, alias_name) =
+        blob_hook::apply_blob_hooks(&ctx.config.hooks, packed_path, path, role, alias_name)?;
+    add_file_blob(ctx, resources, &path, role, &alias_name)
+}
 
-    let alias_name = match alias_name {
-        Some(alias_name) => alias_name,
+fn default_alias_name<'a>(path: &'a Path, alias_name: Option<&'a Path>) -> eyre::Result<&'a Path> {
+    match alias_name {
+        Some(alias_name) => Ok(alias_name),
         None => {
             let filename = path
                 .file_name()
                 .ok_or_eyre("failed to get filename from path")?;
-            Path::new(filename)
+            Ok(Path::new(filename))
         }
-    };
+    }
+}
+
+/// Add a file as a blob without running any hooks.
+///
+/// The file is linked into the resource dir if the config's
+/// [`AutopackConfig::blob_link_mode`] allows it. Programs and scripts are
+/// never hard linked, since they're usually replaced in place once packed.
+fn add_file_blob(
+    ctx: &AutopackContext,
+    resources: &mut Vec<PathBuf>,
+    path: &Path,
+    role: BlobRole,
+    alias_name: &Path,
+) -> eyre::Result<PathBuf> {
+    use std::os::unix::prelude::PermissionsExt as _;
 
     let mut file = std::fs::File::open(path)?;
     let metadata = file.metadata()?;
@@ -1018,32 +2637,425 @@ fn add_named_blob_from(
     let mode = permissions.mode();
     let is_executable = mode & 0o111 != 0;
 
-    let mut contents = vec![];
-    file.read_to_end(&mut contents)?;
+    if ctx.config.dry_run {
+        let mut contents = vec![];
+        file.read_to_end(&mut contents)?;
+        return add_named_blob(ctx, resources, contents, is_executable, alias_name);
+    }
 
-    let resource_path = brioche_resources::add_named_blob(
+    let link_mode = match (ctx.config.blob_link_mode, role) {
+        (brioche_resources::BlobLinkMode::HardLink, BlobRole::Program | BlobRole::Script) => {
+            brioche_resources::BlobLinkMode::Copy
+        }
+        (link_mode, _) => link_mode,
+    };
+    let resource_path = brioche_resources::add_named_blob_from_path(
         &ctx.config.resource_dir,
-        std::io::Cursor::new(contents),
+        path,
         is_executable,
         alias_name,
+        link_mode,
     )?;
+    resources.push(resource_path.clone());
+    Ok(resource_path)
+}
+
+/// Add a file as a blob like [`add_named_blob_from`], reusing the resource
+/// path if the same file was already added with the same alias during
+/// this run. Hooks still run each time, and files without a canonical path
+/// (or substituted by a hook) aren't cached.
+fn add_cached_blob_from(
+    ctx: &AutopackContext,
+    resources: &mut Vec<PathBuf>,
+    packed_path: &Path,
+    path: &Path,
+    canonical_path: Option<&Path>,
+    role: BlobRole,
+    alias_name: Option<&Path>,
+) -> eyre::Result<PathBuf> {
+    let alias_name = default_alias_name(path, alias_name)?;
+    let (hooked_path, alias_name) =
+        blob_hook::apply_blob_hooks(&ctx.config.hooks, packed_path, path, role, alias_name)?;
+    let canonical_path = canonical_path.filter(|_| hooked_path == path);
+    let Some(canonical_path) = canonical_path else {
+        return add_file_blob(ctx, resources, &hooked_path, role, &alias_name);
+    };
+
+    if let Some(resource_path) = ctx.library_cache.resource(canonical_path, &alias_name) {
+        resources.push(resource_path.clone());
+        return Ok(resource_path);
+    }
+
+    let resource_path = add_file_blob(ctx, resources, path, role, &alias_name)?;
+    ctx.library_cache.insert_resource(
+        canonical_path.to_owned(),
+        alias_name.into_owned(),
+        resource_path.clone(),
+    );
     Ok(resource_path)
 }
 
+/// Add a blob to the resource dir and record it in `resources`. For a dry
+/// run, this only records the path the blob would be added at.
+fn add_named_blob(
+    ctx: &AutopackContext,
+    resources: &mut Vec<PathBuf>,
+    contents: Vec<u8>,
+    executable: bool,
+    alias_name: &Path,
+) -> eyre::Result<PathBuf> {
+    let resource_path = if ctx.config.dry_run {
+        brioche_resources::named_blob_path(&contents[..], executable, alias_name)?
+    } else {
+        brioche_resources::add_named_blob(
+            &ctx.config.resource_dir,
+            std::io::Cursor::new(contents),
+            executable,
+            alias_name,
+        )?
+    };
+    resources.push(resource_path.clone());
+    Ok(resource_path)
+}
+
+/// Autopack a dependency if it's pending. Returns the canonical path of
+/// the dependency, or `None` if it's still being packed (because of a
+/// cycle of dependencies), in which case it shouldn't be cached.
 fn try_autopack_dependency(
     ctx: &AutopackContext,
     path: &Path,
-    pending_paths: &mut BTreeMap<PathBuf, AutopackPathConfig>,
-) -> eyre::Result<()> {
+    pending_paths: &PendingPaths,
+) -> eyre::Result<Option<PathBuf>> {
     // Get the canonical path of the dependency
     let canonical_path = path
         .canonicalize()
         .with_context(|| format!("failed to canonicalize path {path:?}"))?;
 
     // If the path is pending, then autopack it
-    if let Some(path_config) = pending_paths.remove(&canonical_path) {
+    if let Some((path_config, _in_progress)) = pending_paths.take_dependency(&canonical_path) {
         autopack_path(ctx, path, &path_config, pending_paths)?;
     }
 
-    Ok(())
+    if pending_paths.is_in_progress(&canonical_path) {
+        Ok(None)
+    } else {
+        Ok(Some(canonical_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brioche_test_fixtures::ElfBuilder;
+
+    use super::*;
+
+    /// Build a config for autopacking `paths`, using a resource dir and
+    /// packed executable under `root`. No kinds of files are enabled.
+    fn autopack_config(root: &Path, paths: Vec<PathBuf>) -> AutopackConfig {
+        let resource_dir = brioche_test_fixtures::create_resource_dir(root).unwrap();
+        AutopackConfig {
+            resource_dir: resource_dir.clone(),
+            all_resource_dirs: vec![resource_dir],
+            inputs: AutopackInputs::Paths(paths),
+            progress: Arc::new(SilentProgressReporter),
+            link_dependencies: vec![],
+            dynamic_binary: None,
+            loader_binary: None,
+            shared_library: None,
+            windows_binary: None,
+            script: None,
+            wasm: None,
+            repack: None,
+            concurrency: None,
+            dry_run: false,
+            report_path: None,
+            manifest_path: None,
+            path_overrides: vec![],
+            target_arch: None,
+            privileged_files: PrivilegedFilePolicy::default(),
+            blob_link_mode: brioche_resources::BlobLinkMode::default(),
+            skip_signatures: vec![],
+            object_inspectors: vec![],
+            hooks: vec![],
+            state_file: None,
+        }
+    }
+
+    fn packed_executable(root: &Path) -> PathBuf {
+        let path = root.join("packed-executable");
+        std::fs::write(&path, b"launcher").unwrap();
+        path
+    }
+
+    fn extract_pack(path: &Path) -> brioche_pack::Pack {
+        let packed = std::fs::File::open(path).unwrap();
+        let extracted = brioche_pack::extract_pack(packed).unwrap();
+        let contents = std::fs::read(path).unwrap();
+        assert_eq!(&contents[..extracted.unpacked_len], b"launcher");
+        extracted.pack
+    }
+
+    #[test]
+    fn test_autopack_script_args() {
+        let root = tempfile::tempdir().unwrap();
+        let dependency = root.path().join("dependency");
+        let sh = dependency.join("usr").join("bin").join("sh");
+        brioche_test_fixtures::write_script(&sh, "/bin/false", "").unwrap();
+        brioche_test_fixtures::add_env_dir_entry(
+            &dependency,
+            "PATH",
+            "usr-bin",
+            Path::new("usr/bin"),
+        )
+        .unwrap();
+
+        let script = root.path().join("bin").join("hello");
+        brioche_test_fixtures::write_script(&script, "/bin/sh -e -u", "echo hello\n").unwrap();
+
+        let mut config = autopack_config(root.path(), vec![script.clone()]);
+        config.link_dependencies = vec![dependency];
+        config.script = Some(ScriptConfig {
+            packed_executable: packed_executable(root.path()),
+            base_path: None,
+            env: BTreeMap::new(),
+            clear_env: false,
+            clear_env_except: vec![],
+        });
+        let results = autopack(&config).unwrap();
+        assert!(results.paths[0].packed);
+
+        let brioche_pack::Pack::Metadata {
+            format, metadata, ..
+        } = extract_pack(&script)
+        else {
+            panic!("expected metadata pack");
+        };
+        assert_eq!(format, runnable_core::FORMAT);
+        let runnable: runnable_core::Runnable = serde_json::from_slice(&metadata).unwrap();
+
+        let interpreter = results.paths[0].interpreter.as_ref().unwrap();
+        assert_eq!(interpreter.source_path, sh.canonicalize().unwrap());
+        assert_eq!(
+            runnable.command,
+            runnable_core::Template::from_resource_path(interpreter.resource_path.clone()).unwrap()
+        );
+
+        // The shebang args come before the script, then the packed
+        // script's own args
+        let args = runnable
+            .args
+            .iter()
+            .map(|arg| match arg {
+                runnable_core::ArgValue::Arg { value } => Some(value),
+                runnable_core::ArgValue::Rest => None,
+                arg => panic!("unexpected arg: {arg:?}"),
+            })
+            .collect::<Vec<_>>();
+        let [Some(first), Some(second), Some(script_arg), None] = args[..] else {
+            panic!("unexpected args: {:?}", runnable.args);
+        };
+        assert_eq!(
+            *first,
+            runnable_core::Template::from_literal(b"-e".to_vec())
+        );
+        assert_eq!(
+            *second,
+            runnable_core::Template::from_literal(b"-u".to_vec())
+        );
+
+        let [runnable_core::TemplateComponent::Resource { resource }] = &script_arg.components[..]
+        else {
+            panic!("expected script resource: {script_arg:?}");
+        };
+        let script_resource = config.resource_dir.join(resource.to_path().unwrap());
+        assert_eq!(
+            std::fs::read(script_resource).unwrap(),
+            b"#!/bin/sh -e -u\necho hello\n"
+        );
+    }
+
+    fn dynamic_linking_config() -> DynamicLinkingConfig {
+        DynamicLinkingConfig {
+            library_paths: vec![],
+            skip_libraries: HashSet::new(),
+            skip_library_globs: vec![],
+            only_library_globs: vec![],
+            extra_libraries: vec![],
+            skip_unknown_libraries: false,
+            resolve_nix_store_paths: false,
+            resolve_rpaths: false,
+            symbol_version_check: SymbolVersionCheck::default(),
+            preserve_symlink_chains: false,
+        }
+    }
+
+    #[test]
+    fn test_autopack_dynamic_binary() {
+        let root = tempfile::tempdir().unwrap();
+        let dependency = root.path().join("dependency");
+        let interpreter = dependency.join("lib64").join("ld-linux-x86-64.so.2");
+        ElfBuilder::shared_library("ld-linux-x86-64.so.2")
+            .write(&interpreter)
+            .unwrap();
+        let libfoo = ElfBuilder::shared_library("libfoo.so.1").needed("libbar.so.1");
+        let libbar = ElfBuilder::shared_library("libbar.so.1");
+        libfoo
+            .write(&dependency.join("lib").join("libfoo.so.1"))
+            .unwrap();
+        libbar
+            .write(&dependency.join("lib").join("libbar.so.1"))
+            .unwrap();
+        brioche_test_fixtures::add_env_dir_entry(
+            &dependency,
+            "LIBRARY_PATH",
+            "lib",
+            Path::new("lib"),
+        )
+        .unwrap();
+
+        let program_path = root.path().join("bin").join("program");
+        let program = ElfBuilder::executable("/lib64/ld-linux-x86-64.so.2").needed("libfoo.so.1");
+        program.write(&program_path).unwrap();
+
+        let mut config = autopack_config(root.path(), vec![program_path.clone()]);
+        config.link_dependencies = vec![dependency];
+        config.dynamic_binary = Some(DynamicBinaryConfig {
+            packed_executable: packed_executable(root.path()),
+            extra_runtime_library_paths: vec![],
+            dynamic_linking: dynamic_linking_config(),
+            mode: DynamicBinaryMode::Pack,
+        });
+        let results = autopack(&config).unwrap();
+        let path_result = &results.paths[0];
+        assert!(path_result.packed);
+        assert!(matches!(
+            path_result.kind,
+            Some(AutopackKind::DynamicBinary)
+        ));
+
+        let library_names = path_result
+            .libraries
+            .iter()
+            .map(|library| &*library.name)
+            .collect::<Vec<_>>();
+        assert_eq!(library_names, ["libfoo.so.1", "libbar.so.1"]);
+
+        let brioche_pack::Pack::LdLinux {
+            program: program_resource,
+            interpreter: interpreter_resource,
+            library_dirs,
+            runtime_library_dirs,
+        } = extract_pack(&program_path)
+        else {
+            panic!("expected ld-linux pack");
+        };
+        assert!(runtime_library_dirs.is_empty());
+
+        let resource_path = |resource: &[u8]| config.resource_dir.join(resource.to_path().unwrap());
+        assert_eq!(
+            std::fs::read(resource_path(&program_resource)).unwrap(),
+            program.build()
+        );
+        assert_eq!(
+            std::fs::read(resource_path(&interpreter_resource)).unwrap(),
+            std::fs::read(&interpreter).unwrap()
+        );
+
+        // Both libraries are copied into the resource dir, and can be
+        // found from the pack's library dirs
+        let find_library = |name: &str| {
+            library_dirs
+                .iter()
+                .map(|library_dir| resource_path(library_dir).join(name))
+                .find(|path| path.is_file())
+                .unwrap_or_else(|| panic!("library {name} not found in library dirs"))
+        };
+        let libfoo_path = find_library("libfoo.so.1");
+        let libbar_path = find_library("libbar.so.1");
+        assert!(libfoo_path.starts_with(&config.resource_dir));
+        assert_eq!(std::fs::read(libfoo_path).unwrap(), libfoo.build());
+        assert_eq!(std::fs::read(libbar_path).unwrap(), libbar.build());
+    }
+
+    #[test]
+    fn test_autopack_static_executable_unpacked() {
+        let root = tempfile::tempdir().unwrap();
+        let bin = root.path().join("bin");
+        let program = ElfBuilder::static_executable().build();
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("program"), &program).unwrap();
+
+        let mut config = autopack_config(root.path(), vec![]);
+        config.inputs = AutopackInputs::Globs {
+            base_path: bin.clone(),
+            patterns: vec!["*".to_string()],
+            exclude_patterns: vec![],
+            follow_symlinks: false,
+        };
+        config.dynamic_binary = Some(DynamicBinaryConfig {
+            packed_executable: packed_executable(root.path()),
+            extra_runtime_library_paths: vec![],
+            dynamic_linking: dynamic_linking_config(),
+            mode: DynamicBinaryMode::Pack,
+        });
+        let results = autopack(&config).unwrap();
+
+        // Static executables are matched by the glob, but aren't packed
+        let [path_result] = &results.paths[..] else {
+            panic!("unexpected results: {results:?}");
+        };
+        assert_eq!(path_result.path, bin.join("program"));
+        assert!(!path_result.packed);
+        assert!(path_result.kind.is_none());
+        assert!(path_result.resources.is_empty());
+        assert_eq!(std::fs::read(bin.join("program")).unwrap(), program);
+    }
+
+    #[test]
+    fn test_pack_source_ld_linux() {
+        let root = tempfile::tempdir().unwrap();
+        let resource_dir = brioche_test_fixtures::create_resource_dir(root.path()).unwrap();
+        let program = ElfBuilder::executable("/lib64/ld-linux-x86-64.so.2").build();
+        let program_path =
+            brioche_test_fixtures::add_resource(&resource_dir, Path::new("program"), &program)
+                .unwrap();
+
+        let pack = brioche_test_fixtures::ld_linux_pack(
+            Path::new("program"),
+            Path::new("ld-linux.so"),
+            &[Path::new("lib")],
+        );
+        let packed_path = root.path().join("bin").join("program");
+        brioche_test_fixtures::write_packed(&packed_path, b"launcher", &pack).unwrap();
+
+        let packed = std::fs::File::open(&packed_path).unwrap();
+        let extracted = brioche_pack::extract_pack(packed).unwrap();
+        assert_eq!(extracted.pack, pack);
+
+        let source = pack_source(&packed_path, &extracted.pack, &[resource_dir]).unwrap();
+        assert!(matches!(source, PackSource::Path(path) if path == program_path));
+    }
+
+    #[test]
+    fn test_pack_source_missing_resource() {
+        let root = tempfile::tempdir().unwrap();
+        let resource_dir = brioche_test_fixtures::create_resource_dir(root.path()).unwrap();
+
+        let pack = brioche_test_fixtures::ld_linux_pack(
+            Path::new("missing"),
+            Path::new("ld-linux.so"),
+            &[],
+        );
+        let packed_path = root.path().join("program");
+        assert!(pack_source(&packed_path, &pack, &[resource_dir]).is_err());
+    }
+
+    #[test]
+    fn test_pack_source_static() {
+        let pack = brioche_pack::Pack::Static {
+            library_dirs: vec![],
+        };
+        let source = pack_source(Path::new("program"), &pack, &[]).unwrap();
+        assert!(matches!(source, PackSource::This));
+    }
 }
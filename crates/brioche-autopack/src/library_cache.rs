@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bstr::ByteSlice as _;
+
+use crate::ObjectInfo;
+
+/// The details of a library needed to resolve its own dependencies.
+#[derive(Debug)]
+pub struct LibraryInfo {
+    pub object: ObjectInfo,
+
+    /// The library dirs from the library's pack, if it's packed and the
+    /// dirs could be found in the resource dirs.
+    pub pack_library_dirs: Vec<PathBuf>,
+}
+
+impl LibraryInfo {
+    /// Read the details of a library. Returns `None` if it isn't an object
+    /// file recognized by any of the object inspectors.
+    pub fn read(
+        ctx: &crate::AutopackContext,
+        contents: &[u8],
+        timings: &mut crate::PhaseTimings,
+    ) -> Option<Self> {
+        let object =
+            crate::PhaseTimings::time(&mut timings.elf_parsing, || ctx.inspect_object(contents))?;
+
+        // If the library has a Brioche pack, then use the included
+        // resources for additional search directories
+        let mut pack_library_dirs = vec![];
+        let contents_cursor = std::io::Cursor::new(contents);
+        if let Ok(extracted_library) = brioche_pack::extract_pack(contents_cursor) {
+            let library_dirs = match &extracted_library.pack {
+                brioche_pack::Pack::LdLinux { library_dirs, .. } => &library_dirs[..],
+                brioche_pack::Pack::Static { library_dirs } => &library_dirs[..],
+                brioche_pack::Pack::Metadata { .. } => &[],
+            };
+
+            pack_library_dirs = library_dirs
+                .iter()
+                .filter_map(|library_dir| library_dir.to_path().ok())
+                .filter_map(|library_dir| {
+                    brioche_resources::find_in_resource_dirs(
+                        &ctx.config.all_resource_dirs,
+                        library_dir,
+                    )
+                })
+                .collect();
+        }
+
+        Some(Self {
+            object,
+            pack_library_dirs,
+        })
+    }
+}
+
+/// Caches libraries by canonical path, so libraries needed by many inputs
+/// (like libc) are only read, parsed, and added to the resource dir once
+/// per autopack run.
+#[derive(Debug, Default)]
+pub struct LibraryCache {
+    libraries: Mutex<HashMap<PathBuf, Arc<Option<LibraryInfo>>>>,
+    resources: Mutex<HashMap<(PathBuf, PathBuf), PathBuf>>,
+}
+
+impl LibraryCache {
+    pub fn library(&self, canonical_path: &Path) -> Option<Arc<Option<LibraryInfo>>> {
+        let libraries = self.libraries.lock().ok()?;
+        libraries.get(canonical_path).cloned()
+    }
+
+    pub fn insert_library(
+        &self,
+        canonical_path: PathBuf,
+        library: Option<LibraryInfo>,
+    ) -> Arc<Option<LibraryInfo>> {
+        let library = Arc::new(library);
+        if let Ok(mut libraries) = self.libraries.lock() {
+            libraries.insert(canonical_path, library.clone());
+        }
+        library
+    }
+
+    /// Get the resource path a file was already added at with the given
+    /// alias name.
+    pub fn resource(&self, canonical_path: &Path, alias_name: &Path) -> Option<PathBuf> {
+        let resources = self.resources.lock().ok()?;
+        resources
+            .get(&(canonical_path.to_owned(), alias_name.to_owned()))
+            .cloned()
+    }
+
+    pub fn insert_resource(&self, canonical_path: PathBuf, alias_name: PathBuf, resource: PathBuf) {
+        if let Ok(mut resources) = self.resources.lock() {
+            resources.insert((canonical_path, alias_name), resource);
+        }
+    }
+}
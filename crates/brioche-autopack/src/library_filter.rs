@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+
+use crate::DynamicLinkingConfig;
+
+/// Decides which libraries to skip, from a dynamic linking config's
+/// `skip_libraries`, `skip_library_globs`, and `only_library_globs`.
+pub struct LibraryFilter<'a> {
+    skip_libraries: &'a HashSet<String>,
+    skip_globs: globset::GlobSet,
+    only_globs: Option<globset::GlobSet>,
+    case_insensitive: bool,
+}
+
+impl<'a> LibraryFilter<'a> {
+    /// Build a filter from a config. Windows DLL names are matched
+    /// case-insensitively.
+    pub fn new(config: &'a DynamicLinkingConfig, case_insensitive: bool) -> eyre::Result<Self> {
+        let skip_globs = build_glob_set(&config.skip_library_globs, case_insensitive)?;
+        let only_globs = if config.only_library_globs.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(
+                &config.only_library_globs,
+                case_insensitive,
+            )?)
+        };
+
+        Ok(Self {
+            skip_libraries: &config.skip_libraries,
+            skip_globs,
+            only_globs,
+            case_insensitive,
+        })
+    }
+
+    pub fn is_skipped(&self, library_name: &str) -> bool {
+        let is_skipped_name = if self.case_insensitive {
+            self.skip_libraries
+                .iter()
+                .any(|skipped| skipped.eq_ignore_ascii_case(library_name))
+        } else {
+            self.skip_libraries.contains(library_name)
+        };
+        if is_skipped_name || self.skip_globs.is_match(library_name) {
+            return true;
+        }
+
+        self.only_globs
+            .as_ref()
+            .is_some_and(|only_globs| !only_globs.is_match(library_name))
+    }
+}
+
+fn build_glob_set(patterns: &[String], case_insensitive: bool) -> eyre::Result<globset::GlobSet> {
+    let mut globs = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = globset::GlobBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .literal_separator(true)
+            .build()?;
+        globs.add(glob);
+    }
+    Ok(globs.build()?)
+}
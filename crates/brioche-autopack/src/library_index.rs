@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context as _;
+
+use crate::ElfArch;
+
+/// An index of libraries by name, built from a list of search paths. This
+/// resolves names the same way as [`crate::find_library`], but only scans
+/// each search path once, so it's cheaper when looking up many libraries.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryIndex {
+    /// Libraries by filename, with earlier search paths first. Every
+    /// match is kept so a library with the right architecture can be
+    /// picked if there are multiple variants.
+    libraries: BTreeMap<String, Vec<PathBuf>>,
+
+    /// Libraries by `DT_SONAME`, for search paths that are library files.
+    /// Only used if there's no match by filename.
+    sonames: BTreeMap<String, Vec<PathBuf>>,
+}
+
+impl LibraryIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_search_paths(library_search_paths: &[PathBuf]) -> eyre::Result<Self> {
+        let mut index = Self::new();
+        for path in library_search_paths {
+            index.add_search_path(path)?;
+        }
+
+        Ok(index)
+    }
+
+    /// Add a search path with a lower precedence than all the existing
+    /// search paths. The search path can either be a directory containing
+    /// libraries, or a library file.
+    pub fn add_search_path(&mut self, path: &Path) -> eyre::Result<()> {
+        if path.is_dir() {
+            let entries = std::fs::read_dir(path)
+                .with_context(|| format!("failed to read directory {}", path.display()))?;
+            for entry in entries {
+                let entry = entry?;
+                let entry_path = entry.path();
+                if !entry_path.is_file() {
+                    continue;
+                }
+
+                if let Ok(name) = entry.file_name().into_string() {
+                    self.libraries.entry(name).or_default().push(entry_path);
+                }
+            }
+        } else if path.is_file() {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.to_string());
+            if let Some(name) = name {
+                self.libraries
+                    .entry(name)
+                    .or_default()
+                    .push(path.to_owned());
+            }
+
+            let Ok(contents) = crate::mapped_file::MappedFile::open(path) else {
+                return Ok(());
+            };
+            let Ok(elf) = goblin::elf::Elf::parse(&contents) else {
+                return Ok(());
+            };
+            if let Some(soname) = elf.soname {
+                self.sonames
+                    .entry(soname.to_string())
+                    .or_default()
+                    .push(path.to_owned());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find a library by name.
+    pub fn find(&self, library_name: &str) -> Option<&Path> {
+        self.find_for_arch(library_name, None)
+    }
+
+    /// Find a library by name. If `arch` is set, libraries that aren't
+    /// ELF files with the same architecture are skipped.
+    pub fn find_for_arch(&self, library_name: &str, arch: Option<ElfArch>) -> Option<&Path> {
+        let by_filename = self.libraries.get(library_name).into_iter().flatten();
+        let by_soname = self.sonames.get(library_name).into_iter().flatten();
+        by_filename
+            .chain(by_soname)
+            .find(|path| arch.is_none_or(|arch| arch.matches_file(path)))
+            .map(|path| &**path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brioche_test_fixtures::ElfBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_library_index_precedence() {
+        let root = tempfile::tempdir().unwrap();
+        let first = root.path().join("first");
+        let second = root.path().join("second");
+        ElfBuilder::shared_library("libfoo.so.1")
+            .write(&second.join("libfoo.so.1"))
+            .unwrap();
+        ElfBuilder::shared_library("libfoo.so.1")
+            .write(&first.join("libfoo.so.1"))
+            .unwrap();
+        ElfBuilder::shared_library("libbar.so.1")
+            .write(&second.join("libbar.so.1"))
+            .unwrap();
+
+        let index = LibraryIndex::from_search_paths(&[first.clone(), second.clone()]).unwrap();
+        assert_eq!(index.find("libfoo.so.1"), Some(&*first.join("libfoo.so.1")));
+        assert_eq!(
+            index.find("libbar.so.1"),
+            Some(&*second.join("libbar.so.1"))
+        );
+        assert_eq!(index.find("libbaz.so.1"), None);
+    }
+
+    #[test]
+    fn test_library_index_soname() {
+        let root = tempfile::tempdir().unwrap();
+        let library = root.path().join("libfoo.so.1.2.3");
+        ElfBuilder::shared_library("libfoo.so.1")
+            .write(&library)
+            .unwrap();
+
+        // Sonames are only indexed for search paths that are library files
+        let index = LibraryIndex::from_search_paths(&[root.path().to_owned()]).unwrap();
+        assert_eq!(index.find("libfoo.so.1.2.3"), Some(&*library));
+        assert_eq!(index.find("libfoo.so.1"), None);
+
+        let index = LibraryIndex::from_search_paths(std::slice::from_ref(&library)).unwrap();
+        assert_eq!(index.find("libfoo.so.1.2.3"), Some(&*library));
+        assert_eq!(index.find("libfoo.so.1"), Some(&*library));
+    }
+}
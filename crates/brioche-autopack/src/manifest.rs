@@ -0,0 +1,60 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use bstr::ByteSlice as _;
+use eyre::Context as _;
+
+use crate::{pack_resources, ResourceKind};
+
+/// The resources referenced by each pack produced by autopack, keyed by
+/// output path. Only direct references are listed: resources referenced
+/// by packed files within other resources aren't followed.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutopackManifest {
+    pub paths: BTreeMap<PathBuf, Vec<AutopackManifestResource>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutopackManifestResource {
+    pub kind: ResourceKind,
+
+    /// The resource path, relative to the resource dir.
+    pub resource: PathBuf,
+}
+
+/// Build a manifest from the packs in the given output paths. Paths
+/// without a pack (such as binaries rewritten in place) are left out.
+pub fn build_manifest<'a>(
+    output_paths: impl IntoIterator<Item = &'a Path>,
+    all_resource_dirs: &[PathBuf],
+) -> eyre::Result<AutopackManifest> {
+    let mut manifest = AutopackManifest::default();
+    for output_path in output_paths {
+        let file = std::fs::File::open(output_path)
+            .with_context(|| format!("failed to open {}", output_path.display()))?;
+        let Ok(extracted) = brioche_pack::extract_pack(file) else {
+            continue;
+        };
+
+        let resources = pack_resources(&extracted.pack, all_resource_dirs)
+            .with_context(|| format!("failed to get resources for {}", output_path.display()))?
+            .into_iter()
+            .map(|(kind, resource)| {
+                let resource = resource
+                    .to_path()
+                    .map_err(|_| eyre::eyre!("invalid resource path"))?;
+                eyre::Ok(AutopackManifestResource {
+                    kind,
+                    resource: resource.to_owned(),
+                })
+            })
+            .collect::<eyre::Result<_>>()?;
+        manifest.paths.insert(output_path.to_owned(), resources);
+    }
+
+    Ok(manifest)
+}
@@ -0,0 +1,80 @@
+use std::{os::fd::AsRawFd as _, path::Path};
+
+/// A read-only memory mapping of a file's contents.
+///
+/// Inspecting an object file usually only touches its headers and dynamic
+/// section, so mapping the file avoids reading all of a large binary (such
+/// as one with debug info) into memory. Only the pages that get accessed
+/// are read from disk.
+///
+/// The file must not be truncated while it's mapped, since accessing the
+/// removed pages would crash the process. Files that get rewritten in
+/// place should only be written after the mapping is no longer used.
+pub struct MappedFile {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// SAFETY: The mapping is read-only and is only unmapped on drop
+unsafe impl Send for MappedFile {}
+unsafe impl Sync for MappedFile {}
+
+impl MappedFile {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        let len = usize::try_from(len)
+            .map_err(|_| std::io::Error::other(format!("file too large to map: {path:?}")))?;
+
+        // Mapping an empty file fails, so use an empty slice instead
+        if len == 0 {
+            return Ok(Self {
+                ptr: std::ptr::null_mut(),
+                len: 0,
+            });
+        }
+
+        // SAFETY: We map the whole file as private and read-only. The
+        // mapping stays valid after the file is closed
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(Self { ptr, len })
+    }
+}
+
+impl std::ops::Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+
+        // SAFETY: `ptr` points to a mapping of `len` readable bytes that
+        // lives as long as `self`
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            // SAFETY: `ptr` and `len` came from a successful `mmap` call
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+
+use crate::{
+    symbol_versions::{self, VersionRequirement},
+    ElfArch,
+};
+
+/// The details autopack needs from an object file (an executable or
+/// library) to find its dependencies and decide how to pack it.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectInfo {
+    /// The program interpreter, for executables that have one. Objects
+    /// with an interpreter are packed as dynamic binaries.
+    pub interpreter: Option<String>,
+
+    /// The names of the libraries the object needs at runtime.
+    pub needed_libraries: Vec<String>,
+
+    /// The name the object is loaded by, if it's a library.
+    pub soname: Option<String>,
+
+    /// Whether the object is a shared library rather than an executable.
+    pub is_lib: bool,
+
+    /// Whether the object is dynamically linked. Executables without an
+    /// interpreter are only packed (as loader binaries) if they are.
+    pub is_dynamic: bool,
+
+    /// Whether the object is a Windows PE binary. These are packed as
+    /// Windows binaries, since DLLs are found differently from other
+    /// libraries.
+    pub is_windows: bool,
+
+    /// The object's architecture, used to skip libraries built for other
+    /// architectures. `None` if the format has no ELF-compatible machine
+    /// type, in which case libraries aren't filtered by architecture.
+    pub arch: Option<ElfArch>,
+
+    /// Library search path entries embedded in the object, like ELF's
+    /// RPATH and RUNPATH. Entries are separated by `:`, and can use
+    /// `$ORIGIN` for the object's directory.
+    pub library_search_entries: Vec<String>,
+
+    /// The symbol versions the object requires from its libraries.
+    pub version_requirements: Vec<VersionRequirement>,
+
+    /// The symbol versions the object defines, or `None` if it doesn't
+    /// define any.
+    pub defined_versions: Option<HashSet<String>>,
+}
+
+/// Reads [`ObjectInfo`] from object files of a particular format. Custom
+/// inspectors can be added with [`crate::AutopackConfig::object_inspectors`]
+/// to support formats beyond ELF.
+pub trait ObjectInspector: std::fmt::Debug + Send + Sync {
+    /// Inspect an object file's contents. Returns `None` if the contents
+    /// aren't in a format handled by this inspector.
+    fn inspect(&self, contents: &[u8]) -> Option<ObjectInfo>;
+}
+
+/// The built-in inspector for ELF files.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ElfInspector;
+
+impl ObjectInspector for ElfInspector {
+    fn inspect(&self, contents: &[u8]) -> Option<ObjectInfo> {
+        let elf = goblin::elf::Elf::parse(contents).ok()?;
+
+        Some(ObjectInfo {
+            interpreter: elf.interpreter.map(|interpreter| interpreter.to_string()),
+            needed_libraries: elf.libraries.iter().map(|lib| lib.to_string()).collect(),
+            soname: elf.soname.map(|soname| soname.to_string()),
+            is_lib: elf.is_lib,
+            is_dynamic: elf.dynamic.is_some(),
+            is_windows: false,
+            arch: Some(ElfArch::from_elf(&elf)),
+            library_search_entries: elf
+                .rpaths
+                .iter()
+                .chain(&elf.runpaths)
+                .map(|entry| entry.to_string())
+                .collect(),
+            version_requirements: symbol_versions::version_requirements(&elf),
+            defined_versions: symbol_versions::defined_versions(&elf),
+        })
+    }
+}
+
+/// The built-in inspector for Windows PE files (executables and DLLs).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PeInspector;
+
+impl ObjectInspector for PeInspector {
+    fn inspect(&self, contents: &[u8]) -> Option<ObjectInfo> {
+        let hint = goblin::peek(&mut std::io::Cursor::new(contents)).ok()?;
+        if !matches!(hint, goblin::Hint::PE) {
+            return None;
+        }
+
+        let pe = goblin::pe::PE::parse(contents).ok()?;
+        Some(ObjectInfo {
+            needed_libraries: pe.libraries.iter().map(|dll| dll.to_string()).collect(),
+            soname: pe.name.map(|name| name.to_string()),
+            is_lib: pe.is_lib,
+            is_dynamic: !pe.libraries.is_empty(),
+            is_windows: true,
+            ..ObjectInfo::default()
+        })
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use eyre::Context as _;
+
+/// How a resource is referenced by a pack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceKind {
+    Program,
+    Interpreter,
+    LibraryDir,
+    #[serde(rename = "resource")]
+    Other,
+}
+
+impl ResourceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Program => "program",
+            Self::Interpreter => "interpreter",
+            Self::LibraryDir => "library_dir",
+            Self::Other => "resource",
+        }
+    }
+}
+
+/// Get the resource paths referenced directly by a pack.
+pub fn pack_resources(
+    pack: &brioche_pack::Pack,
+    all_resource_dirs: &[PathBuf],
+) -> eyre::Result<Vec<(ResourceKind, Vec<u8>)>> {
+    let library_dir_resources = |library_dirs: &[Vec<u8>]| {
+        library_dirs
+            .iter()
+            .map(|library_dir| (ResourceKind::LibraryDir, library_dir.clone()))
+            .collect::<Vec<_>>()
+    };
+
+    let resources = match pack {
+        brioche_pack::Pack::LdLinux {
+            program,
+            interpreter,
+            library_dirs,
+            runtime_library_dirs: _,
+        } => {
+            let mut resources = vec![
+                (ResourceKind::Program, program.clone()),
+                (ResourceKind::Interpreter, interpreter.clone()),
+            ];
+            resources.extend(library_dir_resources(library_dirs));
+            resources
+        }
+        brioche_pack::Pack::Static { library_dirs } => library_dir_resources(library_dirs),
+        brioche_pack::Pack::Metadata {
+            resource_paths,
+            format,
+            metadata,
+        } => {
+            let mut resources = resource_paths
+                .iter()
+                .map(|resource| (ResourceKind::Other, resource.clone()))
+                .collect::<Vec<_>>();
+            if runnable_core::is_runnable_format(format) {
+                let runnable = runnable_core::load_runnable(format, metadata, all_resource_dirs)
+                    .context("failed to load runnable metadata")?;
+                resources.extend(
+                    runnable
+                        .resources()
+                        .into_iter()
+                        .map(|resource| (ResourceKind::Other, resource.clone())),
+                );
+            }
+
+            resources
+        }
+    };
+
+    Ok(resources)
+}
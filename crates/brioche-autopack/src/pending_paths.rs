@@ -0,0 +1,145 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex, MutexGuard},
+    thread::ThreadId,
+};
+
+use crate::AutopackPathConfig;
+
+/// The queue of paths waiting to be autopacked, shared between workers.
+///
+/// A path that's needed as a dependency (e.g. a library or interpreter
+/// from the same input tree) must be packed before it gets copied into the
+/// resource dir. If the dependency is still pending, the worker that needs
+/// it packs it first. If another worker is already packing it, the worker
+/// waits for it to finish, unless that would wait on itself through a
+/// cycle of dependencies. In that case, the dependency is used as-is, which
+/// matches what happens when autopacking with a single worker.
+pub struct PendingPaths {
+    state: Mutex<PendingState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct PendingState {
+    pending: BTreeMap<PathBuf, AutopackPathConfig>,
+    in_progress: HashMap<PathBuf, ThreadId>,
+    waiting: HashMap<ThreadId, PathBuf>,
+    failed: bool,
+}
+
+impl PendingPaths {
+    pub fn new(pending: BTreeMap<PathBuf, AutopackPathConfig>) -> Self {
+        Self {
+            state: Mutex::new(PendingState {
+                pending,
+                ..Default::default()
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Take the next path to autopack. Returns `None` once there are no
+    /// more pending paths, or if another worker failed.
+    pub fn next(&self) -> Option<(PathBuf, AutopackPathConfig, InProgress<'_>)> {
+        let mut state = self.lock();
+        if state.failed {
+            return None;
+        }
+
+        let (path, path_config) = state.pending.pop_first()?;
+        let in_progress = state.start(self, &path);
+        Some((path, path_config, in_progress))
+    }
+
+    /// Take a dependency so it can be packed before it gets used. Returns
+    /// `None` if the dependency isn't pending, after waiting for it to be
+    /// packed if another worker is packing it.
+    pub fn take_dependency(&self, path: &Path) -> Option<(AutopackPathConfig, InProgress<'_>)> {
+        let current_thread = std::thread::current().id();
+        let mut state = self.lock();
+
+        if let Some(path_config) = state.pending.remove(path) {
+            let in_progress = state.start(self, path);
+            return Some((path_config, in_progress));
+        }
+
+        if state.in_progress.contains_key(path) && !state.would_wait_on(path, current_thread) {
+            state.waiting.insert(current_thread, path.to_owned());
+            while state.in_progress.contains_key(path) {
+                state = self
+                    .changed
+                    .wait(state)
+                    .expect("pending paths lock poisoned");
+            }
+            state.waiting.remove(&current_thread);
+        }
+
+        None
+    }
+
+    /// Check if a path is currently being autopacked by any worker.
+    pub fn is_in_progress(&self, path: &Path) -> bool {
+        self.lock().in_progress.contains_key(path)
+    }
+
+    /// Stop other workers from taking new paths after an error.
+    pub fn fail(&self) {
+        self.lock().failed = true;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PendingState> {
+        self.state.lock().expect("pending paths lock poisoned")
+    }
+}
+
+impl PendingState {
+    fn start<'a>(&mut self, pending_paths: &'a PendingPaths, path: &Path) -> InProgress<'a> {
+        self.in_progress
+            .insert(path.to_owned(), std::thread::current().id());
+        InProgress {
+            path: path.to_owned(),
+            pending_paths,
+        }
+    }
+
+    /// Check if waiting for `path` would end up waiting on `thread`,
+    /// by following the workers that are waiting on each other.
+    fn would_wait_on(&self, path: &Path, thread: ThreadId) -> bool {
+        let mut path = path;
+        for _ in 0..=self.waiting.len() {
+            let Some(holder) = self.in_progress.get(path) else {
+                return false;
+            };
+            if *holder == thread {
+                return true;
+            }
+            let Some(next_path) = self.waiting.get(holder) else {
+                return false;
+            };
+            path = next_path;
+        }
+
+        false
+    }
+}
+
+/// Marks a path as being autopacked by the current worker. Dropping it
+/// marks the path as finished (whether or not it succeeded), and wakes any
+/// workers waiting on it.
+pub struct InProgress<'a> {
+    path: PathBuf,
+    pending_paths: &'a PendingPaths,
+}
+
+impl Drop for InProgress<'_> {
+    fn drop(&mut self) {
+        // Don't panic while dropping if the lock was poisoned
+        let Ok(mut state) = self.pending_paths.state.lock() else {
+            return;
+        };
+        state.in_progress.remove(&self.path);
+        self.pending_paths.changed.notify_all();
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    ffi::CString,
+    os::unix::{ffi::OsStrExt as _, fs::PermissionsExt as _},
+    path::Path,
+};
+
+/// What to do when autopacking a file that runs with elevated privileges,
+/// i.e. a setuid or setgid file, or a file with file capabilities.
+///
+/// Packing replaces the file with a packed executable, which drops these
+/// privileges. Keeping them wouldn't be safe either, since the packed
+/// executable runs a program found through the resource dir.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PrivilegedFilePolicy {
+    /// Pack the file anyway (dropping its privileges), and print a warning.
+    #[default]
+    WrapAndWarn,
+
+    /// Leave the file as-is.
+    Skip,
+
+    /// Fail autopacking.
+    Error,
+}
+
+impl std::str::FromStr for PrivilegedFilePolicy {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "wrap-and-warn" => Ok(Self::WrapAndWarn),
+            "skip" => Ok(Self::Skip),
+            "error" => Ok(Self::Error),
+            _ => eyre::bail!(
+                "invalid privileged file policy {value:?} (expected \"wrap-and-warn\", \"skip\", or \"error\")"
+            ),
+        }
+    }
+}
+
+/// Describe the elevated privileges a file runs with, or `None` if it
+/// doesn't have any.
+pub fn file_privileges(path: &Path) -> eyre::Result<Option<String>> {
+    let mode = std::fs::metadata(path)?.permissions().mode();
+
+    let mut privileges = vec![];
+    if mode & libc::S_ISUID != 0 {
+        privileges.push("setuid");
+    }
+    if mode & libc::S_ISGID != 0 {
+        privileges.push("setgid");
+    }
+    if has_file_capabilities(path)? {
+        privileges.push("file capabilities");
+    }
+
+    if privileges.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(privileges.join(", ")))
+    }
+}
+
+fn has_file_capabilities(path: &Path) -> eyre::Result<bool> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let result = unsafe {
+        libc::getxattr(
+            path.as_ptr(),
+            c"security.capability".as_ptr(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if result >= 0 {
+        return Ok(true);
+    }
+
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::ENODATA | libc::ENOTSUP) => Ok(false),
+        _ => Err(error.into()),
+    }
+}
@@ -0,0 +1,119 @@
+use std::path::Path;
+
+use crate::AutopackLibraryResult;
+
+/// An event reported while autopacking.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    /// Started autopacking a path.
+    Started { path: &'a Path },
+
+    /// Finished packing a path.
+    Packed { path: &'a Path },
+
+    /// A path was left as-is.
+    Skipped {
+        path: &'a Path,
+        reason: SkipReason<'a>,
+    },
+
+    /// A path was skipped because it hasn't changed since it was recorded
+    /// in the state file.
+    Unchanged { path: &'a Path },
+
+    /// A library (or DLL) needed by a path was found.
+    LibraryResolved {
+        path: &'a Path,
+        library: &'a AutopackLibraryResult,
+    },
+
+    /// Something that didn't stop a path from being packed, but may stop
+    /// it from running properly.
+    Warning { path: &'a Path, message: &'a str },
+
+    /// Autopacking a path failed.
+    Error { path: &'a Path, message: &'a str },
+}
+
+/// Why a path was skipped.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SkipReason<'a> {
+    /// The path isn't a kind of file that can be packed with the config.
+    Unpackable,
+
+    /// The path matched a content signature from
+    /// [`crate::AutopackConfig::skip_signatures`].
+    Signature { name: &'a str },
+
+    /// The path runs with elevated privileges, and privileged files are
+    /// skipped.
+    Privileges { privileges: &'a str },
+}
+
+/// Receives progress events while autopacking, set with
+/// [`crate::AutopackConfig::progress`]. Paths are autopacked concurrently,
+/// so events for different paths can be interleaved.
+pub trait ProgressReporter: std::fmt::Debug + Send + Sync {
+    fn report(&self, event: &ProgressEvent);
+}
+
+/// Prints human-readable progress to stdout and warnings to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutProgressReporter {
+    /// Only print warnings.
+    pub warnings_only: bool,
+}
+
+impl StdoutProgressReporter {
+    pub fn warnings_only() -> Self {
+        Self {
+            warnings_only: true,
+        }
+    }
+}
+
+impl ProgressReporter for StdoutProgressReporter {
+    fn report(&self, event: &ProgressEvent) {
+        if let ProgressEvent::Warning { message, .. } = event {
+            eprintln!("warning: {message}");
+            return;
+        }
+        if self.warnings_only {
+            return;
+        }
+
+        match event {
+            ProgressEvent::Packed { path } => {
+                println!("autopacked {}", path.display());
+            }
+            ProgressEvent::Skipped { path, reason } => match reason {
+                SkipReason::Unpackable => {
+                    println!("skipped {}", path.display());
+                }
+                SkipReason::Signature { name } => {
+                    println!("skipped {} matching {name}", path.display());
+                }
+                SkipReason::Privileges { privileges } => {
+                    println!("skipped {} with {privileges}", path.display());
+                }
+            },
+            ProgressEvent::Unchanged { path } => {
+                println!("unchanged {}", path.display());
+            }
+            ProgressEvent::Started { .. }
+            | ProgressEvent::LibraryResolved { .. }
+            | ProgressEvent::Warning { .. }
+            | ProgressEvent::Error { .. } => {}
+        }
+    }
+}
+
+/// Ignores all progress events, including warnings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SilentProgressReporter;
+
+impl ProgressReporter for SilentProgressReporter {
+    fn report(&self, _event: &ProgressEvent) {}
+}
@@ -0,0 +1,228 @@
+/// The interpreter and arguments from a script's shebang line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shebang {
+    /// The filename of the interpreter command, without its directory.
+    /// For `env` shebangs, this is the command `env` would run.
+    pub command_name: String,
+    pub args: Vec<String>,
+}
+
+/// Parse a shebang line (without the leading `#!`). Arguments after the
+/// command are split on whitespace, so `#!/usr/bin/perl -w -T` runs `perl`
+/// with the args `-w` and `-T`.
+///
+/// `env` shebangs are resolved to the command `env` would run. The
+/// `-S`/`--split-string` option is supported, so `#!/usr/bin/env -S deno
+/// run --allow-all` runs `deno` with the args `run` and `--allow-all`.
+pub fn parse_shebang(shebang_line: &str) -> eyre::Result<Shebang> {
+    let shebang_line = shebang_line.trim();
+    let shebang_parts = shebang_line.split_once(|c: char| c.is_ascii_whitespace());
+    let (command_path, arg) = match shebang_parts {
+        Some((command_path, arg)) => (command_path.trim(), arg.trim()),
+        None => (shebang_line, ""),
+    };
+
+    let command_name = command_path
+        .split(['/', '\\'])
+        .last()
+        .unwrap_or(command_path);
+
+    if command_name != "env" {
+        return Ok(Shebang {
+            command_name: command_name.to_string(),
+            args: split_args(arg),
+        });
+    }
+
+    eyre::ensure!(!arg.is_empty(), "expected argument for env script");
+    let mut env_args = match split_string_option(arg) {
+        Some(split_string) => split_string_args(split_string)?,
+        None => split_args(arg),
+    };
+    eyre::ensure!(
+        !env_args.is_empty(),
+        "expected command in env script shebang: {shebang_line:?}"
+    );
+    let command_name = env_args.remove(0);
+
+    Ok(Shebang {
+        command_name,
+        args: env_args,
+    })
+}
+
+fn split_args(args: &str) -> Vec<String> {
+    args.split_ascii_whitespace()
+        .map(|arg| arg.to_string())
+        .collect()
+}
+
+/// Get the string passed to `env`'s `-S`/`--split-string` option, if the
+/// argument uses it.
+fn split_string_option(env_arg: &str) -> Option<&str> {
+    if let Some(split_string) = env_arg.strip_prefix("--split-string") {
+        let split_string = split_string
+            .strip_prefix('=')
+            .or_else(|| split_string.strip_prefix(|c: char| c.is_ascii_whitespace()))?;
+        return Some(split_string.trim_start());
+    }
+
+    let split_string = env_arg.strip_prefix("-S")?;
+    Some(split_string.trim_start())
+}
+
+/// Split a string into args like `env -S`. Args are separated by
+/// whitespace, and can be quoted with single or double quotes. Outside of
+/// single quotes, a backslash escapes the next character.
+fn split_string_args(split_string: &str) -> eyre::Result<Vec<String>> {
+    let mut args = vec![];
+    let mut current_arg: Option<String> = None;
+    let mut chars = split_string.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_ascii_whitespace() => {
+                args.extend(current_arg.take());
+            }
+            '\'' => {
+                let arg = current_arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => eyre::bail!("unterminated single quote in {split_string:?}"),
+                    }
+                }
+            }
+            '"' => {
+                let arg = current_arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            let escaped = chars.next().ok_or_else(|| {
+                                eyre::eyre!("unterminated double quote in {split_string:?}")
+                            })?;
+                            arg.push(escaped);
+                        }
+                        Some(c) => arg.push(c),
+                        None => eyre::bail!("unterminated double quote in {split_string:?}"),
+                    }
+                }
+            }
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| eyre::eyre!("trailing backslash in {split_string:?}"))?;
+                current_arg.get_or_insert_with(String::new).push(escaped);
+            }
+            c => {
+                current_arg.get_or_insert_with(String::new).push(c);
+            }
+        }
+    }
+
+    args.extend(current_arg);
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shebang(command_name: &str, args: &[&str]) -> Shebang {
+        Shebang {
+            command_name: command_name.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_shebang() {
+        assert_eq!(parse_shebang("/bin/sh").unwrap(), shebang("sh", &[]));
+        assert_eq!(
+            parse_shebang("/bin/sh -e\n").unwrap(),
+            shebang("sh", &["-e"])
+        );
+    }
+
+    #[test]
+    fn test_parse_multi_arg_shebang() {
+        assert_eq!(
+            parse_shebang("/bin/sh -e -u").unwrap(),
+            shebang("sh", &["-e", "-u"])
+        );
+        assert_eq!(
+            parse_shebang(" /usr/bin/perl  -w\t-T \n").unwrap(),
+            shebang("perl", &["-w", "-T"])
+        );
+        assert_eq!(
+            parse_shebang("/usr/bin/env python3 -u -O").unwrap(),
+            shebang("python3", &["-u", "-O"])
+        );
+    }
+
+    #[test]
+    fn test_parse_env_shebang() {
+        assert_eq!(
+            parse_shebang("/usr/bin/env python3").unwrap(),
+            shebang("python3", &[])
+        );
+        assert!(parse_shebang("/usr/bin/env").is_err());
+    }
+
+    #[test]
+    fn test_parse_env_split_string_shebang() {
+        assert_eq!(
+            parse_shebang("/usr/bin/env -S deno run --allow-all").unwrap(),
+            shebang("deno", &["run", "--allow-all"])
+        );
+        assert_eq!(
+            parse_shebang("/usr/bin/env -Sdeno run").unwrap(),
+            shebang("deno", &["run"])
+        );
+        assert_eq!(
+            parse_shebang("/usr/bin/env --split-string=deno run --allow-all").unwrap(),
+            shebang("deno", &["run", "--allow-all"])
+        );
+        assert_eq!(
+            parse_shebang("/usr/bin/env --split-string deno run").unwrap(),
+            shebang("deno", &["run"])
+        );
+    }
+
+    #[test]
+    fn test_parse_env_split_string_quotes() {
+        assert_eq!(
+            parse_shebang(r#"/usr/bin/env -S node --title 'my app' "--name=a b""#).unwrap(),
+            shebang("node", &["--title", "my app", "--name=a b"])
+        );
+        assert_eq!(
+            parse_shebang(r#"/usr/bin/env -S tool '' a'b'"c""#).unwrap(),
+            shebang("tool", &["", "abc"])
+        );
+    }
+
+    #[test]
+    fn test_parse_env_split_string_escapes() {
+        assert_eq!(
+            parse_shebang(r#"/usr/bin/env -S tool a\ b \"c\" "d\"e" 'f\g'"#).unwrap(),
+            shebang("tool", &["a b", "\"c\"", "d\"e", r"f\g"])
+        );
+        assert!(parse_shebang(r"/usr/bin/env -S tool a\").is_err());
+    }
+
+    #[test]
+    fn test_parse_env_split_string_unterminated_quotes() {
+        assert!(parse_shebang("/usr/bin/env -S tool 'a b").is_err());
+        assert!(parse_shebang(r#"/usr/bin/env -S tool "a b"#).is_err());
+        assert!(parse_shebang(r#"/usr/bin/env -S tool "a\"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_env_split_string_without_args() {
+        assert!(parse_shebang("/usr/bin/env -S").is_err());
+        assert!(parse_shebang("/usr/bin/env -S   ").is_err());
+        assert!(parse_shebang("/usr/bin/env --split-string=").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use eyre::Context as _;
+
+use crate::{directory_config::PathOverrides, AutopackConfig, AutopackPathResult};
+
+/// Hashes recorded by a previous autopack run, used to skip paths that
+/// haven't changed since they were packed.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutopackState {
+    paths: BTreeMap<PathBuf, PathState>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PathState {
+    /// A hash of the config used to pack the path, including any
+    /// per-path overrides.
+    config_hash: String,
+
+    /// The hash of the path before it was packed.
+    input_hash: String,
+
+    /// The hash of the path after it was packed. This is the same as the
+    /// input hash if the path was skipped.
+    output_hash: String,
+
+    /// The hashes of the interpreter and libraries the path was packed
+    /// with, so the path is repacked if any of them change.
+    dependency_hashes: BTreeMap<PathBuf, String>,
+
+    /// The resources added for the path, relative to the resource dir.
+    resources: Vec<PathBuf>,
+}
+
+impl AutopackState {
+    /// Load the state from a file, or start with an empty state if the
+    /// file doesn't exist yet.
+    pub fn load(state_path: &Path) -> eyre::Result<Self> {
+        let state = match std::fs::read(state_path) {
+            Ok(state) => state,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!("failed to read autopack state {}", state_path.display())
+                });
+            }
+        };
+
+        serde_json::from_slice(&state)
+            .with_context(|| format!("failed to parse autopack state {}", state_path.display()))
+    }
+
+    pub fn save(&self, state_path: &Path) -> eyre::Result<()> {
+        let state = serde_json::to_vec_pretty(self)?;
+        std::fs::write(state_path, state)
+            .with_context(|| format!("failed to write autopack state {}", state_path.display()))
+    }
+
+    /// Check if a path is unchanged since it was last recorded: it still
+    /// has the contents it was packed to, it would be packed with the
+    /// same config and dependencies, and its resources still exist.
+    pub fn is_unchanged(
+        &self,
+        path: &Path,
+        config_hash: &str,
+        resource_dir: &Path,
+        file_hashes: &mut HashMap<PathBuf, Option<String>>,
+    ) -> eyre::Result<bool> {
+        let Some(path_state) = self.paths.get(path) else {
+            return Ok(false);
+        };
+
+        if path_state.config_hash != config_hash {
+            return Ok(false);
+        }
+
+        let hashes = std::iter::once((path, &path_state.output_hash)).chain(
+            path_state
+                .dependency_hashes
+                .iter()
+                .map(|(path, hash)| (&**path, hash)),
+        );
+        for (path, expected_hash) in hashes {
+            let hash = match file_hashes.get(path) {
+                Some(hash) => hash.clone(),
+                None => {
+                    let hash = match hash_file(path) {
+                        Ok(hash) => Some(hash),
+                        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+                        Err(error) => {
+                            return Err(error)
+                                .with_context(|| format!("failed to hash {}", path.display()));
+                        }
+                    };
+                    file_hashes.insert(path.to_owned(), hash.clone());
+                    hash
+                }
+            };
+            if hash.as_ref() != Some(expected_hash) {
+                return Ok(false);
+            }
+        }
+
+        let resources_exist = path_state
+            .resources
+            .iter()
+            .all(|resource| resource_dir.join(resource).symlink_metadata().is_ok());
+        Ok(resources_exist)
+    }
+
+    /// Record the hashes for a path after it was autopacked.
+    pub fn record(
+        &mut self,
+        config_hash: String,
+        input_hash: String,
+        path_result: &AutopackPathResult,
+    ) -> eyre::Result<()> {
+        let path = &path_result.path;
+        let output_hash =
+            hash_file(path).with_context(|| format!("failed to hash {}", path.display()))?;
+
+        let dependency_paths = path_result
+            .interpreter
+            .iter()
+            .map(|interpreter| &interpreter.source_path)
+            .chain(
+                path_result
+                    .libraries
+                    .iter()
+                    .filter_map(|library| library.source_path.as_ref()),
+            );
+        let mut dependency_hashes = BTreeMap::new();
+        for dependency_path in dependency_paths {
+            let hash = hash_file(dependency_path)
+                .with_context(|| format!("failed to hash {}", dependency_path.display()))?;
+            dependency_hashes.insert(dependency_path.clone(), hash);
+        }
+
+        self.paths.insert(
+            path.clone(),
+            PathState {
+                config_hash,
+                input_hash,
+                output_hash,
+                dependency_hashes,
+                resources: path_result.resources.clone(),
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Hash the parts of the config that affect how a path is packed. Options
+/// that only change how autopack runs (like the inputs or concurrency) are
+/// left out, as are the contents of files the config refers to, such as
+/// the packed executable.
+pub fn config_hash(config: &AutopackConfig, overrides: &PathOverrides) -> String {
+    let config_debug = format!(
+        "{:?} {overrides:?}",
+        (
+            &config.resource_dir,
+            &config.all_resource_dirs,
+            &config.link_dependencies,
+            (
+                &config.dynamic_binary,
+                &config.loader_binary,
+                &config.shared_library,
+                &config.windows_binary,
+                &config.script,
+                &config.wasm,
+                &config.repack,
+            ),
+            &config.path_overrides,
+            config.target_arch,
+            config.privileged_files,
+            &config.skip_signatures,
+        )
+    );
+    blake3::hash(config_debug.as_bytes()).to_hex().to_string()
+}
+
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use goblin::elf::symver::{VER_FLG_BASE, VER_FLG_WEAK};
+
+/// How to handle resolved libraries that don't define the symbol versions
+/// (e.g. `GLIBC_2.34`) required by the files that depend on them. These
+/// libraries would fail to load at runtime with an error like
+/// `version 'GLIBC_2.34' not found`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymbolVersionCheck {
+    #[default]
+    Disabled,
+    Warn,
+    Error,
+}
+
+impl std::str::FromStr for SymbolVersionCheck {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "disabled" => Ok(Self::Disabled),
+            "warn" => Ok(Self::Warn),
+            "error" => Ok(Self::Error),
+            _ => eyre::bail!(
+                "invalid symbol version check {value:?} (expected \"disabled\", \"warn\", or \"error\")"
+            ),
+        }
+    }
+}
+
+/// The symbol versions required from each library, from the `DT_VERNEED`
+/// entries of the files that depend on it. Weak version requirements are
+/// ignored, since the dynamic linker only warns about them.
+#[derive(Debug, Default)]
+pub struct RequiredVersions {
+    by_library: BTreeMap<String, Vec<RequiredVersion>>,
+}
+
+#[derive(Debug)]
+struct RequiredVersion {
+    version: String,
+    required_by: PathBuf,
+}
+
+impl RequiredVersions {
+    pub fn add(&mut self, requirements: &[VersionRequirement], required_by: &Path) {
+        for requirement in requirements {
+            self.by_library
+                .entry(requirement.library_name.clone())
+                .or_default()
+                .push(RequiredVersion {
+                    version: requirement.version.clone(),
+                    required_by: required_by.to_owned(),
+                });
+        }
+    }
+
+    /// Describe each required version that a resolved library doesn't
+    /// define. Libraries without any version definitions are accepted,
+    /// like the dynamic linker does.
+    pub fn missing_versions(
+        &self,
+        library_name: &str,
+        library_path: &Path,
+        defined_versions: Option<&HashSet<String>>,
+    ) -> Vec<String> {
+        let (Some(required_versions), Some(defined_versions)) =
+            (self.by_library.get(library_name), defined_versions)
+        else {
+            return vec![];
+        };
+
+        required_versions
+            .iter()
+            .filter(|required| !defined_versions.contains(&required.version))
+            .map(|required| {
+                format!(
+                    "{} ({library_name}) does not define version {} required by {}",
+                    library_path.display(),
+                    required.version,
+                    required.required_by.display(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// A symbol version an ELF file requires from one of its libraries.
+#[derive(Debug, Clone)]
+pub struct VersionRequirement {
+    /// The name of the library the version is required from.
+    pub library_name: String,
+
+    /// The version name, such as `GLIBC_2.34`.
+    pub version: String,
+}
+
+/// Get the symbol versions required by an ELF file from its `DT_VERNEED`
+/// entries, skipping weak requirements.
+pub fn version_requirements(elf: &goblin::elf::Elf) -> Vec<VersionRequirement> {
+    let Some(verneed) = &elf.verneed else {
+        return vec![];
+    };
+
+    let mut requirements = vec![];
+    for need in verneed.iter() {
+        let Some(library_name) = elf.dynstrtab.get_at(need.vn_file) else {
+            continue;
+        };
+        for aux in need.iter() {
+            if aux.vna_flags & VER_FLG_WEAK != 0 {
+                continue;
+            }
+            let Some(version) = elf.dynstrtab.get_at(aux.vna_name) else {
+                continue;
+            };
+
+            requirements.push(VersionRequirement {
+                library_name: library_name.to_string(),
+                version: version.to_string(),
+            });
+        }
+    }
+
+    requirements
+}
+
+/// Get the symbol versions defined by an ELF file from its `DT_VERDEF`
+/// entries, or `None` if it doesn't define any.
+pub fn defined_versions(elf: &goblin::elf::Elf) -> Option<HashSet<String>> {
+    let verdef = elf.verdef.as_ref()?;
+
+    let versions = verdef
+        .iter()
+        .filter(|def| def.vd_flags & VER_FLG_BASE == 0)
+        .filter_map(|def| {
+            let aux = def.iter().next()?;
+            elf.dynstrtab.get_at(aux.vda_name)
+        })
+        .map(|version| version.to_string())
+        .collect();
+    Some(versions)
+}
@@ -0,0 +1,80 @@
+use std::{
+    io::{Read as _, Seek as _, Write as _},
+    ops::Range,
+};
+
+/// Get the range of any data appended after the end of an ELF image, such
+/// as the archive of a self-extracting installer. The end of the image is
+/// the furthest end of the ELF headers, segments, and sections. Returns
+/// `None` if `contents` isn't an ELF file or has no trailing data.
+///
+/// `contents` should not include a Brioche pack, so callers should pass
+/// the unpacked part of a packed file.
+pub fn elf_trailing_payload(contents: &[u8]) -> Option<Range<usize>> {
+    let elf = goblin::elf::Elf::parse(contents).ok()?;
+    let header = &elf.header;
+
+    let program_headers_end =
+        header.e_phoff + u64::from(header.e_phnum) * u64::from(header.e_phentsize);
+    let section_headers_end =
+        header.e_shoff + u64::from(header.e_shnum) * u64::from(header.e_shentsize);
+    let segments_end = elf
+        .program_headers
+        .iter()
+        .map(|segment| segment.p_offset + segment.p_filesz);
+    let sections_end = elf
+        .section_headers
+        .iter()
+        .filter(|section| section.sh_type != goblin::elf::section_header::SHT_NOBITS)
+        .map(|section| section.sh_offset + section.sh_size);
+
+    let image_end = [
+        u64::from(header.e_ehsize),
+        program_headers_end,
+        section_headers_end,
+    ]
+    .into_iter()
+    .chain(segments_end)
+    .chain(sections_end)
+    .max()?;
+    let image_end = usize::try_from(image_end).ok()?;
+
+    if image_end < contents.len() {
+        Some(image_end..contents.len())
+    } else {
+        None
+    }
+}
+
+/// Remove any trailing payload from an ELF file (see
+/// [`elf_trailing_payload`]), returning the removed data. Tools like
+/// `strip` and `patchelf` don't preserve trailing data, so it should be
+/// detached before running them, then restored with
+/// [`restore_trailing_payload`].
+pub fn detach_trailing_payload(file: &mut std::fs::File) -> eyre::Result<Option<Vec<u8>>> {
+    let mut contents = vec![];
+    file.rewind()?;
+    file.read_to_end(&mut contents)?;
+
+    let Some(payload_range) = elf_trailing_payload(&contents) else {
+        return Ok(None);
+    };
+
+    file.set_len(payload_range.start.try_into()?)?;
+    let payload = contents.split_off(payload_range.start);
+    Ok(Some(payload))
+}
+
+/// Append a payload removed by [`detach_trailing_payload`] back to the end
+/// of a file.
+pub fn restore_trailing_payload(
+    file: &mut std::fs::File,
+    payload: Option<&[u8]>,
+) -> eyre::Result<()> {
+    if let Some(payload) = payload {
+        file.seek(std::io::SeekFrom::End(0))?;
+        file.write_all(payload)?;
+    }
+
+    Ok(())
+}
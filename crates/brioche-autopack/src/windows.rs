@@ -0,0 +1,240 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use bstr::ByteVec as _;
+use eyre::{Context as _, OptionExt as _};
+
+use crate::{
+    directory_config, pending_paths::PendingPaths, AutopackContext, AutopackLibraryResult,
+    AutopackPathResult, PhaseTimings,
+};
+
+/// The metadata format used for packs added to Windows binaries.
+pub const WINDOWS_DLL_DIRS_FORMAT: &str = "application/vnd.brioche.windows-dll-dirs-v0.1.0+json";
+
+/// The metadata for a Windows binary pack. Each DLL dir is a resource
+/// containing one of the DLLs the binary imports (directly or
+/// transitively), so adding them to the DLL search path lets the binary
+/// find its DLLs.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowsDllDirs {
+    pub dll_dirs: Vec<String>,
+}
+
+pub(crate) fn autopack_windows_binary(
+    ctx: &AutopackContext,
+    source_path: &Path,
+    output_path: &Path,
+    overrides: &directory_config::PathOverrides,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
+) -> eyre::Result<bool> {
+    let Some(windows_binary_config) = &ctx.config.windows_binary else {
+        return Ok(false);
+    };
+    let dynamic_linking = overrides.dynamic_linking(&windows_binary_config.dynamic_linking);
+
+    let contents = std::fs::read(source_path)?;
+    let program_object = PhaseTimings::time(&mut path_result.timings.elf_parsing, || {
+        ctx.inspect_object(&contents)
+    });
+    let Some(program_object) = program_object.filter(|object| object.is_windows) else {
+        eyre::bail!("failed to parse PE file {}", source_path.display());
+    };
+
+    let needed_dlls: VecDeque<_> = program_object
+        .needed_libraries
+        .into_iter()
+        .chain(dynamic_linking.extra_libraries.iter().cloned())
+        .collect();
+
+    let dll_dir_resource_paths = collect_all_dll_dirs(
+        ctx,
+        &dynamic_linking,
+        needed_dlls,
+        pending_paths,
+        path_result,
+    )?;
+
+    let resource_paths = dll_dir_resource_paths
+        .iter()
+        .map(|resource_path| {
+            <Vec<u8>>::from_path_buf(resource_path.clone())
+                .map_err(|_| eyre::eyre!("invalid UTF-8 in path"))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let dll_dirs = dll_dir_resource_paths
+        .into_iter()
+        .map(|resource_path| {
+            resource_path
+                .into_os_string()
+                .into_string()
+                .map_err(|_| eyre::eyre!("invalid UTF-8 in path"))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let metadata = serde_json::to_vec(&WindowsDllDirs { dll_dirs })?;
+    let pack = brioche_pack::Pack::Metadata {
+        resource_paths,
+        format: WINDOWS_DLL_DIRS_FORMAT.to_string(),
+        metadata,
+    };
+
+    if ctx.config.dry_run {
+        return Ok(true);
+    }
+
+    PhaseTimings::time(&mut path_result.timings.pack_injection, || {
+        let file = if source_path == output_path {
+            std::fs::OpenOptions::new().append(true).open(output_path)?
+        } else {
+            let mut new_file = std::fs::File::create(output_path)?;
+            new_file.write_all(&contents)?;
+            new_file
+        };
+        brioche_pack::inject_pack(file, &pack)?;
+        eyre::Ok(())
+    })?;
+
+    Ok(true)
+}
+
+fn collect_all_dll_dirs(
+    ctx: &AutopackContext,
+    dynamic_linking_config: &crate::DynamicLinkingConfig,
+    mut needed_dlls: VecDeque<String>,
+    pending_paths: &PendingPaths,
+    path_result: &mut AutopackPathResult,
+) -> eyre::Result<Vec<PathBuf>> {
+    let mut resource_dll_dirs = vec![];
+    let mut found_dlls = HashSet::new();
+    let mut found_dll_dirs = HashSet::new();
+
+    let library_filter = crate::library_filter::LibraryFilter::new(dynamic_linking_config, true)?;
+    let dll_search_paths = dynamic_linking_config
+        .library_paths
+        .iter()
+        .chain(&ctx.link_dependency_library_paths)
+        .collect::<Vec<_>>();
+
+    while let Some(dll_name) = needed_dlls.pop_front() {
+        // DLL names are case-insensitive, so skip DLLs we've already found
+        // under any case
+        if !found_dlls.insert(dll_name.to_ascii_lowercase()) {
+            continue;
+        }
+
+        let dll_path = PhaseTimings::time(&mut path_result.timings.library_resolution, || {
+            find_dll(&dll_search_paths, &dll_name)
+        })?;
+        let Some(dll_path) = dll_path else {
+            // System DLLs like `KERNEL32.dll` won't be found, so these
+            // usually need to be skipped
+            if dynamic_linking_config.skip_unknown_libraries {
+                path_result.libraries.push(AutopackLibraryResult {
+                    name: dll_name,
+                    source_path: None,
+                    resource_path: None,
+                });
+                continue;
+            } else {
+                eyre::bail!("DLL not found: {dll_name:?}");
+            }
+        };
+
+        // Autopack the DLL if it's pending
+        crate::try_autopack_dependency(ctx, &dll_path, pending_paths)?;
+
+        let is_skipped = library_filter.is_skipped(&dll_name);
+        let mut library_result = AutopackLibraryResult {
+            name: dll_name.clone(),
+            source_path: Some(dll_path.clone()),
+            resource_path: None,
+        };
+        if !is_skipped {
+            let dll_alias = Path::new(&dll_name);
+            let dll_resource_path =
+                PhaseTimings::time(&mut path_result.timings.resource_insertion, || {
+                    crate::add_named_blob_from(
+                        ctx,
+                        &mut path_result.resources,
+                        &path_result.path,
+                        &dll_path,
+                        crate::BlobRole::Library,
+                        Some(dll_alias),
+                    )
+                })
+                .with_context(|| format!("failed to add resource for DLL {dll_path:?}"))?;
+            library_result.resource_path = Some(dll_resource_path.clone());
+
+            let dll_resource_dir = dll_resource_path
+                .parent()
+                .ok_or_eyre("failed to get resource parent dir")?
+                .to_owned();
+            if found_dll_dirs.insert(dll_resource_dir.clone()) {
+                resource_dll_dirs.push(dll_resource_dir);
+            }
+        }
+        ctx.config
+            .progress
+            .report(&crate::ProgressEvent::LibraryResolved {
+                path: &path_result.path,
+                library: &library_result,
+            });
+        path_result.libraries.push(library_result);
+
+        // Add the DLL's own imports
+        let Ok(dll_contents) = std::fs::read(&dll_path) else {
+            continue;
+        };
+        let dll_object = PhaseTimings::time(&mut path_result.timings.elf_parsing, || {
+            ctx.inspect_object(&dll_contents)
+        });
+        let Some(dll_object) = dll_object.filter(|object| object.is_windows) else {
+            continue;
+        };
+        needed_dlls.extend(dll_object.needed_libraries);
+    }
+
+    Ok(resource_dll_dirs)
+}
+
+/// Find a DLL in the search paths. Like on Windows, the name is matched
+/// case-insensitively. Each search path can either be a directory
+/// containing DLLs, or a DLL file.
+fn find_dll(search_paths: &[&PathBuf], dll_name: &str) -> eyre::Result<Option<PathBuf>> {
+    for search_path in search_paths {
+        if search_path.is_dir() {
+            let exact_path = search_path.join(dll_name);
+            if exact_path.is_file() {
+                return Ok(Some(exact_path));
+            }
+
+            let entries = std::fs::read_dir(search_path)
+                .with_context(|| format!("failed to read directory {}", search_path.display()))?;
+            for entry in entries {
+                let entry = entry?;
+                let is_match = entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(dll_name));
+                if is_match && entry.path().is_file() {
+                    return Ok(Some(entry.path()));
+                }
+            }
+        } else if search_path.is_file() {
+            let is_match = search_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.eq_ignore_ascii_case(dll_name));
+            if is_match {
+                return Ok(Some(search_path.to_path_buf()));
+            }
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,78 @@
+//! Format for single-file bundles. A bundle is the `brioche-bundle-exec`
+//! launcher, followed by a tar payload containing a program and its
+//! resources, followed by a fixed-size [`BundleTrailer`].
+
+use std::io::{Read, Seek};
+
+pub const MAGIC: &[u8; 16] = b"brioche-bundle01";
+
+/// Path of the [`BundleMetadata`] file within the payload.
+pub const METADATA_PATH: &str = "brioche-bundle.json";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleMetadata {
+    /// Path of the program to run, relative to the root of the payload.
+    pub entrypoint: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BundleTrailer {
+    /// Hash of the payload, used to name the extraction dir.
+    pub hash: [u8; 32],
+
+    /// Length of the payload, which ends right before the trailer.
+    pub payload_len: u64,
+}
+
+impl BundleTrailer {
+    pub const LEN: usize = 32 + 8 + MAGIC.len();
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..32].copy_from_slice(&self.hash);
+        bytes[32..40].copy_from_slice(&self.payload_len.to_le_bytes());
+        bytes[40..].copy_from_slice(MAGIC);
+        bytes
+    }
+
+    /// Read the trailer from the end of a bundle, leaving the reader
+    /// positioned at the start of the payload.
+    pub fn read(mut bundle: impl Read + Seek) -> Result<Self, ReadBundleError> {
+        let trailer_len: i64 = Self::LEN.try_into().expect("trailer too long");
+        bundle.seek(std::io::SeekFrom::End(-trailer_len))?;
+
+        let mut bytes = [0; Self::LEN];
+        bundle.read_exact(&mut bytes)?;
+        if &bytes[40..] != MAGIC {
+            return Err(ReadBundleError::MissingMagic);
+        }
+
+        let hash = bytes[..32].try_into().expect("invalid hash length");
+        let payload_len = u64::from_le_bytes(bytes[32..40].try_into().expect("invalid length"));
+
+        let payload_offset = payload_len
+            .checked_add(Self::LEN as u64)
+            .and_then(|len| i64::try_from(len).ok())
+            .ok_or(ReadBundleError::InvalidPayloadLength)?;
+        bundle
+            .seek(std::io::SeekFrom::End(-payload_offset))
+            .map_err(|_| ReadBundleError::InvalidPayloadLength)?;
+
+        Ok(Self { hash, payload_len })
+    }
+
+    pub fn hash_hex(&self) -> String {
+        self.hash.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReadBundleError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("bundle trailer not found")]
+    MissingMagic,
+    #[error("invalid bundle payload length")]
+    InvalidPayloadLength,
+}
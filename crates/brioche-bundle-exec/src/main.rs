@@ -0,0 +1,99 @@
+use std::{
+    io::Read as _,
+    os::unix::process::CommandExt as _,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use brioche_bundle_exec::{BundleMetadata, BundleTrailer, ReadBundleError, METADATA_PATH};
+
+const BRIOCHE_BUNDLE_ERROR: u8 = 121;
+
+pub fn main() -> ExitCode {
+    let result = run();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("brioche-bundle error: {err}");
+            ExitCode::from(BRIOCHE_BUNDLE_ERROR)
+        }
+    }
+}
+
+fn run() -> Result<(), BundleError> {
+    let bundle_path = std::env::current_exe()?;
+    let mut bundle = std::fs::File::open(&bundle_path)?;
+    let trailer = BundleTrailer::read(&mut bundle)?;
+
+    // Extract the payload once, then re-use it on later runs
+    let bundle_dir = bundles_dir()?.join(trailer.hash_hex());
+    if !bundle_dir.is_dir() {
+        let parent_dir = bundle_dir.parent().ok_or(BundleError::InvalidPath {
+            path: bundle_dir.clone(),
+        })?;
+        std::fs::create_dir_all(parent_dir)?;
+
+        // Extract to a temporary dir first, so an interrupted extraction
+        // never leaves behind a partial bundle dir
+        let temp_dir = parent_dir.join(format!(".{}-{}", trailer.hash_hex(), ulid::Ulid::new()));
+        let payload = (&mut bundle).take(trailer.payload_len);
+        tar::Archive::new(payload).unpack(&temp_dir)?;
+
+        match std::fs::rename(&temp_dir, &bundle_dir) {
+            Ok(()) => {}
+            Err(_) if bundle_dir.is_dir() => {
+                // Another process extracted the same bundle first
+                std::fs::remove_dir_all(&temp_dir)?;
+            }
+            Err(error) => {
+                return Err(error.into());
+            }
+        }
+    }
+
+    let metadata = std::fs::read(bundle_dir.join(METADATA_PATH))?;
+    let metadata: BundleMetadata = serde_json::from_slice(&metadata)?;
+    let entrypoint = bundle_dir.join(&metadata.entrypoint);
+
+    let mut args = std::env::args_os();
+    let mut command = std::process::Command::new(entrypoint);
+    if let Some(arg0) = args.next() {
+        command.arg0(arg0);
+    }
+    command.args(args);
+
+    let error = command.exec();
+    Err(BundleError::IoError(error))
+}
+
+/// Get the dir where bundles are extracted. Uses `$BRIOCHE_BUNDLE_DIR` if
+/// set, otherwise a dir in the user's cache dir.
+fn bundles_dir() -> Result<PathBuf, BundleError> {
+    if let Some(bundle_dir) = std::env::var_os("BRIOCHE_BUNDLE_DIR") {
+        return Ok(PathBuf::from(bundle_dir));
+    }
+
+    let cache_dir = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(cache_dir) => PathBuf::from(cache_dir),
+        None => {
+            let home = std::env::var_os("HOME").ok_or(BundleError::NoCacheDir)?;
+            Path::new(&home).join(".cache")
+        }
+    };
+
+    Ok(cache_dir.join("brioche").join("bundles"))
+}
+
+#[derive(Debug, thiserror::Error)]
+enum BundleError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    ReadBundleError(#[from] ReadBundleError),
+    #[error("invalid bundle metadata: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("invalid path: {path}")]
+    InvalidPath { path: PathBuf },
+    #[error("could not find cache dir, set $BRIOCHE_BUNDLE_DIR or $HOME")]
+    NoCacheDir,
+}
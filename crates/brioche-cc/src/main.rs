@@ -1,4 +1,4 @@
-use std::{os::unix::process::CommandExt as _, process::ExitCode};
+use std::{collections::HashMap, os::unix::process::CommandExt as _, process::ExitCode};
 
 use eyre::{Context as _, OptionExt as _};
 
@@ -14,30 +14,75 @@ fn main() -> ExitCode {
     }
 }
 
-fn run() -> eyre::Result<()> {
-    let current_exe = std::env::current_exe().context("failed to get current executable")?;
-    let current_exe_name = current_exe
-        .file_name()
-        .ok_or_eyre("failed to get current executable name")?;
-    let current_exe_dir = current_exe
-        .parent()
-        .ok_or_eyre("failed to get current executable dir")?;
-    let current_exe_parent_dir = current_exe_dir
-        .parent()
-        .ok_or_eyre("failed to get current executable dir")?;
-    let cc_resource_dir = current_exe_parent_dir.join("libexec").join("brioche-cc");
-    if !cc_resource_dir.is_dir() {
-        eyre::bail!(
-            "failed to find c resource dir: {}",
-            cc_resource_dir.display()
-        );
+/// Config for the compiler drivers wrapped by `brioche-cc`, read from
+/// `libexec/brioche-cc/tools.json`. Tools are keyed by the name the
+/// wrapper was invoked as (e.g. `cc`, `gfortran`, or `gccgo`). Tools
+/// without an entry use the default config.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ToolsConfig {
+    #[serde(default)]
+    tools: HashMap<String, ToolConfig>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ToolConfig {
+    /// The name of the original tool within `libexec/brioche-cc`. Defaults
+    /// to the name the wrapper was invoked as.
+    #[serde(default)]
+    program: Option<String>,
+
+    /// The flag used to pass the sysroot, or `null` if the tool doesn't
+    /// take a sysroot.
+    #[serde(default = "default_sysroot_flag")]
+    sysroot_flag: Option<String>,
+
+    /// Extra args added before the args passed to the wrapper.
+    #[serde(default)]
+    prepend_args: Vec<String>,
+
+    /// Extra args added after the args passed to the wrapper.
+    #[serde(default)]
+    append_args: Vec<String>,
+}
+
+impl Default for ToolConfig {
+    fn default() -> Self {
+        Self {
+            program: None,
+            sysroot_flag: default_sysroot_flag(),
+            prepend_args: vec![],
+            append_args: vec![],
+        }
     }
+}
+
+fn default_sysroot_flag() -> Option<String> {
+    Some("--sysroot".to_string())
+}
+
+fn run() -> eyre::Result<()> {
+    let wrapper = brioche_toolwrap::Wrapper::new("brioche-cc")?;
+    let tool_name = wrapper
+        .current_exe_name()?
+        .to_str()
+        .ok_or_eyre("invalid UTF-8 in current executable name")?;
+
+    let tools_config_path = wrapper.tool("tools.json");
+    let mut tools_config = match std::fs::read(&tools_config_path) {
+        Ok(tools_config) => serde_json::from_slice::<ToolsConfig>(&tools_config)
+            .with_context(|| format!("failed to parse {}", tools_config_path.display()))?,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => ToolsConfig::default(),
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read {}", tools_config_path.display()));
+        }
+    };
+    let tool_config = tools_config.tools.remove(tool_name).unwrap_or_default();
 
-    let cc = cc_resource_dir.join(current_exe_name);
-    let sysroot_path = cc_resource_dir
-        .join("sysroot")
-        .canonicalize()
-        .context("failed to get sysroot path from 'libexec/brioche-cc/sysroot'")?;
+    let program = tool_config.program.as_deref().unwrap_or(tool_name);
+    let cc = wrapper.tool(program);
 
     let mut args = std::env::args_os();
     let arg0 = args.next();
@@ -48,16 +93,27 @@ fn run() -> eyre::Result<()> {
         command.arg0(&arg0);
     }
 
-    let has_sysroot_arg = args.iter().any(|arg| {
-        let arg_string = arg.to_string_lossy();
-        arg_string == "--sysroot" || arg_string.starts_with("--sysroot=")
-    });
+    if let Some(sysroot_flag) = &tool_config.sysroot_flag {
+        // Check for a sysroot arg, including any passed through `@file` args
+        let sysroot_flag_prefix = format!("{sysroot_flag}=");
+        let expanded_args = brioche_toolwrap::expand_response_files(args.iter().cloned())?;
+        let has_sysroot_arg = expanded_args.iter().any(|arg| {
+            let arg_string = arg.to_string_lossy();
+            arg_string == **sysroot_flag || arg_string.starts_with(&sysroot_flag_prefix)
+        });
 
-    if !has_sysroot_arg {
-        command.arg("--sysroot").arg(sysroot_path);
+        if !has_sysroot_arg {
+            let sysroot_path = wrapper
+                .tool("sysroot")
+                .canonicalize()
+                .context("failed to get sysroot path from 'libexec/brioche-cc/sysroot'")?;
+            command.arg(sysroot_flag).arg(sysroot_path);
+        }
     }
 
+    command.args(&tool_config.prepend_args);
     command.args(&args);
+    command.args(&tool_config.append_args);
 
     let error = command.exec();
     panic!("brioche-cc exec error: {error:#}");
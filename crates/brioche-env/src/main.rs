@@ -0,0 +1,87 @@
+use std::{
+    collections::BTreeMap, ffi::OsString, os::unix::process::CommandExt as _, path::PathBuf,
+    process::ExitCode,
+};
+
+use clap::Parser;
+use eyre::Context as _;
+
+/// Print or run a command with the environment of one or more
+/// dependencies, using the env vars each one sets through `brioche-env.d`.
+#[derive(Debug, Parser)]
+struct Args {
+    /// The dependency dirs to use. Earlier dependencies take precedence
+    #[arg(required = true)]
+    dependencies: Vec<PathBuf>,
+
+    /// A command to run with the environment. If not given, `export`
+    /// statements are printed instead
+    #[arg(last = true)]
+    command: Vec<OsString>,
+}
+
+fn main() -> ExitCode {
+    let result = run();
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("brioche-env error: {:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> eyre::Result<()> {
+    let args = Args::parse();
+
+    // Collect the paths for each env var across all dependencies
+    let mut env_paths = BTreeMap::<String, Vec<PathBuf>>::new();
+    for dependency in &args.dependencies {
+        for env_var in brioche_autopack::dependency_env_vars(dependency)? {
+            let paths = brioche_autopack::dependency_env_var_paths(dependency, &env_var)?;
+            env_paths.entry(env_var).or_default().extend(paths);
+        }
+
+        let bin_dir = dependency.join("bin");
+        if bin_dir.is_dir() {
+            env_paths
+                .entry("PATH".to_string())
+                .or_default()
+                .push(bin_dir);
+        }
+    }
+
+    // Prepend the paths to the current value of each env var
+    let mut env = vec![];
+    for (env_var, paths) in env_paths {
+        let mut value = std::env::join_paths(paths)
+            .with_context(|| format!("failed to build value for ${env_var}"))?;
+        if let Some(current_value) = std::env::var_os(&env_var).filter(|value| !value.is_empty()) {
+            value.push(":");
+            value.push(current_value);
+        }
+        env.push((env_var, value));
+    }
+
+    let mut command_args = args.command.into_iter();
+    let Some(program) = command_args.next() else {
+        for (env_var, value) in &env {
+            println!("export {env_var}={}", shell_quote(value));
+        }
+        return Ok(());
+    };
+
+    let mut command = std::process::Command::new(&program);
+    command.args(command_args);
+    command.envs(env);
+
+    let error = command.exec();
+    Err(error).with_context(|| format!("failed to run {program:?}"))
+}
+
+/// Quote a value for use in a POSIX shell.
+fn shell_quote(value: &std::ffi::OsStr) -> String {
+    let value = value.to_string_lossy();
+    format!("'{}'", value.replace('\'', r#"'\''"#))
+}
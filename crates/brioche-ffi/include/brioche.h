@@ -0,0 +1,39 @@
+#ifndef BRIOCHE_H
+#define BRIOCHE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Functions returning `char *` return NULL on failure. Functions returning
+ * `int` return 0 on success. After a failure, `brioche_last_error` returns
+ * a message describing the error. Returned strings must be freed with
+ * `brioche_string_free`.
+ */
+
+const char *brioche_last_error(void);
+void brioche_string_free(char *string);
+
+char *brioche_runnable_read(const char *program);
+int brioche_runnable_write(const char *program, const char *runnable_json);
+
+char *brioche_resource_add_blob(
+    const char *resource_dir,
+    const uint8_t *contents,
+    size_t contents_len,
+    bool executable,
+    const char *name
+);
+char *brioche_resource_find(const char *program, const char *resource);
+char *brioche_resource_output_dir(const char *program);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,258 @@
+//! C API for reading and writing runnable metadata and working with
+//! resource directories. See `include/brioche.h` for the matching header.
+//!
+//! Functions that can fail return `NULL` (for strings) or a non-zero value
+//! (for status codes), and store an error message that can be retrieved
+//! with [`brioche_last_error`]. Strings returned by this API are owned by
+//! the caller, and must be freed with [`brioche_string_free`].
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    io::Seek as _,
+    path::{Path, PathBuf},
+};
+
+use bstr::{ByteSlice as _, ByteVec as _};
+use eyre::{Context as _, OptionExt as _};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Get the error message from the last failed call on the current thread,
+/// or `NULL` if there was no error. The returned string is only valid
+/// until the next call into this API, and must not be freed.
+#[no_mangle]
+pub extern "C" fn brioche_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|error| match error {
+        Some(error) => error.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+/// Free a string returned by this API.
+///
+/// # Safety
+///
+/// `string` must be `NULL` or a string returned by this API that hasn't
+/// already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn brioche_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Read the runnable metadata from a packed program, returning it as a
+/// JSON string. Runnables stored in a resource are resolved using the
+/// program's resource dirs.
+///
+/// # Safety
+///
+/// `program` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn brioche_runnable_read(program: *const c_char) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        let program = c_path(program)?;
+        let runnable = read_runnable(program)?;
+        let runnable_json = serde_json::to_string(&runnable)?;
+        into_c_string(runnable_json.into_bytes())
+    })
+}
+
+/// Write runnable metadata (as a JSON string) into a program, replacing
+/// any existing pack. Large runnables are stored in the program's output
+/// resource dir. Returns 0 on success.
+///
+/// # Safety
+///
+/// `program` and `runnable_json` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn brioche_runnable_write(
+    program: *const c_char,
+    runnable_json: *const c_char,
+) -> c_int {
+    ffi_call(-1, || {
+        let program = c_path(program)?;
+        let runnable_json = c_bytes(runnable_json)?;
+        let runnable: runnable_core::Runnable =
+            serde_json::from_slice(runnable_json).context("invalid runnable metadata")?;
+        write_runnable(program, &runnable)?;
+        Ok(0)
+    })
+}
+
+/// Add a blob to a resource dir, returning the resource path of the new
+/// blob (relative to the resource dir).
+///
+/// # Safety
+///
+/// `resource_dir` and `name` must be valid NUL-terminated strings, and
+/// `contents` must point to `contents_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn brioche_resource_add_blob(
+    resource_dir: *const c_char,
+    contents: *const u8,
+    contents_len: usize,
+    executable: bool,
+    name: *const c_char,
+) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        let resource_dir = c_path(resource_dir)?;
+        let name = c_path(name)?;
+        eyre::ensure!(
+            !contents.is_null() || contents_len == 0,
+            "contents must not be null"
+        );
+        let contents = if contents_len == 0 {
+            &[][..]
+        } else {
+            std::slice::from_raw_parts(contents, contents_len)
+        };
+
+        let resource_path = brioche_resources::add_named_blob(
+            resource_dir,
+            std::io::Cursor::new(contents),
+            executable,
+            name,
+        )?;
+        into_c_path(resource_path)
+    })
+}
+
+/// Find a resource by its resource path, searching all the resource dirs
+/// for `program`. Returns `NULL` with no error set if the resource was not
+/// found.
+///
+/// # Safety
+///
+/// `program` and `resource` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn brioche_resource_find(
+    program: *const c_char,
+    resource: *const c_char,
+) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        let program = c_path(program)?;
+        let resource = c_path(resource)?;
+        let resource_dirs = brioche_resources::find_resource_dirs(program, true)?;
+        match brioche_resources::find_in_resource_dirs(&resource_dirs, resource) {
+            Some(path) => into_c_path(path),
+            None => Ok(std::ptr::null_mut()),
+        }
+    })
+}
+
+/// Get the resource dir where new resources for `program` should be
+/// written.
+///
+/// # Safety
+///
+/// `program` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn brioche_resource_output_dir(program: *const c_char) -> *mut c_char {
+    ffi_call(std::ptr::null_mut(), || {
+        let program = c_path(program)?;
+        let resource_dir = brioche_resources::find_output_resource_dir(program)?;
+        into_c_path(resource_dir)
+    })
+}
+
+fn read_runnable(program: &Path) -> eyre::Result<runnable_core::Runnable> {
+    let file = std::fs::File::open(program)
+        .with_context(|| format!("failed to open {}", program.display()))?;
+    let extracted = brioche_pack::extract_pack(file)?;
+
+    let brioche_pack::Pack::Metadata {
+        format, metadata, ..
+    } = extracted.pack
+    else {
+        eyre::bail!("{} does not have a metadata pack", program.display());
+    };
+    eyre::ensure!(
+        runnable_core::is_runnable_format(&format),
+        "unsupported metadata format: {format:?}"
+    );
+
+    let resource_dirs = brioche_resources::find_resource_dirs(program, true)?;
+    let runnable = runnable_core::load_runnable(&format, &metadata, &resource_dirs)?;
+    Ok(runnable)
+}
+
+fn write_runnable(program: &Path, runnable: &runnable_core::Runnable) -> eyre::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .open(program)
+        .with_context(|| format!("failed to open {}", program.display()))?;
+
+    // Remove the existing pack, if there is one
+    if let Ok(extracted) = brioche_pack::extract_pack(&mut file) {
+        file.set_len(extracted.unpacked_len.try_into()?)?;
+    }
+    file.seek(std::io::SeekFrom::End(0))?;
+
+    let resource_paths = runnable.resources().into_iter().cloned().collect();
+    let resource_dir = brioche_resources::find_output_resource_dir(program)?;
+    let program_name = program
+        .file_name()
+        .ok_or_eyre("could not get program name from path")?;
+    let mut metadata_name = program_name.to_owned();
+    metadata_name.push(".runnable.json");
+    let pack = brioche_autopack::runnable_metadata_pack(
+        &resource_dir,
+        runnable,
+        resource_paths,
+        Path::new(&metadata_name),
+    )?;
+
+    brioche_pack::inject_pack(&mut file, &pack)?;
+
+    Ok(())
+}
+
+/// Run the body of an FFI function, recording any error (or panic) so it
+/// can be retrieved with [`brioche_last_error`].
+fn ffi_call<T>(error_value: T, f: impl FnOnce() -> eyre::Result<T>) -> T {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    let error = match result {
+        Ok(Ok(value)) => {
+            set_last_error(None);
+            return value;
+        }
+        Ok(Err(error)) => format!("{error:#}"),
+        Err(_) => "panicked".to_string(),
+    };
+
+    set_last_error(Some(error));
+    error_value
+}
+
+fn set_last_error(error: Option<String>) {
+    let error = error
+        .map(|error| CString::new(error.replace('\0', "\\0")).expect("error contains a NUL byte"));
+    LAST_ERROR.with_borrow_mut(|last_error| {
+        *last_error = error;
+    });
+}
+
+unsafe fn c_bytes<'a>(string: *const c_char) -> eyre::Result<&'a [u8]> {
+    eyre::ensure!(!string.is_null(), "unexpected null string");
+    Ok(CStr::from_ptr(string).to_bytes())
+}
+
+unsafe fn c_path<'a>(string: *const c_char) -> eyre::Result<&'a Path> {
+    let bytes = c_bytes(string)?;
+    bytes.to_path().map_err(|_| eyre::eyre!("invalid path"))
+}
+
+fn into_c_string(bytes: Vec<u8>) -> eyre::Result<*mut c_char> {
+    let string = CString::new(bytes).context("string contains a NUL byte")?;
+    Ok(string.into_raw())
+}
+
+fn into_c_path(path: PathBuf) -> eyre::Result<*mut c_char> {
+    let bytes = <Vec<u8>>::from_path_buf(path).map_err(|_| eyre::eyre!("invalid path"))?;
+    into_c_string(bytes)
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
+
+use bstr::ByteSlice as _;
+use eyre::{Context as _, OptionExt as _};
+
+fn main() -> ExitCode {
+    let result = run();
+
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> eyre::Result<ExitCode> {
+    let wrapper = brioche_toolwrap::Wrapper::new("brioche-install")?;
+    let install = wrapper.tool("install");
+    let packed_path = wrapper.tool("brioche-packed");
+
+    // Run the original `install` command first
+    let mut command = std::process::Command::new(&install);
+    command.args(std::env::args_os().skip(1));
+    let status = command.status()?;
+
+    if !status.success() {
+        return Ok(brioche_toolwrap::exit_code(status));
+    }
+
+    if !brioche_toolwrap::env_toggle("BRIOCHE_INSTALL_AUTOPACK", true) {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let args = brioche_toolwrap::expand_response_files(std::env::args_os().skip(1))?;
+    let installed_paths = installed_paths(args.into_iter())?;
+    let skip_unknown_libs =
+        brioche_toolwrap::env_toggle("BRIOCHE_INSTALL_AUTOPACK_SKIP_UNKNOWN_LIBS", false);
+    let resolve_nix_store_paths =
+        brioche_toolwrap::env_toggle("BRIOCHE_INSTALL_AUTOPACK_NIX_STORE", false);
+    let resolve_rpaths = brioche_toolwrap::env_toggle("BRIOCHE_INSTALL_AUTOPACK_RPATHS", false);
+    let privileged_files = match std::env::var("BRIOCHE_INSTALL_AUTOPACK_PRIVILEGED") {
+        Ok(value) if !value.is_empty() => value
+            .parse()
+            .context("invalid value for $BRIOCHE_INSTALL_AUTOPACK_PRIVILEGED")?,
+        _ => brioche_autopack::PrivilegedFilePolicy::default(),
+    };
+    let symbol_version_check = match std::env::var("BRIOCHE_INSTALL_AUTOPACK_SYMBOL_VERSIONS") {
+        Ok(value) if !value.is_empty() => value
+            .parse()
+            .context("invalid value for $BRIOCHE_INSTALL_AUTOPACK_SYMBOL_VERSIONS")?,
+        _ => brioche_autopack::SymbolVersionCheck::default(),
+    };
+    let library_paths = std::env::var_os("LIBRARY_PATH")
+        .map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let all_resource_dirs = brioche_resources::find_resource_dirs(&wrapper.current_exe, true)
+        .context("error while finding resource dir")?;
+
+    for installed_path in installed_paths {
+        if !should_autopack(&installed_path)? {
+            continue;
+        }
+
+        let resource_dir = brioche_resources::find_output_resource_dir(&installed_path)
+            .context("error while finding resource dir")?;
+        let dynamic_linking_config = brioche_autopack::DynamicLinkingConfig {
+            library_paths: library_paths.clone(),
+            skip_libraries: HashSet::new(),
+            skip_library_globs: vec![],
+            only_library_globs: vec![],
+            extra_libraries: vec![],
+            skip_unknown_libraries: skip_unknown_libs,
+            resolve_nix_store_paths,
+            resolve_rpaths,
+            symbol_version_check,
+            preserve_symlink_chains: false,
+        };
+        brioche_autopack::autopack(&brioche_autopack::AutopackConfig {
+            resource_dir,
+            all_resource_dirs: all_resource_dirs.clone(),
+            inputs: brioche_autopack::AutopackInputs::Paths(vec![installed_path.clone()]),
+            progress: Arc::new(brioche_autopack::StdoutProgressReporter::warnings_only()),
+            link_dependencies: vec![wrapper.resource_dir.clone()],
+            dynamic_binary: Some(brioche_autopack::DynamicBinaryConfig {
+                packed_executable: packed_path.clone(),
+                extra_runtime_library_paths: vec![],
+                dynamic_linking: dynamic_linking_config.clone(),
+                mode: brioche_autopack::DynamicBinaryMode::Pack,
+            }),
+            loader_binary: None,
+            shared_library: Some(brioche_autopack::SharedLibraryConfig {
+                dynamic_linking: dynamic_linking_config,
+                allow_empty: true,
+            }),
+            windows_binary: None,
+            wasm: None,
+            repack: None,
+            script: None,
+            concurrency: None,
+            dry_run: false,
+            report_path: None,
+            manifest_path: None,
+            path_overrides: vec![],
+            target_arch: None,
+            privileged_files,
+            blob_link_mode: brioche_resources::BlobLinkMode::default(),
+            skip_signatures: vec![],
+            object_inspectors: vec![],
+            hooks: vec![],
+            state_file: None,
+        })
+        .with_context(|| format!("failed to autopack {}", installed_path.display()))?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Determine the destination paths written by an `install` invocation,
+/// based on the GNU coreutils argument conventions.
+fn installed_paths(
+    mut args: impl Iterator<Item = std::ffi::OsString>,
+) -> eyre::Result<Vec<PathBuf>> {
+    let mut directory_mode = false;
+    let mut no_target_directory = false;
+    let mut target_directory = None;
+    let mut operands = vec![];
+
+    while let Some(arg) = args.next() {
+        let arg_bytes = brioche_toolwrap::arg_bytes(&arg)?;
+
+        if &**arg_bytes == b"--" {
+            // Everything after `--` is an operand
+            for arg in args.by_ref() {
+                operands.push(PathBuf::from(arg));
+            }
+        } else if let Some(long_arg) = arg_bytes.strip_prefix(b"--") {
+            let (name, value) = match long_arg.split_once_str(b"=") {
+                Some((name, value)) => (name, Some(value)),
+                None => (long_arg, None),
+            };
+
+            match name {
+                b"directory" => {
+                    directory_mode = true;
+                }
+                b"no-target-directory" => {
+                    no_target_directory = true;
+                }
+                b"target-directory" => {
+                    let value = match value {
+                        Some(value) => brioche_toolwrap::arg_path(value)?.to_owned(),
+                        None => PathBuf::from(args.next().ok_or_eyre("expected arg after flag")?),
+                    };
+                    target_directory = Some(value);
+                }
+                b"mode" | b"owner" | b"group" | b"suffix" if value.is_none() => {
+                    // These take a value, either with `=` or as the
+                    // next argument
+                    args.next().ok_or_eyre("expected arg after flag")?;
+                }
+                _ => {
+                    // Other long arguments don't take a separate value
+                }
+            }
+        } else if let Some(short_args) = arg_bytes.strip_prefix(b"-").filter(|s| !s.is_empty()) {
+            // Short flags can be combined, e.g. `-Dm755`
+            for (n, flag) in short_args.iter().enumerate() {
+                match flag {
+                    b'd' => {
+                        directory_mode = true;
+                    }
+                    b'T' => {
+                        no_target_directory = true;
+                    }
+                    b'm' | b'o' | b'g' | b'S' | b't' => {
+                        let rest = &short_args[n + 1..];
+                        let value = if rest.is_empty() {
+                            PathBuf::from(args.next().ok_or_eyre("expected arg after flag")?)
+                        } else {
+                            brioche_toolwrap::arg_path(rest)?.to_owned()
+                        };
+
+                        if *flag == b't' {
+                            target_directory = Some(value);
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        } else {
+            let operand = brioche_toolwrap::arg_path(arg_bytes)?;
+            operands.push(operand.to_owned());
+        }
+    }
+
+    if directory_mode {
+        // Only directories are created, so there's nothing to pack
+        return Ok(vec![]);
+    }
+
+    if let Some(target_directory) = target_directory {
+        return operands
+            .iter()
+            .map(|source| {
+                let filename = source
+                    .file_name()
+                    .ok_or_eyre("failed to get filename from path")?;
+                eyre::Ok(target_directory.join(filename))
+            })
+            .collect();
+    }
+
+    let Some((destination, sources)) = operands.split_last() else {
+        return Ok(vec![]);
+    };
+
+    if !no_target_directory && destination.is_dir() {
+        sources
+            .iter()
+            .map(|source| {
+                let filename = source
+                    .file_name()
+                    .ok_or_eyre("failed to get filename from path")?;
+                eyre::Ok(destination.join(filename))
+            })
+            .collect()
+    } else {
+        Ok(vec![destination.clone()])
+    }
+}
+
+/// Returns true if the installed file is a dynamically-linked ELF
+/// executable or shared library that hasn't already been packed.
+fn should_autopack(path: &Path) -> eyre::Result<bool> {
+    if !path.is_file() {
+        return Ok(false);
+    }
+
+    let contents =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    if brioche_pack::extract_pack(std::io::Cursor::new(&contents[..])).is_ok() {
+        return Ok(false);
+    }
+
+    let Ok(elf) = goblin::elf::Elf::parse(&contents) else {
+        return Ok(false);
+    };
+
+    Ok(elf.interpreter.is_some() || elf.is_lib)
+}
@@ -1,17 +1,68 @@
-use std::{collections::HashSet, path::PathBuf, process::ExitCode};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
 
-use bstr::ByteSlice as _;
 use eyre::{Context as _, OptionExt as _};
 
 enum Mode {
     AutopackEnabled {
         output_path: PathBuf,
-        resource_dir: PathBuf,
-        all_resource_dirs: Vec<PathBuf>,
+        config: Box<brioche_autopack::AutopackConfig>,
     },
     AutopackDisabled,
 }
 
+/// Where to save an unpacked copy of the linked output, set with
+/// `$BRIOCHE_LD_KEEP_UNPACKED`.
+#[derive(Debug, Clone, Copy)]
+enum KeepUnpacked {
+    /// Save the copy as `.brioche-unpacked/<name>` next to the output.
+    Dir,
+
+    /// Save the copy as `<name>.unpacked` next to the output.
+    Suffix,
+}
+
+impl KeepUnpacked {
+    fn from_env() -> eyre::Result<Option<Self>> {
+        let Some(value) = std::env::var_os("BRIOCHE_LD_KEEP_UNPACKED") else {
+            return Ok(None);
+        };
+
+        match value.to_str() {
+            Some("") => Ok(None),
+            Some("dir") => Ok(Some(Self::Dir)),
+            Some("suffix") => Ok(Some(Self::Suffix)),
+            _ => {
+                eyre::bail!(
+                    "invalid value for $BRIOCHE_LD_KEEP_UNPACKED: {value:?} (expected \"dir\" or \"suffix\")"
+                );
+            }
+        }
+    }
+
+    fn unpacked_path(self, output_path: &Path) -> eyre::Result<PathBuf> {
+        let output_dir = output_path
+            .parent()
+            .ok_or_eyre("could not get parent of output path")?;
+        let output_name = output_path
+            .file_name()
+            .ok_or_eyre("could not get filename of output path")?;
+
+        match self {
+            Self::Dir => Ok(output_dir.join(".brioche-unpacked").join(output_name)),
+            Self::Suffix => {
+                let mut unpacked_name = output_name.to_owned();
+                unpacked_name.push(".unpacked");
+                Ok(output_dir.join(unpacked_name))
+            }
+        }
+    }
+}
+
 fn main() -> ExitCode {
     let result = run();
 
@@ -25,49 +76,32 @@ fn main() -> ExitCode {
 }
 
 fn run() -> eyre::Result<ExitCode> {
-    let current_exe = std::env::current_exe().context("failed to get current executable")?;
-    let current_exe_name = current_exe
-        .file_name()
-        .ok_or_eyre("failed to get current executable name")?;
-    let current_exe_dir = current_exe
-        .parent()
-        .ok_or_eyre("failed to get current executable dir")?;
-    let current_exe_parent_dir = current_exe_dir
-        .parent()
-        .ok_or_eyre("failed to get current executable dir")?;
-    let ld_resource_dir = current_exe_parent_dir.join("libexec").join("brioche-ld");
-    if !ld_resource_dir.is_dir() {
-        eyre::bail!(
-            "failed to find linker resource dir: {}",
-            ld_resource_dir.display()
-        );
-    }
-
-    let linker = ld_resource_dir.join(current_exe_name);
-    let packed_path = ld_resource_dir.join("brioche-packed");
+    let wrapper = brioche_toolwrap::Wrapper::new("brioche-ld")?;
+    let linker = wrapper.tool(wrapper.current_exe_name()?);
+    let packed_path = wrapper.tool("brioche-packed");
 
     let mut output_path = Some(PathBuf::from("a.out"));
     let mut library_search_paths = vec![];
     let mut input_paths = vec![];
 
-    let mut args = std::env::args_os().skip(1);
+    // Expand `@file` args so we see the output path and library paths,
+    // but pass the original args through to the linker
+    let expanded_args = brioche_toolwrap::expand_response_files(std::env::args_os().skip(1))?;
+    let mut args = expanded_args.into_iter();
     while let Some(arg) = args.next() {
-        let arg = <[u8]>::from_os_str(&arg).ok_or_eyre("invalid arg")?;
-        let arg = bstr::BStr::new(arg);
+        let arg = brioche_toolwrap::arg_bytes(&arg)?;
 
         if &**arg == b"-o" {
             let output = args.next().ok_or_eyre("invalid arg")?;
             output_path = Some(PathBuf::from(output));
         } else if let Some(output) = arg.strip_prefix(b"-o") {
-            let output = output.to_path().map_err(|_| eyre::eyre!("invalid path"))?;
+            let output = brioche_toolwrap::arg_path(output)?;
             output_path = Some(output.to_path_buf());
         } else if &**arg == b"-L" {
             let lib_path = args.next().ok_or_eyre("invalid arg")?;
             library_search_paths.push(PathBuf::from(lib_path));
         } else if let Some(lib_path) = arg.strip_prefix(b"-L") {
-            let lib_path = lib_path
-                .to_path()
-                .map_err(|_| eyre::eyre!("invalid path"))?;
+            let lib_path = brioche_toolwrap::arg_path(lib_path)?;
             library_search_paths.push(lib_path.to_owned());
         } else if &**arg == b"--help" || &**arg == b"--version" || &**arg == b"-v" {
             // Skip packing if we're just showing help or version info
@@ -75,7 +109,7 @@ fn run() -> eyre::Result<ExitCode> {
         } else if arg.starts_with(b"-") {
             // Ignore other arguments
         } else {
-            let input_path = arg.to_path().map_err(|_| eyre::eyre!("invalid path"))?;
+            let input_path = brioche_toolwrap::arg_path(arg)?;
             input_paths.push(input_path.to_owned());
         }
     }
@@ -84,72 +118,136 @@ fn run() -> eyre::Result<ExitCode> {
     // input paths when searching for required libraries
     library_search_paths.extend(input_paths);
 
+    let skip_unknown_libs =
+        brioche_toolwrap::env_toggle("BRIOCHE_LD_AUTOPACK_SKIP_UNKNOWN_LIBS", false);
+    let resolve_nix_store_paths =
+        brioche_toolwrap::env_toggle("BRIOCHE_LD_AUTOPACK_NIX_STORE", false);
+    let resolve_rpaths = brioche_toolwrap::env_toggle("BRIOCHE_LD_AUTOPACK_RPATHS", false);
+    let symbol_version_check = match std::env::var("BRIOCHE_LD_AUTOPACK_SYMBOL_VERSIONS") {
+        Ok(value) if !value.is_empty() => value
+            .parse()
+            .context("invalid value for $BRIOCHE_LD_AUTOPACK_SYMBOL_VERSIONS")?,
+        _ => brioche_autopack::SymbolVersionCheck::default(),
+    };
+    let keep_unpacked = KeepUnpacked::from_env()?;
+
     // Determine whether we will pack the resulting binary or not. We do this
     // before running the command so we can bail early if the resource dir
     // cannot be found.
-    let autopack_mode = std::env::var("BRIOCHE_LD_AUTOPACK");
-    let autopack_mode = match (autopack_mode.as_deref(), output_path) {
-        (Ok("false"), _) | (_, None) => Mode::AutopackDisabled,
-        (_, Some(output_path)) => {
+    let autopack_enabled = brioche_toolwrap::env_toggle("BRIOCHE_LD_AUTOPACK", true);
+    let autopack_mode = match (autopack_enabled, output_path) {
+        (false, _) | (_, None) => Mode::AutopackDisabled,
+        (true, Some(output_path)) => {
             let resource_dir = brioche_resources::find_output_resource_dir(&output_path)
                 .context("error while finding resource dir")?;
-            let all_resource_dirs = brioche_resources::find_resource_dirs(&current_exe, true)
-                .context("error while finding resource dir")?;
-            Mode::AutopackEnabled {
-                output_path,
-                resource_dir,
-                all_resource_dirs,
-            }
-        }
-    };
-    let skip_unknown_libs = matches!(
-        std::env::var("BRIOCHE_LD_AUTOPACK_SKIP_UNKNOWN_LIBS").as_deref(),
-        Ok("true")
-    );
-
-    let mut command = std::process::Command::new(&linker);
-    command.args(std::env::args_os().skip(1));
-    let status = command.status()?;
-
-    if !status.success() {
-        let exit_code = status
-            .code()
-            .and_then(|code| u8::try_from(code).ok())
-            .map(ExitCode::from)
-            .unwrap_or(ExitCode::FAILURE);
-        return Ok(exit_code);
-    }
-
-    match autopack_mode {
-        Mode::AutopackEnabled {
-            output_path,
-            resource_dir,
-            all_resource_dirs,
-        } => {
+            let all_resource_dirs =
+                brioche_resources::find_resource_dirs(&wrapper.current_exe, true)
+                    .context("error while finding resource dir")?;
             let dynamic_linking_config = brioche_autopack::DynamicLinkingConfig {
                 library_paths: library_search_paths,
                 skip_libraries: HashSet::new(),
+                skip_library_globs: vec![],
+                only_library_globs: vec![],
                 extra_libraries: vec![],
                 skip_unknown_libraries: skip_unknown_libs,
+                resolve_nix_store_paths,
+                resolve_rpaths,
+                symbol_version_check,
+                preserve_symlink_chains: false,
             };
-            brioche_autopack::autopack(&brioche_autopack::AutopackConfig {
+            let config = brioche_autopack::AutopackConfig {
                 resource_dir,
                 all_resource_dirs,
-                inputs: brioche_autopack::AutopackInputs::Paths(vec![output_path]),
-                quiet: true,
-                link_dependencies: vec![ld_resource_dir],
+                inputs: brioche_autopack::AutopackInputs::Paths(vec![output_path.clone()]),
+                progress: Arc::new(brioche_autopack::StdoutProgressReporter::warnings_only()),
+                link_dependencies: vec![wrapper.resource_dir],
                 dynamic_binary: Some(brioche_autopack::DynamicBinaryConfig {
                     packed_executable: packed_path,
                     extra_runtime_library_paths: vec![],
                     dynamic_linking: dynamic_linking_config.clone(),
+                    mode: brioche_autopack::DynamicBinaryMode::Pack,
                 }),
+                loader_binary: None,
                 shared_library: Some(brioche_autopack::SharedLibraryConfig {
                     dynamic_linking: dynamic_linking_config,
                     allow_empty: true,
                 }),
+                windows_binary: None,
+                wasm: None,
                 repack: None,
                 script: None,
-            })?;
+                concurrency: None,
+                dry_run: false,
+                report_path: None,
+                manifest_path: None,
+                path_overrides: vec![],
+                target_arch: None,
+                privileged_files: brioche_autopack::PrivilegedFilePolicy::default(),
+                blob_link_mode: brioche_resources::BlobLinkMode::default(),
+                skip_signatures: vec![],
+                object_inspectors: vec![],
+                hooks: vec![],
+                state_file: None,
+            };
+            Mode::AutopackEnabled {
+                output_path,
+                config: Box::new(config),
+            }
+        }
+    };
+
+    let mut command = std::process::Command::new(&linker);
+    command.args(std::env::args_os().skip(1));
+    let mut child = command.spawn()?;
+
+    // Index the library search paths on a worker thread while the linker
+    // runs. Any error is only reported if the linker succeeds
+    let (status, preparation) = std::thread::scope(|scope| {
+        let preparation = match &autopack_mode {
+            Mode::AutopackEnabled { config, .. } => {
+                Some(scope.spawn(|| brioche_autopack::prepare_autopack(config)))
+            }
+            Mode::AutopackDisabled => None,
+        };
+
+        let status = child.wait();
+
+        let preparation = preparation.map(|preparation| {
+            preparation
+                .join()
+                .map_err(|_| eyre::eyre!("autopack preparation panicked"))
+                .and_then(|preparation| preparation)
+        });
+        (status, preparation)
+    });
+    let status = status?;
+
+    if !status.success() {
+        return Ok(brioche_toolwrap::exit_code(status));
+    }
+
+    match autopack_mode {
+        Mode::AutopackEnabled {
+            output_path,
+            config,
+        } => {
+            // Save a copy of the linked output before packing it, for
+            // tools like debuggers that need the original ELF file
+            if let Some(keep_unpacked) = keep_unpacked {
+                let absolute_output_path = std::path::absolute(&output_path)?;
+                let unpacked_path = keep_unpacked.unpacked_path(&absolute_output_path)?;
+                if let Some(unpacked_dir) = unpacked_path.parent() {
+                    std::fs::create_dir_all(unpacked_dir)?;
+                }
+                std::fs::copy(&output_path, &unpacked_path).with_context(|| {
+                    format!("failed to save unpacked output to {unpacked_path:?}")
+                })?;
+            }
+
+            let preparation = preparation
+                .ok_or_eyre("autopack was not prepared")?
+                .context("failed to prepare autopack")?;
+            brioche_autopack::autopack_prepared(&config, preparation)?;
         }
         Mode::AutopackDisabled => {
             // We already wrote the binary, so nothing to do
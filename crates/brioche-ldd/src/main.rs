@@ -0,0 +1,282 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bstr::ByteSlice as _;
+use eyre::{Context as _, OptionExt as _};
+
+fn main() -> ExitCode {
+    let result = run();
+
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> eyre::Result<ExitCode> {
+    let programs = std::env::args_os()
+        .skip(1)
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    eyre::ensure!(!programs.is_empty(), "usage: brioche-ldd <PROGRAM>...");
+
+    let mut all_found = true;
+    for program in &programs {
+        if programs.len() > 1 {
+            println!("{}:", program.display());
+        }
+
+        let found = print_dependencies(program)
+            .with_context(|| format!("failed to get dependencies of {}", program.display()))?;
+        all_found &= found;
+    }
+
+    if all_found {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// Print the interpreter and library closure of a program. Returns `false`
+/// if any dependency could not be found.
+fn print_dependencies(program_path: &Path) -> eyre::Result<bool> {
+    let contents = std::fs::read(program_path)?;
+    let all_resource_dirs = match brioche_resources::find_resource_dirs(program_path, true) {
+        Ok(resource_dirs) => resource_dirs,
+        Err(brioche_resources::PackResourceDirError::NotFound) => vec![],
+        Err(error) => {
+            return Err(error).context("failed to find resource dirs");
+        }
+    };
+
+    let extracted = brioche_pack::extract_pack(std::io::Cursor::new(&contents[..]));
+    let Ok(extracted) = extracted else {
+        // Not a packed program, so resolve libraries the same way the
+        // dynamic linker would by default
+        let library_search_paths = std::env::var_os("LD_LIBRARY_PATH")
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+
+        let Ok(elf) = goblin::elf::Elf::parse(&contents) else {
+            println!("\tnot a dynamic executable");
+            return Ok(true);
+        };
+        if let Some(interpreter) = elf.interpreter {
+            println!("\tinterpreter => {interpreter}");
+        }
+
+        return print_library_closure(&contents, library_search_paths, &all_resource_dirs);
+    };
+
+    match extracted.pack {
+        brioche_pack::Pack::LdLinux {
+            program,
+            interpreter,
+            library_dirs,
+            runtime_library_dirs,
+        } => {
+            let mut found = true;
+
+            let interpreter = print_resource("interpreter", &interpreter, &all_resource_dirs)?;
+            found &= interpreter.is_some();
+
+            let program = print_resource("program", &program, &all_resource_dirs)?;
+
+            let program_dir = program_path
+                .parent()
+                .ok_or_eyre("failed to get parent of program path")?;
+            let mut library_search_paths = vec![];
+            for library_dir in &runtime_library_dirs {
+                let library_dir = library_dir
+                    .to_path()
+                    .map_err(|_| eyre::eyre!("invalid path: {}", bstr::BStr::new(library_dir)))?;
+                library_search_paths.push(program_dir.join(library_dir));
+            }
+            for library_dir in &library_dirs {
+                let library_dir = print_resource("library dir", library_dir, &all_resource_dirs)?;
+                match library_dir {
+                    Some(library_dir) => library_search_paths.push(library_dir),
+                    None => found = false,
+                }
+            }
+
+            let Some(program) = program else {
+                return Ok(false);
+            };
+            let program_contents = std::fs::read(&program)
+                .with_context(|| format!("failed to read program {}", program.display()))?;
+            found &=
+                print_library_closure(&program_contents, library_search_paths, &all_resource_dirs)?;
+
+            Ok(found)
+        }
+        brioche_pack::Pack::Static { library_dirs } => {
+            let mut found = true;
+
+            let mut library_search_paths = vec![];
+            for library_dir in &library_dirs {
+                let library_dir = print_resource("library dir", library_dir, &all_resource_dirs)?;
+                match library_dir {
+                    Some(library_dir) => library_search_paths.push(library_dir),
+                    None => found = false,
+                }
+            }
+
+            let unpacked_contents = &contents[..extracted.unpacked_len];
+            found &=
+                print_library_closure(unpacked_contents, library_search_paths, &all_resource_dirs)?;
+
+            Ok(found)
+        }
+        brioche_pack::Pack::Metadata {
+            resource_paths: _,
+            format,
+            metadata,
+        } => {
+            eyre::ensure!(
+                runnable_core::is_runnable_format(&format),
+                "unknown metadata format: {format:?}"
+            );
+
+            let mut runnable =
+                runnable_core::load_runnable(&format, &metadata, &all_resource_dirs)?;
+            runnable.select_platform(&runnable_core::current_platform());
+            let command = runnable
+                .command
+                .to_os_string(program_path, &all_resource_dirs);
+            let command = match command {
+                Ok(command) => PathBuf::from(command),
+                Err(error) => {
+                    println!("\tcommand => not found ({error})");
+                    return Ok(false);
+                }
+            };
+
+            println!(
+                "\tcommand => {}{}",
+                command.display(),
+                resource_suffix(&command, &all_resource_dirs)
+            );
+            print_dependencies(&command)
+        }
+    }
+}
+
+/// Resolve and print a resource from a pack, returning `None` if it
+/// could not be found.
+fn print_resource(
+    name: &str,
+    resource: &[u8],
+    all_resource_dirs: &[PathBuf],
+) -> eyre::Result<Option<PathBuf>> {
+    let resource = resource
+        .to_path()
+        .map_err(|_| eyre::eyre!("invalid {name} path: {}", bstr::BStr::new(resource)))?;
+    let resource_path = brioche_resources::find_in_resource_dirs(all_resource_dirs, resource);
+
+    match &resource_path {
+        Some(path) => {
+            println!(
+                "\t{name} => {} (resource {})",
+                path.display(),
+                resource.display()
+            );
+        }
+        None => {
+            println!("\t{name} => not found (resource {})", resource.display());
+        }
+    }
+
+    Ok(resource_path)
+}
+
+/// Resolve the full library closure of an ELF file, printing each library
+/// along with the path it resolved to. Returns `false` if any library
+/// could not be found.
+fn print_library_closure(
+    contents: &[u8],
+    mut library_search_paths: Vec<PathBuf>,
+    all_resource_dirs: &[PathBuf],
+) -> eyre::Result<bool> {
+    let elf = goblin::elf::Elf::parse(contents).context("failed to parse ELF file")?;
+    let program_arch = brioche_autopack::ElfArch::from_elf(&elf);
+
+    let mut found_all = true;
+    let mut needed_libraries: VecDeque<_> =
+        elf.libraries.iter().map(|lib| lib.to_string()).collect();
+    let mut seen_libraries = HashSet::new();
+
+    while let Some(library_name) = needed_libraries.pop_front() {
+        if !seen_libraries.insert(library_name.clone()) {
+            continue;
+        }
+
+        let library_path = brioche_autopack::find_library_for_arch(
+            &library_search_paths,
+            &library_name,
+            Some(program_arch),
+        )?;
+        let Some(library_path) = library_path else {
+            println!("\t{library_name} => not found");
+            found_all = false;
+            continue;
+        };
+
+        println!(
+            "\t{library_name} => {}{}",
+            library_path.display(),
+            resource_suffix(&library_path, all_resource_dirs)
+        );
+
+        let Ok(library_contents) = std::fs::read(&library_path) else {
+            continue;
+        };
+        let Ok(library_elf) = goblin::elf::Elf::parse(&library_contents) else {
+            continue;
+        };
+        needed_libraries.extend(library_elf.libraries.iter().map(|lib| lib.to_string()));
+
+        // Packed libraries can include their own library dirs, which get
+        // used as extra search paths (matching autopack's behavior)
+        let library_cursor = std::io::Cursor::new(&library_contents[..]);
+        if let Ok(extracted_library) = brioche_pack::extract_pack(library_cursor) {
+            let library_dirs = match &extracted_library.pack {
+                brioche_pack::Pack::LdLinux { library_dirs, .. } => &library_dirs[..],
+                brioche_pack::Pack::Static { library_dirs } => &library_dirs[..],
+                brioche_pack::Pack::Metadata { .. } => &[],
+            };
+
+            for library_dir in library_dirs {
+                let Ok(library_dir) = library_dir.to_path() else {
+                    continue;
+                };
+                let Some(library_dir_path) =
+                    brioche_resources::find_in_resource_dirs(all_resource_dirs, library_dir)
+                else {
+                    continue;
+                };
+
+                library_search_paths.push(library_dir_path);
+            }
+        }
+    }
+
+    Ok(found_all)
+}
+
+fn resource_suffix(path: &Path, all_resource_dirs: &[PathBuf]) -> String {
+    let resource = all_resource_dirs
+        .iter()
+        .find_map(|resource_dir| path.strip_prefix(resource_dir).ok());
+    match resource {
+        Some(resource) => format!(" (resource {})", resource.display()),
+        None => String::new(),
+    }
+}
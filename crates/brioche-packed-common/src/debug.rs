@@ -0,0 +1,61 @@
+use std::{
+    io::Write as _,
+    sync::{Mutex, OnceLock},
+};
+
+type DebugLog = Mutex<Box<dyn std::io::Write + Send>>;
+
+static DEBUG_LOG: OnceLock<Option<DebugLog>> = OnceLock::new();
+
+/// Enable debug logging if `$BRIOCHE_PACKED_DEBUG` is set to `1` or
+/// `true`. Logs are written to stderr, or appended to the file at
+/// `$BRIOCHE_PACKED_DEBUG_FILE` if it's set. The file is ignored in
+/// [secure mode](crate::is_secure_mode), since whoever starts the program
+/// could use it to write to any path the program can.
+pub fn init() {
+    DEBUG_LOG.get_or_init(|| {
+        let enabled = matches!(
+            std::env::var("BRIOCHE_PACKED_DEBUG").as_deref(),
+            Ok("1" | "true")
+        );
+        if !enabled {
+            return None;
+        }
+
+        let writer: Box<dyn std::io::Write + Send> =
+            match std::env::var_os("BRIOCHE_PACKED_DEBUG_FILE") {
+                Some(path) if !path.is_empty() && !crate::is_secure_mode() => {
+                    let file = std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&path);
+                    match file {
+                        Ok(file) => Box::new(file),
+                        Err(error) => {
+                            eprintln!("brioche-packed: failed to open debug log {path:?}: {error}");
+                            Box::new(std::io::stderr())
+                        }
+                    }
+                }
+                _ => Box::new(std::io::stderr()),
+            };
+        Some(Mutex::new(writer))
+    });
+}
+
+pub fn log(args: std::fmt::Arguments) {
+    let Some(Some(debug_log)) = DEBUG_LOG.get() else {
+        return;
+    };
+
+    let mut debug_log = debug_log.lock().unwrap_or_else(|error| error.into_inner());
+    let _ = writeln!(debug_log, "brioche-packed: {args}");
+}
+
+/// Write a line to the debug log, if debug logging is enabled.
+#[macro_export]
+macro_rules! debug_log {
+    ($($arg:tt)*) => {
+        $crate::debug::log(format_args!($($arg)*))
+    };
+}
@@ -0,0 +1,221 @@
+//! Static programs embedded in a packed launcher.
+//!
+//! A launcher carrying a static pack is laid out as the launcher itself,
+//! followed by the static program, followed by the pack. The end of the
+//! launcher is found from its ELF headers, so everything between that and
+//! the pack is the embedded program.
+
+use std::{io::Write as _, ops::Range, os::fd::FromRawFd as _, path::Path};
+
+const ELF_HEADER_LEN: usize = 64;
+const PROGRAM_HEADER_LEN: usize = 56;
+const SECTION_HEADER_LEN: usize = 64;
+
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const SHT_NOBITS: u32 = 8;
+
+/// Get the range of the static program embedded in the unpacked part of a
+/// packed launcher (everything before the pack).
+pub fn embedded_program_range(unpacked: &[u8]) -> Result<Range<usize>, EmbeddedProgramError> {
+    let launcher_len = elf_file_len(unpacked)?;
+    if launcher_len >= unpacked.len() {
+        return Err(EmbeddedProgramError::NoEmbeddedProgram);
+    }
+
+    Ok(launcher_len..unpacked.len())
+}
+
+/// Copy the static program embedded in the packed launcher at `path` into
+/// an executable in-memory file. `unpacked_len` is the length of the
+/// launcher before the pack. The file is closed on exec, which is fine for
+/// exec'ing it through `/proc/self/fd`, since the kernel opens it first.
+pub fn embedded_program_fd(
+    path: &Path,
+    unpacked_len: usize,
+) -> Result<std::fs::File, EmbeddedProgramError> {
+    let mut contents = std::fs::read(path)?;
+    if contents.len() < unpacked_len {
+        return Err(EmbeddedProgramError::IoError(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "packed program is shorter than expected",
+        )));
+    }
+    contents.truncate(unpacked_len);
+    let program_range = embedded_program_range(&contents)?;
+
+    let fd = unsafe { libc::memfd_create(c"brioche-static-program".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(EmbeddedProgramError::IoError(
+            std::io::Error::last_os_error(),
+        ));
+    }
+    let mut program_file = unsafe { std::fs::File::from_raw_fd(fd) };
+    program_file.write_all(&contents[program_range])?;
+
+    Ok(program_file)
+}
+
+/// Get the length of the ELF file at the start of `contents`, based on the
+/// furthest extent of its headers, segments, and sections.
+fn elf_file_len(contents: &[u8]) -> Result<usize, EmbeddedProgramError> {
+    let header = contents
+        .get(..ELF_HEADER_LEN)
+        .ok_or(EmbeddedProgramError::UnsupportedLauncher)?;
+    let is_supported =
+        header.starts_with(b"\x7fELF") && header[4] == ELFCLASS64 && header[5] == ELFDATA2LSB;
+    if !is_supported {
+        return Err(EmbeddedProgramError::UnsupportedLauncher);
+    }
+
+    let phoff = read_u64(header, 0x20)?;
+    let shoff = read_u64(header, 0x28)?;
+    let phnum = read_u16(header, 0x38)?;
+    let shnum = read_u16(header, 0x3c)?;
+
+    let mut file_len = ELF_HEADER_LEN;
+
+    let program_headers_len = phnum * PROGRAM_HEADER_LEN;
+    file_len = file_len.max(extent(phoff, program_headers_len)?);
+    for n in 0..phnum {
+        let program_header = slice(
+            contents,
+            extent(phoff, n * PROGRAM_HEADER_LEN)?,
+            PROGRAM_HEADER_LEN,
+        )?;
+        let offset = read_u64(program_header, 0x08)?;
+        let size = read_u64(program_header, 0x20)?;
+        file_len = file_len.max(extent(offset, size)?);
+    }
+
+    let section_headers_len = shnum * SECTION_HEADER_LEN;
+    if shoff != 0 {
+        file_len = file_len.max(extent(shoff, section_headers_len)?);
+    }
+    for n in 0..shnum {
+        let section_header = slice(
+            contents,
+            extent(shoff, n * SECTION_HEADER_LEN)?,
+            SECTION_HEADER_LEN,
+        )?;
+        let section_type = read_u32(section_header, 0x04)?;
+        if section_type == SHT_NOBITS {
+            continue;
+        }
+
+        let offset = read_u64(section_header, 0x18)?;
+        let size = read_u64(section_header, 0x20)?;
+        file_len = file_len.max(extent(offset, size)?);
+    }
+
+    Ok(file_len)
+}
+
+fn extent(offset: usize, len: usize) -> Result<usize, EmbeddedProgramError> {
+    offset
+        .checked_add(len)
+        .ok_or(EmbeddedProgramError::UnsupportedLauncher)
+}
+
+fn slice(contents: &[u8], offset: usize, len: usize) -> Result<&[u8], EmbeddedProgramError> {
+    contents
+        .get(offset..extent(offset, len)?)
+        .ok_or(EmbeddedProgramError::UnsupportedLauncher)
+}
+
+fn read_u16(contents: &[u8], offset: usize) -> Result<usize, EmbeddedProgramError> {
+    let bytes = slice(contents, offset, 2)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]).into())
+}
+
+fn read_u32(contents: &[u8], offset: usize) -> Result<u32, EmbeddedProgramError> {
+    let bytes = slice(contents, offset, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(contents: &[u8], offset: usize) -> Result<usize, EmbeddedProgramError> {
+    let bytes = slice(contents, offset, 8)?;
+    let value = u64::from_le_bytes(bytes.try_into().unwrap_or_default());
+    value
+        .try_into()
+        .map_err(|_| EmbeddedProgramError::UnsupportedLauncher)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmbeddedProgramError {
+    #[error("static pack has no embedded program, run the static program directly")]
+    NoEmbeddedProgram,
+    #[error("launcher is not a 64-bit little-endian ELF file")]
+    UnsupportedLauncher,
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read as _, Seek as _};
+
+    use brioche_test_fixtures::ElfBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_embedded_program_range() {
+        let launcher = ElfBuilder::static_executable().build();
+        let program = ElfBuilder::static_executable().needed("libfoo.so").build();
+
+        let mut unpacked = launcher.clone();
+        unpacked.extend_from_slice(&program);
+        let range = embedded_program_range(&unpacked).unwrap();
+        assert_eq!(range, launcher.len()..launcher.len() + program.len());
+        assert_eq!(unpacked[range], program);
+    }
+
+    #[test]
+    fn test_no_embedded_program() {
+        let launcher = ElfBuilder::static_executable().build();
+        assert!(matches!(
+            embedded_program_range(&launcher),
+            Err(EmbeddedProgramError::NoEmbeddedProgram)
+        ));
+
+        assert!(matches!(
+            embedded_program_range(b"#!/bin/sh\n"),
+            Err(EmbeddedProgramError::UnsupportedLauncher)
+        ));
+    }
+
+    #[test]
+    fn test_elf_file_len_with_sections() {
+        // The test binary has section headers, which are usually at the
+        // end of the file
+        let test_binary = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        assert_eq!(elf_file_len(&test_binary).unwrap(), test_binary.len());
+    }
+
+    #[test]
+    fn test_embedded_program_fd() {
+        let root = tempfile::tempdir().unwrap();
+        let launcher = ElfBuilder::static_executable().build();
+        let program = ElfBuilder::static_executable().needed("libfoo.so").build();
+
+        let mut launcher_with_program = launcher.clone();
+        launcher_with_program.extend_from_slice(&program);
+        let pack = brioche_pack::Pack::Static {
+            library_dirs: vec![],
+        };
+        let packed_path = root.path().join("packed");
+        brioche_test_fixtures::write_packed(&packed_path, &launcher_with_program, &pack).unwrap();
+
+        let packed = std::fs::File::open(&packed_path).unwrap();
+        let extracted = brioche_pack::extract_pack(packed).unwrap();
+        assert_eq!(extracted.pack, pack);
+        assert_eq!(extracted.unpacked_len, launcher_with_program.len());
+
+        let mut program_file = embedded_program_fd(&packed_path, extracted.unpacked_len).unwrap();
+        let mut contents = vec![];
+        program_file.rewind().unwrap();
+        program_file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, program);
+    }
+}
@@ -0,0 +1,78 @@
+//! Helpers shared by the packed launchers, `brioche-packed-plain-exec`
+//! and `brioche-packed-userland-exec`.
+
+use std::{ffi::OsString, path::PathBuf};
+
+use bstr::ByteSlice as _;
+
+pub mod debug;
+#[cfg(target_os = "linux")]
+mod embedded;
+
+#[cfg(target_os = "linux")]
+pub use embedded::{embedded_program_fd, embedded_program_range, EmbeddedProgramError};
+
+/// Whether the launcher should run in secure mode, where resources from
+/// world-writable resource dirs are rejected and debug logs can't be
+/// redirected to a file. Secure mode is enabled by setting
+/// `$BRIOCHE_PACKED_SECURE` to `1` or `true`, and is always enabled when
+/// the program runs with elevated privileges (e.g. setuid), since the
+/// environment can't be trusted then.
+pub fn is_secure_mode() -> bool {
+    let env_secure = matches!(
+        std::env::var("BRIOCHE_PACKED_SECURE").as_deref(),
+        Ok("1" | "true")
+    );
+    env_secure || is_privileged()
+}
+
+#[cfg(target_os = "linux")]
+fn is_privileged() -> bool {
+    let at_secure = unsafe { libc::getauxval(libc::AT_SECURE) };
+    at_secure != 0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_privileged() -> bool {
+    false
+}
+
+/// Join library dirs into a value for `$LD_LIBRARY_PATH`, followed by the
+/// current value of `$LD_LIBRARY_PATH` if it's set.
+pub fn ld_library_path(library_dirs: &[PathBuf]) -> Result<bstr::BString, LdLibraryPathError> {
+    let mut ld_library_path = bstr::BString::default();
+    for (n, library_dir) in library_dirs.iter().enumerate() {
+        if n > 0 {
+            ld_library_path.push(b':');
+        }
+
+        let path = <[u8]>::from_path(library_dir).ok_or_else(|| {
+            LdLibraryPathError::InvalidLibraryDir {
+                path: library_dir.to_owned(),
+            }
+        })?;
+        ld_library_path.extend(path);
+    }
+
+    if let Some(env_library_path) = std::env::var_os("LD_LIBRARY_PATH") {
+        let env_library_path = <[u8]>::from_os_str(&env_library_path).ok_or_else(|| {
+            LdLibraryPathError::InvalidEnvValue {
+                value: env_library_path.clone(),
+            }
+        })?;
+        if !env_library_path.is_empty() {
+            ld_library_path.push(b':');
+            ld_library_path.extend(env_library_path);
+        }
+    }
+
+    Ok(ld_library_path)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LdLibraryPathError {
+    #[error("invalid library dir path: {path:?}")]
+    InvalidLibraryDir { path: PathBuf },
+    #[error("invalid $LD_LIBRARY_PATH value: {value:?}")]
+    InvalidEnvValue { value: OsString },
+}
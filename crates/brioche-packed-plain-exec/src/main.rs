@@ -1,5 +1,11 @@
-use std::{ffi::OsString, os::unix::process::CommandExt as _, path::PathBuf, process::ExitCode};
+use std::{
+    ffi::OsString,
+    os::{fd::AsRawFd as _, unix::process::CommandExt as _},
+    path::PathBuf,
+    process::ExitCode,
+};
 
+use brioche_packed_common::{debug, debug_log};
 use bstr::ByteSlice as _;
 
 const BRIOCHE_PACKED_ERROR: u8 = 121;
@@ -16,34 +22,82 @@ pub fn main() -> ExitCode {
 }
 
 fn run() -> Result<(), PackedError> {
+    debug::init();
+
     let program_path = std::env::current_exe()?;
+    debug_log!("program: {}", program_path.display());
     let program_parent_path = program_path
         .parent()
         .ok_or_else(|| PackedError::InvalidPath {
             path: program_path.clone(),
         })?;
     let resource_dirs = brioche_resources::find_resource_dirs(&program_path, true)?;
+    for resource_dir in &resource_dirs {
+        debug_log!("resource dir: {}", resource_dir.display());
+    }
+
+    // This process execs right away, so there's no need to pick up
+    // resources added while it runs
+    brioche_resources::enable_lookup_cache();
+
     let mut program = std::fs::File::open(&program_path)?;
     let extracted = brioche_pack::extract_pack(&mut program)?;
 
-    match extracted.pack {
+    let mut args = std::env::args_os().collect::<Vec<_>>();
+    let introspect = args.get(1).and_then(|arg| Introspect::from_arg(arg));
+    if introspect.is_some() {
+        args.remove(1);
+    }
+
+    match introspect {
+        Some(Introspect::PackInfo) => {
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &extracted.pack)?;
+            println!();
+            return Ok(());
+        }
+        Some(Introspect::ResourceDirs) => {
+            for resource_dir in &resource_dirs {
+                println!("{}", resource_dir.display());
+            }
+            return Ok(());
+        }
+        Some(Introspect::PrintCommand) | None => {}
+    }
+
+    let resolve_options = brioche_resources::ResolveOptions {
+        secure: brioche_packed_common::is_secure_mode(),
+    };
+    if resolve_options.secure {
+        debug_log!("secure mode enabled");
+    }
+    let resolver = brioche_resources::ResourceResolver::new(&resource_dirs, resolve_options);
+
+    // Keeps the in-memory copy of a static program open until it's run
+    let mut _static_program_fd = None;
+
+    let mut command = match extracted.pack {
         brioche_pack::Pack::LdLinux {
             program,
             interpreter,
             library_dirs,
             runtime_library_dirs,
         } => {
-            let mut args = std::env::args_os();
-
-            let interpreter = interpreter
-                .to_path()
-                .map_err(|_| PackedError::InvalidPathBytes {
-                    path: interpreter.clone().into(),
-                })?;
-            let interpreter = brioche_resources::find_in_resource_dirs(&resource_dirs, interpreter)
-                .ok_or_else(|| PackedError::ResourceNotFound {
-                    resource: interpreter.to_owned(),
-                })?;
+            debug_log!("pack: ld-linux");
+            let mut args = args.into_iter();
+
+            // Look up every resource in one pass before resolving them
+            // individually below
+            let resource_subpaths = std::iter::once(&interpreter)
+                .chain(&library_dirs)
+                .chain(std::iter::once(&program))
+                .filter_map(|resource| resource.to_path().ok())
+                .collect::<Vec<_>>();
+            brioche_resources::find_all_in_resource_dirs(&resource_dirs, &resource_subpaths);
+
+            // Compressed blobs are decompressed to a cache dir first
+            let interpreter = resolve_resource(&resolver, &interpreter)?.path;
+            let interpreter = resolver.materialize(&interpreter)?;
+            debug_log!("interpreter: {}", interpreter.display());
             let mut command = std::process::Command::new(interpreter);
 
             let mut resolved_library_dirs = vec![];
@@ -56,59 +110,19 @@ fn run() -> Result<(), PackedError> {
                             path: library_dir.clone().into(),
                         })?;
                 let resolved_library_dir = program_parent_path.join(library_dir);
+                debug_log!("runtime library dir: {}", resolved_library_dir.display());
                 resolved_library_dirs.push(resolved_library_dir);
             }
 
             for library_dir in &library_dirs {
-                let library_dir =
-                    library_dir
-                        .to_path()
-                        .map_err(|_| PackedError::InvalidPathBytes {
-                            path: library_dir.clone().into(),
-                        })?;
-                let library_dir =
-                    brioche_resources::find_in_resource_dirs(&resource_dirs, library_dir)
-                        .ok_or_else(|| PackedError::ResourceNotFound {
-                            resource: library_dir.to_owned(),
-                        })?;
+                let library_dir = resolve_resource(&resolver, library_dir)?.path;
+                debug_log!("library dir: {}", library_dir.display());
                 resolved_library_dirs.push(library_dir);
             }
 
             if !resolved_library_dirs.is_empty() {
-                let mut ld_library_path = bstr::BString::default();
-                for (n, library_dir) in resolved_library_dirs.iter().enumerate() {
-                    if n > 0 {
-                        ld_library_path.push(b':');
-                    }
-
-                    let path =
-                        <[u8]>::from_path(library_dir).ok_or_else(|| PackedError::InvalidPath {
-                            path: library_dir.to_owned(),
-                        })?;
-                    ld_library_path.extend(path);
-                }
-
-                if let Some(env_library_path) = std::env::var_os("LD_LIBRARY_PATH") {
-                    let env_library_path =
-                        <[u8]>::from_os_str(&env_library_path).ok_or_else(|| {
-                            PackedError::InvalidPathOsString {
-                                path: env_library_path.clone(),
-                            }
-                        })?;
-                    if !env_library_path.is_empty() {
-                        ld_library_path.push(b':');
-                        ld_library_path.extend(env_library_path);
-                    }
-                }
-
+                let ld_library_path = ld_library_path(&resolved_library_dirs)?;
                 command.arg("--library-path");
-
-                let ld_library_path =
-                    ld_library_path
-                        .to_os_str()
-                        .map_err(|_| PackedError::InvalidPathBytes {
-                            path: ld_library_path.clone(),
-                        })?;
                 command.arg(ld_library_path);
             }
 
@@ -117,160 +131,175 @@ fn run() -> Result<(), PackedError> {
                 command.arg(arg0);
             }
 
-            let program = program
-                .to_path()
-                .map_err(|_| PackedError::InvalidPathBytes {
-                    path: program.clone().into(),
-                })?;
-            let program = brioche_resources::find_in_resource_dirs(&resource_dirs, program)
-                .ok_or_else(|| PackedError::ResourceNotFound {
-                    resource: program.to_owned(),
-                })?;
-            let program = program.canonicalize()?;
+            let program = resolve_resource(&resolver, &program)?.canonical_path;
+            let program = resolver.materialize(&program)?;
+            debug_log!("program to run: {}", program.display());
             command.arg(program);
 
             command.args(args);
 
-            let error = command.exec();
-            Err(PackedError::IoError(error))
+            command
         }
-        brioche_pack::Pack::Static { .. } => {
-            unimplemented!("execution of a static executable");
+        brioche_pack::Pack::Static { library_dirs } => {
+            debug_log!("pack: static");
+            let mut args = args.into_iter();
+
+            let mut resolved_library_dirs = vec![];
+            for library_dir in &library_dirs {
+                let library_dir = resolve_resource(&resolver, library_dir)?.path;
+                debug_log!("library dir: {}", library_dir.display());
+                resolved_library_dirs.push(library_dir);
+            }
+
+            // The program is embedded between this launcher and the pack.
+            // Copy it into an in-memory file so it can be run on its own
+            let program_fd =
+                brioche_packed_common::embedded_program_fd(&program_path, extracted.unpacked_len)?;
+            let mut command =
+                std::process::Command::new(format!("/proc/self/fd/{}", program_fd.as_raw_fd()));
+
+            if let Some(arg0) = args.next() {
+                command.arg0(arg0);
+            }
+            command.args(args);
+
+            if !resolved_library_dirs.is_empty() {
+                let ld_library_path = ld_library_path(&resolved_library_dirs)?;
+                command.env("LD_LIBRARY_PATH", ld_library_path);
+            }
+
+            _static_program_fd = Some(program_fd);
+            command
         }
         brioche_pack::Pack::Metadata {
             resource_paths: _,
             format,
             metadata,
-        } => match &*format {
-            runnable_core::FORMAT => {
-                let runnable: runnable_core::Runnable = serde_json::from_slice(&metadata)?;
-
-                let program = runnable
-                    .command
-                    .to_os_string(&program_path, &resource_dirs)?;
-
-                let mut command = std::process::Command::new(program);
-                let mut original_args = Some(std::env::args_os().skip(1));
-
-                for arg in &runnable.args {
-                    match arg {
-                        runnable_core::ArgValue::Arg { value } => {
-                            let value = value.to_os_string(&program_path, &resource_dirs)?;
-                            command.arg(value);
-                        }
-                        runnable_core::ArgValue::Rest => {
-                            let original_args =
-                                original_args.take().ok_or(PackedError::RepeatedArgs)?;
-                            command.args(original_args);
-                        }
-                    }
-                }
-
-                if runnable.clear_env {
-                    command.env_clear();
-                }
-
-                for (env_name, env_value) in &runnable.env {
-                    match env_value {
-                        runnable_core::EnvValue::Clear => {
-                            command.env_remove(env_name);
-                        }
-                        runnable_core::EnvValue::Inherit => {
-                            let value = std::env::var_os(env_name);
-                            if let Some(value) = value {
-                                command.env(env_name, value);
-                            }
-                        }
-                        runnable_core::EnvValue::Set { value } => {
-                            let value = value.to_os_string(&program_path, &resource_dirs)?;
-                            command.env(env_name, value);
-                        }
-                        runnable_core::EnvValue::Fallback { value } => {
-                            let current_value = std::env::var_os(env_name);
-                            let current_value = current_value.filter(|value| !value.is_empty());
-                            let value = match current_value {
-                                Some(current_value) => current_value,
-                                None => value.to_os_string(&program_path, &resource_dirs)?,
-                            };
-                            command.env(env_name, value);
-                        }
-                        runnable_core::EnvValue::Prepend { value, separator } => {
-                            let mut value = value.to_os_string(&program_path, &resource_dirs)?;
-                            let separator =
-                                separator
-                                    .to_os_str()
-                                    .map_err(|_| PackedError::InvalidUtf8 {
-                                        bytes: separator.clone().into(),
-                                    })?;
-
-                            let current_value = std::env::var_os(env_name);
-                            let new_value = match current_value {
-                                Some(current_value) if !current_value.is_empty() => {
-                                    value.push(separator);
-                                    value.push(current_value);
-
-                                    value
-                                }
-                                _ => value,
-                            };
-                            command.env(env_name, new_value);
-                        }
-                        runnable_core::EnvValue::Append { value, separator } => {
-                            let value = value.to_os_string(&program_path, &resource_dirs)?;
-                            let separator =
-                                separator
-                                    .to_os_str()
-                                    .map_err(|_| PackedError::InvalidUtf8 {
-                                        bytes: separator.clone().into(),
-                                    })?;
-
-                            let current_value = std::env::var_os(env_name);
-                            let new_value = match current_value {
-                                Some(mut current_value) if !current_value.is_empty() => {
-                                    current_value.push(separator);
-                                    current_value.push(value);
-
-                                    current_value
-                                }
-                                _ => value,
-                            };
-                            command.env(env_name, new_value);
-                        }
-                    }
-                }
-
-                let error = command.exec();
-                Err(PackedError::IoError(error))
-            }
-            _ => {
-                unimplemented!("unknown metdata format {format:?}");
+        } => {
+            // Unknown formats and runnables from a newer version are
+            // rejected when loading
+            debug_log!("pack: metadata ({format})");
+            let mut runnable = runnable_core::load_runnable(&format, &metadata, &resource_dirs)?;
+            let platform = runnable_core::current_platform();
+            debug_log!("platform: {platform}");
+            runnable.select_platform(&platform);
+            runnable.prefetch_resources(&resource_dirs);
+
+            let command = runnable_exec::build_command_with_args(
+                &runnable,
+                &program_path,
+                &resource_dirs,
+                args.get(1..).unwrap_or_default(),
+            )?;
+
+            // When only printing the command, nothing else should run
+            if introspect.is_none() {
+                runnable_exec::apply_process_settings(&runnable)?;
+                runnable_exec::run_pre_commands_with_args(
+                    &runnable,
+                    &command,
+                    &program_path,
+                    &resource_dirs,
+                    args.get(1..).unwrap_or_default(),
+                )?;
             }
-        },
+
+            command
+        }
+    };
+
+    if introspect == Some(Introspect::PrintCommand) {
+        println!("{command:?}");
+        return Ok(());
+    }
+
+    debug_log!("command: {command:?}");
+    let error = command.exec();
+    Err(PackedError::IoError(error))
+}
+
+/// Reserved flags that print information about the packed program instead
+/// of running it. These are only recognized as the first argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Introspect {
+    /// `--brioche-pack-info`: print the extracted pack as JSON.
+    PackInfo,
+
+    /// `--brioche-print-command`: print the fully resolved command that
+    /// would run, using the remaining args.
+    PrintCommand,
+
+    /// `--brioche-resource-dirs`: print the resource dirs that resources
+    /// are found in, in search order.
+    ResourceDirs,
+}
+
+impl Introspect {
+    fn from_arg(arg: &std::ffi::OsStr) -> Option<Self> {
+        match arg.to_str()? {
+            "--brioche-pack-info" => Some(Self::PackInfo),
+            "--brioche-print-command" => Some(Self::PrintCommand),
+            "--brioche-resource-dirs" => Some(Self::ResourceDirs),
+            _ => None,
+        }
     }
 }
 
+/// Resolve a resource from the pack, checking that it stays inside the
+/// resource dirs.
+fn resolve_resource(
+    resolver: &brioche_resources::ResourceResolver,
+    resource: &[u8],
+) -> Result<brioche_resources::ResolvedResource, PackedError> {
+    let subpath = resource
+        .to_path()
+        .map_err(|_| PackedError::InvalidPathBytes {
+            path: resource.into(),
+        })?;
+    let resolved = resolver.resolve(subpath)?;
+    debug_log!(
+        "resolved {} from resource dir {}",
+        subpath.display(),
+        resolved.resource_dir.display()
+    );
+    Ok(resolved)
+}
+
+/// Join library dirs into a value for `$LD_LIBRARY_PATH`, followed by the
+/// current value of `$LD_LIBRARY_PATH` if it's set.
+fn ld_library_path(library_dirs: &[PathBuf]) -> Result<OsString, PackedError> {
+    let ld_library_path = brioche_packed_common::ld_library_path(library_dirs)?;
+    let ld_library_path =
+        ld_library_path
+            .to_os_str()
+            .map_err(|_| PackedError::InvalidPathBytes {
+                path: ld_library_path.clone(),
+            })?;
+    Ok(ld_library_path.to_owned())
+}
+
 #[derive(Debug, thiserror::Error)]
 enum PackedError {
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
-    SerdeJsonError(#[from] serde_json::Error),
-    #[error(transparent)]
     ExtractPackError(#[from] brioche_pack::ExtractPackError),
     #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error(transparent)]
     PackResourceDirError(#[from] brioche_resources::PackResourceDirError),
     #[error(transparent)]
-    RunnableTemplateError(#[from] runnable_core::RunnableTemplateError),
-    #[error("tried to pass remaining arguments more than once")]
-    RepeatedArgs,
-    #[error("resource not found: {resource}")]
-    ResourceNotFound { resource: PathBuf },
-    #[error("invalid UTF-8: {bytes:?}")]
-    InvalidUtf8 { bytes: bstr::BString },
+    LoadRunnableError(#[from] runnable_core::LoadRunnableError),
+    #[error(transparent)]
+    RunnableExecError(#[from] runnable_exec::RunnableExecError),
+    #[error(transparent)]
+    ResolveResourceError(#[from] brioche_resources::ResolveResourceError),
+    #[error(transparent)]
+    LdLibraryPathError(#[from] brioche_packed_common::LdLibraryPathError),
+    #[error(transparent)]
+    EmbeddedProgramError(#[from] brioche_packed_common::EmbeddedProgramError),
     #[error("invalid path: {path:?}")]
     InvalidPathBytes { path: bstr::BString },
     #[error("invalid path: {path:?}")]
     InvalidPath { path: PathBuf },
-    #[error("unconvertable path: {path:?}")]
-    InvalidPathOsString { path: OsString },
 }
@@ -1,7 +1,14 @@
 #![cfg(target_os = "linux")]
 
-use std::ffi::{CStr, CString};
-
+use std::{
+    ffi::{CStr, CString, OsStr, OsString},
+    io::Read as _,
+    os::{fd::AsRawFd as _, unix::ffi::OsStrExt as _},
+    path::Path,
+    process::Command,
+};
+
+use brioche_packed_common::{debug, debug_log};
 use bstr::ByteSlice as _;
 
 const BRIOCHE_PACKED_ERROR: u8 = 121;
@@ -50,9 +57,23 @@ pub unsafe fn entrypoint(argc: libc::c_int, argv: *const *const libc::c_char) ->
 }
 
 fn run(args: &[&CStr], env_vars: &[&CStr]) -> Result<(), PackedError> {
+    debug::init();
+
     let path = std::env::current_exe()?;
+    debug_log!("program: {}", path.display());
     let parent_path = path.parent().ok_or(PackedError::InvalidPath)?;
     let resource_dirs = brioche_resources::find_resource_dirs(&path, true)?;
+    for resource_dir in &resource_dirs {
+        debug_log!("resource dir: {}", resource_dir.display());
+    }
+    let resolve_options = brioche_resources::ResolveOptions {
+        secure: brioche_packed_common::is_secure_mode(),
+    };
+    if resolve_options.secure {
+        debug_log!("secure mode enabled");
+    }
+    let resolver = brioche_resources::ResourceResolver::new(&resource_dirs, resolve_options);
+
     let mut program = std::fs::File::open(&path)?;
     let extracted = brioche_pack::extract_pack(&mut program)?;
 
@@ -63,16 +84,15 @@ fn run(args: &[&CStr], env_vars: &[&CStr]) -> Result<(), PackedError> {
             library_dirs,
             runtime_library_dirs,
         } => {
-            let interpreter = interpreter
-                .to_path()
-                .map_err(|_| PackedError::InvalidPath)?;
-            let interpreter = brioche_resources::find_in_resource_dirs(&resource_dirs, interpreter)
-                .ok_or(PackedError::ResourceNotFound)?;
-
-            let program = program.to_path().map_err(|_| PackedError::InvalidPath)?;
-            let program = brioche_resources::find_in_resource_dirs(&resource_dirs, program)
-                .ok_or(PackedError::ResourceNotFound)?;
-            let program = program.canonicalize()?;
+            debug_log!("pack: ld-linux");
+            // Compressed blobs are decompressed to a cache dir first
+            let interpreter = resolve_resource(&resolver, &interpreter)?.path;
+            let interpreter = resolver.materialize(&interpreter)?;
+            debug_log!("interpreter: {}", interpreter.display());
+
+            let program = resolve_resource(&resolver, &program)?.canonical_path;
+            let program = resolver.materialize(&program)?;
+            debug_log!("program to run: {}", program.display());
             let mut exec = userland_execve::ExecOptions::new(&interpreter);
 
             let interpreter = <[u8]>::from_path(&interpreter).ok_or(PackedError::InvalidPath)?;
@@ -85,16 +105,13 @@ fn run(args: &[&CStr], env_vars: &[&CStr]) -> Result<(), PackedError> {
                     .to_path()
                     .map_err(|_| PackedError::InvalidPath)?;
                 let resolved_library_dir = parent_path.join(library_dir);
+                debug_log!("runtime library dir: {}", resolved_library_dir.display());
                 resolved_library_dirs.push(resolved_library_dir);
             }
 
             for library_dir in &library_dirs {
-                let library_dir = library_dir
-                    .to_path()
-                    .map_err(|_| PackedError::InvalidPath)?;
-                let library_dir =
-                    brioche_resources::find_in_resource_dirs(&resource_dirs, library_dir)
-                        .ok_or(PackedError::ResourceNotFound)?;
+                let library_dir = resolve_resource(&resolver, library_dir)?.path;
+                debug_log!("library dir: {}", library_dir.display());
                 resolved_library_dirs.push(library_dir);
             }
 
@@ -102,24 +119,8 @@ fn run(args: &[&CStr], env_vars: &[&CStr]) -> Result<(), PackedError> {
             exec.arg(interpreter);
 
             if !resolved_library_dirs.is_empty() {
-                let mut ld_library_path = bstr::BString::default();
-                for (n, library_dir) in resolved_library_dirs.iter().enumerate() {
-                    if n > 0 {
-                        ld_library_path.push(b':');
-                    }
-
-                    let path = <[u8]>::from_path(library_dir).ok_or(PackedError::InvalidPath)?;
-                    ld_library_path.extend(path);
-                }
-
-                if let Some(env_library_path) = std::env::var_os("LD_LIBRARY_PATH") {
-                    let env_library_path =
-                        <[u8]>::from_os_str(&env_library_path).ok_or(PackedError::InvalidPath)?;
-                    if !env_library_path.is_empty() {
-                        ld_library_path.push(b':');
-                        ld_library_path.extend(env_library_path);
-                    }
-                }
+                let ld_library_path =
+                    brioche_packed_common::ld_library_path(&resolved_library_dirs)?;
 
                 exec.arg(c"--library-path");
 
@@ -142,15 +143,240 @@ fn run(args: &[&CStr], env_vars: &[&CStr]) -> Result<(), PackedError> {
 
             exec.env_pairs(env_vars);
 
+            debug_log!("exec: {} {:?}", exec.executable.display(), exec.args);
             userland_execve::exec_with_options(exec);
         }
-        brioche_pack::Pack::Static { .. } => {
-            unimplemented!("execution of a static executable");
+        brioche_pack::Pack::Static { library_dirs } => {
+            debug_log!("pack: static");
+            let mut resolved_library_dirs = vec![];
+            for library_dir in &library_dirs {
+                let library_dir = resolve_resource(&resolver, library_dir)?.path;
+                debug_log!("library dir: {}", library_dir.display());
+                resolved_library_dirs.push(library_dir);
+            }
+
+            // The program is embedded between this launcher and the pack.
+            // Copy it into an in-memory file so it can be run on its own
+            let program_file =
+                brioche_packed_common::embedded_program_fd(&path, extracted.unpacked_len)?;
+            let program_path = format!("/proc/self/fd/{}", program_file.as_raw_fd());
+
+            let mut program_env_vars = vec![];
+            if resolved_library_dirs.is_empty() {
+                program_env_vars.extend(env_vars.iter().map(|&env_var| env_var.to_owned()));
+            } else {
+                let library_path = brioche_packed_common::ld_library_path(&resolved_library_dirs)?;
+                let mut ld_library_path = bstr::BString::from("LD_LIBRARY_PATH=");
+                ld_library_path.extend_from_slice(&library_path);
+                debug_log!("env: {ld_library_path}");
+                let ld_library_path =
+                    CString::new(ld_library_path).map_err(|_| PackedError::InvalidPath)?;
+
+                program_env_vars.extend(
+                    env_vars
+                        .iter()
+                        .filter(|env_var| !env_var.to_bytes().starts_with(b"LD_LIBRARY_PATH="))
+                        .map(|&env_var| env_var.to_owned()),
+                );
+                program_env_vars.push(ld_library_path);
+            }
+
+            // Userland exec can't load non-PIE programs, and static PIE
+            // programs crash while relocating themselves, so static
+            // programs are always run with a normal exec
+            exec_static_program_with_execve(&program_path, args, &program_env_vars)
+        }
+        brioche_pack::Pack::Metadata {
+            resource_paths: _,
+            format,
+            metadata,
+        } => {
+            // Unknown formats and runnables from a newer version are
+            // rejected when loading
+            debug_log!("pack: metadata ({format})");
+            let mut runnable = runnable_core::load_runnable(&format, &metadata, &resource_dirs)?;
+            let platform = runnable_core::current_platform();
+            debug_log!("platform: {platform}");
+            runnable.select_platform(&platform);
+            runnable.prefetch_resources(&resource_dirs);
+
+            // The args come from argv, since the Rust runtime doesn't
+            // collect them for us
+            let remaining_args = args
+                .iter()
+                .skip(1)
+                .map(|arg| OsStr::from_bytes(arg.to_bytes()).to_owned())
+                .collect::<Vec<_>>();
+            let command = runnable_exec::build_command_with_args(
+                &runnable,
+                &path,
+                &resource_dirs,
+                &remaining_args,
+            )?;
+
+            runnable_exec::apply_process_settings(&runnable)?;
+            runnable_exec::run_pre_commands_with_args(
+                &runnable,
+                &command,
+                &path,
+                &resource_dirs,
+                &remaining_args,
+            )?;
+
+            debug_log!("command: {command:?}");
+            exec_command(&command, runnable.clear_env, env_vars)
+        }
+    }
+}
+
+/// Run a runnable's command in place of the current process. PIE
+/// executables are loaded with userland exec like ld-linux packs; anything
+/// else (scripts, non-PIE programs, or commands looked up from `$PATH`)
+/// falls back to a normal exec.
+fn exec_command(command: &Command, clear_env: bool, env_vars: &[&CStr]) -> Result<(), PackedError> {
+    let program = Path::new(command.get_program());
+    let is_path = program.as_os_str().as_bytes().contains(&b'/');
+    if !is_path || !is_pie_executable(program)? {
+        return exec_command_with_execve(command, clear_env, env_vars);
+    }
+
+    if let Some(cwd) = command.get_current_dir() {
+        std::env::set_current_dir(cwd)?;
+    }
+
+    let mut exec = userland_execve::ExecOptions::new(program);
+
+    // Add argv0
+    exec.arg(os_str_to_cstring(program.as_os_str())?);
+    for arg in command.get_args() {
+        exec.arg(os_str_to_cstring(arg)?);
+    }
+
+    // Apply the command's env vars on top of the inherited ones, the
+    // same way `Command` does
+    let mut envs: Vec<(OsString, OsString)> = vec![];
+    if !clear_env {
+        for env_var in env_vars {
+            let env_var = env_var.to_bytes();
+            if let Some((name, value)) = env_var.split_once_str(b"=") {
+                envs.push((
+                    OsStr::from_bytes(name).into(),
+                    OsStr::from_bytes(value).into(),
+                ));
+            }
         }
-        brioche_pack::Pack::Metadata { .. } => {
-            unimplemented!("execution of a metadata pack");
+    }
+    for (name, value) in command.get_envs() {
+        envs.retain(|(existing_name, _)| existing_name != name);
+        if let Some(value) = value {
+            envs.push((name.into(), value.into()));
         }
     }
+    for (name, value) in envs {
+        exec.env(os_str_to_cstring(&name)?, os_str_to_cstring(&value)?);
+    }
+
+    debug_log!("exec: {} {:?}", exec.executable.display(), exec.args);
+    userland_execve::exec_with_options(exec);
+}
+
+fn exec_command_with_execve(
+    command: &Command,
+    clear_env: bool,
+    env_vars: &[&CStr],
+) -> Result<(), PackedError> {
+    use std::os::unix::process::CommandExt as _;
+
+    let mut exec = Command::new(command.get_program());
+    exec.args(command.get_args());
+    if let Some(cwd) = command.get_current_dir() {
+        exec.current_dir(cwd);
+    }
+
+    // Start from the env vars passed to this process, since the Rust
+    // runtime doesn't track them for us
+    exec.env_clear();
+    if !clear_env {
+        for env_var in env_vars {
+            let env_var = env_var.to_bytes();
+            if let Some((name, value)) = env_var.split_once_str(b"=") {
+                exec.env(OsStr::from_bytes(name), OsStr::from_bytes(value));
+            }
+        }
+    }
+    for (name, value) in command.get_envs() {
+        match value {
+            Some(value) => exec.env(name, value),
+            None => exec.env_remove(name),
+        };
+    }
+
+    debug_log!("exec: {exec:?}");
+    let error = exec.exec();
+    Err(PackedError::IoError(error))
+}
+
+fn exec_static_program_with_execve(
+    program_path: &str,
+    args: &[&CStr],
+    env_vars: &[CString],
+) -> Result<(), PackedError> {
+    use std::os::unix::process::CommandExt as _;
+
+    let mut exec = Command::new(program_path);
+    let mut args = args.iter();
+    if let Some(arg0) = args.next() {
+        exec.arg0(OsStr::from_bytes(arg0.to_bytes()));
+    }
+    exec.args(args.map(|arg| OsStr::from_bytes(arg.to_bytes())));
+
+    exec.env_clear();
+    for env_var in env_vars {
+        let env_var = env_var.to_bytes();
+        if let Some((name, value)) = env_var.split_once_str(b"=") {
+            exec.env(OsStr::from_bytes(name), OsStr::from_bytes(value));
+        }
+    }
+
+    debug_log!("exec: {exec:?}");
+    let error = exec.exec();
+    Err(PackedError::IoError(error))
+}
+
+/// Check if `path` is a position-independent ELF executable, which is
+/// what userland exec can load.
+fn is_pie_executable(path: &Path) -> Result<bool, PackedError> {
+    let mut header = [0; 18];
+    let mut file = std::fs::File::open(path)?;
+    if file.read_exact(&mut header).is_err() {
+        return Ok(false);
+    }
+
+    let is_elf = header.starts_with(b"\x7fELF");
+    let e_type = u16::from_ne_bytes([header[16], header[17]]);
+    Ok(is_elf && e_type == ET_DYN)
+}
+
+const ET_DYN: u16 = 3;
+
+fn os_str_to_cstring(value: &OsStr) -> Result<CString, PackedError> {
+    CString::new(value.as_bytes()).map_err(|_| PackedError::InvalidPath)
+}
+
+/// Resolve a resource from the pack, checking that it stays inside the
+/// resource dirs.
+fn resolve_resource(
+    resolver: &brioche_resources::ResourceResolver,
+    resource: &[u8],
+) -> Result<brioche_resources::ResolvedResource, PackedError> {
+    let subpath = resource.to_path().map_err(|_| PackedError::InvalidPath)?;
+    let resolved = resolver.resolve(subpath)?;
+    debug_log!(
+        "resolved {} from resource dir {}",
+        subpath.display(),
+        resolved.resource_dir.display()
+    );
+    Ok(resolved)
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -158,13 +384,25 @@ enum PackedError {
     IoError(#[from] std::io::Error),
     ExtractPackError(#[from] brioche_pack::ExtractPackError),
     PackResourceDirError(#[from] brioche_resources::PackResourceDirError),
+    ResolveResourceError(#[from] brioche_resources::ResolveResourceError),
+    LdLibraryPathError(#[from] brioche_packed_common::LdLibraryPathError),
+    LoadRunnableError(#[from] runnable_core::LoadRunnableError),
+    RunnableExecError(#[from] runnable_exec::RunnableExecError),
+    EmbeddedProgramError(#[from] brioche_packed_common::EmbeddedProgramError),
     InvalidPath,
-    ResourceNotFound,
 }
 
 impl std::fmt::Display for PackedError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(error_summary(self))
+        match self {
+            // Resolution errors name the resource dir involved, and
+            // runnable errors name the template or pre-command that failed,
+            // so show the full message instead of just the summary
+            PackedError::ResolveResourceError(error) => write!(f, "{error}"),
+            PackedError::LoadRunnableError(error) => write!(f, "failed to load runnable: {error}"),
+            PackedError::RunnableExecError(error) => write!(f, "{error}"),
+            error => f.write_str(error_summary(error)),
+        }
     }
 }
 
@@ -194,8 +432,35 @@ fn error_summary(error: &PackedError) -> &'static str {
             brioche_resources::PackResourceDirError::IoError(_) => {
                 "error while searching for brioche pack resource dir: io error"
             }
+            brioche_resources::PackResourceDirError::LayoutVersionError(_) => {
+                "brioche pack resource dir uses an unsupported layout version"
+            }
+            brioche_resources::PackResourceDirError::ManifestError(_) => {
+                "failed to load brioche resource manifest"
+            }
+        },
+        PackedError::ResolveResourceError(_) => "failed to resolve resource",
+        PackedError::LdLibraryPathError(error) => match error {
+            brioche_packed_common::LdLibraryPathError::InvalidLibraryDir { .. } => {
+                "invalid library dir path"
+            }
+            brioche_packed_common::LdLibraryPathError::InvalidEnvValue { .. } => {
+                "invalid $LD_LIBRARY_PATH value"
+            }
+        },
+        PackedError::LoadRunnableError(_) => "failed to load runnable",
+        PackedError::RunnableExecError(_) => "failed to run runnable",
+        PackedError::EmbeddedProgramError(error) => match error {
+            brioche_packed_common::EmbeddedProgramError::NoEmbeddedProgram => {
+                "static pack has no embedded program, run the static program directly"
+            }
+            brioche_packed_common::EmbeddedProgramError::UnsupportedLauncher => {
+                "launcher is not a 64-bit little-endian ELF file"
+            }
+            brioche_packed_common::EmbeddedProgramError::IoError(_) => {
+                "failed to copy embedded program: io error"
+            }
         },
         PackedError::InvalidPath => "invalid path",
-        PackedError::ResourceNotFound => "resource not found",
     }
 }
@@ -31,6 +31,10 @@ pub struct AutopackConfigTemplate {
     #[serde(default)]
     exclude_globs: Vec<String>,
 
+    /// Follow symlinks when matching globs, packing each target once.
+    #[serde(default)]
+    follow_symlinks: bool,
+
     #[serde(default)]
     quiet: bool,
 
@@ -42,11 +46,108 @@ pub struct AutopackConfigTemplate {
 
     dynamic_binary: Option<DynamicBinaryConfigTemplate>,
 
+    loader_binary: Option<LoaderBinaryConfigTemplate>,
+
     shared_library: Option<SharedLibraryConfigTemplate>,
 
+    windows_binary: Option<WindowsBinaryConfigTemplate>,
+
     script: Option<ScriptConfigTemplate>,
 
+    wasm: Option<WasmConfigTemplate>,
+
     repack: Option<RepackConfigTemplate>,
+
+    concurrency: Option<std::num::NonZeroUsize>,
+
+    #[serde(default)]
+    path_overrides: Vec<PathConfigOverrideTemplate>,
+
+    /// Only pack ELF files for this target triple's architecture.
+    target: Option<String>,
+
+    #[serde(default)]
+    privileged_files: PrivilegedFilePolicyTemplate,
+
+    /// How to add files to the resource dir: by copying them, or with hard
+    /// links or reflinks when possible.
+    #[serde(default)]
+    blob_link_mode: BlobLinkModeTemplate,
+
+    /// Skip files matching any of these content signatures.
+    #[serde(default)]
+    skip_signatures: Vec<ContentSignatureTemplate>,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+enum PrivilegedFilePolicyTemplate {
+    #[default]
+    WrapAndWarn,
+    Skip,
+    Error,
+}
+
+impl PrivilegedFilePolicyTemplate {
+    fn build(self) -> brioche_autopack::PrivilegedFilePolicy {
+        match self {
+            Self::WrapAndWarn => brioche_autopack::PrivilegedFilePolicy::WrapAndWarn,
+            Self::Skip => brioche_autopack::PrivilegedFilePolicy::Skip,
+            Self::Error => brioche_autopack::PrivilegedFilePolicy::Error,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+enum BlobLinkModeTemplate {
+    #[default]
+    Copy,
+    HardLink,
+    Reflink,
+}
+
+impl BlobLinkModeTemplate {
+    fn build(self) -> brioche_resources::BlobLinkMode {
+        match self {
+            Self::Copy => brioche_resources::BlobLinkMode::Copy,
+            Self::HardLink => brioche_resources::BlobLinkMode::HardLink,
+            Self::Reflink => brioche_resources::BlobLinkMode::Reflink,
+        }
+    }
+}
+
+#[serde_with::serde_as]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct ContentSignatureTemplate {
+    name: String,
+
+    #[serde_as(as = "Option<TickEncoded>")]
+    #[serde(default)]
+    prefix: Option<Vec<u8>>,
+
+    #[serde(default)]
+    elf_sections: Vec<String>,
+}
+
+impl ContentSignatureTemplate {
+    fn build(self) -> brioche_autopack::ContentSignature {
+        let Self {
+            name,
+            prefix,
+            elf_sections,
+        } = self;
+        brioche_autopack::ContentSignature {
+            name,
+            prefix,
+            elf_sections,
+        }
+    }
 }
 
 impl AutopackConfigTemplate {
@@ -54,18 +155,29 @@ impl AutopackConfigTemplate {
         self,
         ctx: &AutopackConfigTemplateContext,
         recipe_path: PathBuf,
+        dry_run: bool,
     ) -> eyre::Result<brioche_autopack::AutopackConfig> {
         let Self {
             paths,
             globs,
             exclude_globs,
+            follow_symlinks,
             quiet,
             link_dependencies,
             self_dependency,
             dynamic_binary,
+            loader_binary,
             shared_library,
+            windows_binary,
             script,
+            wasm,
             repack,
+            concurrency,
+            path_overrides,
+            target,
+            privileged_files,
+            blob_link_mode,
+            skip_signatures,
         } = self;
 
         let paths = paths
@@ -79,11 +191,23 @@ impl AutopackConfigTemplate {
         let dynamic_binary = dynamic_binary
             .map(|opts| opts.build(ctx, &recipe_path))
             .transpose()?;
+        let loader_binary = loader_binary
+            .map(|opts| opts.build(ctx, &recipe_path))
+            .transpose()?;
         let shared_library = shared_library.map(|opts| opts.build(ctx)).transpose()?;
+        let windows_binary = windows_binary.map(|opts| opts.build(ctx)).transpose()?;
         let script = script
             .map(|opts| opts.build(ctx, &recipe_path))
             .transpose()?;
+        let wasm = wasm.map(|opts| opts.build(ctx)).transpose()?;
         let repack = repack.map(|opts| opts.build());
+        let path_overrides = path_overrides
+            .into_iter()
+            .map(|path_override| path_override.build(ctx))
+            .collect::<eyre::Result<_>>()?;
+        let target_arch = target
+            .map(|target| brioche_autopack::ElfArch::from_target_triple(&target))
+            .transpose()?;
 
         if self_dependency {
             link_dependencies.insert(0, recipe_path.clone());
@@ -94,6 +218,7 @@ impl AutopackConfigTemplate {
                 exclude_globs.is_empty(),
                 "cannot exclude glob patterns with only paths"
             );
+            eyre::ensure!(!follow_symlinks, "cannot follow symlinks with only paths");
             let paths = paths
                 .into_iter()
                 .map(|path| recipe_path.join(path))
@@ -104,6 +229,7 @@ impl AutopackConfigTemplate {
             brioche_autopack::AutopackInputs::Globs {
                 patterns: globs,
                 exclude_patterns: exclude_globs,
+                follow_symlinks,
                 base_path: recipe_path.clone(),
             }
         };
@@ -115,16 +241,87 @@ impl AutopackConfigTemplate {
         let resource_dir = brioche_resources::find_output_resource_dir(&program)?;
         let all_resource_dirs = brioche_resources::find_resource_dirs(&program, true)?;
 
+        // Dry runs only print warnings, since the results list what would
+        // be packed
+        let progress = brioche_autopack::StdoutProgressReporter {
+            warnings_only: quiet || dry_run,
+        };
+        let progress = std::sync::Arc::new(progress);
+
         Ok(brioche_autopack::AutopackConfig {
             resource_dir,
             all_resource_dirs,
             inputs,
-            quiet,
+            progress,
             link_dependencies,
             dynamic_binary,
+            loader_binary,
             shared_library,
+            windows_binary,
             script,
+            wasm,
             repack,
+            concurrency,
+            dry_run,
+            report_path: None,
+            manifest_path: None,
+            path_overrides,
+            target_arch,
+            privileged_files: privileged_files.build(),
+            blob_link_mode: blob_link_mode.build(),
+            skip_signatures: skip_signatures
+                .into_iter()
+                .map(ContentSignatureTemplate::build)
+                .collect(),
+            object_inspectors: vec![],
+            hooks: vec![],
+            state_file: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct PathConfigOverrideTemplate {
+    glob: String,
+
+    #[serde(default)]
+    skip_libraries: HashSet<String>,
+
+    skip_unknown_libraries: Option<bool>,
+
+    packed_executable: Option<TemplatePath>,
+
+    #[serde(default)]
+    script_interpreters: HashMap<String, String>,
+
+    #[serde(default)]
+    script_interpreter_args: HashMap<String, Vec<String>>,
+}
+
+impl PathConfigOverrideTemplate {
+    fn build(
+        self,
+        ctx: &AutopackConfigTemplateContext,
+    ) -> eyre::Result<brioche_autopack::PathConfigOverride> {
+        let Self {
+            glob,
+            skip_libraries,
+            skip_unknown_libraries,
+            packed_executable,
+            script_interpreters,
+            script_interpreter_args,
+        } = self;
+
+        let packed_executable = packed_executable.map(|path| path.build(ctx)).transpose()?;
+
+        Ok(brioche_autopack::PathConfigOverride {
+            glob,
+            skip_libraries,
+            skip_unknown_libraries,
+            packed_executable,
+            script_interpreters,
+            script_interpreter_args,
         })
     }
 }
@@ -138,11 +335,53 @@ struct DynamicLinkingConfigTemplate {
     #[serde(default)]
     skip_libraries: HashSet<String>,
 
+    /// Skip libraries with names matching any of these globs.
+    #[serde(default)]
+    skip_library_globs: Vec<String>,
+
+    /// Only include libraries with names matching one of these globs.
+    #[serde(default)]
+    only_library_globs: Vec<String>,
+
     #[serde(default)]
     extra_libraries: Vec<String>,
 
     #[serde(default)]
     skip_unknown_libraries: bool,
+
+    #[serde(default)]
+    resolve_nix_store_paths: bool,
+
+    #[serde(default)]
+    resolve_rpaths: bool,
+
+    #[serde(default)]
+    symbol_version_check: SymbolVersionCheckTemplate,
+
+    /// Add each library's SONAME symlinks to its library dir.
+    #[serde(default)]
+    preserve_symlink_chains: bool,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+enum SymbolVersionCheckTemplate {
+    #[default]
+    Disabled,
+    Warn,
+    Error,
+}
+
+impl SymbolVersionCheckTemplate {
+    fn build(self) -> brioche_autopack::SymbolVersionCheck {
+        match self {
+            Self::Disabled => brioche_autopack::SymbolVersionCheck::Disabled,
+            Self::Warn => brioche_autopack::SymbolVersionCheck::Warn,
+            Self::Error => brioche_autopack::SymbolVersionCheck::Error,
+        }
+    }
 }
 
 impl DynamicLinkingConfigTemplate {
@@ -153,8 +392,14 @@ impl DynamicLinkingConfigTemplate {
         let Self {
             library_paths,
             skip_libraries,
+            skip_library_globs,
+            only_library_globs,
             extra_libraries,
             skip_unknown_libraries,
+            resolve_nix_store_paths,
+            resolve_rpaths,
+            symbol_version_check,
+            preserve_symlink_chains,
         } = self;
 
         let library_paths = library_paths
@@ -165,8 +410,14 @@ impl DynamicLinkingConfigTemplate {
         Ok(brioche_autopack::DynamicLinkingConfig {
             library_paths,
             skip_libraries,
+            skip_library_globs,
+            only_library_globs,
             extra_libraries,
             skip_unknown_libraries,
+            resolve_nix_store_paths,
+            resolve_rpaths,
+            symbol_version_check: symbol_version_check.build(),
+            preserve_symlink_chains,
         })
     }
 }
@@ -181,6 +432,30 @@ pub struct DynamicBinaryConfigTemplate {
 
     #[serde(flatten)]
     dynamic_linking: DynamicLinkingConfigTemplate,
+
+    #[serde(default)]
+    mode: DynamicBinaryModeTemplate,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+enum DynamicBinaryModeTemplate {
+    #[default]
+    Pack,
+    #[serde(rename_all = "camelCase")]
+    RewriteInPlace { interpreter: Option<PathBuf> },
+}
+
+impl DynamicBinaryModeTemplate {
+    fn build(self) -> brioche_autopack::DynamicBinaryMode {
+        match self {
+            Self::Pack => brioche_autopack::DynamicBinaryMode::Pack,
+            Self::RewriteInPlace { interpreter } => {
+                brioche_autopack::DynamicBinaryMode::RewriteInPlace { interpreter }
+            }
+        }
+    }
 }
 
 impl DynamicBinaryConfigTemplate {
@@ -193,31 +468,83 @@ impl DynamicBinaryConfigTemplate {
             packed_executable,
             extra_runtime_library_paths,
             dynamic_linking,
+            mode,
         } = self;
 
         let packed_executable = packed_executable.build(ctx)?;
         let dynamic_linking = dynamic_linking.build(ctx)?;
 
-        let extra_runtime_library_paths = extra_runtime_library_paths
-            .into_iter()
-            .map(|path| {
-                let path = recipe_path.join(path);
-                eyre::ensure!(
-                    path.starts_with(recipe_path),
-                    "path {path:?} is not relative to recipe path",
-                );
-                eyre::Ok(path)
-            })
-            .collect::<eyre::Result<_>>()?;
+        let extra_runtime_library_paths =
+            build_runtime_library_paths(extra_runtime_library_paths, recipe_path)?;
 
         Ok(brioche_autopack::DynamicBinaryConfig {
             packed_executable,
             extra_runtime_library_paths,
             dynamic_linking,
+            mode: mode.build(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LoaderBinaryConfigTemplate {
+    packed_executable: TemplatePath,
+
+    /// The loader to run binaries with, since they have no interpreter.
+    loader: TemplatePath,
+
+    #[serde(default)]
+    extra_runtime_library_paths: Vec<PathBuf>,
+
+    #[serde(flatten)]
+    dynamic_linking: DynamicLinkingConfigTemplate,
+}
+
+impl LoaderBinaryConfigTemplate {
+    fn build(
+        self,
+        ctx: &AutopackConfigTemplateContext,
+        recipe_path: &Path,
+    ) -> eyre::Result<brioche_autopack::LoaderBinaryConfig> {
+        let Self {
+            packed_executable,
+            loader,
+            extra_runtime_library_paths,
+            dynamic_linking,
+        } = self;
+
+        Ok(brioche_autopack::LoaderBinaryConfig {
+            packed_executable: packed_executable.build(ctx)?,
+            loader: loader.build(ctx)?,
+            extra_runtime_library_paths: build_runtime_library_paths(
+                extra_runtime_library_paths,
+                recipe_path,
+            )?,
+            dynamic_linking: dynamic_linking.build(ctx)?,
         })
     }
 }
 
+/// Resolve runtime library paths relative to the recipe, ensuring they
+/// stay within it.
+fn build_runtime_library_paths(
+    paths: Vec<PathBuf>,
+    recipe_path: &Path,
+) -> eyre::Result<Vec<PathBuf>> {
+    paths
+        .into_iter()
+        .map(|path| {
+            let path = recipe_path.join(path);
+            eyre::ensure!(
+                path.starts_with(recipe_path),
+                "path {path:?} is not relative to recipe path",
+            );
+            eyre::Ok(path)
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SharedLibraryConfigTemplate {
@@ -247,6 +574,26 @@ impl SharedLibraryConfigTemplate {
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowsBinaryConfigTemplate {
+    #[serde(flatten)]
+    dynamic_linking: DynamicLinkingConfigTemplate,
+}
+
+impl WindowsBinaryConfigTemplate {
+    fn build(
+        self,
+        ctx: &AutopackConfigTemplateContext,
+    ) -> eyre::Result<brioche_autopack::WindowsBinaryConfig> {
+        let Self { dynamic_linking } = self;
+
+        let dynamic_linking = dynamic_linking.build(ctx)?;
+
+        Ok(brioche_autopack::WindowsBinaryConfig { dynamic_linking })
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ScriptConfigTemplate {
@@ -257,6 +604,14 @@ pub struct ScriptConfigTemplate {
 
     #[serde(default)]
     clear_env: bool,
+
+    #[serde(default)]
+    clear_env_except: Vec<String>,
+
+    /// Which entries to copy from directories added as resources for env
+    /// vars.
+    #[serde(default)]
+    env_directories: DirectoryOptionsTemplate,
 }
 
 impl ScriptConfigTemplate {
@@ -269,13 +624,16 @@ impl ScriptConfigTemplate {
             packed_executable,
             env,
             clear_env,
+            clear_env_except,
+            env_directories,
         } = self;
 
         let packed_executable = packed_executable.build(ctx)?;
+        let directory_options = env_directories.build();
         let env = env
             .into_iter()
             .map(|(env_var, value)| {
-                let value = value.build(ctx, &env_var)?;
+                let value = value.build(ctx, &env_var, &directory_options)?;
                 eyre::Ok((env_var, value))
             })
             .collect::<eyre::Result<_>>()?;
@@ -285,18 +643,117 @@ impl ScriptConfigTemplate {
             base_path: Some(recipe_path.into()),
             env,
             clear_env,
+            clear_env_except,
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WasmConfigTemplate {
+    packed_executable: TemplatePath,
+
+    /// The wasm runtime command to find in the link dependencies.
+    runtime: String,
+
+    #[serde(default)]
+    runtime_args: Vec<String>,
+}
+
+impl WasmConfigTemplate {
+    fn build(
+        self,
+        ctx: &AutopackConfigTemplateContext,
+    ) -> eyre::Result<brioche_autopack::WasmConfig> {
+        let Self {
+            packed_executable,
+            runtime,
+            runtime_args,
+        } = self;
+
+        Ok(brioche_autopack::WasmConfig {
+            packed_executable: packed_executable.build(ctx)?,
+            runtime,
+            runtime_args,
         })
     }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase")]
-pub struct RepackConfigTemplate {}
+pub struct RepackConfigTemplate {
+    #[serde(default)]
+    refresh_libraries: bool,
+}
 
 impl RepackConfigTemplate {
     fn build(self) -> brioche_autopack::RepackConfig {
-        let Self {} = self;
-        brioche_autopack::RepackConfig {}
+        let Self { refresh_libraries } = self;
+        brioche_autopack::RepackConfig { refresh_libraries }
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct DirectoryOptionsTemplate {
+    /// Only copy entries matching these globs (and their contents).
+    #[serde(default)]
+    include: Vec<String>,
+
+    #[serde(default)]
+    exclude: Vec<String>,
+
+    #[serde(default)]
+    symlinks: DirectorySymlinkPolicyTemplate,
+
+    /// Return an error for special files like sockets instead of skipping
+    /// them.
+    #[serde(default)]
+    error_on_special_files: bool,
+}
+
+impl DirectoryOptionsTemplate {
+    fn build(self) -> brioche_resources::AddDirectoryOptions {
+        let Self {
+            include,
+            exclude,
+            symlinks,
+            error_on_special_files,
+        } = self;
+
+        let special_files = if error_on_special_files {
+            brioche_resources::SpecialFilePolicy::Error
+        } else {
+            brioche_resources::SpecialFilePolicy::Skip
+        };
+
+        brioche_resources::AddDirectoryOptions {
+            include,
+            exclude,
+            symlinks: symlinks.build(),
+            special_files,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+enum DirectorySymlinkPolicyTemplate {
+    #[default]
+    Preserve,
+    Follow,
+    Skip,
+}
+
+impl DirectorySymlinkPolicyTemplate {
+    fn build(self) -> brioche_resources::DirectorySymlinkPolicy {
+        match self {
+            Self::Preserve => brioche_resources::DirectorySymlinkPolicy::Preserve,
+            Self::Follow => brioche_resources::DirectorySymlinkPolicy::Follow,
+            Self::Skip => brioche_resources::DirectorySymlinkPolicy::Skip,
+        }
     }
 }
 
@@ -320,12 +777,27 @@ enum EnvValueTemplate {
         value: EnvValueTemplateValue,
         #[serde_as(as = "TickEncoded")]
         separator: Vec<u8>,
+        #[serde(default)]
+        if_exists: bool,
     },
     #[serde(rename_all = "camelCase")]
     Append {
         value: EnvValueTemplateValue,
         #[serde_as(as = "TickEncoded")]
         separator: Vec<u8>,
+        #[serde(default)]
+        if_exists: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    MergedView {
+        values: Vec<EnvValueTemplateValue>,
+    },
+    #[serde(rename_all = "camelCase")]
+    PrependPath {
+        path: EnvValueTemplateValueComponent,
+        #[serde_as(as = "TickEncoded")]
+        #[serde(default)]
+        subpath: Vec<u8>,
     },
 }
 
@@ -334,25 +806,67 @@ impl EnvValueTemplate {
         self,
         ctx: &AutopackConfigTemplateContext,
         env_var: &str,
+        directory_options: &brioche_resources::AddDirectoryOptions,
     ) -> eyre::Result<runnable_core::EnvValue> {
         match self {
             Self::Clear => Ok(runnable_core::EnvValue::Clear),
             Self::Inherit => Ok(runnable_core::EnvValue::Inherit),
             Self::Set { value } => {
-                let value = value.build(ctx, env_var)?;
+                let value = value.build(ctx, env_var, directory_options)?;
                 Ok(runnable_core::EnvValue::Set { value })
             }
             Self::Fallback { value } => {
-                let value = value.build(ctx, env_var)?;
+                let value = value.build(ctx, env_var, directory_options)?;
                 Ok(runnable_core::EnvValue::Fallback { value })
             }
-            Self::Prepend { value, separator } => {
-                let value = value.build(ctx, env_var)?;
-                Ok(runnable_core::EnvValue::Prepend { value, separator })
+            Self::Prepend {
+                value,
+                separator,
+                if_exists,
+            } => {
+                let value = value.build(ctx, env_var, directory_options)?;
+                Ok(runnable_core::EnvValue::Prepend {
+                    value,
+                    separator,
+                    if_exists,
+                })
+            }
+            Self::Append {
+                value,
+                separator,
+                if_exists,
+            } => {
+                let value = value.build(ctx, env_var, directory_options)?;
+                Ok(runnable_core::EnvValue::Append {
+                    value,
+                    separator,
+                    if_exists,
+                })
             }
-            Self::Append { value, separator } => {
-                let value = value.build(ctx, env_var)?;
-                Ok(runnable_core::EnvValue::Append { value, separator })
+            Self::MergedView { values } => {
+                let values = values
+                    .into_iter()
+                    .map(|value| value.build(ctx, env_var, directory_options))
+                    .collect::<eyre::Result<_>>()?;
+                Ok(runnable_core::EnvValue::MergedView { values })
+            }
+            Self::PrependPath { path, subpath } => {
+                let path = match path.build(ctx, env_var, directory_options)? {
+                    runnable_core::TemplateComponent::RelativePath { path } => {
+                        runnable_core::RunnablePath::RelativePath { path }
+                    }
+                    runnable_core::TemplateComponent::Resource { resource } => {
+                        runnable_core::RunnablePath::Resource { resource }
+                    }
+                    runnable_core::TemplateComponent::Literal { .. }
+                    | runnable_core::TemplateComponent::EnvVar { .. }
+                    | runnable_core::TemplateComponent::ProgramDir => {
+                        eyre::bail!(
+                            "path for env var ${env_var} must be a relative path, resource, or variable"
+                        );
+                    }
+                };
+                Ok(runnable_core::EnvValue::PrependPath { path, subpath })
             }
         }
     }
@@ -369,11 +883,12 @@ impl EnvValueTemplateValue {
         self,
         ctx: &AutopackConfigTemplateContext,
         env_var: &str,
+        directory_options: &brioche_resources::AddDirectoryOptions,
     ) -> eyre::Result<runnable_core::Template> {
         let components = self
             .components
             .into_iter()
-            .map(|component| component.build(ctx, env_var))
+            .map(|component| component.build(ctx, env_var, directory_options))
             .collect::<eyre::Result<_>>()?;
 
         Ok(runnable_core::Template { components })
@@ -400,6 +915,14 @@ enum EnvValueTemplateValueComponent {
         #[serde_as(as = "TickEncoded")]
         resource: Vec<u8>,
     },
+    #[serde(rename_all = "camelCase")]
+    EnvVar {
+        name: String,
+        #[serde_as(as = "Option<TickEncoded>")]
+        #[serde(default)]
+        fallback: Option<Vec<u8>>,
+    },
+    ProgramDir,
     Variable(TemplateVariable),
 }
 
@@ -408,6 +931,7 @@ impl EnvValueTemplateValueComponent {
         self,
         ctx: &AutopackConfigTemplateContext,
         env_var: &str,
+        directory_options: &brioche_resources::AddDirectoryOptions,
     ) -> eyre::Result<runnable_core::TemplateComponent> {
         match self {
             Self::Literal { value } => Ok(runnable_core::TemplateComponent::Literal { value }),
@@ -417,15 +941,21 @@ impl EnvValueTemplateValueComponent {
             Self::Resource { resource } => {
                 Ok(runnable_core::TemplateComponent::Resource { resource })
             }
+            Self::EnvVar { name, fallback } => {
+                Ok(runnable_core::TemplateComponent::EnvVar { name, fallback })
+            }
+            Self::ProgramDir => Ok(runnable_core::TemplateComponent::ProgramDir),
             Self::Variable(variable) => {
                 let value = ctx.get(&variable)?;
                 match value {
                     TemplateVariableValue::Path(path) => {
-                        let resource = brioche_resources::add_named_resource_directory(
-                            &ctx.resource_dir,
-                            path,
-                            env_var,
-                        )?;
+                        let resource =
+                            brioche_resources::add_named_resource_directory_with_options(
+                                &ctx.resource_dir,
+                                path,
+                                env_var,
+                                directory_options,
+                            )?;
                         let resource = <Vec<u8>>::from_path_buf(resource)
                             .map_err(|_| eyre::eyre!("invalid path"))?;
                         Ok(runnable_core::TemplateComponent::Resource { resource })
@@ -0,0 +1,68 @@
+use std::{
+    io::{Read as _, Seek as _, Write as _},
+    os::unix::fs::OpenOptionsExt as _,
+    path::Path,
+};
+
+use eyre::{Context as _, OptionExt as _};
+
+/// Write a single-file bundle containing a program and its resource
+/// closure. The bundle starts with the `launcher` executable, which
+/// extracts the payload on first run, then runs the program from the
+/// extracted dir.
+pub fn export_bundle(program: &Path, launcher: &Path, output: &Path) -> eyre::Result<()> {
+    let program_name = program
+        .file_name()
+        .ok_or_eyre("could not get program name from path")?;
+    let program_entry_path = Path::new("bin").join(program_name);
+    let entrypoint = program_entry_path
+        .to_str()
+        .ok_or_eyre("program name is not valid UTF-8")?
+        .to_string();
+
+    let mut output_file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o777)
+        .open(output)
+        .with_context(|| format!("failed to open {}", output.display()))?;
+
+    let mut launcher_file = std::fs::File::open(launcher)
+        .with_context(|| format!("failed to open launcher {}", launcher.display()))?;
+    let payload_start = std::io::copy(&mut launcher_file, &mut output_file)?;
+
+    // Write the payload
+    let mut payload = tar::Builder::new(&mut output_file);
+    crate::closure::append_program_closure(&mut payload, program, &program_entry_path)?;
+
+    let metadata = brioche_bundle_exec::BundleMetadata { entrypoint };
+    let metadata = serde_json::to_vec(&metadata)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(metadata.len().try_into()?);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    payload.append_data(
+        &mut header,
+        brioche_bundle_exec::METADATA_PATH,
+        &metadata[..],
+    )?;
+    payload.into_inner()?;
+
+    // Hash the payload, then write the trailer after it
+    let payload_end = output_file.stream_position()?;
+    let payload_len = payload_end - payload_start;
+    output_file.seek(std::io::SeekFrom::Start(payload_start))?;
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut (&mut output_file).take(payload_len), &mut hasher)?;
+
+    let trailer = brioche_bundle_exec::BundleTrailer {
+        hash: *hasher.finalize().as_bytes(),
+        payload_len,
+    };
+    output_file.seek(std::io::SeekFrom::Start(payload_end))?;
+    output_file.write_all(&trailer.to_bytes())?;
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use brioche_autopack::{pack_resources, ResourceKind};
+use bstr::ByteSlice as _;
+use eyre::Context as _;
+
+/// Collect every resource needed to run a program: the resources
+/// referenced by its pack, plus the resources referenced by any packed
+/// files within those resources. Returns the resource paths (relative
+/// to the resource dir), along with how each one was first referenced.
+pub fn resource_closure(
+    program: &Path,
+    all_resource_dirs: &[PathBuf],
+) -> eyre::Result<BTreeMap<PathBuf, ResourceKind>> {
+    let mut resources = BTreeMap::new();
+    let mut pending_files = VecDeque::from([program.to_owned()]);
+
+    while let Some(file_path) = pending_files.pop_front() {
+        let file = std::fs::File::open(&file_path)
+            .with_context(|| format!("failed to open {}", file_path.display()))?;
+        let Ok(extracted) = brioche_pack::extract_pack(file) else {
+            continue;
+        };
+
+        for (kind, resource) in pack_resources(&extracted.pack, all_resource_dirs)? {
+            let resource = resource
+                .to_path()
+                .map_err(|_| eyre::eyre!("invalid resource path"))?;
+            if resources.contains_key(resource) {
+                continue;
+            }
+            resources.insert(resource.to_owned(), kind);
+
+            let resource_path =
+                brioche_resources::find_in_resource_dirs(all_resource_dirs, resource)
+                    .ok_or_else(|| eyre::eyre!("resource not found: {}", resource.display()))?;
+
+            // Check the files within the resource for packs of their own
+            for entry in walkdir::WalkDir::new(&resource_path).follow_links(true) {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    pending_files.push_back(entry.into_path());
+                }
+            }
+        }
+    }
+
+    Ok(resources)
+}
+
+/// Add a program and its resource closure to a tar archive. The program is
+/// added at `program_entry_path`, and resources are added under
+/// `brioche-resources.d`. Symlinks are followed, so the archive can be
+/// extracted anywhere.
+pub fn append_program_closure<W: std::io::Write>(
+    archive: &mut tar::Builder<W>,
+    program: &Path,
+    program_entry_path: &Path,
+) -> eyre::Result<()> {
+    let all_resource_dirs = brioche_resources::find_resource_dirs(program, true)
+        .with_context(|| format!("failed to find resource dirs for {}", program.display()))?;
+    let resources = resource_closure(program, &all_resource_dirs)?;
+
+    archive.mode(tar::HeaderMode::Deterministic);
+    archive.follow_symlinks(true);
+
+    archive
+        .append_path_with_name(program, program_entry_path)
+        .with_context(|| format!("failed to add {} to archive", program.display()))?;
+
+    for resource in resources.keys() {
+        let resource_path = brioche_resources::find_in_resource_dirs(&all_resource_dirs, resource)
+            .ok_or_else(|| eyre::eyre!("resource not found: {}", resource.display()))?;
+        let entry_path = Path::new("brioche-resources.d").join(resource);
+        if resource_path.is_dir() {
+            archive.append_dir_all(&entry_path, &resource_path)
+        } else {
+            archive.append_path_with_name(&resource_path, &entry_path)
+        }
+        .with_context(|| format!("failed to add resource {} to archive", resource.display()))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,281 @@
+use std::{os::unix::fs::OpenOptionsExt as _, path::Path};
+
+use eyre::{Context as _, OptionExt as _};
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ConvertTo {
+    /// A runnable metadata pack, which runs the interpreter directly
+    Metadata,
+
+    /// An `ld-linux` pack
+    LdLinux,
+}
+
+pub struct ConvertOptions<'a> {
+    pub program: &'a Path,
+    pub to: ConvertTo,
+    pub packed_executable: &'a Path,
+    pub output: &'a Path,
+}
+
+/// Convert a packed program to a different pack type. Runnables created
+/// from `ld-linux` packs call the interpreter with `--library-path` and the
+/// program, the same way the packed runtime does. Only runnables with that
+/// exact shape can be converted back into `ld-linux` packs.
+///
+/// The pack is written to a copy of `packed_executable`, since the packed
+/// runtime must support the new pack type.
+pub fn convert(options: &ConvertOptions) -> eyre::Result<()> {
+    let program = std::fs::File::open(options.program)
+        .with_context(|| format!("failed to open {}", options.program.display()))?;
+    let extracted = brioche_pack::extract_pack(program)
+        .with_context(|| format!("failed to extract pack from {}", options.program.display()))?;
+
+    let new_pack = match (options.to, extracted.pack) {
+        (
+            ConvertTo::Metadata,
+            brioche_pack::Pack::LdLinux {
+                program,
+                interpreter,
+                library_dirs,
+                runtime_library_dirs,
+            },
+        ) => {
+            let runnable =
+                ld_linux_runnable(&program, &interpreter, &library_dirs, &runtime_library_dirs);
+            let resource_paths = runnable.resources().into_iter().cloned().collect();
+
+            let output_resource_dir = brioche_resources::find_output_resource_dir(options.output)?;
+            let program_name = options
+                .program
+                .file_name()
+                .ok_or_eyre("could not get program name from path")?;
+            let mut metadata_name = program_name.to_owned();
+            metadata_name.push(".runnable.json");
+            brioche_autopack::runnable_metadata_pack(
+                &output_resource_dir,
+                &runnable,
+                resource_paths,
+                Path::new(&metadata_name),
+            )?
+        }
+        (
+            ConvertTo::LdLinux,
+            brioche_pack::Pack::Metadata {
+                resource_paths: _,
+                format,
+                metadata,
+            },
+        ) => {
+            eyre::ensure!(
+                runnable_core::is_runnable_format(&format),
+                "unsupported metadata format: {format:?}"
+            );
+            let all_resource_dirs = brioche_resources::find_resource_dirs(options.program, true)?;
+            let runnable = runnable_core::load_runnable(&format, &metadata, &all_resource_dirs)
+                .context("failed to load runnable metadata")?;
+            runnable_ld_linux_pack(&runnable)?
+        }
+        (ConvertTo::Metadata, pack @ brioche_pack::Pack::Metadata { .. })
+        | (ConvertTo::LdLinux, pack @ brioche_pack::Pack::LdLinux { .. }) => {
+            // Already the right pack type, so just switch the runtime
+            pack
+        }
+        (_, brioche_pack::Pack::Static { .. }) => {
+            eyre::bail!("cannot convert static packs");
+        }
+    };
+
+    let mut packed_executable =
+        std::fs::File::open(options.packed_executable).with_context(|| {
+            format!(
+                "failed to open packed executable {}",
+                options.packed_executable.display()
+            )
+        })?;
+
+    // Write to a temporary file first, since the output may be the same
+    // file as the program
+    let output_dir = options
+        .output
+        .parent()
+        .ok_or_eyre("could not get parent of output path")?;
+    let output_name = options
+        .output
+        .file_name()
+        .ok_or_eyre("could not get output filename")?;
+    let mut temp_name = output_name.to_owned();
+    temp_name.push(format!(".convert-{}", std::process::id()));
+    let temp_path = output_dir.join(temp_name);
+
+    let mut output = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o777)
+        .open(&temp_path)
+        .with_context(|| format!("failed to create {}", temp_path.display()))?;
+    std::io::copy(&mut packed_executable, &mut output)?;
+    brioche_pack::inject_pack(&mut output, &new_pack)?;
+    drop(output);
+
+    std::fs::rename(&temp_path, options.output)
+        .with_context(|| format!("failed to write {}", options.output.display()))?;
+
+    Ok(())
+}
+
+fn ld_linux_runnable(
+    program: &[u8],
+    interpreter: &[u8],
+    library_dirs: &[Vec<u8>],
+    runtime_library_dirs: &[Vec<u8>],
+) -> runnable_core::Runnable {
+    let library_path_components =
+        runtime_library_dirs
+            .iter()
+            .map(|path| runnable_core::TemplateComponent::RelativePath { path: path.clone() })
+            .chain(library_dirs.iter().map(|resource| {
+                runnable_core::TemplateComponent::Resource {
+                    resource: resource.clone(),
+                }
+            }));
+
+    let mut library_path = runnable_core::Template::default();
+    for (n, component) in library_path_components.enumerate() {
+        if n > 0 {
+            library_path
+                .components
+                .push(runnable_core::TemplateComponent::Literal {
+                    value: b":".to_vec(),
+                });
+        }
+        library_path.components.push(component);
+    }
+
+    let mut args = vec![];
+    if !library_path.components.is_empty() {
+        args.push(runnable_core::ArgValue::Arg {
+            value: runnable_core::Template::from_literal(b"--library-path".to_vec()),
+        });
+        args.push(runnable_core::ArgValue::Arg {
+            value: library_path,
+        });
+    }
+    args.push(runnable_core::ArgValue::Arg {
+        value: resource_template(program),
+    });
+    args.push(runnable_core::ArgValue::Rest);
+
+    runnable_core::Runnable {
+        command: resource_template(interpreter),
+        args,
+        env: vec![],
+        clear_env: false,
+        clear_env_except: vec![],
+        umask: None,
+        nice: None,
+        rlimits: vec![],
+        cwd: None,
+        platforms: vec![],
+        pre_commands: vec![],
+        source: None,
+    }
+}
+
+fn runnable_ld_linux_pack(runnable: &runnable_core::Runnable) -> eyre::Result<brioche_pack::Pack> {
+    eyre::ensure!(
+        runnable.env.is_empty()
+            && !runnable.clear_env
+            && runnable.cwd.is_none()
+            && runnable.platforms.is_empty()
+            && runnable.pre_commands.is_empty(),
+        "cannot convert runnable with env vars, a working directory, platform variants, or pre-commands to an ld-linux pack"
+    );
+
+    let interpreter = template_resource(&runnable.command)
+        .ok_or_eyre("runnable command must be a single resource to convert to an ld-linux pack")?;
+
+    let mut args = runnable.args.iter().peekable();
+    let mut library_dirs = vec![];
+    let mut runtime_library_dirs = vec![];
+
+    let is_library_path_flag = |arg: &runnable_core::ArgValue| match arg {
+        runnable_core::ArgValue::Arg { value } => {
+            template_literal(value) == Some(b"--library-path")
+        }
+        runnable_core::ArgValue::Spread { .. }
+        | runnable_core::ArgValue::IfEnvSet { .. }
+        | runnable_core::ArgValue::Optional { .. }
+        | runnable_core::ArgValue::Rest => false,
+    };
+    if args.next_if(|arg| is_library_path_flag(arg)).is_some() {
+        let Some(runnable_core::ArgValue::Arg {
+            value: library_path,
+        }) = args.next()
+        else {
+            eyre::bail!("expected library path after --library-path");
+        };
+
+        for (n, component) in library_path.components.iter().enumerate() {
+            match component {
+                runnable_core::TemplateComponent::Literal { value }
+                    if n % 2 == 1 && value == b":" => {}
+                runnable_core::TemplateComponent::RelativePath { path }
+                    if n % 2 == 0 && library_dirs.is_empty() =>
+                {
+                    runtime_library_dirs.push(path.clone());
+                }
+                runnable_core::TemplateComponent::Resource { resource } if n % 2 == 0 => {
+                    library_dirs.push(resource.clone());
+                }
+                _ => {
+                    eyre::bail!(
+                        "unsupported library path component for an ld-linux pack: {component:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    let program = match args.next() {
+        Some(runnable_core::ArgValue::Arg { value }) => template_resource(value),
+        _ => None,
+    };
+    let program = program.ok_or_eyre(
+        "expected a resource for the program argument to convert to an ld-linux pack",
+    )?;
+
+    eyre::ensure!(
+        matches!(args.next(), Some(runnable_core::ArgValue::Rest)) && args.next().is_none(),
+        "runnable args must end with the remaining args to convert to an ld-linux pack"
+    );
+
+    Ok(brioche_pack::Pack::LdLinux {
+        program: program.to_vec(),
+        interpreter: interpreter.to_vec(),
+        library_dirs,
+        runtime_library_dirs,
+    })
+}
+
+fn resource_template(resource: &[u8]) -> runnable_core::Template {
+    runnable_core::Template {
+        components: vec![runnable_core::TemplateComponent::Resource {
+            resource: resource.to_vec(),
+        }],
+    }
+}
+
+fn template_resource(template: &runnable_core::Template) -> Option<&[u8]> {
+    match &template.components[..] {
+        [runnable_core::TemplateComponent::Resource { resource }] => Some(resource),
+        _ => None,
+    }
+}
+
+fn template_literal(template: &runnable_core::Template) -> Option<&[u8]> {
+    match &template.components[..] {
+        [runnable_core::TemplateComponent::Literal { value }] => Some(value),
+        _ => None,
+    }
+}
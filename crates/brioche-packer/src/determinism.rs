@@ -0,0 +1,135 @@
+use std::{
+    collections::BTreeMap,
+    os::unix::fs::PermissionsExt as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use eyre::Context as _;
+
+use crate::autopack_template::{AutopackConfigTemplate, AutopackConfigTemplateContext};
+
+/// Autopack a copy of a recipe twice, each time with a new resource dir,
+/// and return a description of each difference between the two runs. Both
+/// runs use the same paths, so absolute paths in the outputs don't show up
+/// as differences. The recipe itself isn't changed.
+pub fn check_determinism(
+    config_template: &AutopackConfigTemplate,
+    ctx: &AutopackConfigTemplateContext,
+    recipe_path: &Path,
+) -> eyre::Result<Vec<String>> {
+    let temp_dir = tempfile::tempdir()?;
+    let run_dir = temp_dir.path().join("run");
+    let first_run_dir = temp_dir.path().join("first");
+
+    autopack_copy(config_template, ctx, recipe_path, &run_dir)?;
+    std::fs::rename(&run_dir, &first_run_dir)?;
+    autopack_copy(config_template, ctx, recipe_path, &run_dir)?;
+
+    let first_entries = tree_entries(&first_run_dir)?;
+    let second_entries = tree_entries(&run_dir)?;
+
+    let mut differences = vec![];
+    for (path, first_entry) in &first_entries {
+        match second_entries.get(path) {
+            Some(second_entry) if second_entry == first_entry => {}
+            Some(_) => {
+                differences.push(format!("{} differs", path.display()));
+            }
+            None => {
+                differences.push(format!("{} only in first run", path.display()));
+            }
+        }
+    }
+    for path in second_entries.keys() {
+        if !first_entries.contains_key(path) {
+            differences.push(format!("{} only in second run", path.display()));
+        }
+    }
+
+    Ok(differences)
+}
+
+/// Copy the recipe into `run_dir`, then autopack the copy using a new
+/// resource dir next to it.
+fn autopack_copy(
+    config_template: &AutopackConfigTemplate,
+    ctx: &AutopackConfigTemplateContext,
+    recipe_path: &Path,
+    run_dir: &Path,
+) -> eyre::Result<()> {
+    let recipe_copy = run_dir.join("recipe");
+    let resource_dir = run_dir.join("brioche-resources.d");
+    std::fs::create_dir_all(&resource_dir)?;
+    copy_tree(recipe_path, &recipe_copy)
+        .with_context(|| format!("failed to copy recipe {}", recipe_path.display()))?;
+
+    let mut config = config_template.clone().build(ctx, recipe_copy, false)?;
+    config.progress = Arc::new(brioche_autopack::StdoutProgressReporter::warnings_only());
+    config.all_resource_dirs.retain(|dir| *dir != resource_dir);
+    config.all_resource_dirs.insert(0, resource_dir.clone());
+    config.resource_dir = resource_dir;
+
+    brioche_autopack::autopack(&config)?;
+    Ok(())
+}
+
+fn copy_tree(source: &Path, dest: &Path) -> eyre::Result<()> {
+    for entry in walkdir::WalkDir::new(source) {
+        let entry = entry?;
+        let relative_path = entry.path().strip_prefix(source)?;
+        let dest_path = dest.join(relative_path);
+
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            std::fs::create_dir_all(&dest_path)?;
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(entry.path())?;
+            std::os::unix::fs::symlink(target, &dest_path)?;
+        } else {
+            std::fs::copy(entry.path(), &dest_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum TreeEntry {
+    Dir,
+    File {
+        hash: blake3::Hash,
+        executable: bool,
+    },
+    Symlink {
+        target: PathBuf,
+    },
+}
+
+/// Get every entry in a directory tree, keyed by path relative to `root`.
+fn tree_entries(root: &Path) -> eyre::Result<BTreeMap<PathBuf, TreeEntry>> {
+    let mut entries = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(root) {
+        let entry = entry?;
+        let relative_path = entry.path().strip_prefix(root)?.to_owned();
+
+        let file_type = entry.file_type();
+        let tree_entry = if file_type.is_dir() {
+            TreeEntry::Dir
+        } else if file_type.is_symlink() {
+            TreeEntry::Symlink {
+                target: std::fs::read_link(entry.path())?,
+            }
+        } else {
+            let contents = std::fs::read(entry.path())?;
+            let mode = entry.metadata()?.permissions().mode();
+            TreeEntry::File {
+                hash: blake3::hash(&contents),
+                executable: mode & 0o111 != 0,
+            }
+        };
+        entries.insert(relative_path, tree_entry);
+    }
+
+    Ok(entries)
+}
@@ -10,6 +10,13 @@ use clap::Parser;
 use eyre::{Context as _, OptionExt as _};
 
 mod autopack_template;
+mod bundle;
+mod closure;
+mod convert;
+mod determinism;
+mod make_runnable;
+mod oci;
+mod sbom;
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Parser)]
@@ -21,6 +28,10 @@ enum Args {
         output: PathBuf,
         #[arg(long)]
         pack: String,
+        /// A static program to embed between the launcher and a static
+        /// pack, so the launcher runs it.
+        #[arg(long)]
+        embed: Option<PathBuf>,
     },
     Autopack(AutopackArgs),
     Read {
@@ -30,6 +41,54 @@ enum Args {
         program: PathBuf,
     },
     UpdateSource(UpdateSourceArgs),
+    Validate {
+        program: PathBuf,
+    },
+    RunnableSchema,
+    Edit(EditArgs),
+    Convert(ConvertArgs),
+    MakeRunnable(MakeRunnableArgs),
+    WrapDir(WrapDirArgs),
+    ExportOci(ExportOciArgs),
+    ExportBundle(ExportBundleArgs),
+    Sbom(SbomArgs),
+    /// Remove temporary files left in a resource dir by interrupted writes,
+    /// and fix aliases to missing blobs where possible
+    RepairResources {
+        resource_dir: PathBuf,
+
+        /// Only report what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Re-hash the blobs and directories in a resource dir, and check that
+    /// its aliases resolve within it
+    VerifyResources {
+        resource_dir: PathBuf,
+    },
+    /// Upgrade a resource dir to the current layout version
+    MigrateResources {
+        resource_dir: PathBuf,
+
+        /// Only report what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print the number and size of the resources in a resource dir, and
+    /// the largest blobs and directories
+    ResourceStats {
+        resource_dir: PathBuf,
+
+        /// How many of the largest blobs and directories to list
+        #[arg(long, default_value_t = 10)]
+        largest: usize,
+    },
+    LibraryIndex {
+        /// Library directories or library files to index, in order of
+        /// precedence
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
 }
 
 impl std::str::FromStr for AutopackTemplateValue {
@@ -80,8 +139,14 @@ fn run() -> eyre::Result<()> {
             packed,
             output,
             pack,
+            embed,
         } => {
             let pack = serde_json::from_str(&pack)?;
+            validate_pack_metadata(&pack)?;
+            eyre::ensure!(
+                embed.is_none() || matches!(pack, brioche_pack::Pack::Static { .. }),
+                "--embed can only be used with a static pack"
+            );
 
             let mut packed = std::fs::File::open(packed)?;
             let mut output = std::fs::OpenOptions::new()
@@ -93,6 +158,12 @@ fn run() -> eyre::Result<()> {
 
             std::io::copy(&mut packed, &mut output)?;
 
+            if let Some(embed) = embed {
+                let mut embed = std::fs::File::open(&embed)
+                    .with_context(|| format!("failed to open {}", embed.display()))?;
+                std::io::copy(&mut embed, &mut output)?;
+            }
+
             brioche_pack::inject_pack(&mut output, &pack)?;
         }
         Args::Autopack(args) => {
@@ -102,7 +173,8 @@ fn run() -> eyre::Result<()> {
             let mut program = std::fs::File::open(program)?;
             let extracted = brioche_pack::extract_pack(&mut program)?;
 
-            serde_json::to_writer_pretty(std::io::stdout().lock(), &extracted.pack)?;
+            let pack = pack_to_json(&extracted.pack)?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &pack)?;
             println!();
         }
         Args::SourcePath {
@@ -130,6 +202,179 @@ fn run() -> eyre::Result<()> {
         Args::UpdateSource(args) => {
             run_update_source(args)?;
         }
+        Args::Validate { program } => {
+            run_validate(&program)?;
+        }
+        Args::Edit(args) => {
+            run_edit(args)?;
+        }
+        Args::Convert(args) => {
+            convert::convert(&convert::ConvertOptions {
+                program: &args.program,
+                to: args.to,
+                packed_executable: &args.packed_executable,
+                output: args.output.as_deref().unwrap_or(&args.program),
+            })?;
+        }
+        Args::MakeRunnable(args) => {
+            let template = serde_json::from_str(&args.template)
+                .context("failed to parse runnable template")?;
+            make_runnable::make_runnable(&make_runnable::MakeRunnableOptions {
+                program: &args.program,
+                runnable: &args.runnable,
+                template: &template,
+                output: args.output.as_deref().unwrap_or(&args.program),
+                encoding: args.encoding,
+            })?;
+        }
+        Args::WrapDir(args) => {
+            let template = serde_json::from_str(&args.template)
+                .context("failed to parse runnable template")?;
+            let programs =
+                make_runnable::wrap_dir(&args.dir, &args.runnable, &template, args.encoding)?;
+            for program in &programs {
+                println!("wrapped {}", program.display());
+            }
+        }
+        Args::ExportOci(args) => {
+            let architecture = args
+                .architecture
+                .as_deref()
+                .unwrap_or_else(|| oci::default_architecture());
+            oci::export_oci(&oci::ExportOciOptions {
+                program: &args.program,
+                output: &args.output,
+                tag: &args.tag,
+                architecture,
+            })?;
+        }
+        Args::ExportBundle(args) => {
+            let launcher = match args.launcher {
+                Some(launcher) => launcher,
+                None => {
+                    let current_exe = std::env::current_exe()?;
+                    let current_exe_dir = current_exe
+                        .parent()
+                        .ok_or_eyre("failed to get current executable dir")?;
+                    current_exe_dir.join("brioche-bundle-exec")
+                }
+            };
+            bundle::export_bundle(&args.program, &launcher, &args.output)?;
+        }
+        Args::RepairResources {
+            resource_dir,
+            dry_run,
+        } => {
+            let report = brioche_resources::repair(&resource_dir, dry_run)?;
+            let action = if dry_run { "would remove" } else { "removed" };
+            for path in report
+                .removed_temp_blobs
+                .iter()
+                .chain(&report.removed_temp_aliases)
+                .chain(&report.removed_temp_directories)
+            {
+                println!("{action} {}", path.display());
+            }
+            let action = if dry_run { "would fix" } else { "fixed" };
+            for path in &report.fixed_aliases {
+                println!("{action} alias {}", path.display());
+            }
+            for path in &report.dangling_aliases {
+                println!("dangling alias {}", path.display());
+            }
+            if report.is_empty() {
+                println!("nothing to repair");
+            }
+        }
+        Args::VerifyResources { resource_dir } => {
+            let report = brioche_resources::verify(&resource_dir)?;
+            for path in &report.corrupted_blobs {
+                println!("corrupted blob {}", path.display());
+            }
+            for path in &report.corrupted_directories {
+                println!("corrupted directory {}", path.display());
+            }
+            for path in &report.unrecognized_entries {
+                println!("unrecognized entry {}", path.display());
+            }
+            for path in &report.dangling_aliases {
+                println!("dangling alias {}", path.display());
+            }
+            for path in &report.escaping_aliases {
+                println!("alias outside resource dir {}", path.display());
+            }
+            if !report.is_empty() {
+                eyre::bail!(
+                    "resource dir {} failed verification",
+                    resource_dir.display()
+                );
+            }
+
+            println!("resource dir is intact");
+        }
+        Args::MigrateResources {
+            resource_dir,
+            dry_run,
+        } => {
+            let report = brioche_resources::migrate(&resource_dir, dry_run)?;
+            if report.from_version != report.to_version {
+                let action = if dry_run { "would migrate" } else { "migrated" };
+                println!(
+                    "{action} from layout version {} to {}",
+                    report.from_version, report.to_version
+                );
+            } else if report.wrote_marker {
+                let action = if dry_run { "would mark" } else { "marked" };
+                println!(
+                    "{action} resource dir as layout version {}",
+                    report.to_version
+                );
+            } else {
+                println!(
+                    "resource dir is already layout version {}",
+                    report.to_version
+                );
+            }
+        }
+        Args::ResourceStats {
+            resource_dir,
+            largest,
+        } => {
+            let stats = brioche_resources::stats(&resource_dir, largest)?;
+            println!("blobs: {} ({} bytes)", stats.blob_count, stats.blob_size);
+            println!(
+                "directories: {} ({} bytes)",
+                stats.directory_count, stats.directory_size
+            );
+            println!("aliases: {}", stats.alias_count);
+            println!("total size: {} bytes", stats.total_size());
+            if !stats.largest_entries.is_empty() {
+                println!("largest entries:");
+                for entry in &stats.largest_entries {
+                    println!("  {} {}", entry.size, entry.path.display());
+                }
+            }
+        }
+        Args::LibraryIndex { paths } => {
+            let index = brioche_autopack::LibraryIndex::from_search_paths(&paths)?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &index)?;
+            println!();
+        }
+        Args::Sbom(args) => {
+            let document = sbom::sbom(&args.paths, args.format)?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &document)?;
+            println!();
+        }
+        Args::RunnableSchema => {
+            let mut schema = schemars::schema_for!(runnable_core::Runnable);
+
+            // Tag the schema with the metadata format, so consumers can
+            // tell which version of the runnable format it describes
+            schema.schema.metadata().title = Some(runnable_core::FORMAT.to_string());
+
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &schema)?;
+            println!();
+        }
     }
 
     Ok(())
@@ -149,6 +394,36 @@ struct AutopackArgs {
 
     #[arg(long = "var", value_parser)]
     variables: Vec<AutopackTemplateValue>,
+
+    /// Print how long each phase took for each path
+    #[arg(long)]
+    timings: bool,
+
+    /// Print what would be packed and which resources would be added,
+    /// without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Write a JSON report of each path's kind, interpreter, libraries,
+    /// and resources
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Write a JSON manifest of the resources referenced by each packed
+    /// path
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+
+    /// Record the hashes of autopacked paths in this file, and skip paths
+    /// that haven't changed since a previous run with the same file
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Autopack a copy of the recipe twice in a temporary directory and
+    /// report any differences between the two runs, without changing the
+    /// recipe
+    #[arg(long, conflicts_with_all = ["dry_run", "state_file"])]
+    check_determinism: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -194,13 +469,65 @@ fn run_autopack(args: AutopackArgs) -> eyre::Result<()> {
         variables,
         resource_dir,
     };
-    let config = config_template.build(ctx, recipe_path)?;
 
-    brioche_autopack::autopack(&config)?;
+    if args.check_determinism {
+        let differences = determinism::check_determinism(&config_template, ctx, &recipe_path)?;
+        if !differences.is_empty() {
+            for difference in &differences {
+                eprintln!("{difference}");
+            }
+            eyre::bail!(
+                "autopack is not deterministic: {} paths differ between runs",
+                differences.len()
+            );
+        }
+
+        println!("autopack is deterministic");
+        return Ok(());
+    }
+
+    let mut config = config_template.build(ctx, recipe_path, args.dry_run)?;
+    config.report_path = args.report;
+    config.manifest_path = args.manifest;
+    config.state_file = args.state_file;
+
+    let results = brioche_autopack::autopack(&config)?;
+
+    if args.dry_run {
+        for path_result in &results.paths {
+            let action = if path_result.packed { "pack" } else { "skip" };
+            println!("{action} {}", path_result.path.display());
+            for resource in &path_result.resources {
+                println!("  resource {}", resource.display());
+            }
+        }
+    }
+
+    if args.timings {
+        for path_result in &results.paths {
+            print_phase_timings(
+                &path_result.path.display().to_string(),
+                &path_result.timings,
+            );
+        }
+        print_phase_timings("total", &results.total_timings());
+    }
 
     Ok(())
 }
 
+fn print_phase_timings(label: &str, timings: &brioche_autopack::PhaseTimings) {
+    eprintln!(
+        "{label}: kind={:?} elf={:?} resolve={:?} resources={:?} inject={:?} total={:?}",
+        timings.kind_detection,
+        timings.elf_parsing,
+        timings.library_resolution,
+        timings.resource_insertion,
+        timings.pack_injection,
+        timings.total(),
+    );
+}
+
 #[derive(Debug, Parser)]
 struct UpdateSourceArgs {
     program: PathBuf,
@@ -293,6 +620,606 @@ fn run_update_source(args: UpdateSourceArgs) -> eyre::Result<()> {
     Ok(())
 }
 
+fn run_validate(program_path: &Path) -> eyre::Result<()> {
+    let mut program = std::fs::File::open(program_path)
+        .with_context(|| format!("failed to open {}", program_path.display()))?;
+    let extracted = brioche_pack::extract_pack(&mut program)
+        .with_context(|| format!("failed to extract pack from {}", program_path.display()))?;
+    let all_resource_dirs = brioche_resources::find_resource_dirs(program_path, true)
+        .with_context(|| {
+            format!(
+                "failed to find resource dirs for {}",
+                program_path.display()
+            )
+        })?;
+
+    let mut errors = vec![];
+    validate_pack(
+        program_path,
+        &extracted.pack,
+        &all_resource_dirs,
+        &mut errors,
+    );
+
+    if errors.is_empty() {
+        println!("{}: ok", program_path.display());
+        return Ok(());
+    }
+
+    for error in &errors {
+        eprintln!("{}: {error}", program_path.display());
+    }
+    eprintln!("searched resource dirs:");
+    for resource_dir in &all_resource_dirs {
+        eprintln!("  {}", resource_dir.display());
+    }
+
+    eyre::bail!(
+        "validation of {} failed with {} error(s)",
+        program_path.display(),
+        errors.len()
+    );
+}
+
+fn validate_pack(
+    program_path: &Path,
+    pack: &brioche_pack::Pack,
+    all_resource_dirs: &[PathBuf],
+    errors: &mut Vec<String>,
+) {
+    match pack {
+        brioche_pack::Pack::LdLinux {
+            program,
+            interpreter,
+            library_dirs,
+            runtime_library_dirs,
+        } => {
+            validate_resource("program", program, all_resource_dirs, errors);
+            validate_resource("interpreter", interpreter, all_resource_dirs, errors);
+            for library_dir in library_dirs {
+                validate_resource("library dir", library_dir, all_resource_dirs, errors);
+            }
+
+            let program_dir = program_path.parent();
+            for library_dir in runtime_library_dirs {
+                let Ok(library_dir_path) = library_dir.to_path() else {
+                    errors.push(format!(
+                        "invalid runtime library dir path: {}",
+                        bstr::BStr::new(library_dir)
+                    ));
+                    continue;
+                };
+                let Some(program_dir) = program_dir else {
+                    errors.push("could not get parent of program path".to_string());
+                    continue;
+                };
+                let resolved_library_dir = program_dir.join(library_dir_path);
+                if !resolved_library_dir.is_dir() {
+                    errors.push(format!(
+                        "runtime library dir not found: {}",
+                        resolved_library_dir.display()
+                    ));
+                }
+            }
+        }
+        brioche_pack::Pack::Static { library_dirs } => {
+            for library_dir in library_dirs {
+                validate_resource("library dir", library_dir, all_resource_dirs, errors);
+            }
+        }
+        brioche_pack::Pack::Metadata {
+            resource_paths,
+            format,
+            metadata,
+        } => {
+            for resource_path in resource_paths {
+                validate_resource("resource", resource_path, all_resource_dirs, errors);
+            }
+
+            if runnable_core::is_runnable_format(format) {
+                match runnable_core::load_runnable(format, metadata, all_resource_dirs) {
+                    Ok(runnable) => {
+                        validate_runnable(
+                            program_path,
+                            &runnable,
+                            resource_paths,
+                            all_resource_dirs,
+                            errors,
+                        );
+                    }
+                    Err(error) => {
+                        errors.push(format!("failed to load runnable metadata: {error}"));
+                    }
+                }
+            } else if format == brioche_autopack::WINDOWS_DLL_DIRS_FORMAT {
+                // The DLL dirs are the resource paths, which were already
+                // validated
+            } else {
+                errors.push(format!("unknown metadata format: {format:?}"));
+            }
+        }
+    }
+}
+
+/// Convert a pack to JSON for display. Bincode-encoded runnable metadata
+/// (see [`runnable_core::BINCODE_FORMAT`]) is decoded and shown as a JSON
+/// object, since the raw bytes aren't readable.
+fn pack_to_json(pack: &brioche_pack::Pack) -> eyre::Result<serde_json::Value> {
+    let mut value = serde_json::to_value(pack)?;
+    if let brioche_pack::Pack::Metadata {
+        format, metadata, ..
+    } = pack
+    {
+        if format == runnable_core::BINCODE_FORMAT {
+            let runnable = runnable_core::Runnable::from_bincode(metadata)
+                .context("failed to decode runnable metadata")?;
+            value["metadata"] = serde_json::to_value(&runnable)?;
+        }
+    }
+
+    Ok(value)
+}
+
+/// Check a runnable in a metadata pack for problems before injecting it.
+/// Runnables stored in a resource (see [`runnable_core::REFERENCE_FORMAT`])
+/// aren't checked, since the resource may not exist yet.
+fn validate_pack_metadata(pack: &brioche_pack::Pack) -> eyre::Result<()> {
+    let brioche_pack::Pack::Metadata {
+        resource_paths,
+        format,
+        metadata,
+    } = pack
+    else {
+        return Ok(());
+    };
+    let runnable: runnable_core::Runnable = match &**format {
+        runnable_core::FORMAT | runnable_core::FORMAT_V0_1_0 => {
+            serde_json::from_slice(metadata).context("failed to parse runnable metadata")?
+        }
+        runnable_core::BINCODE_FORMAT => runnable_core::Runnable::from_bincode(metadata)
+            .context("failed to decode runnable metadata")?,
+        _ => {
+            return Ok(());
+        }
+    };
+    let diagnostics = runnable.validate(resource_paths);
+    if !diagnostics.is_empty() {
+        let diagnostics = diagnostics
+            .iter()
+            .map(|diagnostic| format!("- {diagnostic}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        eyre::bail!("invalid runnable metadata:\n{diagnostics}");
+    }
+
+    Ok(())
+}
+
+fn validate_runnable(
+    program_path: &Path,
+    runnable: &runnable_core::Runnable,
+    resource_paths: &[Vec<u8>],
+    all_resource_dirs: &[PathBuf],
+    errors: &mut Vec<String>,
+) {
+    for diagnostic in runnable.validate(resource_paths) {
+        errors.push(diagnostic.to_string());
+    }
+
+    // Templates aren't rendered, since env var components depend on the
+    // environment when the program runs rather than when it's packed.
+    // Everything else about the components was already checked by
+    // `Runnable::validate`, except that their resources exist
+    let mut validate_template = |name: &str, template: &runnable_core::Template| {
+        for component in &template.components {
+            if let runnable_core::TemplateComponent::Resource { resource } = component {
+                validate_resource(name, resource, all_resource_dirs, errors);
+            }
+        }
+    };
+
+    validate_template("command", &runnable.command);
+
+    for (n, arg) in runnable.args.iter().enumerate() {
+        match arg {
+            runnable_core::ArgValue::Arg { value }
+            | runnable_core::ArgValue::Spread { value, .. } => {
+                validate_template(&format!("arg {n}"), value);
+            }
+            runnable_core::ArgValue::IfEnvSet { .. } | runnable_core::ArgValue::Optional { .. } => {
+                // Depends on the environment when the program runs
+            }
+            runnable_core::ArgValue::Rest => {}
+        }
+    }
+
+    for (env_name, env_value) in &runnable.env {
+        for value in env_value.templates() {
+            validate_template(&format!("env var ${env_name}"), value);
+        }
+    }
+
+    for (n, pre_command) in runnable.pre_commands.iter().enumerate() {
+        validate_template(&format!("pre-command {n}"), &pre_command.command);
+        for (m, arg) in pre_command.args.iter().enumerate() {
+            if let runnable_core::ArgValue::Arg { value }
+            | runnable_core::ArgValue::Spread { value, .. } = arg
+            {
+                validate_template(&format!("pre-command {n} arg {m}"), value);
+            }
+        }
+    }
+
+    for (platform, variant) in &runnable.platforms {
+        validate_template(&format!("{platform} command"), &variant.command);
+        for (n, arg) in variant.args.iter().flatten().enumerate() {
+            if let runnable_core::ArgValue::Arg { value }
+            | runnable_core::ArgValue::Spread { value, .. } = arg
+            {
+                validate_template(&format!("{platform} arg {n}"), value);
+            }
+        }
+    }
+
+    for (env_name, env_value) in &runnable.env {
+        if let runnable_core::EnvValue::PrependPath { path, .. } = env_value {
+            if let Err(error) = path.to_path_buf(program_path, all_resource_dirs) {
+                errors.push(format!("failed to resolve env var ${env_name}: {error}"));
+            }
+        }
+    }
+
+    if let Some(source) = &runnable.source {
+        match &source.path {
+            runnable_core::RunnablePath::RelativePath { path } => {
+                let Ok(path) = path.to_path() else {
+                    errors.push(format!("invalid source path: {}", bstr::BStr::new(path)));
+                    return;
+                };
+                let source_path = program_path.join(path);
+                if !source_path.exists() {
+                    errors.push(format!("source path not found: {}", path.display()));
+                }
+            }
+            runnable_core::RunnablePath::Resource { resource } => {
+                validate_resource("source", resource, all_resource_dirs, errors);
+            }
+        }
+    }
+}
+
+fn validate_resource(
+    name: &str,
+    resource: &[u8],
+    all_resource_dirs: &[PathBuf],
+    errors: &mut Vec<String>,
+) {
+    let Ok(resource_path) = resource.to_path() else {
+        errors.push(format!(
+            "invalid {name} resource path: {}",
+            bstr::BStr::new(resource)
+        ));
+        return;
+    };
+
+    if brioche_resources::find_in_resource_dirs(all_resource_dirs, resource_path).is_none() {
+        errors.push(format!(
+            "{name} resource not found: {}",
+            resource_path.display()
+        ));
+    }
+}
+
+#[derive(Debug, Parser)]
+struct ConvertArgs {
+    program: PathBuf,
+
+    /// The pack type to convert to
+    #[arg(long, value_enum)]
+    to: convert::ConvertTo,
+
+    /// The packed executable to use as the runtime for the converted
+    /// program. Must support the new pack type
+    #[arg(long)]
+    packed_executable: PathBuf,
+
+    /// Where to write the converted program. Defaults to updating the
+    /// program in place
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+struct MakeRunnableArgs {
+    program: PathBuf,
+
+    /// The runnable launcher to pack the runnable into
+    #[arg(long)]
+    runnable: PathBuf,
+
+    /// The runnable metadata as JSON. `{ "variable": "target" }` objects
+    /// are replaced with the program
+    #[arg(long)]
+    template: String,
+
+    /// Where to write the runnable. Defaults to replacing the program in
+    /// place
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// How to encode the runnable metadata
+    #[arg(long, value_enum, default_value = "json")]
+    encoding: RunnableEncoding,
+}
+
+#[derive(Debug, Parser)]
+struct WrapDirArgs {
+    /// The directory with the executables to wrap, which are replaced
+    /// in place
+    dir: PathBuf,
+
+    /// The runnable launcher to pack each runnable into
+    #[arg(long)]
+    runnable: PathBuf,
+
+    /// The runnable metadata for each executable as JSON.
+    /// `{ "variable": "target" }` objects are replaced with the executable
+    #[arg(long)]
+    template: String,
+
+    /// How to encode the runnable metadata
+    #[arg(long, value_enum, default_value = "json")]
+    encoding: RunnableEncoding,
+}
+
+#[derive(Debug, Parser)]
+struct ExportOciArgs {
+    program: PathBuf,
+
+    /// The directory to write the OCI image layout to
+    #[arg(long)]
+    output: PathBuf,
+
+    /// The reference name to tag the image with in the layout's index
+    #[arg(long, default_value = "latest")]
+    tag: String,
+
+    /// The image architecture, using OCI names (e.g. `amd64`, `arm64`).
+    /// Defaults to the current platform
+    #[arg(long)]
+    architecture: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+struct ExportBundleArgs {
+    program: PathBuf,
+
+    /// The path to write the bundle to
+    #[arg(long)]
+    output: PathBuf,
+
+    /// The launcher to embed in the bundle. Defaults to
+    /// `brioche-bundle-exec` next to `brioche-packer`
+    #[arg(long)]
+    launcher: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+struct SbomArgs {
+    /// Files or directories to include. Directories are walked recursively
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// The SBOM document format
+    #[arg(long, value_enum, default_value = "spdx")]
+    format: sbom::SbomFormat,
+}
+
+#[derive(Debug, Parser)]
+struct EditArgs {
+    program: PathBuf,
+
+    /// A JSON merge patch (RFC 7396) to apply to the runnable metadata
+    #[arg(long)]
+    merge_patch: Option<String>,
+
+    /// Set an env var to a literal value, in the form `<NAME>=<VALUE>`
+    #[arg(long = "env-set", value_parser)]
+    env_set: Vec<EnvVarAssignment>,
+
+    /// Remove an env var entry from the runnable metadata
+    #[arg(long = "env-unset")]
+    env_unset: Vec<String>,
+
+    /// How to encode the edited runnable metadata. Defaults to the
+    /// program's current encoding
+    #[arg(long, value_enum)]
+    encoding: Option<RunnableEncoding>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum RunnableEncoding {
+    /// JSON metadata, stored in a resource if it's large
+    Json,
+
+    /// Compact bincode metadata, always stored in the pack
+    Bincode,
+}
+
+#[derive(Debug, Clone)]
+struct EnvVarAssignment {
+    name: String,
+    value: String,
+}
+
+impl std::str::FromStr for EnvVarAssignment {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| eyre::eyre!("expected `<NAME>=<VALUE>` format"))?;
+        eyre::ensure!(!name.is_empty(), "env var name must not be empty");
+
+        Ok(Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+fn run_edit(args: EditArgs) -> eyre::Result<()> {
+    let program = std::fs::File::open(&args.program)?;
+    let extracted = brioche_pack::extract_pack(program)?;
+
+    let brioche_pack::Pack::Metadata {
+        resource_paths,
+        format,
+        metadata,
+    } = extracted.pack
+    else {
+        eyre::bail!(
+            "expected a metadata pack, but {} has a different pack type",
+            args.program.display()
+        );
+    };
+    eyre::ensure!(
+        runnable_core::is_runnable_format(&format),
+        "unsupported metadata format: {format:?}"
+    );
+
+    let all_resource_dirs = brioche_resources::find_resource_dirs(&args.program, true)?;
+    let runnable = runnable_core::load_runnable(&format, &metadata, &all_resource_dirs)
+        .context("failed to load runnable metadata")?;
+
+    // If the metadata was stored in a resource, drop the old resource from
+    // the pack. A new one gets added if the edited metadata is still large
+    let mut resource_paths = resource_paths;
+    if format == runnable_core::REFERENCE_FORMAT {
+        let reference: runnable_core::RunnableReference = serde_json::from_slice(&metadata)?;
+        resource_paths.retain(|resource_path| *resource_path != reference.resource);
+    }
+
+    let mut runnable_json = serde_json::to_value(&runnable)?;
+    if let Some(merge_patch) = &args.merge_patch {
+        let merge_patch: serde_json::Value =
+            serde_json::from_str(merge_patch).context("failed to parse merge patch")?;
+        apply_merge_patch(&mut runnable_json, merge_patch);
+    }
+
+    let mut runnable: runnable_core::Runnable = serde_json::from_value(runnable_json)
+        .context("runnable metadata is invalid after applying merge patch")?;
+
+    for name in &args.env_unset {
+        runnable.env.retain(|(env_name, _)| env_name != name);
+    }
+
+    for EnvVarAssignment { name, value } in args.env_set {
+        let env_value = runnable_core::EnvValue::Set {
+            value: runnable_core::Template::from_literal(value.into_bytes()),
+        };
+        let existing = runnable
+            .env
+            .iter_mut()
+            .find(|(env_name, _)| *env_name == name);
+        match existing {
+            Some((_, existing_value)) => {
+                *existing_value = env_value;
+            }
+            None => {
+                runnable.env.push((name, env_value));
+            }
+        }
+    }
+
+    // Make sure any resources referenced by the edited metadata are
+    // included in the pack's resource paths
+    for resource in runnable.resources() {
+        if !resource_paths.contains(resource) {
+            resource_paths.push(resource.clone());
+        }
+    }
+
+    let encoding = args
+        .encoding
+        .unwrap_or(if format == runnable_core::BINCODE_FORMAT {
+            RunnableEncoding::Bincode
+        } else {
+            RunnableEncoding::Json
+        });
+    let new_pack = runnable_pack(&args.program, &runnable, resource_paths, encoding)?;
+
+    let mut program = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&args.program)?;
+    program.set_len(extracted.unpacked_len.try_into()?)?;
+    program.seek(std::io::SeekFrom::End(0))?;
+
+    brioche_pack::inject_pack(&mut program, &new_pack)?;
+
+    Ok(())
+}
+
+/// Build the metadata pack for a runnable packed into `program`. JSON
+/// metadata that's too large to store in the pack is added to the
+/// program's output resource dir.
+fn runnable_pack(
+    program: &Path,
+    runnable: &runnable_core::Runnable,
+    mut resource_paths: Vec<Vec<u8>>,
+    encoding: RunnableEncoding,
+) -> eyre::Result<brioche_pack::Pack> {
+    let pack = match encoding {
+        RunnableEncoding::Json => {
+            let output_resource_dir = brioche_resources::find_output_resource_dir(program)?;
+            let program_name = program
+                .file_name()
+                .ok_or_eyre("could not get program name from path")?;
+            let mut metadata_name = program_name.to_owned();
+            metadata_name.push(".runnable.json");
+            brioche_autopack::runnable_metadata_pack(
+                &output_resource_dir,
+                runnable,
+                resource_paths,
+                Path::new(&metadata_name),
+            )?
+        }
+        RunnableEncoding::Bincode => {
+            resource_paths.sort();
+            resource_paths.dedup();
+            brioche_pack::Pack::Metadata {
+                resource_paths,
+                format: runnable_core::BINCODE_FORMAT.to_string(),
+                metadata: runnable.to_bincode()?,
+            }
+        }
+    };
+
+    Ok(pack)
+}
+
+/// Apply a JSON merge patch, as described by RFC 7396.
+fn apply_merge_patch(target: &mut serde_json::Value, patch: serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    let target = target.as_object_mut().expect("target is not an object");
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            let target_value = target.entry(key).or_insert(serde_json::Value::Null);
+            apply_merge_patch(target_value, value);
+        }
+    }
+}
+
 pub fn is_executable(permissions: &std::fs::Permissions) -> bool {
     use std::os::unix::fs::PermissionsExt as _;
 
@@ -0,0 +1,169 @@
+use std::{
+    os::unix::fs::{OpenOptionsExt as _, PermissionsExt as _},
+    path::{Path, PathBuf},
+};
+
+use bstr::ByteVec as _;
+use eyre::{Context as _, OptionExt as _};
+
+/// The template variable replaced with the program being wrapped.
+const TARGET_VARIABLE: &str = "target";
+
+pub struct MakeRunnableOptions<'a> {
+    pub program: &'a Path,
+    pub runnable: &'a Path,
+    pub template: &'a serde_json::Value,
+    pub output: &'a Path,
+    pub encoding: crate::RunnableEncoding,
+}
+
+/// Wrap a program in a runnable launcher. The program is added to the
+/// output's resource dir, and `template` (runnable metadata as JSON) is
+/// packed into a copy of the `runnable` launcher with the given encoding. Any `{ "variable":
+/// "target" }` object in the template is replaced with the program's
+/// resource, such as a command of `{ "components": [{ "variable": "target"
+/// }] }`.
+pub fn make_runnable(options: &MakeRunnableOptions) -> eyre::Result<()> {
+    let output_resource_dir = brioche_resources::find_output_resource_dir(options.output)?;
+    let program_name = options
+        .program
+        .file_name()
+        .ok_or_eyre("could not get program name from path")?;
+
+    let program = std::fs::File::open(options.program)
+        .with_context(|| format!("failed to open {}", options.program.display()))?;
+    let target_resource = brioche_resources::add_named_blob(
+        &output_resource_dir,
+        program,
+        true,
+        Path::new(program_name),
+    )?;
+    let target_resource = <Vec<u8>>::from_path_buf(target_resource)
+        .map_err(|_| eyre::eyre!("invalid resource path"))?;
+    let target_component = serde_json::to_value(runnable_core::TemplateComponent::Resource {
+        resource: target_resource,
+    })?;
+
+    let mut runnable = options.template.clone();
+    replace_variable(&mut runnable, TARGET_VARIABLE, &target_component);
+    let runnable: runnable_core::Runnable =
+        serde_json::from_value(runnable).context("invalid runnable template")?;
+
+    let resource_paths: Vec<_> = runnable.resources().into_iter().cloned().collect();
+    let diagnostics = runnable.validate(&resource_paths);
+    if !diagnostics.is_empty() {
+        let diagnostics = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect::<Vec<_>>();
+        eyre::bail!("invalid runnable template:\n{}", diagnostics.join("\n"));
+    }
+
+    let pack = crate::runnable_pack(options.output, &runnable, resource_paths, options.encoding)?;
+
+    let mut runnable_launcher = std::fs::File::open(options.runnable).with_context(|| {
+        format!(
+            "failed to open runnable launcher {}",
+            options.runnable.display()
+        )
+    })?;
+
+    // Write to a temporary file first, since the output may be the same
+    // file as the program
+    let output_dir = options
+        .output
+        .parent()
+        .ok_or_eyre("could not get parent of output path")?;
+    let output_name = options
+        .output
+        .file_name()
+        .ok_or_eyre("could not get output filename")?;
+    let mut temp_name = output_name.to_owned();
+    temp_name.push(format!(".make-runnable-{}", std::process::id()));
+    let temp_path = output_dir.join(temp_name);
+
+    let mut output = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o777)
+        .open(&temp_path)
+        .with_context(|| format!("failed to create {}", temp_path.display()))?;
+    std::io::copy(&mut runnable_launcher, &mut output)?;
+    brioche_pack::inject_pack(&mut output, &pack)?;
+    drop(output);
+
+    std::fs::rename(&temp_path, options.output)
+        .with_context(|| format!("failed to write {}", options.output.display()))?;
+
+    Ok(())
+}
+
+/// Wrap every executable file directly inside `dir` with
+/// [`make_runnable`], replacing each one in place. Returns the paths of
+/// the wrapped programs.
+pub fn wrap_dir(
+    dir: &Path,
+    runnable: &Path,
+    template: &serde_json::Value,
+    encoding: crate::RunnableEncoding,
+) -> eyre::Result<Vec<PathBuf>> {
+    let mut programs = vec![];
+    for entry in std::fs::read_dir(dir)
+        .with_context(|| format!("failed to read directory {}", dir.display()))?
+    {
+        let entry = entry?;
+
+        // Symlinks are left alone, so they keep pointing to the wrapped
+        // program
+        let metadata = entry.metadata()?;
+        let is_executable = metadata.permissions().mode() & 0o111 != 0;
+        if metadata.is_file() && is_executable {
+            programs.push(entry.path());
+        }
+    }
+    programs.sort();
+
+    for program in &programs {
+        make_runnable(&MakeRunnableOptions {
+            program,
+            runnable,
+            template,
+            output: program,
+            encoding,
+        })
+        .with_context(|| format!("failed to wrap {}", program.display()))?;
+    }
+
+    Ok(programs)
+}
+
+/// Replace each `{ "variable": <name> }` object in `value` with
+/// `replacement`.
+fn replace_variable(value: &mut serde_json::Value, name: &str, replacement: &serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            let is_variable = object.len() == 1
+                && object
+                    .get("variable")
+                    .and_then(|variable| variable.as_str())
+                    == Some(name);
+            if is_variable {
+                *value = replacement.clone();
+                return;
+            }
+
+            for value in object.values_mut() {
+                replace_variable(value, name, replacement);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                replace_variable(value, name, replacement);
+            }
+        }
+        serde_json::Value::Null
+        | serde_json::Value::Bool(_)
+        | serde_json::Value::Number(_)
+        | serde_json::Value::String(_) => {}
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    io::{Read as _, Write as _},
+    path::{Path, PathBuf},
+};
+
+use eyre::{Context as _, OptionExt as _};
+use sha2::Digest as _;
+
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+pub struct ExportOciOptions<'a> {
+    pub program: &'a Path,
+    pub output: &'a Path,
+    pub tag: &'a str,
+    pub architecture: &'a str,
+}
+
+/// Export a program and its resource closure as an OCI image layout. The
+/// image has a single layer, with the program at `/bin/<name>` and the
+/// resources under `/brioche-resources.d`, so the program finds its
+/// resources the same way it does outside the container.
+pub fn export_oci(options: &ExportOciOptions) -> eyre::Result<()> {
+    let program_name = options
+        .program
+        .file_name()
+        .ok_or_eyre("could not get program name from path")?;
+    let blobs_dir = options.output.join("blobs").join("sha256");
+    std::fs::create_dir_all(&blobs_dir)
+        .with_context(|| format!("failed to create {}", blobs_dir.display()))?;
+
+    // Build the layer
+    let layer_temp_path = blobs_dir.join("layer.tar.tmp");
+    let layer_file = std::fs::File::create(&layer_temp_path)?;
+    let mut layer = tar::Builder::new(layer_file);
+    let program_entry_path = Path::new("bin").join(program_name);
+    crate::closure::append_program_closure(&mut layer, options.program, &program_entry_path)?;
+    layer.into_inner()?.sync_all()?;
+    let layer = commit_blob_file(&blobs_dir, &layer_temp_path)?;
+
+    // Write the image config
+    let entrypoint = Path::new("/").join(&program_entry_path);
+    let entrypoint = entrypoint
+        .to_str()
+        .ok_or_eyre("program name is not valid UTF-8")?;
+    let config = serde_json::json!({
+        "architecture": options.architecture,
+        "os": "linux",
+        "config": {
+            "Entrypoint": [entrypoint],
+        },
+        "rootfs": {
+            "type": "layers",
+            "diff_ids": [layer.digest],
+        },
+    });
+    let config = write_blob(&blobs_dir, &serde_json::to_vec(&config)?)?;
+
+    // Write the manifest, then the index pointing to it
+    let manifest = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "config": config.descriptor(CONFIG_MEDIA_TYPE),
+        "layers": [layer.descriptor(LAYER_MEDIA_TYPE)],
+    });
+    let manifest = write_blob(&blobs_dir, &serde_json::to_vec(&manifest)?)?;
+
+    let mut manifest_descriptor = manifest.descriptor(MANIFEST_MEDIA_TYPE);
+    manifest_descriptor["annotations"] = serde_json::json!({
+        "org.opencontainers.image.ref.name": options.tag,
+    });
+    let index = serde_json::json!({
+        "schemaVersion": 2,
+        "manifests": [manifest_descriptor],
+    });
+    std::fs::write(
+        options.output.join("index.json"),
+        serde_json::to_vec(&index)?,
+    )?;
+    std::fs::write(
+        options.output.join("oci-layout"),
+        serde_json::to_vec(&serde_json::json!({ "imageLayoutVersion": "1.0.0" }))?,
+    )?;
+
+    Ok(())
+}
+
+/// Get the OCI architecture name for the current platform.
+pub fn default_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    }
+}
+
+struct Blob {
+    digest: String,
+    size: u64,
+}
+
+impl Blob {
+    fn descriptor(&self, media_type: &str) -> serde_json::Value {
+        serde_json::json!({
+            "mediaType": media_type,
+            "digest": self.digest,
+            "size": self.size,
+        })
+    }
+}
+
+fn write_blob(blobs_dir: &Path, contents: &[u8]) -> eyre::Result<Blob> {
+    let hash = hex_digest(sha2::Sha256::digest(contents));
+    let mut file = std::fs::File::create(blobs_dir.join(&hash))?;
+    file.write_all(contents)?;
+
+    Ok(Blob {
+        digest: format!("sha256:{hash}"),
+        size: contents.len().try_into()?,
+    })
+}
+
+/// Hash a file written to the blobs dir, then move it to its final path.
+fn commit_blob_file(blobs_dir: &Path, temp_path: &Path) -> eyre::Result<Blob> {
+    let mut file = std::fs::File::open(temp_path)?;
+    let mut hasher = sha2::Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let length = file.read(&mut buffer)?;
+        if length == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..length]);
+        size += u64::try_from(length)?;
+    }
+
+    let hash = hex_digest(hasher.finalize());
+    let blob_path: PathBuf = blobs_dir.join(&hash);
+    std::fs::rename(temp_path, &blob_path)?;
+
+    Ok(Blob {
+        digest: format!("sha256:{hash}"),
+        size,
+    })
+}
+
+fn hex_digest(digest: impl AsRef<[u8]>) -> String {
+    digest
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
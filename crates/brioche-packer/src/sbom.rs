@@ -0,0 +1,273 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context as _;
+use sha2::Digest as _;
+
+use brioche_autopack::ResourceKind;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum SbomFormat {
+    Spdx,
+    Cyclonedx,
+}
+
+/// A file included in the SBOM.
+struct SbomFile {
+    /// The path of the file. For input files, this is the path as given,
+    /// and for resources this is the path within the resource dir.
+    name: String,
+    sha256: String,
+    kind: SbomFileKind,
+    resource: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SbomFileKind {
+    Input,
+    Resource(ResourceKind),
+}
+
+impl SbomFileKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Input => "input",
+            Self::Resource(kind) => kind.as_str(),
+        }
+    }
+}
+
+/// Build an SBOM for the given paths. Directories are walked recursively,
+/// and each packed file contributes its full resource closure.
+pub fn sbom(paths: &[PathBuf], format: SbomFormat) -> eyre::Result<serde_json::Value> {
+    let mut files = BTreeMap::new();
+
+    for path in paths {
+        for entry in walkdir::WalkDir::new(path).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let input_path = entry.path();
+            let name = input_path.display().to_string();
+            files.insert(
+                name.clone(),
+                SbomFile {
+                    name,
+                    sha256: sha256_file(input_path)?,
+                    kind: SbomFileKind::Input,
+                    resource: None,
+                },
+            );
+
+            let all_resource_dirs = brioche_resources::find_resource_dirs(input_path, true)
+                .with_context(|| {
+                    format!("failed to find resource dirs for {}", input_path.display())
+                })?;
+            let resources = crate::closure::resource_closure(input_path, &all_resource_dirs)?;
+            for (resource, kind) in resources {
+                add_resource_files(&mut files, &resource, kind, &all_resource_dirs)?;
+            }
+        }
+    }
+
+    let files = files.into_values().collect::<Vec<_>>();
+    let document = match format {
+        SbomFormat::Spdx => spdx_document(&files),
+        SbomFormat::Cyclonedx => cyclonedx_document(&files),
+    };
+    Ok(document)
+}
+
+fn add_resource_files(
+    files: &mut BTreeMap<String, SbomFile>,
+    resource: &Path,
+    kind: ResourceKind,
+    all_resource_dirs: &[PathBuf],
+) -> eyre::Result<()> {
+    let resource_path = brioche_resources::find_in_resource_dirs(all_resource_dirs, resource)
+        .ok_or_else(|| eyre::eyre!("resource not found: {}", resource.display()))?;
+
+    for entry in walkdir::WalkDir::new(&resource_path)
+        .follow_links(true)
+        .sort_by_file_name()
+    {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let subpath = entry.path().strip_prefix(&resource_path)?;
+        let name = resource.join(subpath).display().to_string();
+        if files.contains_key(&name) {
+            continue;
+        }
+
+        files.insert(
+            name.clone(),
+            SbomFile {
+                name,
+                sha256: sha256_file(entry.path())?,
+                kind: SbomFileKind::Resource(kind),
+                resource: Some(resource.display().to_string()),
+            },
+        );
+    }
+
+    Ok(())
+}
+
+fn spdx_document(files: &[SbomFile]) -> serde_json::Value {
+    let spdx_files = files
+        .iter()
+        .enumerate()
+        .map(|(n, file)| {
+            let mut comment = format!("brioche: {}", file.kind.as_str());
+            if let Some(resource) = &file.resource {
+                comment.push_str(&format!(", resource {resource}"));
+            }
+
+            serde_json::json!({
+                "SPDXID": format!("SPDXRef-File-{n}"),
+                "fileName": file.name,
+                "fileTypes": ["BINARY"],
+                "checksums": [{
+                    "algorithm": "SHA256",
+                    "checksumValue": file.sha256,
+                }],
+                "comment": comment,
+            })
+        })
+        .collect::<Vec<_>>();
+    let relationships = (0..files.len())
+        .map(|n| {
+            serde_json::json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": format!("SPDXRef-File-{n}"),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": "brioche-sbom",
+        "documentNamespace": format!("https://brioche.dev/spdx/{}", document_hash(files)),
+        "creationInfo": {
+            "created": timestamp(),
+            "creators": ["Tool: brioche-packer"],
+        },
+        "files": spdx_files,
+        "relationships": relationships,
+    })
+}
+
+fn cyclonedx_document(files: &[SbomFile]) -> serde_json::Value {
+    let components = files
+        .iter()
+        .map(|file| {
+            let mut properties = vec![serde_json::json!({
+                "name": "brioche:kind",
+                "value": file.kind.as_str(),
+            })];
+            if let Some(resource) = &file.resource {
+                properties.push(serde_json::json!({
+                    "name": "brioche:resource",
+                    "value": resource,
+                }));
+            }
+
+            serde_json::json!({
+                "type": "file",
+                "name": file.name,
+                "hashes": [{
+                    "alg": "SHA-256",
+                    "content": file.sha256,
+                }],
+                "properties": properties,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "serialNumber": format!("urn:brioche:sbom:{}", document_hash(files)),
+        "metadata": {
+            "timestamp": timestamp(),
+            "tools": [{ "name": "brioche-packer" }],
+        },
+        "components": components,
+    })
+}
+
+/// Hash the names and hashes of every file, used to give each document a
+/// stable unique ID.
+fn document_hash(files: &[SbomFile]) -> String {
+    let mut hasher = sha2::Sha256::new();
+    for file in files {
+        hasher.update(file.name.as_bytes());
+        hasher.update([0]);
+        hasher.update(file.sha256.as_bytes());
+        hasher.update([0]);
+    }
+    hex(&hasher.finalize())
+}
+
+fn sha256_file(path: &Path) -> eyre::Result<String> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut hasher = sha2::Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Get the document timestamp as an RFC 3339 string. Uses
+/// `$SOURCE_DATE_EPOCH` if set, so documents can be reproducible.
+fn timestamp() -> String {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0)
+        });
+
+    let days = seconds / 86400;
+    let time_of_day = seconds % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time_of_day / 3600,
+        (time_of_day / 60) % 60,
+        time_of_day % 60
+    )
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) date, based
+/// on Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
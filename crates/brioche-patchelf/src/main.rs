@@ -0,0 +1,360 @@
+use std::{
+    io::{Read as _, Seek as _},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use bstr::{ByteSlice as _, ByteVec as _};
+use eyre::{Context as _, OptionExt as _};
+
+#[derive(Debug)]
+enum PatchelfArg {
+    Arg(std::ffi::OsString),
+    Output(PathBuf),
+    InputPath(PathBuf),
+}
+
+fn main() -> ExitCode {
+    let result = run();
+
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> eyre::Result<ExitCode> {
+    let wrapper = brioche_toolwrap::Wrapper::new("brioche-patchelf")?;
+    let patchelf = wrapper.tool("patchelf");
+
+    // If autopacking is disabled, call the original `patchelf` binary and
+    // bail early
+    if !brioche_toolwrap::env_toggle("BRIOCHE_PATCHELF_AUTOPACK", true) {
+        let mut command = std::process::Command::new(patchelf);
+        command.args(std::env::args_os().skip(1));
+        let status = command.status()?;
+
+        return Ok(brioche_toolwrap::exit_code(status));
+    }
+
+    let mut args = std::env::args_os().skip(1);
+    let mut patchelf_args = vec![];
+    let mut modifies_files = false;
+
+    // Parse each argument
+    while let Some(arg) = args.next() {
+        let arg_bytes = brioche_toolwrap::arg_bytes(&arg)?;
+
+        match &**arg_bytes {
+            b"--output" => {
+                let output = args.next().ok_or_eyre("expected path after --output")?;
+                patchelf_args.push(PatchelfArg::Output(PathBuf::from(output)));
+            }
+            b"--replace-needed" => {
+                // Replacing a library takes two extra parameters
+                let old_library = args.next().ok_or_eyre("expected arg after flag")?;
+                let new_library = args.next().ok_or_eyre("expected arg after flag")?;
+                patchelf_args.extend([
+                    PatchelfArg::Arg(arg),
+                    PatchelfArg::Arg(old_library),
+                    PatchelfArg::Arg(new_library),
+                ]);
+                modifies_files = true;
+            }
+            b"--page-size" | b"--allowed-rpath-prefixes" => {
+                // Options that take an extra parameter, but don't modify
+                // the input files on their own
+                let next_arg = args.next().ok_or_eyre("expected arg after flag")?;
+                patchelf_args.extend([PatchelfArg::Arg(arg), PatchelfArg::Arg(next_arg)]);
+            }
+            b"--set-interpreter"
+            | b"--set-soname"
+            | b"--set-rpath"
+            | b"--add-rpath"
+            | b"--add-needed"
+            | b"--remove-needed"
+            | b"--set-os-abi"
+            | b"--clear-symbol-version"
+            | b"--rename-dynamic-symbols" => {
+                // These are the (known) arguments that take an extra
+                // parameter
+                let next_arg = args.next().ok_or_eyre("expected arg after flag")?;
+                patchelf_args.extend([PatchelfArg::Arg(arg), PatchelfArg::Arg(next_arg)]);
+                modifies_files = true;
+            }
+            _ => {
+                if arg_bytes.starts_with(b"--print-")
+                    || &**arg_bytes == b"--debug"
+                    || &**arg_bytes == b"--version"
+                    || &**arg_bytes == b"--help"
+                {
+                    // Read-only arguments
+                    patchelf_args.push(PatchelfArg::Arg(arg));
+                } else if arg_bytes.starts_with(b"-") {
+                    // Any other flag modifies the input files
+                    patchelf_args.push(PatchelfArg::Arg(arg));
+                    modifies_files = true;
+                } else {
+                    // Other args are treated as input files
+                    let input_path = brioche_toolwrap::arg_path(arg_bytes)?;
+                    patchelf_args.push(PatchelfArg::InputPath(input_path.to_owned()));
+                }
+            }
+        }
+    }
+
+    // Remap packed files so patchelf edits the underlying ELF file instead
+    // of the packed file
+    let remapped_files = remap_files(&mut patchelf_args)?;
+
+    let patchelf_args = patchelf_args
+        .into_iter()
+        .flat_map(|arg| match arg {
+            PatchelfArg::Arg(arg) => vec![arg],
+            PatchelfArg::Output(path) => vec!["--output".into(), path.into_os_string()],
+            PatchelfArg::InputPath(path) => vec![path.into_os_string()],
+        })
+        .collect::<Vec<_>>();
+
+    // Call the original patchelf process
+    let mut command = std::process::Command::new(patchelf);
+    command.args(&patchelf_args);
+    let status = command.status()?;
+
+    if !status.success() {
+        return Ok(brioche_toolwrap::exit_code(status));
+    }
+
+    // Write back each remapped file, unless patchelf was only used to
+    // print information about the files
+    if modifies_files {
+        for remapped_file in remapped_files {
+            finish_remapped_file(remapped_file)?;
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+enum RemapFile {
+    Inject {
+        pack: brioche_pack::Pack,
+        temp_file: tempfile::NamedTempFile,
+        trailing_payload: Option<Vec<u8>>,
+        output_path: PathBuf,
+    },
+    UpdateSource {
+        extracted: brioche_pack::ExtractedPack,
+        input_path: PathBuf,
+        temp_file: tempfile::NamedTempFile,
+        trailing_payload: Option<Vec<u8>>,
+        output_path: PathBuf,
+    },
+}
+
+fn remap_files(args: &mut Vec<PatchelfArg>) -> eyre::Result<Vec<RemapFile>> {
+    let mut output_path_indices = args.iter().enumerate().filter_map(|(n, arg)| match arg {
+        PatchelfArg::Output(_) => Some(n),
+        _ => None,
+    });
+    let output_path_index = output_path_indices.next();
+    eyre::ensure!(
+        output_path_indices.next().is_none(),
+        "--output argument specified multiple times"
+    );
+    let output_path = output_path_index.map(|index| match &args[index] {
+        PatchelfArg::Output(path) => path.clone(),
+        _ => unreachable!(),
+    });
+
+    let input_path_count = args
+        .iter()
+        .filter(|arg| matches!(arg, PatchelfArg::InputPath(_)))
+        .count();
+    eyre::ensure!(
+        output_path.is_none() || input_path_count <= 1,
+        "multiple input paths specified with --output"
+    );
+
+    let mut remapped_files = vec![];
+    let mut remove_output_arg = false;
+
+    for arg in args.iter_mut() {
+        let PatchelfArg::InputPath(path) = arg else {
+            continue;
+        };
+
+        // Try to extract a pack from the input path
+        let mut input = std::fs::File::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let Ok(extracted) = brioche_pack::extract_pack(&mut input) else {
+            // Not a packed file, so patchelf can edit it directly
+            continue;
+        };
+
+        let all_resource_dirs = brioche_resources::find_resource_dirs(path, true)
+            .with_context(|| format!("failed to get resource dirs for {}", path.display()))?;
+        let source_path = brioche_autopack::pack_source(path, &extracted.pack, &all_resource_dirs)
+            .with_context(|| format!("failed to get source path for {}", path.display()))?;
+
+        let mut temp_file = tempfile::NamedTempFile::new()?;
+        let input_path = std::mem::replace(path, temp_file.path().to_path_buf());
+        let output_path = output_path.clone().unwrap_or_else(|| input_path.clone());
+
+        // We write the output file ourselves after patchelf finishes
+        remove_output_arg = true;
+
+        match source_path {
+            brioche_autopack::PackSource::This => {
+                // The pack was appended to the original file, so patch
+                // the unpacked part then re-add the same pack
+                input.rewind()?;
+                let mut unpacked_input = input.take(extracted.unpacked_len.try_into()?);
+                std::io::copy(&mut unpacked_input, &mut temp_file)?;
+                let trailing_payload =
+                    brioche_autopack::detach_trailing_payload(temp_file.as_file_mut())?;
+
+                remapped_files.push(RemapFile::Inject {
+                    pack: extracted.pack,
+                    temp_file,
+                    trailing_payload,
+                    output_path,
+                });
+            }
+            brioche_autopack::PackSource::Path(source_path) => {
+                // The pack refers to a different source file, so patch
+                // a copy of the source then update the pack to point to
+                // the patched copy
+                let mut source = std::fs::File::open(&source_path)?;
+                std::io::copy(&mut source, &mut temp_file)?;
+                let trailing_payload =
+                    brioche_autopack::detach_trailing_payload(temp_file.as_file_mut())?;
+
+                remapped_files.push(RemapFile::UpdateSource {
+                    extracted,
+                    input_path,
+                    temp_file,
+                    trailing_payload,
+                    output_path,
+                });
+            }
+        }
+    }
+
+    if remove_output_arg {
+        if let Some(output_path_index) = output_path_index {
+            args.remove(output_path_index);
+        }
+    }
+
+    Ok(remapped_files)
+}
+
+fn finish_remapped_file(remapped_file: RemapFile) -> eyre::Result<()> {
+    match remapped_file {
+        RemapFile::Inject {
+            pack,
+            mut temp_file,
+            trailing_payload,
+            output_path,
+        } => {
+            // Restore any trailing payload we detached before running the tool
+            brioche_autopack::restore_trailing_payload(
+                temp_file.as_file_mut(),
+                trailing_payload.as_deref(),
+            )?;
+
+            let mut output = std::fs::File::create(&output_path)
+                .with_context(|| format!("failed to open output {}", output_path.display()))?;
+
+            temp_file.rewind()?;
+            std::io::copy(&mut temp_file, &mut output)?;
+
+            brioche_pack::inject_pack(&mut output, &pack)?;
+        }
+        RemapFile::UpdateSource {
+            extracted,
+            input_path,
+            mut temp_file,
+            trailing_payload,
+            output_path,
+        } => {
+            // Restore any trailing payload we detached before running the tool
+            brioche_autopack::restore_trailing_payload(
+                temp_file.as_file_mut(),
+                trailing_payload.as_deref(),
+            )?;
+
+            let input_resource_dirs = brioche_resources::find_resource_dirs(&input_path, true)?;
+            let output_resource_dir = brioche_resources::find_output_resource_dir(&output_path)?;
+
+            let brioche_pack::Pack::LdLinux {
+                program,
+                interpreter,
+                library_dirs,
+                runtime_library_dirs,
+            } = extracted.pack
+            else {
+                eyre::bail!("unsupported pack to update source: {:#?}", extracted.pack);
+            };
+
+            // Re-use the original program's name and permissions for the
+            // patched program
+            let program = program
+                .to_path()
+                .map_err(|_| eyre::eyre!("invalid program path: {}", bstr::BStr::new(&program)))?;
+            let program_name = program
+                .file_name()
+                .ok_or_eyre("could not get program name from path")?;
+            let program_path =
+                brioche_resources::find_in_resource_dirs(&input_resource_dirs, program)
+                    .ok_or_eyre("could not find program in resource dirs")?;
+            let program_metadata =
+                std::fs::metadata(&program_path).context("could not get program metadata")?;
+            let is_executable = is_executable(&program_metadata.permissions());
+
+            temp_file.rewind()?;
+            let new_program = brioche_resources::add_named_blob(
+                &output_resource_dir,
+                &mut temp_file,
+                is_executable,
+                std::path::Path::new(program_name),
+            )?;
+            let new_program = <Vec<u8>>::from_path_buf(new_program)
+                .map_err(|_| eyre::eyre!("invalid UTF-8 in path"))?;
+
+            let new_pack = brioche_pack::Pack::LdLinux {
+                program: new_program,
+                interpreter,
+                library_dirs,
+                runtime_library_dirs,
+            };
+
+            // Copy the unpacked part of the input (the packed executable)
+            // to a temp file first, since the input and output paths may
+            // be the same file
+            let unpacked_len: u64 = extracted.unpacked_len.try_into()?;
+            let input = std::fs::File::open(&input_path)?;
+            let mut unpacked = tempfile::NamedTempFile::new()?;
+            std::io::copy(&mut input.take(unpacked_len), &mut unpacked)?;
+            unpacked.rewind()?;
+
+            let mut output = std::fs::File::create(&output_path)
+                .with_context(|| format!("failed to open output {}", output_path.display()))?;
+            std::io::copy(&mut unpacked, &mut output)?;
+
+            brioche_pack::inject_pack(&mut output, &new_pack)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn is_executable(permissions: &std::fs::Permissions) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    permissions.mode() & 0o100 != 0
+}
@@ -0,0 +1,122 @@
+use std::{
+    ffi::OsString,
+    io::Write as _,
+    os::unix::process::CommandExt as _,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bstr::{ByteSlice as _, ByteVec as _};
+use eyre::Context as _;
+
+fn main() -> ExitCode {
+    let result = run();
+
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("brioche-pkg-config error: {:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> eyre::Result<ExitCode> {
+    let wrapper = brioche_toolwrap::Wrapper::new("brioche-pkg-config")?;
+    let pkg_config = wrapper.tool("pkg-config");
+    let args = std::env::args_os().skip(1).collect::<Vec<_>>();
+
+    // If rewriting is disabled, call the original `pkg-config` binary
+    if !brioche_toolwrap::env_toggle("BRIOCHE_PKG_CONFIG_REWRITE", true) {
+        let error = std::process::Command::new(&pkg_config).args(&args).exec();
+        return Err(error).context("failed to exec pkg-config");
+    }
+
+    let mut command = std::process::Command::new(&pkg_config);
+
+    // Dependencies expose their `.pc` directories through symlinks under
+    // `brioche-env.d/env/PKG_CONFIG_PATH`. Resolve the symlinks first, so
+    // that pkg-config computes the prefix relative to the real dependency
+    // directory rather than the symlink
+    for env_var in ["PKG_CONFIG_PATH", "PKG_CONFIG_LIBDIR"] {
+        if let Some(search_path) = std::env::var_os(env_var) {
+            let search_path = canonicalize_search_path(&search_path)
+                .with_context(|| format!("failed to resolve ${env_var}"))?;
+            command.env(env_var, search_path);
+        }
+    }
+
+    // Relocate the `prefix` variable of each `.pc` file based on where the
+    // file was found, unless the caller asked for something else
+    let has_prefix_arg = args
+        .iter()
+        .any(|arg| arg == "--define-prefix" || arg == "--dont-define-prefix");
+    if !has_prefix_arg {
+        command.arg("--define-prefix");
+    }
+
+    command.args(&args);
+    command.stderr(std::process::Stdio::inherit());
+    let output = command.output().context("failed to run pkg-config")?;
+
+    let stdout = rewrite_output(&output.stdout);
+    std::io::stdout().lock().write_all(&stdout)?;
+
+    Ok(brioche_toolwrap::exit_code(output.status))
+}
+
+fn canonicalize_search_path(search_path: &std::ffi::OsStr) -> eyre::Result<OsString> {
+    let paths = std::env::split_paths(search_path).map(|path| canonicalize_or_keep(&path));
+    let search_path = std::env::join_paths(paths)?;
+    Ok(search_path)
+}
+
+/// Rewrite `-L`/`-I` flags and bare paths in pkg-config's output so they
+/// point to canonical paths instead of going through symlinks.
+fn rewrite_output(output: &[u8]) -> Vec<u8> {
+    let mut rewritten = Vec::with_capacity(output.len());
+
+    for (n, line) in output.split_str("\n").enumerate() {
+        if n > 0 {
+            rewritten.push(b'\n');
+        }
+
+        for (n, word) in line.split_str(" ").enumerate() {
+            if n > 0 {
+                rewritten.push(b' ');
+            }
+
+            let (flag, path) = if let Some(path) = word.strip_prefix(b"-L") {
+                (&b"-L"[..], path)
+            } else if let Some(path) = word.strip_prefix(b"-I") {
+                (&b"-I"[..], path)
+            } else if word.starts_with(b"/") {
+                (&b""[..], word)
+            } else {
+                rewritten.extend_from_slice(word);
+                continue;
+            };
+
+            let rewritten_path = path
+                .to_path()
+                .ok()
+                .map(canonicalize_or_keep)
+                .and_then(|path| <Vec<u8>>::from_path_buf(path).ok());
+            match rewritten_path {
+                Some(rewritten_path) => {
+                    rewritten.extend_from_slice(flag);
+                    rewritten.extend_from_slice(&rewritten_path);
+                }
+                None => {
+                    rewritten.extend_from_slice(word);
+                }
+            }
+        }
+    }
+
+    rewritten
+}
+
+fn canonicalize_or_keep(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
@@ -0,0 +1,37 @@
+use std::process::ExitCode;
+
+use eyre::Context as _;
+
+fn main() -> ExitCode {
+    let result = run();
+
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("brioche-ranlib error: {:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> eyre::Result<ExitCode> {
+    let wrapper = brioche_toolwrap::Wrapper::new("brioche-ranlib")?;
+    let ranlib = wrapper.tool("ranlib");
+    let args = std::env::args_os().skip(1);
+
+    let mut command = std::process::Command::new(ranlib);
+
+    if !brioche_toolwrap::env_toggle("BRIOCHE_RANLIB_DETERMINISTIC", true) {
+        command.args(args);
+    } else {
+        // Force deterministic mode, dropping any request for the
+        // non-deterministic mode
+        command.arg("-D");
+        command.args(args.filter(|arg| arg != "-U"));
+        command.env("ZERO_AR_DATE", "1");
+    }
+
+    let status = command.status().context("failed to run ranlib")?;
+
+    Ok(brioche_toolwrap::exit_code(status))
+}
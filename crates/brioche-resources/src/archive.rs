@@ -0,0 +1,259 @@
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path, PathBuf},
+};
+
+/// Write a set of resources to a tar stream, along with everything they
+/// reference within the resource dir. Each resource path is relative to
+/// the resource dir, such as a path returned by [`crate::add_named_blob`].
+///
+/// Symlinks are kept as symlinks, and their targets are included if they
+/// are within the resource dir, so an alias brings its blob along with it.
+/// Entries are written in sorted order with fixed timestamps and
+/// ownership, so the same resources always produce the same stream. Use
+/// [`import_closure`] to add the resources to another resource dir.
+pub fn export_closure(
+    resource_dir: &Path,
+    resource_paths: &[PathBuf],
+    writer: impl std::io::Write,
+) -> Result<(), ExportClosureError> {
+    let mut entries = BTreeSet::new();
+    let mut pending = vec![];
+    for resource_path in resource_paths {
+        let normalized = normalize_path(resource_path)
+            .filter(|path| !path.as_os_str().is_empty())
+            .ok_or_else(|| ExportClosureError::InvalidResourcePath(resource_path.clone()))?;
+        pending.push(normalized);
+    }
+
+    while let Some(path) = pending.pop() {
+        if entries.contains(&path) {
+            continue;
+        }
+
+        let full_path = resource_dir.join(&path);
+        let metadata = match full_path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ExportClosureError::ResourceNotFound(path));
+            }
+            Err(error) => {
+                return Err(error.into());
+            }
+        };
+
+        if metadata.is_symlink() {
+            let target = std::fs::read_link(&full_path)?;
+            let parent = path.parent().unwrap_or(Path::new(""));
+            if let Some(target) = normalize_path(&parent.join(target)) {
+                if resource_dir.join(&target).symlink_metadata().is_ok() {
+                    pending.push(target);
+                }
+            }
+        } else if metadata.is_dir() {
+            for entry in std::fs::read_dir(&full_path)? {
+                let entry = entry?;
+                pending.push(path.join(entry.file_name()));
+            }
+        }
+
+        // Include parent directories so the stream can be extracted
+        // with consistent permissions
+        for ancestor in path.ancestors().skip(1) {
+            if !ancestor.as_os_str().is_empty() {
+                entries.insert(ancestor.to_owned());
+            }
+        }
+        entries.insert(path);
+    }
+
+    let mut archive = tar::Builder::new(writer);
+    archive.mode(tar::HeaderMode::Deterministic);
+    archive.follow_symlinks(false);
+    for entry in &entries {
+        archive.append_path_with_name(resource_dir.join(entry), entry)?;
+    }
+    archive.finish()?;
+
+    Ok(())
+}
+
+/// Add the resources from a tar stream written by [`export_closure`] to a
+/// resource dir.
+///
+/// The stream is extracted to a temporary directory within the resource
+/// dir first. Each blob and directory is checked against the hash in its
+/// name, then blobs and directories are moved into place before any
+/// aliases, so aliases never point to missing resources. Existing
+/// directories are left as-is, since they have the same contents.
+pub fn import_closure(
+    reader: impl std::io::Read,
+    resource_dir: &Path,
+) -> Result<(), ImportClosureError> {
+    std::fs::create_dir_all(resource_dir)?;
+    let temp_dir = resource_dir.join(format!("import-{}", ulid::Ulid::new()));
+
+    let result = tar::Archive::new(reader)
+        .unpack(&temp_dir)
+        .map_err(ImportClosureError::from)
+        .and_then(|()| import_extracted(&temp_dir, resource_dir));
+
+    match std::fs::remove_dir_all(&temp_dir) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) if result.is_ok() => {
+            return Err(error.into());
+        }
+        Err(_) => {}
+    }
+
+    result
+}
+
+fn import_extracted(temp_dir: &Path, resource_dir: &Path) -> Result<(), ImportClosureError> {
+    if !temp_dir.is_dir() {
+        // Nothing was extracted, so the stream was empty
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(temp_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !matches!(name.to_str(), Some("blobs" | "directories" | "aliases")) {
+            return Err(ImportClosureError::UnexpectedEntry(name.into()));
+        }
+    }
+
+    let blobs_dir = resource_dir.join("blobs");
+    for entry in crate::repair::read_dir_if_exists(&temp_dir.join("blobs"))? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let blob_path = Path::new("blobs").join(&file_name);
+
+        let blob_name = file_name.to_str().unwrap_or_default();
+        let (expected_hash, executable, compressed) =
+            crate::compression::parse_blob_file_name(blob_name)
+                .ok_or_else(|| ImportClosureError::UnexpectedEntry(blob_path.clone()))?;
+
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            return Err(ImportClosureError::UnexpectedEntry(blob_path));
+        }
+
+        let hash =
+            crate::compression::hash_blob_file(&entry.path(), compressed, expected_hash.hasher())?;
+        if hash != Some(expected_hash)
+            || !crate::platform::executable_matches(&metadata, executable)
+        {
+            return Err(ImportClosureError::HashMismatch(blob_path));
+        }
+
+        std::fs::create_dir_all(&blobs_dir)?;
+        std::fs::rename(entry.path(), blobs_dir.join(&file_name))?;
+    }
+
+    let directories_dir = resource_dir.join("directories");
+    for entry in crate::repair::read_dir_if_exists(&temp_dir.join("directories"))? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let directory_path = Path::new("directories").join(&file_name);
+
+        let expected_hash = file_name
+            .to_str()
+            .and_then(crate::parse_directory_name)
+            .ok_or_else(|| ImportClosureError::UnexpectedEntry(directory_path.clone()))?;
+        if !entry.file_type()?.is_dir() {
+            return Err(ImportClosureError::UnexpectedEntry(directory_path));
+        }
+        if crate::hash_directory(&entry.path(), expected_hash.hasher())? != expected_hash {
+            return Err(ImportClosureError::HashMismatch(directory_path));
+        }
+
+        let dest_path = directories_dir.join(&file_name);
+        if dest_path.is_dir() {
+            continue;
+        }
+        std::fs::create_dir_all(&directories_dir)?;
+        std::fs::rename(entry.path(), dest_path)?;
+    }
+
+    let temp_aliases_dir = temp_dir.join("aliases");
+    if temp_aliases_dir.is_dir() {
+        for entry in walkdir::WalkDir::new(&temp_aliases_dir).sort_by_file_name() {
+            let entry = entry.map_err(std::io::Error::from)?;
+            let relative_path = entry
+                .path()
+                .strip_prefix(temp_dir)
+                .expect("walkdir entry is not in temp dir");
+            if entry.file_type().is_dir() {
+                continue;
+            } else if !entry.path_is_symlink() {
+                return Err(ImportClosureError::UnexpectedEntry(
+                    relative_path.to_owned(),
+                ));
+            }
+
+            // Aliases must point within the resource dir
+            let target = std::fs::read_link(entry.path())?;
+            let parent = relative_path.parent().unwrap_or(Path::new(""));
+            if normalize_path(&parent.join(target)).is_none() {
+                return Err(ImportClosureError::EscapingAlias(relative_path.to_owned()));
+            }
+
+            let dest_path = resource_dir.join(relative_path);
+            if let Some(dest_parent) = dest_path.parent() {
+                std::fs::create_dir_all(dest_parent)?;
+            }
+            std::fs::rename(entry.path(), dest_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve `.` and `..` components in a relative path without touching
+/// the filesystem. Returns `None` for absolute paths and paths that go
+/// above their starting point.
+fn normalize_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(component) => {
+                normalized.push(component);
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return None;
+            }
+        }
+    }
+
+    Some(normalized)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportClosureError {
+    #[error("invalid resource path: {0:?}")]
+    InvalidResourcePath(PathBuf),
+    #[error("resource not found: {0:?}")]
+    ResourceNotFound(PathBuf),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportClosureError {
+    #[error("unexpected entry in resource archive: {0:?}")]
+    UnexpectedEntry(PathBuf),
+    #[error("hash mismatch for resource {0:?}")]
+    HashMismatch(PathBuf),
+    #[error("alias points outside the resource dir: {0:?}")]
+    EscapingAlias(PathBuf),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
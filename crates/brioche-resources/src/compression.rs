@@ -0,0 +1,434 @@
+use std::{
+    io::{Read as _, Seek as _, Write as _},
+    path::{Path, PathBuf},
+};
+
+use crate::{ResourceHash, ResourceHasher};
+
+/// Written at the start of compressed blobs, before the zstd stream, so
+/// readers can tell them apart from uncompressed blobs.
+pub const COMPRESSED_BLOB_MAGIC: [u8; 8] = *b"brzstd\x00\x01";
+
+/// Added to the names of compressed blobs, like `<hash>.zst` or
+/// `<hash>.x.zst`.
+pub const COMPRESSED_BLOB_SUFFIX: &str = ".zst";
+
+/// Env var for the directory [`materialize_blob`] writes decompressed
+/// blobs to. Defaults to `brioche/blobs` in the user's cache dir.
+pub const BLOB_CACHE_DIR_ENV_VAR: &str = "BRIOCHE_BLOB_CACHE_DIR";
+
+/// How to store a blob added with [`crate::add_blob_with_options`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlobCompression {
+    /// Store the contents as-is.
+    #[default]
+    None,
+
+    /// Compress the contents with zstd at the given level.
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+/// Writes a blob's contents to a file, compressing them if needed.
+pub(crate) enum BlobWriter {
+    Plain(std::fs::File),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, std::fs::File>),
+}
+
+impl BlobWriter {
+    pub(crate) fn new(file: std::fs::File, compression: BlobCompression) -> std::io::Result<Self> {
+        match compression {
+            BlobCompression::None => Ok(Self::Plain(file)),
+            #[cfg(feature = "zstd")]
+            BlobCompression::Zstd { level } => {
+                let mut file = file;
+                file.write_all(&COMPRESSED_BLOB_MAGIC)?;
+                let encoder = zstd::Encoder::new(file, level)?;
+                Ok(Self::Zstd(encoder))
+            }
+        }
+    }
+
+    /// Finish writing, including the end of the compressed stream.
+    pub(crate) fn finish(self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(mut file) => file.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => {
+                let mut file = encoder.finish()?;
+                file.flush()
+            }
+        }
+    }
+}
+
+impl std::io::Write for BlobWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Reads a blob's contents, decompressing them if needed. Returned by
+/// [`open_blob`].
+pub struct BlobReader {
+    inner: BlobReaderInner,
+}
+
+enum BlobReaderInner {
+    Plain(std::fs::File),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Decoder<'static, std::io::BufReader<std::fs::File>>),
+}
+
+impl std::io::Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.inner {
+            BlobReaderInner::Plain(file) => file.read(buf),
+            #[cfg(feature = "zstd")]
+            BlobReaderInner::Zstd(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Returns true if the file at `path` (such as a path from
+/// [`crate::find_in_resource_dirs`]) is a compressed blob.
+pub fn is_compressed_blob(path: &Path) -> std::io::Result<bool> {
+    let mut file = std::fs::File::open(path)?;
+    read_magic(&mut file)
+}
+
+/// Open a blob or other resource file for reading. Compressed blobs are
+/// decompressed while reading, so the contents are the same either way.
+/// Reading compressed blobs requires the `zstd` feature.
+pub fn open_blob(path: &Path) -> std::io::Result<BlobReader> {
+    let mut file = std::fs::File::open(path)?;
+    if !read_magic(&mut file)? {
+        file.rewind()?;
+        return Ok(BlobReader {
+            inner: BlobReaderInner::Plain(file),
+        });
+    }
+
+    open_compressed_blob(path, file)
+}
+
+#[cfg(feature = "zstd")]
+fn open_compressed_blob(_path: &Path, file: std::fs::File) -> std::io::Result<BlobReader> {
+    let decoder = zstd::Decoder::new(file)?;
+    Ok(BlobReader {
+        inner: BlobReaderInner::Zstd(decoder),
+    })
+}
+
+#[cfg(not(feature = "zstd"))]
+fn open_compressed_blob(path: &Path, _file: std::fs::File) -> std::io::Result<BlobReader> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!(
+            "{} is a compressed blob, but zstd support is not enabled",
+            path.display()
+        ),
+    ))
+}
+
+/// Read the full contents of a blob or other resource file, like
+/// [`open_blob`].
+pub fn read_blob(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut reader = open_blob(path)?;
+    let mut contents = vec![];
+    reader.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Get a path with the uncompressed contents of a blob, for programs and
+/// libraries that have to be loaded from a file. Uncompressed blobs are
+/// returned as-is. Compressed blobs are decompressed once into
+/// `$BRIOCHE_BLOB_CACHE_DIR` (or the user's cache dir), named by their
+/// hash, and checked against the hash while decompressing.
+pub fn materialize_blob(path: &Path) -> Result<PathBuf, MaterializeBlobError> {
+    if !is_compressed_blob(path)? {
+        return Ok(path.to_owned());
+    }
+
+    let cache_dir = match std::env::var_os(BLOB_CACHE_DIR_ENV_VAR) {
+        Some(cache_dir) => PathBuf::from(cache_dir),
+        None => {
+            let cache_dir = match std::env::var_os("XDG_CACHE_HOME") {
+                Some(cache_dir) => PathBuf::from(cache_dir),
+                None => {
+                    let home = std::env::var_os("HOME").ok_or(MaterializeBlobError::NoCacheDir)?;
+                    Path::new(&home).join(".cache")
+                }
+            };
+            cache_dir.join("brioche").join("blobs")
+        }
+    };
+
+    materialize_blob_in(&cache_dir, path)
+}
+
+fn materialize_blob_in(cache_dir: &Path, path: &Path) -> Result<PathBuf, MaterializeBlobError> {
+    // Aliases point to the blob, which is named by its hash
+    let blob_path = path.canonicalize()?;
+    let (hash, executable, _) = blob_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(parse_blob_file_name)
+        .ok_or_else(|| MaterializeBlobError::InvalidBlobName(blob_path.clone()))?;
+    let blob_name = crate::blob_name(&hash, executable);
+
+    let cached_path = cache_dir.join(&blob_name);
+    if cached_path.is_file() {
+        return Ok(cached_path);
+    }
+
+    std::fs::create_dir_all(cache_dir)?;
+    let temp_path = cache_dir.join(format!("{blob_name}-{}", ulid::Ulid::new()));
+    let mut temp_file_options = std::fs::OpenOptions::new();
+    temp_file_options.create_new(true).write(true);
+    crate::platform::set_executable_mode(&mut temp_file_options, executable);
+    let temp_file = temp_file_options.open(&temp_path)?;
+
+    let mut writer = crate::HashingWriter {
+        file: BlobWriter::new(temp_file, BlobCompression::None)?,
+        digest: hash.hasher().digest(),
+    };
+    let result =
+        open_blob(&blob_path).and_then(|mut reader| std::io::copy(&mut reader, &mut writer));
+    let crate::HashingWriter { file, digest } = writer;
+    let result = result.and_then(|_| file.finish());
+    if let Err(error) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(error.into());
+    }
+    if digest.finalize() != hash {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(MaterializeBlobError::HashMismatch(blob_path));
+    }
+
+    std::fs::rename(&temp_path, &cached_path)?;
+    Ok(cached_path)
+}
+
+/// Parse the name of a file in `blobs/`, returning its hash, whether it's
+/// executable, and whether it's compressed.
+pub(crate) fn parse_blob_file_name(file_name: &str) -> Option<(ResourceHash, bool, bool)> {
+    let (blob_name, compressed) = match file_name.strip_suffix(COMPRESSED_BLOB_SUFFIX) {
+        Some(blob_name) => (blob_name, true),
+        None => (file_name, false),
+    };
+    let (hash, executable) = crate::parse_blob_name(blob_name)?;
+    Some((hash, executable, compressed))
+}
+
+/// Hash the uncompressed contents of a file in `blobs/`. Returns `None` if
+/// the file's header doesn't match whether its name says it's compressed.
+pub(crate) fn hash_blob_file(
+    path: &Path,
+    compressed: bool,
+    hasher: ResourceHasher,
+) -> std::io::Result<Option<ResourceHash>> {
+    if is_compressed_blob(path)? != compressed {
+        return Ok(None);
+    }
+
+    let reader = open_blob(path)?;
+    let hash = hasher.hash_reader(reader)?;
+    Ok(Some(hash))
+}
+
+fn read_magic(file: &mut std::fs::File) -> std::io::Result<bool> {
+    let mut header = Vec::with_capacity(COMPRESSED_BLOB_MAGIC.len());
+    let magic_len = COMPRESSED_BLOB_MAGIC.len().try_into().unwrap_or(u64::MAX);
+    std::io::Read::by_ref(file)
+        .take(magic_len)
+        .read_to_end(&mut header)?;
+    Ok(header == COMPRESSED_BLOB_MAGIC)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MaterializeBlobError {
+    #[error(
+        "could not find cache dir for decompressed blobs, set $BRIOCHE_BLOB_CACHE_DIR or $HOME"
+    )]
+    NoCacheDir,
+    #[error("compressed blob {0:?} is not named like a blob")]
+    InvalidBlobName(PathBuf),
+    #[error("decompressed contents of {0:?} don't match its hash")]
+    HashMismatch(PathBuf),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uncompressed_blob() {
+        let resource_dir = tempfile::tempdir().unwrap();
+        let added = crate::add_blob(
+            resource_dir.path(),
+            &b"hello"[..],
+            false,
+            Path::new("hello"),
+        )
+        .unwrap();
+        let path = resource_dir.path().join(&added.path);
+
+        assert!(!is_compressed_blob(&path).unwrap());
+        assert_eq!(read_blob(&path).unwrap(), b"hello");
+        assert_eq!(materialize_blob(&path).unwrap(), path);
+    }
+
+    #[test]
+    fn test_blob_round_trip() {
+        #[cfg(feature = "zstd")]
+        let compression = BlobCompression::Zstd { level: 3 };
+        #[cfg(not(feature = "zstd"))]
+        let compression = BlobCompression::None;
+
+        let resource_dir = tempfile::tempdir().unwrap();
+        let contents = b"round trip contents ".repeat(100);
+        let added = crate::add_blob_with_options(
+            resource_dir.path(),
+            &contents[..],
+            true,
+            Path::new("program"),
+            &crate::AddBlobOptions { compression },
+        )
+        .unwrap();
+
+        let resource_dirs = [resource_dir.path().to_owned()];
+        let path = crate::find_in_resource_dirs(&resource_dirs, &added.path).unwrap();
+        assert_eq!(
+            is_compressed_blob(&path).unwrap(),
+            compression != BlobCompression::None
+        );
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let materialized = materialize_blob_in(cache_dir.path(), &path).unwrap();
+        assert_eq!(std::fs::read(&materialized).unwrap(), contents);
+        assert_eq!(read_blob(&path).unwrap(), contents);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_compressed_blob_without_feature() {
+        let resource_dir = tempfile::tempdir().unwrap();
+        let added = crate::add_blob(
+            resource_dir.path(),
+            &b"hello"[..],
+            false,
+            Path::new("hello"),
+        )
+        .unwrap();
+
+        // Write a blob with a compressed header, as if it was added by a
+        // build with the `zstd` feature
+        let blob_path = resource_dir
+            .path()
+            .join("blobs")
+            .join(format!("{}{COMPRESSED_BLOB_SUFFIX}", added.hash));
+        let mut blob = COMPRESSED_BLOB_MAGIC.to_vec();
+        blob.extend_from_slice(b"compressed");
+        std::fs::write(&blob_path, blob).unwrap();
+
+        assert!(is_compressed_blob(&blob_path).unwrap());
+        let error = read_blob(&blob_path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let error = materialize_blob_in(cache_dir.path(), &blob_path).unwrap_err();
+        assert!(matches!(
+            error,
+            MaterializeBlobError::IoError(error) if error.kind() == std::io::ErrorKind::Unsupported
+        ));
+        assert!(std::fs::read_dir(cache_dir.path())
+            .unwrap()
+            .next()
+            .is_none());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_compressed_blob() {
+        let resource_dir = tempfile::tempdir().unwrap();
+        let contents = b"compressed contents ".repeat(100);
+        let added = crate::add_blob_with_options(
+            resource_dir.path(),
+            &contents[..],
+            true,
+            Path::new("program"),
+            &crate::AddBlobOptions {
+                compression: BlobCompression::Zstd { level: 3 },
+            },
+        )
+        .unwrap();
+        let path = resource_dir.path().join(&added.path);
+
+        let blob_path = path.canonicalize().unwrap();
+        let blob_name = blob_path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(blob_name, format!("{}.x.zst", added.hash));
+        assert_eq!(added.size, u64::try_from(contents.len()).unwrap());
+        assert!(std::fs::metadata(&blob_path).unwrap().len() < added.size);
+
+        assert!(is_compressed_blob(&path).unwrap());
+        assert_eq!(read_blob(&path).unwrap(), contents);
+        assert!(crate::verify(resource_dir.path()).unwrap().is_empty());
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let materialized = materialize_blob_in(cache_dir.path(), &path).unwrap();
+        assert_eq!(
+            materialized,
+            cache_dir.path().join(format!("{}.x", added.hash))
+        );
+        assert_eq!(std::fs::read(&materialized).unwrap(), contents);
+        assert_eq!(
+            materialize_blob_in(cache_dir.path(), &path).unwrap(),
+            materialized
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_verify_compressed_blob_without_suffix() {
+        let resource_dir = tempfile::tempdir().unwrap();
+        let added = crate::add_blob_with_options(
+            resource_dir.path(),
+            &b"hello"[..],
+            false,
+            Path::new("hello"),
+            &crate::AddBlobOptions {
+                compression: BlobCompression::Zstd { level: 3 },
+            },
+        )
+        .unwrap();
+
+        // A compressed blob under an uncompressed blob's name is corrupted,
+        // even though its decompressed contents match the hash
+        let blobs_dir = resource_dir.path().join("blobs");
+        let uncompressed_path = blobs_dir.join(added.hash.to_string());
+        std::fs::rename(
+            blobs_dir.join(format!("{}.zst", added.hash)),
+            &uncompressed_path,
+        )
+        .unwrap();
+
+        let report = crate::verify(resource_dir.path()).unwrap();
+        assert_eq!(report.corrupted_blobs, [uncompressed_path]);
+    }
+}
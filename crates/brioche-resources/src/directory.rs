@@ -0,0 +1,147 @@
+use std::path::Path;
+
+/// Options for which entries [`crate::add_named_resource_directory_with_options`]
+/// copies from the source directory. By default, everything is copied
+/// except special files (like sockets and FIFOs), which are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct AddDirectoryOptions {
+    /// Glob patterns for entries to copy, matched against paths relative
+    /// to the source directory. An entry is included if it or any of its
+    /// parent directories matches. If empty, every entry is included.
+    pub include: Vec<String>,
+
+    /// Glob patterns for entries to skip, matched like `include`. Excluded
+    /// directories are skipped along with everything in them, so use a
+    /// pattern like `**/.git` to skip a directory at any depth.
+    pub exclude: Vec<String>,
+
+    pub symlinks: DirectorySymlinkPolicy,
+
+    pub special_files: SpecialFilePolicy,
+}
+
+/// How symlinks in a resource directory's source are copied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DirectorySymlinkPolicy {
+    /// Copy symlinks as symlinks, with the same target.
+    #[default]
+    Preserve,
+
+    /// Copy the file or directory each symlink points to instead. Broken
+    /// symlinks return an error.
+    Follow,
+
+    /// Leave symlinks out.
+    Skip,
+}
+
+/// How entries that aren't regular files, directories, or symlinks (such
+/// as sockets, FIFOs, and device files) are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpecialFilePolicy {
+    /// Leave special files out.
+    #[default]
+    Skip,
+
+    /// Return an error if there are any special files.
+    Error,
+}
+
+/// Copy `source` to `dest` (which must not exist yet), filtering entries
+/// based on `options`.
+pub(crate) fn copy_filtered(
+    source: &Path,
+    dest: &Path,
+    options: &AddDirectoryOptions,
+) -> Result<(), crate::AddNamedDirectoryError> {
+    let include = build_glob_set(&options.include)?;
+    let exclude = build_glob_set(&options.exclude)?;
+    let follow_links = options.symlinks == DirectorySymlinkPolicy::Follow;
+
+    std::fs::create_dir(dest)?;
+
+    let walkdir = walkdir::WalkDir::new(source)
+        .min_depth(1)
+        .follow_links(follow_links)
+        .into_iter()
+        .filter_entry(|entry| {
+            let relative_path = relative_entry_path(source, entry.path());
+            !exclude.is_match(relative_path)
+        });
+    for entry in walkdir {
+        let entry = entry?;
+        let relative_path = relative_entry_path(source, entry.path());
+        if !options.include.is_empty() && !matches_with_parents(&include, relative_path) {
+            continue;
+        }
+
+        let dest_path = dest.join(relative_path);
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            std::fs::create_dir_all(&dest_path)?;
+            continue;
+        }
+
+        // Included files can be inside directories that weren't included
+        // themselves
+        if let Some(parent) = dest_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        if file_type.is_file() {
+            std::fs::copy(entry.path(), &dest_path)?;
+        } else if file_type.is_symlink() {
+            match options.symlinks {
+                DirectorySymlinkPolicy::Preserve => {
+                    let target = std::fs::read_link(entry.path())?;
+                    crate::platform::symlink(&target, &dest_path)?;
+                }
+                DirectorySymlinkPolicy::Follow | DirectorySymlinkPolicy::Skip => {}
+            }
+        } else {
+            match options.special_files {
+                SpecialFilePolicy::Skip => {}
+                SpecialFilePolicy::Error => {
+                    return Err(crate::AddNamedDirectoryError::SpecialFile {
+                        path: entry.path().to_owned(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<globset::GlobSet, crate::AddNamedDirectoryError> {
+    let mut glob_set = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = globset::Glob::new(pattern).map_err(|error| {
+            crate::AddNamedDirectoryError::InvalidGlob {
+                pattern: pattern.clone(),
+                source: error,
+            }
+        })?;
+        glob_set.add(glob);
+    }
+
+    let glob_set =
+        glob_set
+            .build()
+            .map_err(|error| crate::AddNamedDirectoryError::InvalidGlob {
+                pattern: patterns.join(", "),
+                source: error,
+            })?;
+    Ok(glob_set)
+}
+
+fn matches_with_parents(glob_set: &globset::GlobSet, path: &Path) -> bool {
+    path.ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .any(|ancestor| glob_set.is_match(ancestor))
+}
+
+fn relative_entry_path<'a>(source: &Path, path: &'a Path) -> &'a Path {
+    path.strip_prefix(source)
+        .expect("walkdir entry is not in directory")
+}
@@ -0,0 +1,183 @@
+use sha2::Digest as _;
+
+/// The environment variable selecting the hash algorithm for new
+/// resources, either `blake3` (the default) or `sha256`.
+pub const HASHER_ENV_VAR: &str = "BRIOCHE_RESOURCE_HASHER";
+
+/// The hash algorithm used to name blobs and directories in a resource
+/// dir. BLAKE3 hashes are written as plain hex, and other algorithms
+/// prefix the hex with their name (e.g. `sha256-<hex>`), so resources
+/// hashed with different algorithms can share a resource dir. Existing
+/// resources are always checked with the algorithm from their name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ResourceHasher {
+    #[default]
+    Blake3,
+
+    /// SHA-256, for deployments that require FIPS-approved digests.
+    Sha256,
+}
+
+impl ResourceHasher {
+    /// Get the hasher for new resources from [`HASHER_ENV_VAR`].
+    pub fn from_env() -> Result<Self, std::io::Error> {
+        match std::env::var(HASHER_ENV_VAR) {
+            Ok(value) => value.parse(),
+            Err(std::env::VarError::NotPresent) => Ok(Self::default()),
+            Err(std::env::VarError::NotUnicode(value)) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid {HASHER_ENV_VAR}: {value:?}"),
+            )),
+        }
+    }
+
+    pub fn digest(self) -> ResourceDigest {
+        match self {
+            Self::Blake3 => ResourceDigest::Blake3(Box::new(blake3::Hasher::new())),
+            Self::Sha256 => ResourceDigest::Sha256(sha2::Sha256::new()),
+        }
+    }
+
+    /// Hash everything read from `reader`.
+    pub fn hash_reader(self, mut reader: impl std::io::Read) -> std::io::Result<ResourceHash> {
+        let mut digest = self.digest();
+        std::io::copy(&mut reader, &mut digest)?;
+        Ok(digest.finalize())
+    }
+
+    pub fn hash(self, contents: &[u8]) -> ResourceHash {
+        let mut digest = self.digest();
+        digest.update(contents);
+        digest.finalize()
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Blake3 => "",
+            Self::Sha256 => "sha256-",
+        }
+    }
+}
+
+impl std::str::FromStr for ResourceHasher {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blake3" => Ok(Self::Blake3),
+            "sha256" => Ok(Self::Sha256),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("unknown resource hasher: {s:?}"),
+            )),
+        }
+    }
+}
+
+/// An in-progress hash, created with [`ResourceHasher::digest`].
+#[derive(Debug, Clone)]
+pub enum ResourceDigest {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(sha2::Sha256),
+}
+
+impl ResourceDigest {
+    pub fn update(&mut self, contents: &[u8]) {
+        match self {
+            Self::Blake3(hasher) => {
+                hasher.update(contents);
+            }
+            Self::Sha256(hasher) => {
+                hasher.update(contents);
+            }
+        }
+    }
+
+    pub fn finalize(self) -> ResourceHash {
+        match self {
+            Self::Blake3(hasher) => ResourceHash {
+                hasher: ResourceHasher::Blake3,
+                bytes: *hasher.finalize().as_bytes(),
+            },
+            Self::Sha256(hasher) => ResourceHash {
+                hasher: ResourceHasher::Sha256,
+                bytes: hasher.finalize().into(),
+            },
+        }
+    }
+}
+
+impl std::io::Write for ResourceDigest {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A resource's hash, along with the algorithm used to compute it. This
+/// is formatted and parsed the same way it appears in blob and directory
+/// names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceHash {
+    hasher: ResourceHasher,
+    bytes: [u8; 32],
+}
+
+impl ResourceHash {
+    pub fn hasher(&self) -> ResourceHasher {
+        self.hasher
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
+}
+
+impl From<blake3::Hash> for ResourceHash {
+    fn from(hash: blake3::Hash) -> Self {
+        Self {
+            hasher: ResourceHasher::Blake3,
+            bytes: *hash.as_bytes(),
+        }
+    }
+}
+
+impl std::fmt::Display for ResourceHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.hasher.prefix())?;
+        for byte in self.bytes {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for ResourceHash {
+    type Err = InvalidResourceHash;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hasher, hex) = match s.strip_prefix(ResourceHasher::Sha256.prefix()) {
+            Some(hex) => (ResourceHasher::Sha256, hex),
+            None => (ResourceHasher::Blake3, s),
+        };
+
+        if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(InvalidResourceHash);
+        }
+        let mut bytes = [0; 32];
+        for (byte, digits) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| InvalidResourceHash)?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| InvalidResourceHash)?;
+        }
+
+        Ok(Self { hasher, bytes })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid resource hash")]
+pub struct InvalidResourceHash;
@@ -0,0 +1,134 @@
+use std::path::{Path, PathBuf};
+
+/// The resource dir layout written by this version of the crate. Resource
+/// dirs without a `version` marker use layout version 1, which predates
+/// the marker.
+pub const LAYOUT_VERSION: u32 = 1;
+
+const VERSION_FILE_NAME: &str = "version";
+
+/// Get the layout version of a resource dir from its `version` marker.
+/// Returns version 1 if there's no marker (including if the resource dir
+/// doesn't exist yet).
+pub fn layout_version(resource_dir: &Path) -> Result<u32, LayoutVersionError> {
+    let version_path = resource_dir.join(VERSION_FILE_NAME);
+    let contents = match std::fs::read_to_string(&version_path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(1);
+        }
+        Err(error) => {
+            return Err(error.into());
+        }
+    };
+
+    match contents.trim().parse() {
+        Ok(version) if version >= 1 => Ok(version),
+        _ => Err(LayoutVersionError::InvalidMarker {
+            path: version_path,
+            contents,
+        }),
+    }
+}
+
+/// Check that a resource dir uses a layout version this crate can read.
+/// Older layouts can be upgraded with [`migrate`].
+pub fn check_layout_version(resource_dir: &Path) -> Result<(), LayoutVersionError> {
+    let version = layout_version(resource_dir)?;
+    if version > LAYOUT_VERSION {
+        return Err(LayoutVersionError::Unsupported {
+            resource_dir: resource_dir.to_owned(),
+            version,
+        });
+    }
+    if version < LAYOUT_VERSION {
+        return Err(LayoutVersionError::NeedsMigration {
+            resource_dir: resource_dir.to_owned(),
+            version,
+        });
+    }
+
+    Ok(())
+}
+
+/// The changes made (or that would be made) by [`migrate`].
+#[derive(Debug)]
+pub struct MigrateReport {
+    pub from_version: u32,
+    pub to_version: u32,
+
+    /// Whether the `version` marker was written (or would be written).
+    pub wrote_marker: bool,
+}
+
+/// Upgrade a resource dir to [`LAYOUT_VERSION`], then write its `version`
+/// marker. Resource dirs with a newer layout than this crate supports are
+/// left as-is, and return an error.
+///
+/// This should not be called while another process is using the resource
+/// dir. If `dry_run` is set, the returned report describes the migration
+/// without changing anything.
+pub fn migrate(resource_dir: &Path, dry_run: bool) -> Result<MigrateReport, LayoutVersionError> {
+    let from_version = layout_version(resource_dir)?;
+    if from_version > LAYOUT_VERSION {
+        return Err(LayoutVersionError::Unsupported {
+            resource_dir: resource_dir.to_owned(),
+            version: from_version,
+        });
+    }
+
+    let mut version = from_version;
+    while version < LAYOUT_VERSION {
+        migrate_step(resource_dir, version, dry_run)?;
+        version += 1;
+    }
+
+    let version_path = resource_dir.join(VERSION_FILE_NAME);
+    let wrote_marker = version != from_version || !version_path.is_file();
+    if wrote_marker && !dry_run {
+        write_layout_version(resource_dir, version)?;
+    }
+
+    Ok(MigrateReport {
+        from_version,
+        to_version: version,
+        wrote_marker,
+    })
+}
+
+/// Upgrade a resource dir from `version` to the next layout version.
+fn migrate_step(
+    _resource_dir: &Path,
+    version: u32,
+    _dry_run: bool,
+) -> Result<(), LayoutVersionError> {
+    // Each layout change should add a step here. There are none yet, since
+    // version 1 is the first layout
+    unreachable!("no migration from layout version {version}")
+}
+
+fn write_layout_version(resource_dir: &Path, version: u32) -> Result<(), std::io::Error> {
+    std::fs::create_dir_all(resource_dir)?;
+
+    let temp_id = ulid::Ulid::new();
+    let temp_path = resource_dir.join(format!("{VERSION_FILE_NAME}-{temp_id}"));
+    std::fs::write(&temp_path, format!("{version}\n"))?;
+    std::fs::rename(&temp_path, resource_dir.join(VERSION_FILE_NAME))?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayoutVersionError {
+    #[error(
+        "resource dir {resource_dir:?} uses layout version {version}, but only versions up to {LAYOUT_VERSION} are supported"
+    )]
+    Unsupported { resource_dir: PathBuf, version: u32 },
+    #[error(
+        "resource dir {resource_dir:?} uses layout version {version}, run `brioche-packer migrate-resources` to upgrade it to version {LAYOUT_VERSION}"
+    )]
+    NeedsMigration { resource_dir: PathBuf, version: u32 },
+    #[error("invalid layout version marker {path:?}: {contents:?}")]
+    InvalidMarker { path: PathBuf, contents: String },
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
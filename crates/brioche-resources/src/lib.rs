@@ -1,13 +1,56 @@
 use std::{
     io::Write as _,
-    os::unix::fs::{OpenOptionsExt as _, PermissionsExt as _},
     path::{Path, PathBuf},
 };
 
-use bstr::ByteSlice as _;
-
-const SEARCH_DEPTH_LIMIT: u32 = 64;
-
+pub use archive::{export_closure, import_closure, ExportClosureError, ImportClosureError};
+pub use compression::{
+    is_compressed_blob, materialize_blob, open_blob, read_blob, BlobCompression, BlobReader,
+    MaterializeBlobError, BLOB_CACHE_DIR_ENV_VAR, COMPRESSED_BLOB_MAGIC, COMPRESSED_BLOB_SUFFIX,
+};
+pub use directory::{AddDirectoryOptions, DirectorySymlinkPolicy, SpecialFilePolicy};
+pub use hasher::{
+    InvalidResourceHash, ResourceDigest, ResourceHash, ResourceHasher, HASHER_ENV_VAR,
+};
+pub use layout::{
+    check_layout_version, layout_version, migrate, LayoutVersionError, MigrateReport,
+    LAYOUT_VERSION,
+};
+pub use link::BlobLinkMode;
+pub use lookup::{enable_lookup_cache, find_all_in_resource_dirs};
+pub use manifest::{
+    ManifestError, ResourceManifest, DEFAULT_SEARCH_DEPTH, MANIFEST_ENV_VAR, MANIFEST_FILENAME,
+};
+#[cfg(feature = "url")]
+pub use remote::{fetch_remote_resource, FetchResourceError, REMOTE_URL_ENV_VAR};
+pub use repair::{repair, RepairError, RepairReport};
+pub use resolve::{ResolveOptions, ResolveResourceError, ResolvedResource, ResourceResolver};
+pub use stats::{stats, ResourceEntry, ResourceStats, StatsError};
+pub use symlink_chain::{add_symlink_chain, symlink_chain, SymlinkChain};
+pub use verify::{verify, VerifyError, VerifyReport};
+
+mod archive;
+mod compression;
+mod directory;
+mod hasher;
+mod layout;
+mod link;
+mod lookup;
+mod manifest;
+mod platform;
+#[cfg(feature = "url")]
+mod remote;
+mod repair;
+mod resolve;
+mod stats;
+mod symlink_chain;
+mod verify;
+
+/// Find the resource dirs for a program, in the order they should be
+/// searched: `$BRIOCHE_RESOURCE_DIR`, then `$BRIOCHE_INPUT_RESOURCE_DIRS`
+/// (if `include_readonly` is set), then the dirs from the program's
+/// [`ResourceManifest`], then any `brioche-resources.d` dirs in the
+/// program's parent directories.
 pub fn find_resource_dirs(
     program: &Path,
     include_readonly: bool,
@@ -19,27 +62,49 @@ pub fn find_resource_dirs(
 
     if include_readonly {
         if let Some(input_resource_dirs) = std::env::var_os("BRIOCHE_INPUT_RESOURCE_DIRS") {
-            if let Some(input_resource_dirs) = <[u8]>::from_os_str(&input_resource_dirs) {
-                for input_resource_dir in input_resource_dirs.split_str(b":") {
-                    if let Ok(path) = input_resource_dir.to_path() {
-                        paths.push(path.to_owned());
+            // Windows paths can contain `:`, so only split on the
+            // platform's separator there
+            #[cfg(unix)]
+            {
+                use bstr::ByteSlice as _;
+
+                if let Some(input_resource_dirs) = <[u8]>::from_os_str(&input_resource_dirs) {
+                    for input_resource_dir in input_resource_dirs.split_str(b":") {
+                        if input_resource_dir.is_empty() {
+                            continue;
+                        }
+                        if let Ok(path) = input_resource_dir.to_path() {
+                            paths.push(path.to_owned());
+                        }
                     }
                 }
             }
 
+            #[cfg(not(unix))]
             for input_resource_dir in std::env::split_paths(&input_resource_dirs) {
-                paths.push(input_resource_dir);
+                if !input_resource_dir.as_os_str().is_empty() {
+                    paths.push(input_resource_dir);
+                }
             }
         }
     }
 
-    match find_resource_dirs_from_program(program, &mut paths) {
-        Ok(()) | Err(PackResourceDirError::NotFound) => {}
-        Err(error) => {
-            return Err(error);
+    let manifest = ResourceManifest::find(program)?.unwrap_or_default();
+    paths.extend(manifest.resource_dirs);
+
+    if manifest.search_depth > 0 {
+        match find_resource_dirs_from_program(program, manifest.search_depth, &mut paths) {
+            Ok(()) | Err(PackResourceDirError::NotFound) => {}
+            Err(error) => {
+                return Err(error);
+            }
         }
     }
 
+    for path in &paths {
+        check_layout_version(path)?;
+    }
+
     if !paths.is_empty() {
         Ok(paths)
     } else {
@@ -56,19 +121,25 @@ pub fn find_output_resource_dir(program: &Path) -> Result<PathBuf, PackResourceD
     Ok(resource_dir)
 }
 
+/// Find a resource in the first resource dir that contains it.
+///
+/// With the `url` feature, a resource that isn't in any resource dir is
+/// fetched from the remote store set by [`REMOTE_URL_ENV_VAR`], if any.
+/// Errors while fetching are treated as the resource not being found; use
+/// [`fetch_remote_resource`] directly to get the error.
+///
+/// If [`enable_lookup_cache`] was called, the result is cached for the rest
+/// of the process. Use [`find_all_in_resource_dirs`] to find several
+/// resources at once.
 pub fn find_in_resource_dirs(resource_dirs: &[PathBuf], subpath: &Path) -> Option<PathBuf> {
-    for resource_dir in resource_dirs {
-        let path = resource_dir.join(subpath);
-        if path.exists() {
-            return Some(path);
-        }
-    }
-
-    None
+    find_all_in_resource_dirs(resource_dirs, &[subpath])
+        .pop()
+        .flatten()
 }
 
 fn find_resource_dirs_from_program(
     program: &Path,
+    search_depth: u32,
     resource_dirs: &mut Vec<PathBuf>,
 ) -> Result<(), PackResourceDirError> {
     let program = std::env::current_dir()?.join(program);
@@ -79,7 +150,7 @@ fn find_resource_dirs_from_program(
 
     let mut found = false;
     let mut reached_end = false;
-    for _ in 0..SEARCH_DEPTH_LIMIT {
+    for _ in 0..search_depth {
         let pack_resource_dir = current_dir.join("brioche-resources.d");
         if pack_resource_dir.is_dir() {
             resource_dirs.push(pack_resource_dir);
@@ -103,18 +174,30 @@ fn find_resource_dirs_from_program(
     }
 }
 
+/// Add a blob to the resource dir, named by its hash using the algorithm
+/// from [`HASHER_ENV_VAR`] (BLAKE3 by default).
 pub fn add_named_blob(
+    resource_dir: &Path,
+    contents: impl std::io::Seek + std::io::Read,
+    executable: bool,
+    name: &Path,
+) -> Result<PathBuf, AddBlobError> {
+    let hasher = ResourceHasher::from_env()?;
+    add_named_blob_with_hasher(resource_dir, contents, executable, name, hasher)
+}
+
+/// Add a blob to the resource dir, like [`add_named_blob`], hashing it
+/// with the given algorithm.
+pub fn add_named_blob_with_hasher(
     resource_dir: &Path,
     mut contents: impl std::io::Seek + std::io::Read,
     executable: bool,
     name: &Path,
+    hasher: ResourceHasher,
 ) -> Result<PathBuf, AddBlobError> {
-    let mut hasher = blake3::Hasher::new();
-    std::io::copy(&mut contents, &mut hasher)?;
-    let hash = hasher.finalize();
+    let hash = hasher.hash_reader(&mut contents)?;
 
-    let blob_suffix = if executable { ".x" } else { "" };
-    let blob_name = format!("{hash}{blob_suffix}");
+    let blob_name = blob_name(&hash, executable);
 
     contents.seek(std::io::SeekFrom::Start(0))?;
 
@@ -126,22 +209,222 @@ pub fn add_named_blob(
 
     let mut blob_file_options = std::fs::OpenOptions::new();
     blob_file_options.create_new(true).write(true);
-    if executable {
-        blob_file_options.mode(0o777);
-    }
+    platform::set_executable_mode(&mut blob_file_options, executable);
     let mut blob_file = blob_file_options.open(&blob_temp_path)?;
     std::io::copy(&mut contents, &mut blob_file)?;
     drop(blob_file);
     std::fs::rename(&blob_temp_path, &blob_path)?;
 
-    let alias_dir = resource_dir.join("aliases").join(name).join(&blob_name);
+    add_blob_alias(resource_dir, &blob_path, &blob_name, blob_temp_id, name)
+}
+
+/// A blob added to a resource dir by [`add_blob`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddedBlob {
+    /// The path of the blob's alias, relative to the resource dir.
+    pub path: PathBuf,
+
+    /// The hash the blob is named by.
+    pub hash: ResourceHash,
+
+    /// The size of the blob's contents in bytes.
+    pub size: u64,
+
+    /// Whether the blob was written to the resource dir. This is false if
+    /// a blob with the same contents already existed, in which case only
+    /// the alias was added.
+    pub was_new: bool,
+}
+
+/// Add a blob to the resource dir, like [`add_named_blob`], hashing the
+/// contents while they're written. Unlike [`add_named_blob`], the contents
+/// are only read once, so they don't need to be seekable.
+pub fn add_blob(
+    resource_dir: &Path,
+    contents: impl std::io::Read,
+    executable: bool,
+    name: &Path,
+) -> Result<AddedBlob, AddBlobError> {
+    add_blob_with_options(
+        resource_dir,
+        contents,
+        executable,
+        name,
+        &AddBlobOptions::default(),
+    )
+}
+
+/// Options for [`add_blob_with_options`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddBlobOptions {
+    /// How to store the blob. Compressed blobs are named with
+    /// [`COMPRESSED_BLOB_SUFFIX`], and have to be read with [`open_blob`]
+    /// or [`read_blob`], or decompressed with [`materialize_blob`] before
+    /// they can be run or loaded. Blobs in library dirs or other
+    /// directories shouldn't be compressed, since they're loaded directly.
+    pub compression: BlobCompression,
+}
+
+/// Add a blob to the resource dir, like [`add_blob`]. The blob is named
+/// by the hash of its uncompressed contents, even if it's compressed.
+pub fn add_blob_with_options(
+    resource_dir: &Path,
+    mut contents: impl std::io::Read,
+    executable: bool,
+    name: &Path,
+    options: &AddBlobOptions,
+) -> Result<AddedBlob, AddBlobError> {
+    let hasher = ResourceHasher::from_env()?;
+
+    let blob_dir = resource_dir.join("blobs");
+    let blob_temp_id = ulid::Ulid::new();
+    let blob_temp_path = blob_dir.join(format!("blob-{blob_temp_id}"));
+    std::fs::create_dir_all(&blob_dir)?;
+
+    let mut blob_file_options = std::fs::OpenOptions::new();
+    blob_file_options.create_new(true).write(true);
+    platform::set_executable_mode(&mut blob_file_options, executable);
+    let blob_file = blob_file_options.open(&blob_temp_path)?;
+
+    let mut writer = HashingWriter {
+        file: compression::BlobWriter::new(blob_file, options.compression)?,
+        digest: hasher.digest(),
+    };
+    let size = match std::io::copy(&mut contents, &mut writer) {
+        Ok(size) => size,
+        Err(error) => {
+            drop(writer);
+            let _ = std::fs::remove_file(&blob_temp_path);
+            return Err(error.into());
+        }
+    };
+    let HashingWriter { file, digest } = writer;
+    if let Err(error) = file.finish() {
+        let _ = std::fs::remove_file(&blob_temp_path);
+        return Err(error.into());
+    }
+    let hash = digest.finalize();
+
+    let mut blob_name = blob_name(&hash, executable);
+    if options.compression != BlobCompression::None {
+        blob_name.push_str(COMPRESSED_BLOB_SUFFIX);
+    }
+    let blob_path = blob_dir.join(&blob_name);
+    let was_new = !blob_path.is_file();
+    if was_new {
+        std::fs::rename(&blob_temp_path, &blob_path)?;
+    } else {
+        std::fs::remove_file(&blob_temp_path)?;
+    }
+
+    let path = add_blob_alias(resource_dir, &blob_path, &blob_name, blob_temp_id, name)?;
+    Ok(AddedBlob {
+        path,
+        hash,
+        size,
+        was_new,
+    })
+}
+
+/// Returns true if the resource dir has a blob with the given hash. If it
+/// does, [`add_existing_blob`] can add an alias for it without reading the
+/// contents again.
+pub fn contains_blob(resource_dir: &Path, hash: &ResourceHash, executable: bool) -> bool {
+    let blob_name = blob_name(hash, executable);
+    resource_dir.join("blobs").join(blob_name).is_file()
+}
+
+/// Add an alias for a blob that's already in the resource dir (see
+/// [`contains_blob`]). Returns the alias path, like [`add_named_blob`].
+pub fn add_existing_blob(
+    resource_dir: &Path,
+    hash: &ResourceHash,
+    executable: bool,
+    name: &Path,
+) -> Result<PathBuf, AddBlobError> {
+    let blob_name = blob_name(hash, executable);
+    let blob_path = resource_dir.join("blobs").join(&blob_name);
+    if !blob_path.is_file() {
+        return Err(AddBlobError::BlobNotFound { blob_name });
+    }
+
+    add_blob_alias(
+        resource_dir,
+        &blob_path,
+        &blob_name,
+        ulid::Ulid::new(),
+        name,
+    )
+}
+
+/// Writes to a blob file while hashing everything written.
+struct HashingWriter {
+    file: compression::BlobWriter,
+    digest: ResourceDigest,
+}
+
+impl std::io::Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.digest.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Add a file as a named blob, like [`add_named_blob`]. Depending on
+/// `link_mode`, the blob may be hard linked or reflinked from `source`
+/// instead of copied. If the file can't be linked (e.g. because it's on a
+/// different filesystem from the resource dir), it's copied instead.
+pub fn add_named_blob_from_path(
+    resource_dir: &Path,
+    source: &Path,
+    executable: bool,
+    name: &Path,
+    link_mode: BlobLinkMode,
+) -> Result<PathBuf, AddBlobError> {
+    let mut file = std::fs::File::open(source)?;
+    let hash = ResourceHasher::from_env()?.hash_reader(&mut file)?;
+    drop(file);
+
+    let blob_name = blob_name(&hash, executable);
+
+    let blob_dir = resource_dir.join("blobs");
+    let blob_path = blob_dir.join(&blob_name);
+    let blob_temp_id = ulid::Ulid::new();
+    let blob_temp_path = blob_dir.join(format!("{blob_name}-{blob_temp_id}"));
+    std::fs::create_dir_all(&blob_dir)?;
+
+    link::link_or_copy(source, &blob_temp_path, executable, link_mode)?;
+    std::fs::rename(&blob_temp_path, &blob_path)?;
+
+    // Renaming does nothing if the blob is already a hard link to the
+    // same file, so clean up the temporary link
+    if blob_temp_path.symlink_metadata().is_ok() {
+        std::fs::remove_file(&blob_temp_path)?;
+    }
+
+    add_blob_alias(resource_dir, &blob_path, &blob_name, blob_temp_id, name)
+}
+
+fn add_blob_alias(
+    resource_dir: &Path,
+    blob_path: &Path,
+    blob_name: &str,
+    blob_temp_id: ulid::Ulid,
+    name: &Path,
+) -> Result<PathBuf, AddBlobError> {
+    let alias_dir = resource_dir.join("aliases").join(name).join(blob_name);
     std::fs::create_dir_all(&alias_dir)?;
 
     let temp_alias_path = alias_dir.join(format!("{}-{blob_temp_id}", name.display()));
     let alias_path = alias_dir.join(name);
-    let blob_pack_relative_path = pathdiff::diff_paths(&blob_path, &alias_dir)
+    let blob_pack_relative_path = pathdiff::diff_paths(blob_path, &alias_dir)
         .expect("blob path is not a prefix of alias path");
-    std::os::unix::fs::symlink(blob_pack_relative_path, &temp_alias_path)?;
+    platform::symlink(&blob_pack_relative_path, &temp_alias_path)?;
     std::fs::rename(&temp_alias_path, &alias_path)?;
 
     let alias_path = alias_path
@@ -149,19 +432,120 @@ pub fn add_named_blob(
         .expect("alias path is not in resource dir");
     Ok(alias_path.to_owned())
 }
+
+/// Get the path that [`add_named_blob`] would return for the given
+/// contents, without writing anything to the resource dir.
+pub fn named_blob_path(
+    mut contents: impl std::io::Read,
+    executable: bool,
+    name: &Path,
+) -> Result<PathBuf, std::io::Error> {
+    let hash = ResourceHasher::from_env()?.hash_reader(&mut contents)?;
+
+    let blob_name = blob_name(&hash, executable);
+    Ok(Path::new("aliases").join(name).join(blob_name).join(name))
+}
+
+fn blob_name(hash: &ResourceHash, executable: bool) -> String {
+    let blob_suffix = if executable { ".x" } else { "" };
+    format!("{hash}{blob_suffix}")
+}
+
+/// Parse a blob name, returning its hash and whether it's executable.
+/// Blobs are named `<hash>` or `<hash>.x` for executables.
+fn parse_blob_name(blob_name: &str) -> Option<(ResourceHash, bool)> {
+    let (hash, executable) = match blob_name.strip_suffix(".x") {
+        Some(hash) => (hash, true),
+        None => (blob_name, false),
+    };
+    let hash = hash.parse().ok()?;
+    Some((hash, executable))
+}
+
+/// Parse a directory name (`<hash>.d`), returning its hash.
+fn parse_directory_name(directory_name: &str) -> Option<ResourceHash> {
+    let hash = directory_name.strip_suffix(".d")?;
+    hash.parse().ok()
+}
+
+/// Download a file and add it as a named blob, like [`add_named_blob`].
+/// The downloaded contents must match `expected_hash` (the same hash used to
+/// name blobs), otherwise nothing is added to the resource dir. The blob is
+/// named with the algorithm of `expected_hash`.
+#[cfg(feature = "url")]
+pub fn add_named_blob_from_url(
+    resource_dir: &Path,
+    url: &str,
+    expected_hash: &ResourceHash,
+    executable: bool,
+    name: &Path,
+) -> Result<PathBuf, AddBlobFromUrlError> {
+    use std::io::Read as _;
+
+    let response = ureq::get(url)
+        .call()
+        .map_err(|error| AddBlobFromUrlError::Download {
+            url: url.to_string(),
+            source: Box::new(error),
+        })?;
+
+    let mut contents = vec![];
+    response.into_reader().read_to_end(&mut contents)?;
+
+    let hash = expected_hash.hasher().hash(&contents);
+    if hash != *expected_hash {
+        return Err(AddBlobFromUrlError::HashMismatch {
+            url: url.to_string(),
+            expected: *expected_hash,
+            actual: hash,
+        });
+    }
+
+    let alias_path = add_named_blob_with_hasher(
+        resource_dir,
+        std::io::Cursor::new(contents),
+        executable,
+        name,
+        expected_hash.hasher(),
+    )?;
+    Ok(alias_path)
+}
+
+/// Copy a directory into the resource dir, named by the hash of its
+/// contents. Special files in the directory are skipped.
 pub fn add_named_resource_directory(
     resource_dir: &Path,
     source: &Path,
     hint_name: &str,
+) -> Result<PathBuf, AddNamedDirectoryError> {
+    add_named_resource_directory_with_options(
+        resource_dir,
+        source,
+        hint_name,
+        &AddDirectoryOptions::default(),
+    )
+}
+
+/// Copy a directory into the resource dir, like
+/// [`add_named_resource_directory`], choosing which entries to copy with
+/// `options`.
+pub fn add_named_resource_directory_with_options(
+    resource_dir: &Path,
+    source: &Path,
+    hint_name: &str,
+    options: &AddDirectoryOptions,
 ) -> Result<PathBuf, AddNamedDirectoryError> {
     let resources_directories_dir = resource_dir.join("directories");
     std::fs::create_dir_all(&resources_directories_dir)?;
 
     let temp_name = ulid::Ulid::new().to_string();
     let temp_path = resources_directories_dir.join(temp_name);
-    copy_dir::copy_dir(source, &temp_path)?;
+    if let Err(error) = directory::copy_filtered(source, &temp_path, options) {
+        let _ = std::fs::remove_dir_all(&temp_path);
+        return Err(error);
+    }
 
-    let directory_hash = hash_directory(&temp_path)?;
+    let directory_hash = hash_directory(&temp_path, ResourceHasher::from_env()?)?;
     let directory_name = format!("{directory_hash}.d");
     let hashed_path = resources_directories_dir.join(&directory_name);
     std::fs::rename(&temp_path, &hashed_path)?;
@@ -172,7 +556,7 @@ pub fn add_named_resource_directory(
 
     let hashed_relative_path = pathdiff::diff_paths(hashed_path, &alias_dir)
         .expect("hashed path is not a prefix of alias path");
-    std::os::unix::fs::symlink(hashed_relative_path, &alias_path)?;
+    platform::symlink(&hashed_relative_path, &alias_path)?;
 
     let alias_path = alias_path
         .strip_prefix(resource_dir)
@@ -180,13 +564,18 @@ pub fn add_named_resource_directory(
     Ok(alias_path.to_owned())
 }
 
-fn hash_directory(path: &Path) -> Result<blake3::Hash, std::io::Error> {
+/// Hash a directory's contents. Entries are hashed by their path relative
+/// to the directory, so the hash doesn't depend on where it's stored.
+fn hash_directory(path: &Path, hasher: ResourceHasher) -> Result<ResourceHash, std::io::Error> {
     let walkdir = walkdir::WalkDir::new(path).sort_by_file_name();
-    let mut hasher = blake3::Hasher::new();
+    let mut hasher = hasher.digest();
 
     for entry in walkdir {
         let entry = entry?;
-        let entry_path = entry.path();
+        let entry_path = entry
+            .path()
+            .strip_prefix(path)
+            .expect("walkdir entry is not in directory");
         let metadata = entry.metadata()?;
         let file_type = metadata.file_type();
         let entry_path_encoded = entry_path.as_os_str().as_encoded_bytes();
@@ -194,9 +583,7 @@ fn hash_directory(path: &Path) -> Result<blake3::Hash, std::io::Error> {
 
         if file_type.is_file() {
             let file_len = metadata.len();
-            let permissions = metadata.permissions();
-            let mode = permissions.mode();
-            let is_executable = mode & 0o111 != 0;
+            let is_executable = platform::is_executable(&metadata);
             let mut file = std::fs::File::open(path.join(entry_path))?;
 
             writeln!(hasher, "f:{entry_path_encoded}:{file_len}:{is_executable}")?;
@@ -225,16 +612,92 @@ pub enum PackResourceDirError {
     IoError(#[from] std::io::Error),
     #[error("reached depth limit while searching for brioche pack resource dir")]
     DepthLimitReached,
+    #[error(transparent)]
+    LayoutVersionError(#[from] LayoutVersionError),
+    #[error(transparent)]
+    ManifestError(#[from] ManifestError),
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum AddBlobError {
+    #[error("blob not found in resource dir: {blob_name}")]
+    BlobNotFound { blob_name: String },
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 }
 
+#[cfg(feature = "url")]
+#[derive(Debug, thiserror::Error)]
+pub enum AddBlobFromUrlError {
+    #[error("failed to download {url}: {source}")]
+    Download {
+        url: String,
+        source: Box<ureq::Error>,
+    },
+    #[error("hash mismatch for {url}: expected {expected}, got {actual}")]
+    HashMismatch {
+        url: String,
+        expected: ResourceHash,
+        actual: ResourceHash,
+    },
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    AddBlobError(#[from] AddBlobError),
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum AddNamedDirectoryError {
+    #[error("invalid glob {pattern:?}: {source}")]
+    InvalidGlob {
+        pattern: String,
+        source: globset::Error,
+    },
+    #[error("unsupported special file in resource directory: {path:?}")]
+    SpecialFile { path: PathBuf },
     #[error(transparent)]
     IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    WalkDirError(#[from] walkdir::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_resource_dirs_from_env_and_program() {
+        let root = tempfile::tempdir().unwrap();
+        let program = root.path().join("bin").join("program");
+        let program_resource_dir = brioche_test_fixtures::create_resource_dir(root.path()).unwrap();
+
+        let output_dir = root.path().join("output");
+        let input_a = root.path().join("input-a");
+        let input_b = root.path().join("input-b");
+
+        // This is the only test that reads these env vars
+        std::env::remove_var(MANIFEST_ENV_VAR);
+        std::env::set_var("BRIOCHE_RESOURCE_DIR", &output_dir);
+        std::env::set_var(
+            "BRIOCHE_INPUT_RESOURCE_DIRS",
+            format!(":{}::{}:", input_a.display(), input_b.display()),
+        );
+
+        let readonly_dirs = find_resource_dirs(&program, true).unwrap();
+        let output_dirs = find_resource_dirs(&program, false).unwrap();
+
+        std::env::remove_var("BRIOCHE_RESOURCE_DIR");
+        std::env::remove_var("BRIOCHE_INPUT_RESOURCE_DIRS");
+
+        assert_eq!(
+            readonly_dirs,
+            [
+                output_dir.clone(),
+                input_a,
+                input_b,
+                program_resource_dir.clone()
+            ]
+        );
+        assert_eq!(output_dirs, [output_dir, program_resource_dir]);
+    }
 }
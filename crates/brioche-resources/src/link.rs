@@ -0,0 +1,72 @@
+use std::path::Path;
+
+/// How [`crate::add_named_blob_from_path`] should add a file to the
+/// resource dir.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlobLinkMode {
+    /// Copy the file's contents.
+    #[default]
+    Copy,
+
+    /// Hard link the file into the resource dir, so the blob shares
+    /// storage with the original. Changing the original file in place
+    /// would change the blob too, so this should only be used for files
+    /// that won't be changed after being added.
+    HardLink,
+
+    /// Clone the file with a reflink (`FICLONE`), so the blob shares
+    /// storage with the original until either one is changed. Only some
+    /// filesystems (such as Btrfs and XFS) support reflinks.
+    Reflink,
+}
+
+/// Create `dest` with the contents of `source`, linking it if possible
+/// with the given mode and copying it otherwise. Hard links are only used
+/// if `source` is already executable (or not) to match `executable`.
+pub fn link_or_copy(
+    source: &Path,
+    dest: &Path,
+    executable: bool,
+    link_mode: BlobLinkMode,
+) -> std::io::Result<()> {
+    let mut source_file = std::fs::File::open(source)?;
+
+    if link_mode == BlobLinkMode::HardLink {
+        let metadata = source_file.metadata()?;
+        if crate::platform::executable_matches(&metadata, executable)
+            && std::fs::hard_link(source, dest).is_ok()
+        {
+            return Ok(());
+        }
+    }
+
+    let mut dest_file_options = std::fs::OpenOptions::new();
+    dest_file_options.create_new(true).write(true);
+    crate::platform::set_executable_mode(&mut dest_file_options, executable);
+    let mut dest_file = dest_file_options.open(dest)?;
+
+    if link_mode == BlobLinkMode::Reflink && reflink(&source_file, &dest_file).is_ok() {
+        return Ok(());
+    }
+
+    std::io::copy(&mut source_file, &mut dest_file)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(source: &std::fs::File, dest: &std::fs::File) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd as _;
+
+    // SAFETY: Both file descriptors are valid for the duration of the call
+    let result = unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE as _, source.as_raw_fd()) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &std::fs::File, _dest: &std::fs::File) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
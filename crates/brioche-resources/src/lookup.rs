@@ -0,0 +1,90 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+type LookupKey = (Vec<PathBuf>, PathBuf);
+
+static LOOKUP_CACHE: OnceLock<Mutex<HashMap<LookupKey, Option<PathBuf>>>> = OnceLock::new();
+
+/// Remember the result of every resource lookup for the rest of the
+/// process, including resources that weren't found. This saves repeated
+/// filesystem checks (which can be slow on network filesystems), but means
+/// resources added to a resource dir after they were looked up won't be
+/// found. Intended for short-lived processes like packed program launchers.
+pub fn enable_lookup_cache() {
+    LOOKUP_CACHE.get_or_init(Default::default);
+}
+
+/// Find several resources at once, like calling
+/// [`crate::find_in_resource_dirs`] for each subpath. Resource dirs that
+/// don't exist are only checked once, rather than once per subpath.
+pub fn find_all_in_resource_dirs(
+    resource_dirs: &[PathBuf],
+    subpaths: &[&Path],
+) -> Vec<Option<PathBuf>> {
+    let cache = LOOKUP_CACHE.get();
+    let mut results = vec![None; subpaths.len()];
+    let mut pending = vec![];
+
+    match cache {
+        Some(cache) => {
+            let cache = cache.lock().unwrap_or_else(|error| error.into_inner());
+            for (index, subpath) in subpaths.iter().enumerate() {
+                let key = (resource_dirs.to_vec(), subpath.to_path_buf());
+                match cache.get(&key) {
+                    Some(result) => {
+                        results[index] = result.clone();
+                    }
+                    None => {
+                        pending.push(index);
+                    }
+                }
+            }
+        }
+        None => {
+            pending.extend(0..subpaths.len());
+        }
+    }
+
+    if pending.is_empty() {
+        return results;
+    }
+
+    for resource_dir in resource_dirs {
+        if !resource_dir.is_dir() {
+            continue;
+        }
+
+        pending.retain(|&index| {
+            let path = resource_dir.join(subpaths[index]);
+            if path.exists() {
+                results[index] = Some(path);
+                false
+            } else {
+                true
+            }
+        });
+        if pending.is_empty() {
+            break;
+        }
+    }
+
+    #[cfg(feature = "url")]
+    for &index in &pending {
+        if let Ok(Some(path)) = crate::fetch_remote_resource(subpaths[index]) {
+            results[index] = Some(path);
+        }
+    }
+
+    if let Some(cache) = cache {
+        let mut cache = cache.lock().unwrap_or_else(|error| error.into_inner());
+        for (subpath, result) in subpaths.iter().zip(&results) {
+            let key = (resource_dirs.to_vec(), subpath.to_path_buf());
+            cache.entry(key).or_insert_with(|| result.clone());
+        }
+    }
+
+    results
+}
@@ -0,0 +1,118 @@
+use std::path::{Path, PathBuf};
+
+/// The name of the manifest file that can be placed next to a program to
+/// configure how its resources are found.
+pub const MANIFEST_FILENAME: &str = "brioche-resources.toml";
+
+/// The environment variable with the path of a manifest to use instead of
+/// the one next to the program.
+pub const MANIFEST_ENV_VAR: &str = "BRIOCHE_RESOURCE_MANIFEST";
+
+/// The default number of parent directories to search for a
+/// `brioche-resources.d` dir.
+pub const DEFAULT_SEARCH_DEPTH: u32 = 64;
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ResourceManifestFile {
+    #[serde(default)]
+    resource_dirs: Vec<PathBuf>,
+
+    #[serde(default)]
+    remote_urls: Vec<String>,
+
+    search_depth: Option<u32>,
+}
+
+/// Resource search settings loaded from a `brioche-resources.toml` file.
+/// Environment variables take precedence over the manifest, so its resource
+/// dirs are searched after `$BRIOCHE_RESOURCE_DIR` and
+/// `$BRIOCHE_INPUT_RESOURCE_DIRS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceManifest {
+    /// Resource dirs to search, in order, before searching the program's
+    /// parent directories. Relative paths in the file are resolved from
+    /// the manifest's directory. If `$BRIOCHE_RESOURCE_DIR` isn't set,
+    /// new resources are written to the first one.
+    pub resource_dirs: Vec<PathBuf>,
+
+    /// Base URLs of remote stores to fetch missing resources from, tried in
+    /// order after `$BRIOCHE_RESOURCE_REMOTE_URL`. Only used with the `url`
+    /// feature.
+    pub remote_urls: Vec<String>,
+
+    /// How many parent directories of the program to search for a
+    /// `brioche-resources.d` dir. Set to 0 to only use the listed resource
+    /// dirs.
+    pub search_depth: u32,
+}
+
+impl Default for ResourceManifest {
+    fn default() -> Self {
+        Self {
+            resource_dirs: vec![],
+            remote_urls: vec![],
+            search_depth: DEFAULT_SEARCH_DEPTH,
+        }
+    }
+}
+
+impl ResourceManifest {
+    /// Load the manifest from [`MANIFEST_ENV_VAR`] if set, or from the
+    /// [`MANIFEST_FILENAME`] file next to `program` if it exists.
+    pub fn find(program: &Path) -> Result<Option<Self>, ManifestError> {
+        if let Some(manifest_path) = std::env::var_os(MANIFEST_ENV_VAR) {
+            let manifest = Self::load(Path::new(&manifest_path))?;
+            return Ok(Some(manifest));
+        }
+
+        let program = std::env::current_dir()?.join(program);
+        let Some(program_dir) = program.parent() else {
+            return Ok(None);
+        };
+        let manifest_path = program_dir.join(MANIFEST_FILENAME);
+        match Self::load(&manifest_path) {
+            Ok(manifest) => Ok(Some(manifest)),
+            Err(ManifestError::IoError(error)) if error.kind() == std::io::ErrorKind::NotFound => {
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ManifestError> {
+        let contents = std::fs::read_to_string(path)?;
+        let manifest: ResourceManifestFile =
+            toml::from_str(&contents).map_err(|error| ManifestError::ParseError {
+                path: path.to_owned(),
+                source: Box::new(error),
+            })?;
+
+        let manifest_dir = std::path::absolute(path)?
+            .parent()
+            .map(Path::to_owned)
+            .unwrap_or_default();
+        let resource_dirs = manifest
+            .resource_dirs
+            .into_iter()
+            .map(|dir| manifest_dir.join(dir))
+            .collect();
+
+        Ok(Self {
+            resource_dirs,
+            remote_urls: manifest.remote_urls,
+            search_depth: manifest.search_depth.unwrap_or(DEFAULT_SEARCH_DEPTH),
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("failed to parse resource manifest {path:?}: {source}")]
+    ParseError {
+        path: PathBuf,
+        source: Box<toml::de::Error>,
+    },
+    #[error("failed to read resource manifest: {0}")]
+    IoError(#[from] std::io::Error),
+}
@@ -0,0 +1,78 @@
+//! Filesystem operations that differ between platforms. Resource dirs are
+//! laid out the same way everywhere, but platforms without Unix symlinks
+//! or mode bits fall back to the closest equivalent.
+
+use std::path::Path;
+
+/// Create a symlink at `link` pointing to `target`, which is relative to
+/// `link`'s parent directory.
+///
+/// On Windows, creating symlinks can require extra privileges, so if the
+/// symlink can't be created the target is copied to `link` instead.
+#[cfg(unix)]
+pub fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    let resolved_target = link.parent().unwrap_or(Path::new("")).join(target);
+    let is_dir = resolved_target.is_dir();
+    let result = if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    };
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(_) if is_dir => {
+            copy_dir::copy_dir(&resolved_target, link)?;
+            Ok(())
+        }
+        Err(_) => {
+            std::fs::copy(&resolved_target, link)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Returns true if any of the file's executable bits are set. Always
+/// returns false on platforms without mode bits.
+#[cfg(unix)]
+pub fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+pub fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// Returns true if the file's permissions match a blob's `.x` suffix.
+/// Always returns true on platforms without mode bits, since the suffix is
+/// the only record of whether the blob is executable.
+pub fn executable_matches(metadata: &std::fs::Metadata, executable: bool) -> bool {
+    !cfg!(unix) || is_executable(metadata) == executable
+}
+
+/// Set the mode for files created with `options`, so executable blobs get
+/// executable bits. Does nothing on platforms without mode bits.
+#[cfg(unix)]
+pub fn set_executable_mode(options: &mut std::fs::OpenOptions, executable: bool) {
+    use std::os::unix::fs::OpenOptionsExt as _;
+
+    if executable {
+        options.mode(0o777);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn set_executable_mode(_options: &mut std::fs::OpenOptions, _executable: bool) {}
@@ -0,0 +1,149 @@
+use std::path::{Component, Path, PathBuf};
+
+/// The environment variable with the base URL of a remote store to fetch
+/// missing resources from. Blobs are fetched from `<base>/blobs/<hash>`,
+/// with the hash formatted as in blob names. More stores can be listed in
+/// a [`crate::ResourceManifest`].
+pub const REMOTE_URL_ENV_VAR: &str = "BRIOCHE_RESOURCE_REMOTE_URL";
+
+/// Fetch a missing resource from the remote store set by
+/// [`REMOTE_URL_ENV_VAR`] or the stores from the running program's
+/// [`crate::ResourceManifest`], returning its path in the writable resource
+/// dir. The writable resource dir is `$BRIOCHE_RESOURCE_DIR`, or the first
+/// resource dir from the manifest. Stores are tried in order until one has
+/// the resource.
+///
+/// Only blob aliases (`aliases/<name>/<blob>/<name>`) and the directories
+/// containing them (`aliases/<name>/<blob>`) can be fetched, since the
+/// blob's hash is part of the path. Returns `Ok(None)` if no remote store
+/// or writable resource dir is set, or if the resource can't be fetched by
+/// path (such as resource directories).
+pub fn fetch_remote_resource(subpath: &Path) -> Result<Option<PathBuf>, FetchResourceError> {
+    let Some(alias) = parse_alias_path(subpath) else {
+        return Ok(None);
+    };
+
+    let manifest = crate::ResourceManifest::find(&std::env::current_exe()?)?;
+    let (manifest_urls, manifest_resource_dir) = match manifest {
+        Some(manifest) => (
+            manifest.remote_urls,
+            manifest.resource_dirs.into_iter().next(),
+        ),
+        None => (vec![], None),
+    };
+
+    let mut base_urls = vec![];
+    if let Some(base_url) = std::env::var_os(REMOTE_URL_ENV_VAR) {
+        let base_url = base_url
+            .into_string()
+            .map_err(|_| FetchResourceError::InvalidUrl)?;
+        base_urls.push(base_url);
+    }
+    base_urls.extend(manifest_urls);
+
+    let resource_dir = std::env::var_os("BRIOCHE_RESOURCE_DIR")
+        .map(PathBuf::from)
+        .or(manifest_resource_dir);
+    let Some(resource_dir) = resource_dir else {
+        return Ok(None);
+    };
+
+    let mut alias_path = None;
+    let mut last_error = None;
+    for base_url in &base_urls {
+        let url = format!("{}/blobs/{}", base_url.trim_end_matches('/'), alias.hash);
+        match crate::add_named_blob_from_url(
+            &resource_dir,
+            &url,
+            &alias.hash,
+            alias.executable,
+            &alias.name,
+        ) {
+            Ok(path) => {
+                alias_path = Some(path);
+                break;
+            }
+            Err(error) => {
+                last_error = Some(error);
+            }
+        }
+    }
+    let alias_path = match (alias_path, last_error) {
+        (Some(alias_path), _) => alias_path,
+        (None, Some(error)) => {
+            return Err(error.into());
+        }
+        (None, None) => {
+            return Ok(None);
+        }
+    };
+
+    let path = if alias.is_dir {
+        let alias_dir = alias_path
+            .parent()
+            .expect("alias path has no parent directory");
+        resource_dir.join(alias_dir)
+    } else {
+        resource_dir.join(alias_path)
+    };
+    Ok(Some(path))
+}
+
+struct BlobAlias {
+    name: PathBuf,
+    hash: crate::ResourceHash,
+    executable: bool,
+    is_dir: bool,
+}
+
+fn parse_alias_path(subpath: &Path) -> Option<BlobAlias> {
+    let mut components = subpath.components();
+    if components.next() != Some(Component::Normal("aliases".as_ref())) {
+        return None;
+    }
+    let components = components
+        .map(|component| match component {
+            Component::Normal(component) => Some(component),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    // The blob name is surrounded by the alias name on both sides (or just
+    // before it for the alias dir), and the alias name may have more than
+    // one component
+    for (index, component) in components.iter().enumerate().skip(1) {
+        let Some(blob_name) = component.to_str() else {
+            continue;
+        };
+        let Some((hash, executable)) = crate::parse_blob_name(blob_name) else {
+            continue;
+        };
+
+        let name = &components[..index];
+        let rest = &components[index + 1..];
+        if !rest.is_empty() && rest != name {
+            continue;
+        }
+
+        return Some(BlobAlias {
+            name: name.iter().collect(),
+            hash,
+            executable,
+            is_dir: rest.is_empty(),
+        });
+    }
+
+    None
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FetchResourceError {
+    #[error("{REMOTE_URL_ENV_VAR} is not valid UTF-8")]
+    InvalidUrl,
+    #[error(transparent)]
+    AddBlobFromUrlError(#[from] crate::AddBlobFromUrlError),
+    #[error(transparent)]
+    ManifestError(#[from] crate::ManifestError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
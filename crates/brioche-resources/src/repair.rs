@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+
+/// The changes made (or that would be made) by [`repair`].
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    /// Temporary blob files left by interrupted writers.
+    pub removed_temp_blobs: Vec<PathBuf>,
+
+    /// Temporary alias symlinks left by interrupted writers.
+    pub removed_temp_aliases: Vec<PathBuf>,
+
+    /// Temporary directory copies left by interrupted writers.
+    pub removed_temp_directories: Vec<PathBuf>,
+
+    /// Alias symlinks that were pointed at a blob with the same hash but
+    /// different permissions, because the original blob was missing.
+    pub fixed_aliases: Vec<PathBuf>,
+
+    /// Alias symlinks whose targets are missing and couldn't be fixed.
+    pub dangling_aliases: Vec<PathBuf>,
+}
+
+impl RepairReport {
+    pub fn is_empty(&self) -> bool {
+        self.removed_temp_blobs.is_empty()
+            && self.removed_temp_aliases.is_empty()
+            && self.removed_temp_directories.is_empty()
+            && self.fixed_aliases.is_empty()
+            && self.dangling_aliases.is_empty()
+    }
+}
+
+/// Clean up a resource dir after writers were interrupted. Temporary
+/// blobs, aliases, and directories are removed, and aliases to missing
+/// blobs are repointed if the same blob exists with different permissions.
+/// Dangling aliases that can't be fixed are only reported.
+///
+/// This should not be called while another process is writing to the
+/// resource dir, since its temporary files would be removed. If `dry_run`
+/// is set, the returned report lists what would change without changing
+/// anything.
+pub fn repair(resource_dir: &Path, dry_run: bool) -> Result<RepairReport, RepairError> {
+    let mut report = RepairReport::default();
+
+    // Temporary blobs are named `<hash>-<ulid>` or `<hash>.x-<ulid>`
+    let blobs_dir = resource_dir.join("blobs");
+    for entry in read_dir_if_exists(&blobs_dir)? {
+        let entry = entry?;
+        if has_temp_suffix(&entry.file_name()) {
+            if !dry_run {
+                std::fs::remove_file(entry.path())?;
+            }
+            report.removed_temp_blobs.push(entry.path());
+        }
+    }
+
+    // Temporary directories are named with just a ULID
+    let directories_dir = resource_dir.join("directories");
+    for entry in read_dir_if_exists(&directories_dir)? {
+        let entry = entry?;
+        let is_temp = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| ulid::Ulid::from_string(name).is_ok());
+        if is_temp {
+            if !dry_run {
+                std::fs::remove_dir_all(entry.path())?;
+            }
+            report.removed_temp_directories.push(entry.path());
+        }
+    }
+
+    let aliases_dir = resource_dir.join("aliases");
+    if aliases_dir.is_dir() {
+        for entry in walkdir::WalkDir::new(&aliases_dir) {
+            let entry = entry.map_err(std::io::Error::from)?;
+            if !entry.path_is_symlink() {
+                continue;
+            }
+
+            // Temporary aliases are named `<name>-<ulid>`
+            if has_temp_suffix(entry.file_name()) {
+                if !dry_run {
+                    std::fs::remove_file(entry.path())?;
+                }
+                report.removed_temp_aliases.push(entry.path().to_owned());
+                continue;
+            }
+
+            if entry.path().exists() {
+                continue;
+            }
+
+            let target = std::fs::read_link(entry.path())?;
+            let replacement_target = alternate_blob_target(&target).filter(|replacement| {
+                entry
+                    .path()
+                    .parent()
+                    .is_some_and(|parent| parent.join(replacement).is_file())
+            });
+            match replacement_target {
+                Some(replacement_target) => {
+                    if !dry_run {
+                        replace_symlink(entry.path(), &replacement_target)?;
+                    }
+                    report.fixed_aliases.push(entry.path().to_owned());
+                }
+                None => {
+                    report.dangling_aliases.push(entry.path().to_owned());
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+pub fn read_dir_if_exists(
+    path: &Path,
+) -> Result<Box<dyn Iterator<Item = std::io::Result<std::fs::DirEntry>>>, std::io::Error> {
+    match std::fs::read_dir(path) {
+        Ok(entries) => Ok(Box::new(entries)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            Ok(Box::new(std::iter::empty()))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns true if a filename ends with `-<ulid>`, as used by temporary
+/// files while writing resources.
+pub fn has_temp_suffix(name: &std::ffi::OsStr) -> bool {
+    let Some(name) = name.to_str() else {
+        return false;
+    };
+    let Some((_, suffix)) = name.rsplit_once('-') else {
+        return false;
+    };
+
+    ulid::Ulid::from_string(suffix).is_ok()
+}
+
+/// Get the path to the same blob with the opposite permissions, i.e.
+/// `<hash>.x` for `<hash>` and vice versa.
+fn alternate_blob_target(target: &Path) -> Option<PathBuf> {
+    let blob_name = target.file_name()?.to_str()?;
+    let alternate_name = match blob_name.strip_suffix(".x") {
+        Some(hash) => hash.to_string(),
+        None => format!("{blob_name}.x"),
+    };
+    Some(target.with_file_name(alternate_name))
+}
+
+/// Atomically create or replace a symlink.
+pub fn replace_symlink(path: &Path, target: &Path) -> Result<(), std::io::Error> {
+    let temp_id = ulid::Ulid::new();
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!("-{temp_id}"));
+    let temp_path = path.with_file_name(temp_name);
+
+    crate::platform::symlink(target, &temp_path)?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RepairError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
@@ -0,0 +1,280 @@
+use std::path::{Path, PathBuf};
+
+/// Options for [`ResourceResolver`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResolveOptions {
+    /// Reject resources from resource dirs that any user can write to,
+    /// since another user could replace what the resource points to.
+    pub secure: bool,
+}
+
+/// A resource found by [`ResourceResolver::resolve`].
+#[derive(Debug, Clone)]
+pub struct ResolvedResource {
+    /// The path of the resource within the resource dir it was found in.
+    /// Symlinks aren't resolved, so this is the path to use when running
+    /// or loading the resource.
+    pub path: PathBuf,
+
+    /// The canonical path of the resource, with all symlinks resolved.
+    pub canonical_path: PathBuf,
+
+    /// The resource dir the resource was found in.
+    pub resource_dir: PathBuf,
+}
+
+/// Finds resources like [`crate::find_in_resource_dirs`], then checks that
+/// each resource's canonical path is still inside one of the resource dirs
+/// (so a symlink can't point it somewhere else). Errors include the
+/// resource dir the resource came from, or the dirs that were searched if
+/// it wasn't found.
+///
+/// The resource dirs are canonicalized once when the resolver is created,
+/// so create one resolver and use it for every resource.
+#[derive(Debug, Clone)]
+pub struct ResourceResolver {
+    resource_dirs: Vec<PathBuf>,
+    canonical_resource_dirs: Vec<Option<CanonicalResourceDir>>,
+    options: ResolveOptions,
+}
+
+#[derive(Debug, Clone)]
+struct CanonicalResourceDir {
+    path: PathBuf,
+    world_writable: bool,
+}
+
+impl ResourceResolver {
+    pub fn new(resource_dirs: &[PathBuf], options: ResolveOptions) -> Self {
+        // Resource dirs that don't exist can't contain any resources, so
+        // they're skipped when checking canonical paths
+        let canonical_resource_dirs = resource_dirs
+            .iter()
+            .map(|resource_dir| {
+                let path = resource_dir.canonicalize().ok()?;
+
+                // Treat dirs that can't be checked as writable, so they're
+                // rejected in secure mode
+                let world_writable = options.secure && is_world_writable(&path).unwrap_or(true);
+                Some(CanonicalResourceDir {
+                    path,
+                    world_writable,
+                })
+            })
+            .collect();
+
+        Self {
+            resource_dirs: resource_dirs.to_vec(),
+            canonical_resource_dirs,
+            options,
+        }
+    }
+
+    pub fn resolve(&self, subpath: &Path) -> Result<ResolvedResource, ResolveResourceError> {
+        let path = crate::find_in_resource_dirs(&self.resource_dirs, subpath).ok_or_else(|| {
+            ResolveResourceError::NotFound {
+                subpath: subpath.to_owned(),
+                searched: self.resource_dirs.clone(),
+            }
+        })?;
+        let found_index = self
+            .resource_dirs
+            .iter()
+            .position(|resource_dir| path.starts_with(resource_dir))
+            .ok_or_else(|| ResolveResourceError::UnknownResourceDir {
+                subpath: subpath.to_owned(),
+                path: path.clone(),
+            })?;
+        let resource_dir = &self.resource_dirs[found_index];
+
+        let canonical_path = path.canonicalize()?;
+        let containing_index = self
+            .canonical_resource_dirs
+            .iter()
+            .position(|canonical_resource_dir| {
+                canonical_resource_dir
+                    .as_ref()
+                    .is_some_and(|dir| canonical_path.starts_with(&dir.path))
+            })
+            .ok_or_else(|| ResolveResourceError::OutsideResourceDirs {
+                subpath: subpath.to_owned(),
+                resource_dir: resource_dir.clone(),
+                path: canonical_path.clone(),
+            })?;
+
+        if self.options.secure {
+            for index in [found_index, containing_index] {
+                let world_writable = self.canonical_resource_dirs[index]
+                    .as_ref()
+                    .is_some_and(|dir| dir.world_writable);
+                if world_writable {
+                    return Err(ResolveResourceError::WorldWritable {
+                        subpath: subpath.to_owned(),
+                        resource_dir: self.resource_dirs[index].clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(ResolvedResource {
+            path,
+            canonical_path,
+            resource_dir: resource_dir.clone(),
+        })
+    }
+
+    /// Get a path to a resolved blob that can be run or loaded directly,
+    /// decompressing it with [`crate::materialize_blob`] if it's
+    /// compressed. In secure mode, compressed blobs are rejected, since
+    /// they'd be run from a cache dir outside the resource dirs.
+    pub fn materialize(&self, path: &Path) -> Result<PathBuf, ResolveResourceError> {
+        if self.options.secure && crate::is_compressed_blob(path)? {
+            return Err(ResolveResourceError::CompressedInSecureMode {
+                path: path.to_owned(),
+            });
+        }
+
+        let path = crate::materialize_blob(path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(unix)]
+fn is_world_writable(path: &Path) -> Result<bool, std::io::Error> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let metadata = std::fs::metadata(path)?;
+    Ok(metadata.permissions().mode() & 0o002 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_world_writable(_path: &Path) -> Result<bool, std::io::Error> {
+    Ok(false)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResolveResourceError {
+    #[error("resource {subpath:?} not found (searched {searched:?})")]
+    NotFound {
+        subpath: PathBuf,
+        searched: Vec<PathBuf>,
+    },
+    #[error("resource {subpath:?} was found at {path:?}, which is not in a resource dir")]
+    UnknownResourceDir { subpath: PathBuf, path: PathBuf },
+    #[error(
+        "resource {subpath:?} from resource dir {resource_dir:?} resolves to {path:?}, which is outside the resource dirs"
+    )]
+    OutsideResourceDirs {
+        subpath: PathBuf,
+        resource_dir: PathBuf,
+        path: PathBuf,
+    },
+    #[error("resource {subpath:?} is in world-writable resource dir {resource_dir:?}")]
+    WorldWritable {
+        subpath: PathBuf,
+        resource_dir: PathBuf,
+    },
+    #[error("resource {path:?} is compressed, which isn't allowed in secure mode")]
+    CompressedInSecureMode { path: PathBuf },
+    #[error(transparent)]
+    MaterializeBlobError(#[from] crate::MaterializeBlobError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    use super::*;
+
+    struct TestDirs {
+        _root: tempfile::TempDir,
+        root: PathBuf,
+        resource_dirs: Vec<PathBuf>,
+    }
+
+    fn test_dirs() -> TestDirs {
+        let root = tempfile::tempdir().unwrap();
+        let root_path = root.path().canonicalize().unwrap();
+        let resource_dirs = vec![root_path.join("a"), root_path.join("b")];
+        for resource_dir in &resource_dirs {
+            std::fs::create_dir(resource_dir).unwrap();
+        }
+        std::fs::write(resource_dirs[1].join("file"), "").unwrap();
+        std::fs::write(root_path.join("outside"), "").unwrap();
+
+        TestDirs {
+            _root: root,
+            root: root_path,
+            resource_dirs,
+        }
+    }
+
+    #[test]
+    fn test_resolve_keeps_found_path() {
+        let dirs = test_dirs();
+        std::os::unix::fs::symlink(
+            dirs.resource_dirs[1].join("file"),
+            dirs.resource_dirs[0].join("alias"),
+        )
+        .unwrap();
+
+        let resolver = ResourceResolver::new(&dirs.resource_dirs, ResolveOptions::default());
+        let resolved = resolver.resolve(Path::new("alias")).unwrap();
+        assert_eq!(resolved.path, dirs.resource_dirs[0].join("alias"));
+        assert_eq!(resolved.canonical_path, dirs.resource_dirs[1].join("file"));
+        assert_eq!(resolved.resource_dir, dirs.resource_dirs[0]);
+    }
+
+    #[test]
+    fn test_resolve_outside_resource_dirs() {
+        let dirs = test_dirs();
+        std::os::unix::fs::symlink(
+            dirs.root.join("outside"),
+            dirs.resource_dirs[0].join("escape"),
+        )
+        .unwrap();
+
+        let resolver = ResourceResolver::new(&dirs.resource_dirs, ResolveOptions::default());
+        let error = resolver.resolve(Path::new("escape")).unwrap_err();
+        assert!(matches!(
+            error,
+            ResolveResourceError::OutsideResourceDirs { resource_dir, .. }
+                if resource_dir == dirs.resource_dirs[0]
+        ));
+    }
+
+    #[test]
+    fn test_resolve_not_found() {
+        let dirs = test_dirs();
+
+        let resolver = ResourceResolver::new(&dirs.resource_dirs, ResolveOptions::default());
+        let error = resolver.resolve(Path::new("missing")).unwrap_err();
+        assert!(matches!(
+            error,
+            ResolveResourceError::NotFound { searched, .. } if searched == dirs.resource_dirs
+        ));
+    }
+
+    #[test]
+    fn test_resolve_world_writable() {
+        let dirs = test_dirs();
+        std::fs::set_permissions(
+            &dirs.resource_dirs[1],
+            std::fs::Permissions::from_mode(0o777),
+        )
+        .unwrap();
+
+        let resolver = ResourceResolver::new(&dirs.resource_dirs, ResolveOptions::default());
+        assert!(resolver.resolve(Path::new("file")).is_ok());
+
+        let resolver = ResourceResolver::new(&dirs.resource_dirs, ResolveOptions { secure: true });
+        let error = resolver.resolve(Path::new("file")).unwrap_err();
+        assert!(matches!(
+            error,
+            ResolveResourceError::WorldWritable { resource_dir, .. }
+                if resource_dir == dirs.resource_dirs[1]
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+
+use crate::repair::{has_temp_suffix, read_dir_if_exists};
+
+/// Counts and sizes of the resources in a resource dir, returned by
+/// [`stats`].
+#[derive(Debug, Default)]
+pub struct ResourceStats {
+    pub blob_count: u64,
+
+    /// The total size of all blobs, in bytes. Blobs hard linked from
+    /// outside the resource dir are counted at their full size.
+    pub blob_size: u64,
+
+    pub directory_count: u64,
+
+    /// The total size of the files in all directory resources, in bytes.
+    pub directory_size: u64,
+
+    /// The number of alias symlinks.
+    pub alias_count: u64,
+
+    /// The largest blobs and directories, largest first.
+    pub largest_entries: Vec<ResourceEntry>,
+}
+
+impl ResourceStats {
+    /// The total size of all blobs and directories, in bytes.
+    pub fn total_size(&self) -> u64 {
+        self.blob_size + self.directory_size
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResourceEntry {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Count the blobs, directories, and aliases in a resource dir, and find
+/// the `largest_count` largest blobs and directories. Temporary files left
+/// by interrupted writers aren't counted (see [`crate::repair`]).
+pub fn stats(resource_dir: &Path, largest_count: usize) -> Result<ResourceStats, StatsError> {
+    let mut stats = ResourceStats::default();
+    let mut entries = vec![];
+
+    let blobs_dir = resource_dir.join("blobs");
+    for entry in read_dir_if_exists(&blobs_dir)? {
+        let entry = entry?;
+        if has_temp_suffix(&entry.file_name()) {
+            continue;
+        }
+
+        let size = entry.metadata()?.len();
+        stats.blob_count += 1;
+        stats.blob_size += size;
+        entries.push(ResourceEntry {
+            path: entry.path(),
+            size,
+        });
+    }
+
+    let directories_dir = resource_dir.join("directories");
+    for entry in read_dir_if_exists(&directories_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+
+        // Temporary directories are named with just a ULID
+        let name = file_name.to_str().unwrap_or_default();
+        if ulid::Ulid::from_string(name).is_ok() {
+            continue;
+        }
+
+        let mut size = 0;
+        for file in walkdir::WalkDir::new(entry.path()) {
+            let file = file.map_err(std::io::Error::from)?;
+            if file.file_type().is_file() {
+                size += file.metadata().map_err(std::io::Error::from)?.len();
+            }
+        }
+
+        stats.directory_count += 1;
+        stats.directory_size += size;
+        entries.push(ResourceEntry {
+            path: entry.path(),
+            size,
+        });
+    }
+
+    let aliases_dir = resource_dir.join("aliases");
+    if aliases_dir.is_dir() {
+        for entry in walkdir::WalkDir::new(&aliases_dir) {
+            let entry = entry.map_err(std::io::Error::from)?;
+            if entry.path_is_symlink() && !has_temp_suffix(entry.file_name()) {
+                stats.alias_count += 1;
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    entries.truncate(largest_count);
+    stats.largest_entries = entries;
+
+    Ok(stats)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StatsError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
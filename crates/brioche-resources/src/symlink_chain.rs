@@ -0,0 +1,159 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+};
+
+/// Symlinks can point to other symlinks, so give up after this many.
+const MAX_CHAIN_LENGTH: usize = 40;
+
+/// The names a file is known by within its directory, such as a library
+/// with SONAME symlinks (`libfoo.so -> libfoo.so.1 -> libfoo.so.1.2.3`).
+/// Returned by [`symlink_chain`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymlinkChain {
+    /// The name of the file the chain ends at.
+    pub real_name: OsString,
+
+    /// Each symlink in the chain, mapped to the name it points to.
+    pub links: BTreeMap<OsString, OsString>,
+}
+
+/// Find the symlinks leading to and from `path` within its directory.
+/// Starting from `path`, symlinks are followed while they point to another
+/// name in the same directory. Other symlinks in the directory that point
+/// to any name in the chain are included too, so the chain for
+/// `libfoo.so.1` also includes `libfoo.so`.
+pub fn symlink_chain(path: &Path) -> std::io::Result<SymlinkChain> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let Some(file_name) = path.file_name() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("path has no file name: {}", path.display()),
+        ));
+    };
+
+    let mut links = BTreeMap::new();
+    let mut real_name = file_name.to_owned();
+    while let Some(target) = sibling_link_target(&dir.join(&real_name))? {
+        if links.len() >= MAX_CHAIN_LENGTH || links.contains_key(&real_name) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("too many levels of symlinks: {}", path.display()),
+            ));
+        }
+        links.insert(real_name, target.clone());
+        real_name = target;
+    }
+
+    // Add other links pointing into the chain, repeating until no more
+    // are found so links to links are included
+    let mut siblings = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_symlink() {
+            continue;
+        }
+        if let Some(target) = sibling_link_target(&entry.path())? {
+            siblings.push((entry.file_name(), target));
+        }
+    }
+    loop {
+        let mut added = false;
+        for (name, target) in &siblings {
+            let in_chain = *target == real_name || links.contains_key(target);
+            if in_chain && *name != real_name && !links.contains_key(name) {
+                links.insert(name.clone(), target.clone());
+                added = true;
+            }
+        }
+        if !added {
+            break;
+        }
+    }
+
+    Ok(SymlinkChain { real_name, links })
+}
+
+/// Add the symlinks from a chain next to a blob alias, so the alias dir
+/// exposes every name from the chain. The chain's real name points to the
+/// blob, and each link points to the same name it did originally. If the
+/// alias itself is a link in the chain, it's replaced with a symlink to
+/// its target in the chain.
+///
+/// Returns the paths of the added symlinks, relative to the resource dir.
+pub fn add_symlink_chain(
+    resource_dir: &Path,
+    alias_path: &Path,
+    chain: &SymlinkChain,
+) -> Result<Vec<PathBuf>, crate::AddBlobError> {
+    let alias_path = resource_dir.join(alias_path);
+    let invalid_alias_path = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid alias path: {}", alias_path.display()),
+        )
+    };
+    let alias_dir = alias_path.parent().ok_or_else(invalid_alias_path)?;
+    let blob_name = alias_dir.file_name().ok_or_else(invalid_alias_path)?;
+
+    // The alias dir is named after the blob
+    let blob_path = resource_dir.join("blobs").join(blob_name);
+    let blob_target = pathdiff::diff_paths(&blob_path, alias_dir)
+        .expect("blob path is not a prefix of alias path");
+
+    // Add the real name first, so the other links never dangle
+    let mut added = vec![];
+    let real_link = (chain.real_name.as_os_str(), blob_target.as_path());
+    let links = chain
+        .links
+        .iter()
+        .map(|(name, target)| (name.as_os_str(), Path::new(target)));
+    for (name, target) in std::iter::once(real_link).chain(links) {
+        if !is_file_name(name) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid symlink chain name: {name:?}"),
+            )
+            .into());
+        }
+
+        let link_path = alias_dir.join(name);
+        crate::repair::replace_symlink(&link_path, target)?;
+        let link_path = link_path
+            .strip_prefix(resource_dir)
+            .expect("alias path is not in resource dir");
+        added.push(link_path.to_owned());
+    }
+
+    Ok(added)
+}
+
+/// If `path` is a symlink to another name in the same directory, return
+/// that name.
+fn sibling_link_target(path: &Path) -> std::io::Result<Option<OsString>> {
+    let target = match std::fs::read_link(path) {
+        Ok(target) => target,
+        Err(error) if error.kind() == std::io::ErrorKind::InvalidInput => {
+            // Not a symlink
+            return Ok(None);
+        }
+        Err(error) => {
+            return Err(error);
+        }
+    };
+
+    if is_file_name(target.as_os_str()) {
+        Ok(Some(target.into_os_string()))
+    } else {
+        Ok(None)
+    }
+}
+
+fn is_file_name(name: &OsStr) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
@@ -0,0 +1,148 @@
+use std::path::{Path, PathBuf};
+
+use crate::repair::{has_temp_suffix, read_dir_if_exists};
+
+/// The problems found by [`verify`].
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Blobs whose contents don't match the hash in their name, or whose
+    /// permissions don't match their `.x` suffix.
+    pub corrupted_blobs: Vec<PathBuf>,
+
+    /// Directories whose contents don't match the hash in their name.
+    pub corrupted_directories: Vec<PathBuf>,
+
+    /// Entries in `blobs/` or `directories/` that aren't named like a
+    /// resource or a temporary file, or compressed blobs that can't be
+    /// read without the `zstd` feature.
+    pub unrecognized_entries: Vec<PathBuf>,
+
+    /// Alias symlinks whose targets are missing.
+    pub dangling_aliases: Vec<PathBuf>,
+
+    /// Alias symlinks that resolve to a path outside the resource dir.
+    pub escaping_aliases: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    pub fn is_empty(&self) -> bool {
+        self.corrupted_blobs.is_empty()
+            && self.corrupted_directories.is_empty()
+            && self.unrecognized_entries.is_empty()
+            && self.dangling_aliases.is_empty()
+            && self.escaping_aliases.is_empty()
+    }
+}
+
+/// Check the integrity of a resource dir. Each blob and directory is
+/// re-hashed and compared against its name, and each alias is checked to
+/// resolve to a path within the resource dir. Temporary files left by
+/// interrupted writers are ignored (see [`crate::repair`]).
+///
+/// Nothing in the resource dir is changed.
+pub fn verify(resource_dir: &Path) -> Result<VerifyReport, VerifyError> {
+    let mut report = VerifyReport::default();
+
+    let blobs_dir = resource_dir.join("blobs");
+    for entry in read_dir_if_exists(&blobs_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if has_temp_suffix(&file_name) {
+            continue;
+        }
+
+        let blob_name = file_name.to_str().unwrap_or_default();
+        let Some((expected_hash, executable, compressed)) =
+            crate::compression::parse_blob_file_name(blob_name)
+        else {
+            report.unrecognized_entries.push(entry.path());
+            continue;
+        };
+
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            report.corrupted_blobs.push(entry.path());
+            continue;
+        }
+
+        let hash = match crate::compression::hash_blob_file(
+            &entry.path(),
+            compressed,
+            expected_hash.hasher(),
+        ) {
+            Ok(hash) => hash,
+            Err(error) if error.kind() == std::io::ErrorKind::Unsupported => {
+                // Compressed blobs can't be checked without zstd support
+                report.unrecognized_entries.push(entry.path());
+                continue;
+            }
+            Err(error) => {
+                return Err(error.into());
+            }
+        };
+        if hash != Some(expected_hash)
+            || !crate::platform::executable_matches(&metadata, executable)
+        {
+            report.corrupted_blobs.push(entry.path());
+        }
+    }
+
+    let directories_dir = resource_dir.join("directories");
+    for entry in read_dir_if_exists(&directories_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let name = file_name.to_str().unwrap_or_default();
+
+        // Temporary directories are named with just a ULID
+        if ulid::Ulid::from_string(name).is_ok() {
+            continue;
+        }
+
+        let Some(expected_hash) = crate::parse_directory_name(name) else {
+            report.unrecognized_entries.push(entry.path());
+            continue;
+        };
+
+        if !entry.file_type()?.is_dir() {
+            report.corrupted_directories.push(entry.path());
+            continue;
+        }
+        let hash = crate::hash_directory(&entry.path(), expected_hash.hasher())?;
+        if hash != expected_hash {
+            report.corrupted_directories.push(entry.path());
+        }
+    }
+
+    let aliases_dir = resource_dir.join("aliases");
+    if aliases_dir.is_dir() {
+        let canonical_resource_dir = resource_dir.canonicalize()?;
+        for entry in walkdir::WalkDir::new(&aliases_dir) {
+            let entry = entry.map_err(std::io::Error::from)?;
+            if !entry.path_is_symlink() || has_temp_suffix(entry.file_name()) {
+                continue;
+            }
+
+            match entry.path().canonicalize() {
+                Ok(target) => {
+                    if !target.starts_with(&canonical_resource_dir) {
+                        report.escaping_aliases.push(entry.path().to_owned());
+                    }
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                    report.dangling_aliases.push(entry.path().to_owned());
+                }
+                Err(error) => {
+                    return Err(error.into());
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
@@ -48,45 +48,27 @@ fn main() -> ExitCode {
 }
 
 fn run() -> eyre::Result<ExitCode> {
-    let current_exe = std::env::current_exe().context("failed to get current executable")?;
-    let current_exe_dir = current_exe
-        .parent()
-        .ok_or_eyre("failed to get current executable dir")?;
-    let current_exe_parent_dir = current_exe_dir
-        .parent()
-        .ok_or_eyre("failed to get current executable dir")?;
-    let strip_resource_dir = current_exe_parent_dir.join("libexec").join("brioche-strip");
-    if !strip_resource_dir.is_dir() {
-        eyre::bail!(
-            "failed to find strip resource dir: {}",
-            strip_resource_dir.display()
-        );
-    }
-
-    let strip = strip_resource_dir.join("strip");
+    let wrapper = brioche_toolwrap::Wrapper::new("brioche-strip")?;
+    let strip = wrapper.tool("strip");
 
     // If autopacking is disabled, call the original `strip` binary and
     // bail early
-    if let Ok("false") = std::env::var("BRIOCHE_STRIP_AUTOPACK").as_deref() {
+    if !brioche_toolwrap::env_toggle("BRIOCHE_STRIP_AUTOPACK", true) {
         let mut command = std::process::Command::new(strip);
         command.args(std::env::args_os().skip(1));
         let status = command.status()?;
 
-        let exit_code = status
-            .code()
-            .and_then(|code| u8::try_from(code).ok())
-            .map(ExitCode::from)
-            .unwrap_or(ExitCode::FAILURE);
-        return Ok(exit_code);
+        return Ok(brioche_toolwrap::exit_code(status));
     }
 
-    let mut args = std::env::args_os().skip(1);
+    // Expand `@file` args, since input paths may need to be remapped
+    let args = brioche_toolwrap::expand_response_files(std::env::args_os().skip(1))?;
+    let mut args = args.into_iter();
     let mut strip_args = vec![];
 
     // Parse each argument
     while let Some(arg) = args.next() {
-        let arg_bytes = <[u8]>::from_os_str(&arg).ok_or_eyre("invalid arg")?;
-        let arg_bytes = bstr::BStr::new(arg_bytes);
+        let arg_bytes = brioche_toolwrap::arg_bytes(&arg)?;
 
         match &**arg_bytes {
             b"-F"
@@ -117,20 +99,14 @@ fn run() -> eyre::Result<ExitCode> {
             _ => {
                 if let Some(output) = arg_bytes.strip_prefix(b"-o") {
                     // Support "-o<path>" syntax
-                    let output = output.to_path().map_err(|_| eyre::eyre!("invalid path"))?;
+                    let output = brioche_toolwrap::arg_path(output)?;
                     strip_args.push(StripArg::DashOPath(output.to_path_buf()))
                 } else if arg_bytes.starts_with(b"-") {
                     // Pass through any extra argument starting with a "-"
                     strip_args.push(StripArg::Arg(arg));
-                } else if arg_bytes.starts_with(b"@") {
-                    // @ is used to parse extra args from a file
-                    // (not yet implemented)
-                    eyre::bail!("using @ for passing args is not supported");
                 } else {
                     // Other args are treated as input files
-                    let input_path = arg_bytes
-                        .to_path()
-                        .map_err(|_| eyre::eyre!("invalid path"))?;
+                    let input_path = brioche_toolwrap::arg_path(arg_bytes)?;
                     strip_args.push(StripArg::InputPath(input_path.to_owned()));
                 }
             }
@@ -153,12 +129,7 @@ fn run() -> eyre::Result<ExitCode> {
     let status = command.status()?;
 
     if !status.success() {
-        let exit_code = status
-            .code()
-            .and_then(|code| u8::try_from(code).ok())
-            .map(ExitCode::from)
-            .unwrap_or(ExitCode::FAILURE);
-        return Ok(exit_code);
+        return Ok(brioche_toolwrap::exit_code(status));
     }
 
     // Finish processing each file we remapped
@@ -173,12 +144,14 @@ enum RemapFile {
     Inject {
         pack: brioche_pack::Pack,
         temp_file: tempfile::NamedTempFile,
+        trailing_payload: Option<Vec<u8>>,
         output_path: PathBuf,
     },
     UpdateSource {
         extracted: brioche_pack::ExtractedPack,
         input_path: PathBuf,
         temp_file: tempfile::NamedTempFile,
+        trailing_payload: Option<Vec<u8>>,
         output_path: PathBuf,
     },
 }
@@ -250,6 +223,8 @@ fn remap_files(args: &mut Vec<StripArg>, remapped_files: &mut Vec<RemapFile>) ->
                     let mut unpacked_input = input.take(extracted.unpacked_len.try_into()?);
                     let mut temp_file = tempfile::NamedTempFile::new()?;
                     std::io::copy(&mut unpacked_input, &mut temp_file)?;
+                    let trailing_payload =
+                        brioche_autopack::detach_trailing_payload(temp_file.as_file_mut())?;
 
                     // Replace the input and output path args with just
                     // the new temporary path
@@ -260,6 +235,7 @@ fn remap_files(args: &mut Vec<StripArg>, remapped_files: &mut Vec<RemapFile>) ->
                     // output then inject the pack
                     remapped_files.push(RemapFile::Inject {
                         temp_file,
+                        trailing_payload,
                         output_path,
                         pack: extracted.pack,
                     });
@@ -271,6 +247,8 @@ fn remap_files(args: &mut Vec<StripArg>, remapped_files: &mut Vec<RemapFile>) ->
                     let mut source = std::fs::File::open(&source_path)?;
                     let mut temp_file = tempfile::NamedTempFile::new()?;
                     std::io::copy(&mut source, &mut temp_file)?;
+                    let trailing_payload =
+                        brioche_autopack::detach_trailing_payload(temp_file.as_file_mut())?;
 
                     // Replace the input and output path args with just
                     // the new temporary path
@@ -284,6 +262,7 @@ fn remap_files(args: &mut Vec<StripArg>, remapped_files: &mut Vec<RemapFile>) ->
                         input_path,
                         extracted,
                         temp_file,
+                        trailing_payload,
                         output_path,
                     });
                 }
@@ -337,6 +316,9 @@ fn remap_files(args: &mut Vec<StripArg>, remapped_files: &mut Vec<RemapFile>) ->
                                     input.take(extracted.unpacked_len.try_into()?);
                                 let mut temp_file = tempfile::NamedTempFile::new()?;
                                 std::io::copy(&mut unpacked_input, &mut temp_file)?;
+                                let trailing_payload = brioche_autopack::detach_trailing_payload(
+                                    temp_file.as_file_mut(),
+                                )?;
 
                                 // Replace the input path argument with
                                 // the temp path
@@ -349,6 +331,7 @@ fn remap_files(args: &mut Vec<StripArg>, remapped_files: &mut Vec<RemapFile>) ->
                                 remapped_files.push(RemapFile::Inject {
                                     output_path: original_path,
                                     temp_file,
+                                    trailing_payload,
                                     pack: extracted.pack,
                                 });
                             }
@@ -359,6 +342,9 @@ fn remap_files(args: &mut Vec<StripArg>, remapped_files: &mut Vec<RemapFile>) ->
                                 let mut source_path = std::fs::File::open(&source_path)?;
                                 let mut temp_file = tempfile::NamedTempFile::new()?;
                                 std::io::copy(&mut source_path, &mut temp_file)?;
+                                let trailing_payload = brioche_autopack::detach_trailing_payload(
+                                    temp_file.as_file_mut(),
+                                )?;
 
                                 // Replace the input path argument with
                                 // the temp path
@@ -371,6 +357,7 @@ fn remap_files(args: &mut Vec<StripArg>, remapped_files: &mut Vec<RemapFile>) ->
                                     input_path: original_path.clone(),
                                     extracted,
                                     temp_file,
+                                    trailing_payload,
                                     output_path: original_path,
                                 });
                             }
@@ -389,8 +376,15 @@ fn finish_remapped_file(remapped_file: RemapFile) -> eyre::Result<()> {
         RemapFile::Inject {
             pack,
             mut temp_file,
+            trailing_payload,
             output_path,
         } => {
+            // Restore any trailing payload we detached before running the tool
+            brioche_autopack::restore_trailing_payload(
+                temp_file.as_file_mut(),
+                trailing_payload.as_deref(),
+            )?;
+
             // Open the output file
             let mut output = std::fs::File::create(output_path).with_context(|| {
                 format!("failed to open output {}", temp_file.path().display(),)
@@ -407,8 +401,15 @@ fn finish_remapped_file(remapped_file: RemapFile) -> eyre::Result<()> {
             input_path,
             extracted,
             mut temp_file,
+            trailing_payload,
             output_path,
         } => {
+            // Restore any trailing payload we detached before running the tool
+            brioche_autopack::restore_trailing_payload(
+                temp_file.as_file_mut(),
+                trailing_payload.as_deref(),
+            )?;
+
             // Get the resource dirs
             let input_resource_dirs = brioche_resources::find_resource_dirs(&input_path, true)?;
             let output_resource_dir = brioche_resources::find_output_resource_dir(&output_path)?;
@@ -440,13 +441,13 @@ fn finish_remapped_file(remapped_file: RemapFile) -> eyre::Result<()> {
                     // Add the temp file as a new resource. We re-use the
                     // original program's name and permissions
                     temp_file.rewind()?;
-                    let new_source_resource = brioche_resources::add_named_blob(
+                    let new_source_resource = brioche_resources::add_blob(
                         &output_resource_dir,
                         &mut temp_file,
                         is_executable,
                         program_name,
                     )?;
-                    let new_source_resource = <Vec<u8>>::from_path_buf(new_source_resource)
+                    let new_source_resource = <Vec<u8>>::from_path_buf(new_source_resource.path)
                         .map_err(|_| eyre::eyre!("invalid UTF-8 in path"))?;
 
                     // Re-use the same details from the pack, but with the
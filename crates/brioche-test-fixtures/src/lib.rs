@@ -0,0 +1,319 @@
+//! Helpers for building synthetic files for integration tests: minimal ELF
+//! executables and shared libraries, scripts, packed programs, and resource
+//! dir layouts.
+//!
+//! The generated ELF files only contain the structures the Brioche tools
+//! inspect (the interpreter, `DT_NEEDED`, `DT_SONAME`, and `DT_RUNPATH`).
+//! They have no code, so they can't actually be run.
+
+use std::{
+    io::Write as _,
+    os::unix::{ffi::OsStrExt as _, fs::PermissionsExt as _},
+    path::{Path, PathBuf},
+};
+
+const ELF_HEADER_LEN: u64 = 64;
+const PROGRAM_HEADER_LEN: u64 = 56;
+const DYNAMIC_ENTRY_LEN: u64 = 16;
+
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_SONAME: u64 = 14;
+const DT_RUNPATH: u64 = 29;
+
+/// Builder for a minimal 64-bit little-endian ELF file.
+#[derive(Debug, Clone)]
+pub struct ElfBuilder {
+    shared: bool,
+    interpreter: Option<String>,
+    needed: Vec<String>,
+    soname: Option<String>,
+    runpath: Option<String>,
+}
+
+impl ElfBuilder {
+    /// Start building a dynamically-linked executable, using the given
+    /// interpreter path (e.g. `/lib64/ld-linux-x86-64.so.2`).
+    pub fn executable(interpreter: &str) -> Self {
+        Self {
+            shared: false,
+            interpreter: Some(interpreter.to_string()),
+            needed: vec![],
+            soname: None,
+            runpath: None,
+        }
+    }
+
+    /// Start building a static executable, without an interpreter.
+    pub fn static_executable() -> Self {
+        Self {
+            shared: false,
+            interpreter: None,
+            needed: vec![],
+            soname: None,
+            runpath: None,
+        }
+    }
+
+    /// Start building a shared library with the given `DT_SONAME`.
+    pub fn shared_library(soname: &str) -> Self {
+        Self {
+            shared: true,
+            interpreter: None,
+            needed: vec![],
+            soname: Some(soname.to_string()),
+            runpath: None,
+        }
+    }
+
+    /// Set or remove the interpreter.
+    pub fn interpreter(mut self, interpreter: Option<&str>) -> Self {
+        self.interpreter = interpreter.map(|interpreter| interpreter.to_string());
+        self
+    }
+
+    /// Add a `DT_NEEDED` entry.
+    pub fn needed(mut self, library: &str) -> Self {
+        self.needed.push(library.to_string());
+        self
+    }
+
+    /// Set the `DT_RUNPATH` entry.
+    pub fn runpath(mut self, runpath: Option<&str>) -> Self {
+        self.runpath = runpath.map(|runpath| runpath.to_string());
+        self
+    }
+
+    /// Build the ELF file contents.
+    pub fn build(&self) -> Vec<u8> {
+        // Build the string table, recording the offset of each string
+        let mut strtab = vec![0];
+        let mut add_string = |string: &str| {
+            let offset = strtab.len() as u64;
+            strtab.extend_from_slice(string.as_bytes());
+            strtab.push(0);
+            offset
+        };
+        let needed_offsets = self
+            .needed
+            .iter()
+            .map(|library| add_string(library))
+            .collect::<Vec<_>>();
+        let soname_offset = self.soname.as_deref().map(&mut add_string);
+        let runpath_offset = self.runpath.as_deref().map(&mut add_string);
+
+        let interp = self.interpreter.as_ref().map(|interpreter| {
+            let mut interp = interpreter.as_bytes().to_vec();
+            interp.push(0);
+            interp
+        });
+
+        // Lay out the file: header, program headers, then the interpreter,
+        // string table, and dynamic section
+        // Static executables without any dynamic entries get no dynamic section
+        let has_dynamic = self.shared
+            || interp.is_some()
+            || !self.needed.is_empty()
+            || self.soname.is_some()
+            || self.runpath.is_some();
+        let num_program_headers = 1 + u64::from(interp.is_some()) + u64::from(has_dynamic);
+        let interp_offset = ELF_HEADER_LEN + PROGRAM_HEADER_LEN * num_program_headers;
+        let interp_len = interp.as_ref().map_or(0, |interp| interp.len() as u64);
+        let strtab_offset = interp_offset + interp_len;
+        let dynamic_offset = align(strtab_offset + strtab.len() as u64, 8);
+
+        let mut dynamic = vec![];
+        for offset in needed_offsets {
+            dynamic.push((DT_NEEDED, offset));
+        }
+        if let Some(offset) = soname_offset {
+            dynamic.push((DT_SONAME, offset));
+        }
+        if let Some(offset) = runpath_offset {
+            dynamic.push((DT_RUNPATH, offset));
+        }
+
+        // Addresses match file offsets, offset by the base address
+        let base_address = if self.shared { 0 } else { 0x400000 };
+        if has_dynamic {
+            dynamic.push((DT_STRTAB, base_address + strtab_offset));
+            dynamic.push((DT_STRSZ, strtab.len() as u64));
+            dynamic.push((DT_NULL, 0));
+        }
+
+        let dynamic_len = dynamic.len() as u64 * DYNAMIC_ENTRY_LEN;
+        let file_len = dynamic_offset + dynamic_len;
+
+        let mut elf = vec![];
+
+        // ELF header
+        elf.extend_from_slice(b"\x7fELF");
+        elf.extend_from_slice(&[2, 1, 1, 0]); // 64-bit, little-endian, version 1, System V ABI
+        elf.extend_from_slice(&[0; 8]);
+        let elf_type = if self.shared { ET_DYN } else { ET_EXEC };
+        elf.extend_from_slice(&elf_type.to_le_bytes());
+        elf.extend_from_slice(&machine().to_le_bytes());
+        elf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+        elf.extend_from_slice(&base_address.to_le_bytes()); // e_entry
+        elf.extend_from_slice(&ELF_HEADER_LEN.to_le_bytes()); // e_phoff
+        elf.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+        elf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+        elf.extend_from_slice(&(ELF_HEADER_LEN as u16).to_le_bytes()); // e_ehsize
+        elf.extend_from_slice(&(PROGRAM_HEADER_LEN as u16).to_le_bytes()); // e_phentsize
+        elf.extend_from_slice(&(num_program_headers as u16).to_le_bytes()); // e_phnum
+        elf.extend_from_slice(&64u16.to_le_bytes()); // e_shentsize
+        elf.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+        elf.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+        // Program headers
+        let mut program_header = |p_type: u32, p_flags: u32, offset: u64, len: u64, align: u64| {
+            elf.extend_from_slice(&p_type.to_le_bytes());
+            elf.extend_from_slice(&p_flags.to_le_bytes());
+            elf.extend_from_slice(&offset.to_le_bytes());
+            elf.extend_from_slice(&(base_address + offset).to_le_bytes()); // p_vaddr
+            elf.extend_from_slice(&(base_address + offset).to_le_bytes()); // p_paddr
+            elf.extend_from_slice(&len.to_le_bytes()); // p_filesz
+            elf.extend_from_slice(&len.to_le_bytes()); // p_memsz
+            elf.extend_from_slice(&align.to_le_bytes());
+        };
+        if interp.is_some() {
+            program_header(PT_INTERP, 4, interp_offset, interp_len, 1);
+        }
+        program_header(PT_LOAD, 4 | 2, 0, file_len, 0x1000);
+        if has_dynamic {
+            program_header(PT_DYNAMIC, 4 | 2, dynamic_offset, dynamic_len, 8);
+        }
+
+        // Interpreter and string table
+        if let Some(interp) = &interp {
+            elf.extend_from_slice(interp);
+        }
+        elf.extend_from_slice(&strtab);
+        elf.resize(dynamic_offset as usize, 0);
+
+        // Dynamic section
+        for (tag, value) in dynamic {
+            elf.extend_from_slice(&tag.to_le_bytes());
+            elf.extend_from_slice(&value.to_le_bytes());
+        }
+
+        elf
+    }
+
+    /// Build the ELF file and write it to `path`, marking it executable.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        write_executable(path, &self.build())
+    }
+}
+
+/// Write a script with the given shebang line (without the leading `#!`)
+/// and body, marking it executable.
+pub fn write_script(path: &Path, shebang: &str, body: &str) -> std::io::Result<()> {
+    let mut contents = format!("#!{shebang}\n").into_bytes();
+    contents.extend_from_slice(body.as_bytes());
+    write_executable(path, &contents)
+}
+
+/// Build an `ld-linux` pack for `program`, which runs it with
+/// `interpreter` and searches `library_dirs` for libraries. All paths are
+/// relative to the resource dir.
+pub fn ld_linux_pack(
+    program: &Path,
+    interpreter: &Path,
+    library_dirs: &[&Path],
+) -> brioche_pack::Pack {
+    brioche_pack::Pack::LdLinux {
+        program: path_bytes(program),
+        interpreter: path_bytes(interpreter),
+        library_dirs: library_dirs
+            .iter()
+            .map(|library_dir| path_bytes(library_dir))
+            .collect(),
+        runtime_library_dirs: vec![],
+    }
+}
+
+/// Write a packed program, made of `launcher` with `pack` injected at the
+/// end, marking it executable.
+pub fn write_packed(
+    path: &Path,
+    launcher: &[u8],
+    pack: &brioche_pack::Pack,
+) -> std::io::Result<()> {
+    let mut contents = launcher.to_vec();
+    brioche_pack::inject_pack(&mut contents, pack).map_err(std::io::Error::other)?;
+    write_executable(path, &contents)
+}
+
+/// Add a file to a resource dir at `subpath`, returning its full path.
+pub fn add_resource(
+    resource_dir: &Path,
+    subpath: &Path,
+    contents: &[u8],
+) -> std::io::Result<PathBuf> {
+    let path = resource_dir.join(subpath);
+    write_executable(&path, contents)?;
+    Ok(path)
+}
+
+/// Create an empty `brioche-resources.d` dir within `root`, returning its
+/// path. Programs anywhere under `root` will find it when searching for
+/// resource dirs.
+pub fn create_resource_dir(root: &Path) -> std::io::Result<PathBuf> {
+    let resource_dir = root.join("brioche-resources.d");
+    std::fs::create_dir_all(&resource_dir)?;
+    Ok(resource_dir)
+}
+
+/// Add a `brioche-env.d` entry to a dependency, so `env_var` includes
+/// `target` (a path relative to the dependency root).
+pub fn add_env_dir_entry(
+    dependency: &Path,
+    env_var: &str,
+    name: &str,
+    target: &Path,
+) -> std::io::Result<()> {
+    let env_dir = dependency.join("brioche-env.d").join("env").join(env_var);
+    std::fs::create_dir_all(&env_dir)?;
+
+    // Entries are 3 levels deep within the dependency
+    let target = Path::new("../../..").join(target);
+    std::os::unix::fs::symlink(target, env_dir.join(name))
+}
+
+fn write_executable(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(contents)?;
+    file.set_permissions(std::fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.as_os_str().as_bytes().to_vec()
+}
+
+fn align(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Get the ELF machine type for the current architecture.
+fn machine() -> u16 {
+    match std::env::consts::ARCH {
+        "aarch64" => 183,
+        "riscv64" => 243,
+        _ => 62,
+    }
+}
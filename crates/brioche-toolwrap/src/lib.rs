@@ -0,0 +1,268 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bstr::{ByteSlice as _, ByteVec as _};
+use eyre::{Context as _, OptionExt as _};
+
+/// Limit for nested `@file` arguments, to guard against files that
+/// (directly or indirectly) include themselves.
+const RESPONSE_FILE_DEPTH_LIMIT: u32 = 32;
+
+/// The location of a wrapper executable and the resources for the tool it
+/// wraps.
+#[derive(Debug, Clone)]
+pub struct Wrapper {
+    /// The path of the current (wrapper) executable.
+    pub current_exe: PathBuf,
+
+    /// The tool's resource dir, located at `libexec/<name>` relative to
+    /// the parent of the wrapper's `bin` dir.
+    pub resource_dir: PathBuf,
+}
+
+impl Wrapper {
+    /// Find the resource dir `libexec/<name>` for the current executable.
+    pub fn new(name: &str) -> eyre::Result<Self> {
+        let current_exe = std::env::current_exe().context("failed to get current executable")?;
+        let current_exe_dir = current_exe
+            .parent()
+            .ok_or_eyre("failed to get current executable dir")?;
+        let current_exe_parent_dir = current_exe_dir
+            .parent()
+            .ok_or_eyre("failed to get current executable dir")?;
+        let resource_dir = current_exe_parent_dir.join("libexec").join(name);
+        if !resource_dir.is_dir() {
+            eyre::bail!(
+                "failed to find {name} resource dir: {}",
+                resource_dir.display()
+            );
+        }
+
+        Ok(Self {
+            current_exe,
+            resource_dir,
+        })
+    }
+
+    /// Get the file name of the current executable. Wrappers that are
+    /// installed under several names (e.g. `cc` and `c++`) use this to
+    /// pick the matching tool from the resource dir.
+    pub fn current_exe_name(&self) -> eyre::Result<&OsStr> {
+        self.current_exe
+            .file_name()
+            .ok_or_eyre("failed to get current executable name")
+    }
+
+    /// Get the path to a file within the tool's resource dir.
+    pub fn tool(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.resource_dir.join(name)
+    }
+}
+
+/// Convert the exit status of a wrapped tool into the wrapper's exit code.
+pub fn exit_code(status: std::process::ExitStatus) -> ExitCode {
+    status
+        .code()
+        .and_then(|code| u8::try_from(code).ok())
+        .map(ExitCode::from)
+        .unwrap_or(ExitCode::FAILURE)
+}
+
+/// Read a boolean toggle from an environment variable. Only the exact
+/// values `true` and `false` are recognized, anything else (including an
+/// unset variable) returns `default`.
+pub fn env_toggle(name: &str, default: bool) -> bool {
+    toggle_value(std::env::var(name).ok().as_deref(), default)
+}
+
+fn toggle_value(value: Option<&str>, default: bool) -> bool {
+    match value {
+        Some("true") => true,
+        Some("false") => false,
+        _ => default,
+    }
+}
+
+/// Get the raw bytes of an argument.
+pub fn arg_bytes(arg: &OsStr) -> eyre::Result<&bstr::BStr> {
+    let arg = <[u8]>::from_os_str(arg).ok_or_eyre("invalid arg")?;
+    Ok(bstr::BStr::new(arg))
+}
+
+/// Interpret part of an argument as a path.
+pub fn arg_path(arg: &[u8]) -> eyre::Result<&Path> {
+    arg.to_path().map_err(|_| eyre::eyre!("invalid path"))
+}
+
+/// Expand `@file` arguments by reading extra arguments from each file,
+/// following the same conventions as GCC and binutils: arguments are
+/// separated by whitespace, can be quoted with `'` or `"`, and `\` escapes
+/// the next character. If the file can't be read, the argument is kept
+/// as-is.
+pub fn expand_response_files(
+    args: impl IntoIterator<Item = OsString>,
+) -> eyre::Result<Vec<OsString>> {
+    let mut expanded = vec![];
+    for arg in args {
+        expand_response_file_arg(arg, &mut expanded, 0)?;
+    }
+
+    Ok(expanded)
+}
+
+fn expand_response_file_arg(
+    arg: OsString,
+    expanded: &mut Vec<OsString>,
+    depth: u32,
+) -> eyre::Result<()> {
+    let arg_bytes = arg_bytes(&arg)?;
+    let Some(path) = arg_bytes.strip_prefix(b"@") else {
+        expanded.push(arg);
+        return Ok(());
+    };
+
+    let path = arg_path(path)?;
+    let Ok(contents) = std::fs::read(path) else {
+        expanded.push(arg);
+        return Ok(());
+    };
+
+    eyre::ensure!(
+        depth < RESPONSE_FILE_DEPTH_LIMIT,
+        "too many nested response files while reading {}",
+        path.display()
+    );
+
+    for file_arg in split_response_file(&contents) {
+        let file_arg = Vec::into_os_string(file_arg).map_err(|_| eyre::eyre!("invalid arg"))?;
+        expand_response_file_arg(file_arg, expanded, depth + 1)?;
+    }
+
+    Ok(())
+}
+
+fn split_response_file(contents: &[u8]) -> Vec<Vec<u8>> {
+    let mut args = vec![];
+    let mut current: Option<Vec<u8>> = None;
+    let mut quote = None;
+    let mut bytes = contents.iter().copied();
+
+    while let Some(byte) = bytes.next() {
+        match (byte, quote) {
+            (b'\\', _) => {
+                if let Some(escaped) = bytes.next() {
+                    current.get_or_insert_with(Vec::new).push(escaped);
+                }
+            }
+            (b'\'' | b'"', None) => {
+                quote = Some(byte);
+                current.get_or_insert_with(Vec::new);
+            }
+            (_, Some(quote_byte)) if byte == quote_byte => {
+                quote = None;
+            }
+            (_, None) if byte.is_ascii_whitespace() => {
+                if let Some(arg) = current.take() {
+                    args.push(arg);
+                }
+            }
+            _ => {
+                current.get_or_insert_with(Vec::new).push(byte);
+            }
+        }
+    }
+
+    if let Some(arg) = current {
+        args.push(arg);
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(contents: &str) -> Vec<String> {
+        split_response_file(contents.as_bytes())
+            .into_iter()
+            .map(|arg| String::from_utf8(arg).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_toggle_value() {
+        assert!(toggle_value(Some("true"), false));
+        assert!(!toggle_value(Some("false"), true));
+        assert!(toggle_value(None, true));
+        assert!(!toggle_value(None, false));
+
+        // Only the exact values count
+        assert!(toggle_value(Some("1"), true));
+        assert!(!toggle_value(Some("TRUE"), false));
+        assert!(toggle_value(Some(""), true));
+    }
+
+    #[test]
+    fn test_split_response_file() {
+        assert_eq!(
+            split("-c  foo.c\n-o\tfoo.o\n"),
+            ["-c", "foo.c", "-o", "foo.o"]
+        );
+        assert_eq!(split(""), Vec::<String>::new());
+        assert_eq!(split("  \n "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_split_response_file_quotes() {
+        assert_eq!(
+            split(r#"'a b' "c d" e"f g"h 'it"s'"#),
+            ["a b", "c d", "ef gh", "it\"s"]
+        );
+        assert_eq!(split(r#"'' """#), ["", ""]);
+
+        // An unterminated quote runs to the end of the file
+        assert_eq!(split("'a b"), ["a b"]);
+    }
+
+    #[test]
+    fn test_split_response_file_escapes() {
+        assert_eq!(split(r"a\ b c\\d \'e\'"), ["a b", r"c\d", "'e'"]);
+        assert_eq!(split(r#""a\"b""#), [r#"a"b"#]);
+
+        // A trailing backslash is dropped
+        assert_eq!(split("a\\"), ["a"]);
+    }
+
+    #[test]
+    fn test_expand_response_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let outer = dir.path().join("outer.rsp");
+        let inner = dir.path().join("inner.rsp");
+        std::fs::write(&inner, "-lfoo 'b c'").unwrap();
+        std::fs::write(&outer, format!("-a @{} -d", inner.display())).unwrap();
+
+        let missing = format!("@{}", dir.path().join("missing.rsp").display());
+        let args = [
+            "cc".into(),
+            format!("@{}", outer.display()).into(),
+            missing.clone().into(),
+            "-z".into(),
+        ];
+        let expanded = expand_response_files(args).unwrap();
+        assert_eq!(expanded, ["cc", "-a", "-lfoo", "b c", "-d", &missing, "-z"]);
+    }
+
+    #[test]
+    fn test_expand_response_files_depth_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("self.rsp");
+        std::fs::write(&path, format!("-a @{}", path.display())).unwrap();
+
+        let result = expand_response_files([format!("@{}", path.display()).into()]);
+        assert!(result.is_err());
+    }
+}
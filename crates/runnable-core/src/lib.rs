@@ -1,11 +1,126 @@
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
-use bstr::{ByteSlice as _, ByteVec as _};
+use bstr::ByteVec as _;
 use encoding::TickEncoded;
 
+pub use validate::RunnableDiagnostic;
+
 pub mod encoding;
+mod validate;
+
+/// The version of the runnable formats written by this crate. This is
+/// bumped when runnables gain fields that older launchers would silently
+/// ignore (like `cwd` or `preCommands` in v0.2.0), so older launchers
+/// reject them instead of running the program differently.
+pub const FORMAT_VERSION: (u64, u64, u64) = (0, 2, 0);
+
+pub const FORMAT: &str = "application/vnd.brioche.runnable-v0.2.0+json";
+
+/// Metadata format for runnables where the runnable metadata itself is
+/// stored in a resource, and the pack only contains a [`RunnableReference`].
+/// Used to keep large runnable metadata out of the packed executable.
+pub const REFERENCE_FORMAT: &str = "application/vnd.brioche.runnable-reference-v0.2.0+json";
+
+/// Metadata format for runnables encoded with bincode (see
+/// [`Runnable::to_bincode`]). This is smaller and faster to decode than
+/// [`FORMAT`], which helps for complex runnables.
+pub const BINCODE_FORMAT: &str = "application/vnd.brioche.runnable-v0.2.0+bincode";
+
+/// The v0.1.0 version of [`FORMAT`]. v0.1.0 runnables are a subset of
+/// v0.2.0 runnables, so they're still loaded.
+pub const FORMAT_V0_1_0: &str = "application/vnd.brioche.runnable-v0.1.0+json";
+
+/// The v0.1.0 version of [`REFERENCE_FORMAT`].
+pub const REFERENCE_FORMAT_V0_1_0: &str = "application/vnd.brioche.runnable-reference-v0.1.0+json";
 
-pub const FORMAT: &str = "application/vnd.brioche.runnable-v0.1.0+json";
+pub fn is_runnable_format(format: &str) -> bool {
+    matches!(
+        format,
+        FORMAT | REFERENCE_FORMAT | BINCODE_FORMAT | FORMAT_V0_1_0 | REFERENCE_FORMAT_V0_1_0
+    )
+}
+
+/// Check if `format` is a runnable format with a newer version than
+/// [`FORMAT_VERSION`], meaning it was written by a newer version of this
+/// crate.
+pub fn is_newer_runnable_format(format: &str) -> bool {
+    let Some(versioned) = format
+        .strip_prefix("application/vnd.brioche.runnable-")
+        .map(|format| format.strip_prefix("reference-").unwrap_or(format))
+    else {
+        return false;
+    };
+    let Some((version, _encoding)) = versioned
+        .strip_prefix('v')
+        .and_then(|versioned| versioned.split_once('+'))
+    else {
+        return false;
+    };
+
+    let mut components = version.split('.').map(|component| component.parse::<u64>());
+    let version = match (
+        components.next(),
+        components.next(),
+        components.next(),
+        components.next(),
+    ) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => (major, minor, patch),
+        _ => {
+            return false;
+        }
+    };
+
+    version > FORMAT_VERSION
+}
+
+/// The name of the platform this was built for, used as a key for
+/// [`Runnable::platforms`]. Names are `<os>-<arch>`, such as
+/// `linux-x86_64` or `linux-aarch64`.
+pub fn current_platform() -> String {
+    format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+/// Load a runnable from the format and metadata of a metadata pack. If the
+/// metadata is a [`RunnableReference`], the referenced resource is read
+/// from `resource_dirs`.
+#[cfg(feature = "resources")]
+pub fn load_runnable(
+    format: &str,
+    metadata: &[u8],
+    resource_dirs: &[PathBuf],
+) -> Result<Runnable, LoadRunnableError> {
+    use bstr::ByteSlice as _;
+
+    match format {
+        FORMAT | FORMAT_V0_1_0 => {
+            let runnable = serde_json::from_slice(metadata)?;
+            Ok(runnable)
+        }
+        REFERENCE_FORMAT | REFERENCE_FORMAT_V0_1_0 => {
+            let reference: RunnableReference = serde_json::from_slice(metadata)?;
+            let resource_subpath = reference
+                .resource
+                .to_path()
+                .map_err(|_| RunnableTemplateError::PathError)?;
+            let resource_path =
+                brioche_resources::find_in_resource_dirs(resource_dirs, resource_subpath)
+                    .ok_or_else(|| RunnableTemplateError::ResourceNotFound {
+                        resource: bstr::BString::new(reference.resource.clone()),
+                    })?;
+            let runnable_metadata = brioche_resources::read_blob(&resource_path)?;
+            let runnable = serde_json::from_slice(&runnable_metadata)?;
+            Ok(runnable)
+        }
+        BINCODE_FORMAT => {
+            let runnable = Runnable::from_bincode(metadata)?;
+            Ok(runnable)
+        }
+        _ if is_newer_runnable_format(format) => {
+            Err(LoadRunnableError::UnsupportedVersion(format.to_string()))
+        }
+        _ => Err(LoadRunnableError::UnknownFormat(format.to_string())),
+    }
+}
 
 #[serde_with::serde_as]
 #[derive(
@@ -29,10 +144,264 @@ pub struct Runnable {
 
     pub clear_env: bool,
 
+    /// Patterns for env vars to keep when [`Runnable::clear_env`] is set,
+    /// such as `LANG*`, `TERM`, or `HOME`. `*` matches any sequence of
+    /// characters and `?` matches any single character. Env vars set in
+    /// [`Runnable::env`] are applied afterwards, so they take precedence.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clear_env_except: Vec<String>,
+
+    /// The file mode creation mask to set before running the command, so
+    /// created files get the same permissions regardless of the caller's
+    /// umask.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub umask: Option<u32>,
+
+    /// The niceness to run the command with. This is an absolute value,
+    /// not an adjustment to the caller's niceness.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+
+    /// Resource limits to set before running the command, such as the
+    /// maximum number of open files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde_as(as = "serde_with::Map<_, _>")]
+    pub rlimits: Vec<(RlimitResource, Rlimit)>,
+
+    /// The working directory to run the command from. If unset, the
+    /// command runs from the caller's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<Template>,
+
+    /// Commands to run instead of `command` on specific platforms, keyed
+    /// by platform name (see [`current_platform`]). Platforms without a
+    /// variant run `command`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde_as(as = "serde_with::Map<_, _>")]
+    pub platforms: Vec<(String, RunnableVariant)>,
+
+    /// Commands to run before the main command, in order. Each one must
+    /// succeed before the next one runs. They run with the same env vars
+    /// and working directory as the main command.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_commands: Vec<RunnableCommand>,
+
     #[serde(default)]
     pub source: Option<RunnableSource>,
 }
 
+impl Runnable {
+    /// Encode the runnable for [`BINCODE_FORMAT`] metadata.
+    pub fn to_bincode(&self) -> Result<Vec<u8>, bincode::error::EncodeError> {
+        bincode::encode_to_vec(self, bincode::config::standard())
+    }
+
+    /// Decode a runnable from [`BINCODE_FORMAT`] metadata.
+    pub fn from_bincode(metadata: &[u8]) -> Result<Self, bincode::error::DecodeError> {
+        let (runnable, _) = bincode::decode_from_slice(metadata, bincode::config::standard())?;
+        Ok(runnable)
+    }
+
+    /// Get the paths of all resources referenced by the runnable,
+    /// including the resources for every platform variant.
+    pub fn resources(&self) -> Vec<&Vec<u8>> {
+        let arg_templates = self.args.iter().filter_map(|arg| arg.template());
+        let env_templates = self
+            .env
+            .iter()
+            .flat_map(|(_, env_value)| env_value.templates());
+        let variant_templates = self.platforms.iter().flat_map(|(_, variant)| {
+            let variant_arg_templates = variant
+                .args
+                .iter()
+                .flatten()
+                .filter_map(|arg| arg.template());
+            std::iter::once(&variant.command).chain(variant_arg_templates)
+        });
+        let pre_command_templates = self.pre_commands.iter().flat_map(|pre_command| {
+            let pre_command_arg_templates =
+                pre_command.args.iter().filter_map(|arg| arg.template());
+            std::iter::once(&pre_command.command).chain(pre_command_arg_templates)
+        });
+
+        let template_resources = std::iter::once(&self.command)
+            .chain(arg_templates)
+            .chain(env_templates)
+            .chain(&self.cwd)
+            .chain(variant_templates)
+            .chain(pre_command_templates)
+            .flat_map(|template| &template.components)
+            .filter_map(|component| match component {
+                TemplateComponent::Literal { .. }
+                | TemplateComponent::RelativePath { .. }
+                | TemplateComponent::EnvVar { .. }
+                | TemplateComponent::ProgramDir => None,
+                TemplateComponent::Resource { resource } => Some(resource),
+            });
+        let env_path_resources = self
+            .env
+            .iter()
+            .filter_map(|(_, env_value)| match env_value {
+                EnvValue::PrependPath { path, .. } => path.resource(),
+                _ => None,
+            });
+        let source_resource = self
+            .source
+            .as_ref()
+            .and_then(|source| source.path.resource());
+
+        template_resources
+            .chain(env_path_resources)
+            .chain(source_resource)
+            .collect()
+    }
+
+    /// Use the variant for `platform` from [`Runnable::platforms`], if there
+    /// is one, replacing the command (and args, if the variant sets them).
+    /// All variants are removed afterwards, so the runnable only describes
+    /// what to run on `platform`.
+    pub fn select_platform(&mut self, platform: &str) {
+        let platforms = std::mem::take(&mut self.platforms);
+        let variant = platforms
+            .into_iter()
+            .find_map(|(name, variant)| (name == platform).then_some(variant));
+        if let Some(variant) = variant {
+            self.command = variant.command;
+            if let Some(args) = variant.args {
+                self.args = args;
+            }
+        }
+    }
+
+    /// Find all resources referenced by the runnable in one pass. With
+    /// [`brioche_resources::enable_lookup_cache`], later lookups for these
+    /// resources (such as from [`Template::to_os_string`]) don't need to
+    /// check the resource dirs again. Resources with invalid paths are
+    /// skipped.
+    #[cfg(feature = "resources")]
+    pub fn prefetch_resources(&self, resource_dirs: &[PathBuf]) {
+        use bstr::ByteSlice as _;
+
+        let resources = self.resources();
+        let subpaths = resources
+            .iter()
+            .filter_map(|resource| resource.to_path().ok())
+            .collect::<Vec<_>>();
+        brioche_resources::find_all_in_resource_dirs(resource_dirs, &subpaths);
+    }
+}
+
+/// A command run before a runnable's main command. See
+/// [`Runnable::pre_commands`].
+#[derive(
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+    bincode::Encode,
+    bincode::Decode,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct RunnableCommand {
+    pub command: Template,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<ArgValue>,
+}
+
+/// A platform-specific command for a runnable. See [`Runnable::platforms`].
+#[derive(
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+    bincode::Encode,
+    bincode::Decode,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct RunnableVariant {
+    pub command: Template,
+
+    /// Args to use instead of the runnable's args. If unset, the
+    /// runnable's args are used with this command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<ArgValue>>,
+}
+
+/// A resource that can be limited with [`Runnable::rlimits`]. These match
+/// the `RLIMIT_*` constants used with `setrlimit`.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+    bincode::Encode,
+    bincode::Decode,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RlimitResource {
+    /// Maximum size of a core dump, in bytes.
+    Core,
+    /// Maximum CPU time, in seconds.
+    Cpu,
+    /// Maximum size of the data segment, in bytes.
+    Data,
+    /// Maximum size of created files, in bytes.
+    Fsize,
+    /// Maximum number of bytes of locked memory.
+    Memlock,
+    /// Maximum number of open file descriptors.
+    Nofile,
+    /// Maximum number of processes for the user.
+    Nproc,
+    /// Maximum size of the stack, in bytes.
+    Stack,
+}
+
+impl RlimitResource {
+    /// The name of the resource, as used in runnable metadata.
+    pub fn name(self) -> &'static str {
+        match self {
+            RlimitResource::Core => "core",
+            RlimitResource::Cpu => "cpu",
+            RlimitResource::Data => "data",
+            RlimitResource::Fsize => "fsize",
+            RlimitResource::Memlock => "memlock",
+            RlimitResource::Nofile => "nofile",
+            RlimitResource::Nproc => "nproc",
+            RlimitResource::Stack => "stack",
+        }
+    }
+}
+
+/// The limits to set for a resource. Limits that aren't set keep the
+/// caller's value. Unprivileged processes can't raise the hard limit, or
+/// set the soft limit above the hard limit.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+    bincode::Encode,
+    bincode::Decode,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct Rlimit {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub soft: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hard: Option<u64>,
+}
+
+#[serde_with::serde_as]
 #[derive(
     Debug,
     serde::Serialize,
@@ -48,9 +417,43 @@ pub enum ArgValue {
     Arg {
         value: Template,
     },
+    /// Render the template, then split it by `separator` into multiple
+    /// args. Empty components are skipped, so an empty value adds no args.
+    #[serde(rename_all = "camelCase")]
+    Spread {
+        value: Template,
+        #[serde_as(as = "TickEncoded")]
+        separator: Vec<u8>,
+    },
+    /// Add the arg only if the env var `name` is set to a non-empty value
+    /// when the command runs.
+    #[serde(rename_all = "camelCase")]
+    IfEnvSet {
+        name: String,
+        value: Template,
+    },
+    /// Add the arg only if the template can be rendered (e.g. its
+    /// resources exist and its env vars are set) and isn't empty.
+    #[serde(rename_all = "camelCase")]
+    Optional {
+        value: Template,
+    },
     Rest,
 }
 
+impl ArgValue {
+    /// Get the template used by the arg value, if any.
+    pub fn template(&self) -> Option<&Template> {
+        match self {
+            ArgValue::Arg { value }
+            | ArgValue::Spread { value, .. }
+            | ArgValue::IfEnvSet { value, .. }
+            | ArgValue::Optional { value } => Some(value),
+            ArgValue::Rest => None,
+        }
+    }
+}
+
 #[serde_with::serde_as]
 #[derive(
     Debug,
@@ -79,13 +482,58 @@ pub enum EnvValue {
         value: Template,
         #[serde_as(as = "TickEncoded")]
         separator: Vec<u8>,
+        /// Only add the components of `value` (split by `separator`) that
+        /// exist when the program runs. If none exist, the env var is
+        /// left unchanged.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        if_exists: bool,
     },
     #[serde(rename_all = "camelCase")]
     Append {
         value: Template,
         #[serde_as(as = "TickEncoded")]
         separator: Vec<u8>,
+        /// Only add the components of `value` (split by `separator`) that
+        /// exist when the program runs. If none exist, the env var is
+        /// left unchanged.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        if_exists: bool,
     },
+    /// Set the env var to a single directory that merges the contents of
+    /// each directory in `values`, for tools that expect one real
+    /// directory (like `$JAVA_HOME`) instead of a list of paths. The merged
+    /// directory is a tree of symlinks built when the program first runs,
+    /// and cached for later runs. Earlier values take precedence when
+    /// paths conflict.
+    #[serde(rename_all = "camelCase")]
+    MergedView {
+        values: Vec<Template>,
+    },
+    /// Prepend a directory from a dependency to a `$PATH`-like env var,
+    /// such as `<dependency>/bin`. `subpath` is joined onto the resolved
+    /// `path`, and the result is separated from the current value with
+    /// `:`.
+    #[serde(rename_all = "camelCase")]
+    PrependPath {
+        path: RunnablePath,
+        #[serde_as(as = "TickEncoded")]
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        subpath: Vec<u8>,
+    },
+}
+
+impl EnvValue {
+    /// Get all templates used by the env value.
+    pub fn templates(&self) -> Vec<&Template> {
+        match self {
+            EnvValue::Clear | EnvValue::Inherit | EnvValue::PrependPath { .. } => vec![],
+            EnvValue::Set { value }
+            | EnvValue::Fallback { value }
+            | EnvValue::Prepend { value, .. }
+            | EnvValue::Append { value, .. } => vec![value],
+            EnvValue::MergedView { values } => values.iter().collect(),
+        }
+    }
 }
 
 #[serde_with::serde_as]
@@ -93,6 +541,8 @@ pub enum EnvValue {
     Debug,
     Clone,
     Default,
+    PartialEq,
+    Eq,
     serde::Serialize,
     serde::Deserialize,
     schemars::JsonSchema,
@@ -123,11 +573,84 @@ impl Template {
         })
     }
 
+    /// Append the components of `other` to the end of this template.
+    /// Adjacent literals are merged, and empty literals are dropped.
+    pub fn join(mut self, other: Template) -> Self {
+        for component in other.components {
+            self.push_component(component);
+        }
+        self
+    }
+
+    /// Append `component` as a path segment, adding a `/` between the
+    /// template and the component if neither side already has one. If the
+    /// template is empty, `component` is added as-is.
+    ///
+    /// Relative paths and resources are rendered as absolute paths, so
+    /// joining one onto a non-empty template is usually a mistake.
+    pub fn join_path(mut self, component: TemplateComponent) -> Self {
+        let Some(last) = self.components.last() else {
+            self.push_component(component);
+            return self;
+        };
+
+        let ends_with_separator = match last {
+            TemplateComponent::Literal { value } => value.ends_with(b"/"),
+            TemplateComponent::RelativePath { .. }
+            | TemplateComponent::Resource { .. }
+            | TemplateComponent::EnvVar { .. }
+            | TemplateComponent::ProgramDir => false,
+        };
+
+        let component = match component {
+            TemplateComponent::Literal { value } if ends_with_separator => {
+                let start = value
+                    .iter()
+                    .position(|&byte| byte != b'/')
+                    .unwrap_or(value.len());
+                TemplateComponent::Literal {
+                    value: value[start..].to_vec(),
+                }
+            }
+            TemplateComponent::Literal { value } if value.starts_with(b"/") => {
+                TemplateComponent::Literal { value }
+            }
+            component => {
+                if !ends_with_separator {
+                    self.push_component(TemplateComponent::Literal {
+                        value: b"/".to_vec(),
+                    });
+                }
+                component
+            }
+        };
+        self.push_component(component);
+        self
+    }
+
+    fn push_component(&mut self, component: TemplateComponent) {
+        match (self.components.last_mut(), component) {
+            (_, TemplateComponent::Literal { value }) if value.is_empty() => {}
+            (
+                Some(TemplateComponent::Literal { value: last_value }),
+                TemplateComponent::Literal { value },
+            ) => {
+                last_value.extend_from_slice(&value);
+            }
+            (_, component) => {
+                self.components.push(component);
+            }
+        }
+    }
+
+    #[cfg(feature = "resources")]
     pub fn to_os_string(
         &self,
-        program: &Path,
+        program: &std::path::Path,
         resource_dirs: &[PathBuf],
     ) -> Result<std::ffi::OsString, RunnableTemplateError> {
+        use bstr::ByteSlice as _;
+
         let mut os_string = std::ffi::OsString::new();
 
         for component in &self.components {
@@ -154,6 +677,27 @@ impl Template {
                         })?;
                     os_string.push(resource_path);
                 }
+                TemplateComponent::ProgramDir => {
+                    let program_dir = program
+                        .parent()
+                        .ok_or(RunnableTemplateError::InvalidProgramPath)?;
+                    os_string.push(program_dir);
+                }
+                TemplateComponent::EnvVar { name, fallback } => {
+                    let value = std::env::var_os(name).filter(|value| !value.is_empty());
+                    match (value, fallback) {
+                        (Some(value), _) => {
+                            os_string.push(value);
+                        }
+                        (None, Some(fallback)) => {
+                            let fallback = fallback.to_os_str()?;
+                            os_string.push(fallback);
+                        }
+                        (None, None) => {
+                            return Err(RunnableTemplateError::EnvVarNotSet { name: name.clone() });
+                        }
+                    }
+                }
             }
         }
 
@@ -165,6 +709,8 @@ impl Template {
 #[derive(
     Debug,
     Clone,
+    PartialEq,
+    Eq,
     serde::Serialize,
     serde::Deserialize,
     schemars::JsonSchema,
@@ -189,6 +735,18 @@ pub enum TemplateComponent {
         #[serde_as(as = "TickEncoded")]
         resource: Vec<u8>,
     },
+    /// The value of an environment variable when the command runs. If the
+    /// variable is unset or empty, `fallback` is used instead.
+    #[serde(rename_all = "camelCase")]
+    EnvVar {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[serde_as(as = "Option<TickEncoded>")]
+        fallback: Option<Vec<u8>>,
+    },
+    /// The directory containing the packed program, for composing values
+    /// like `--plugin-dir=<program dir>/plugins`.
+    ProgramDir,
 }
 #[serde_with::serde_as]
 #[derive(
@@ -235,6 +793,49 @@ impl RunnablePath {
             .map_err(|_| RunnableTemplateError::PathError)?;
         Ok(Self::Resource { resource })
     }
+
+    /// Get the resource referenced by the path, if any.
+    pub fn resource(&self) -> Option<&Vec<u8>> {
+        match self {
+            RunnablePath::RelativePath { .. } => None,
+            RunnablePath::Resource { resource } => Some(resource),
+        }
+    }
+
+    #[cfg(feature = "resources")]
+    pub fn to_path_buf(
+        &self,
+        program: &std::path::Path,
+        resource_dirs: &[PathBuf],
+    ) -> Result<PathBuf, RunnableTemplateError> {
+        use bstr::ByteSlice as _;
+
+        match self {
+            RunnablePath::RelativePath { path } => {
+                let program_dir = program
+                    .parent()
+                    .ok_or(RunnableTemplateError::InvalidProgramPath)?;
+                let path = path.to_path()?;
+                Ok(program_dir.join(path))
+            }
+            RunnablePath::Resource { resource } => {
+                let resource_subpath = resource.to_path()?;
+                brioche_resources::find_in_resource_dirs(resource_dirs, resource_subpath)
+                    .ok_or_else(|| {
+                        let resource = bstr::BString::new(resource.clone());
+                        RunnableTemplateError::ResourceNotFound { resource }
+                    })
+            }
+        }
+    }
+}
+
+#[serde_with::serde_as]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RunnableReference {
+    #[serde_as(as = "TickEncoded")]
+    pub resource: Vec<u8>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -245,10 +846,175 @@ pub enum RunnableTemplateError {
     PathError,
     #[error("invalid program path")]
     InvalidProgramPath,
+    #[cfg(feature = "resources")]
     #[error(transparent)]
     PackResourceDirError(#[from] brioche_resources::PackResourceDirError),
     #[error("resource not found: {resource}")]
     ResourceNotFound { resource: bstr::BString },
+    #[error("env var {name:?} is not set and has no fallback")]
+    EnvVarNotSet { name: String },
     #[error("tried prepending and appending to env var")]
     PrependAndAppend,
 }
+
+#[cfg(feature = "resources")]
+#[derive(Debug, thiserror::Error)]
+pub enum LoadRunnableError {
+    #[error("unknown metadata format: {0:?}")]
+    UnknownFormat(String),
+    #[error("runnable format {0:?} is newer than this launcher supports, repack the program with a matching launcher")]
+    UnsupportedVersion(String),
+    #[error("failed to deserialize runnable metadata: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("failed to decode runnable metadata: {0}")]
+    BincodeError(#[from] bincode::error::DecodeError),
+    #[error("failed to read runnable metadata resource: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    RunnableTemplateError(#[from] RunnableTemplateError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(value: &str) -> TemplateComponent {
+        TemplateComponent::Literal {
+            value: value.as_bytes().to_vec(),
+        }
+    }
+
+    fn resource(resource: &str) -> TemplateComponent {
+        TemplateComponent::Resource {
+            resource: resource.as_bytes().to_vec(),
+        }
+    }
+
+    fn template(components: impl Into<Vec<TemplateComponent>>) -> Template {
+        Template {
+            components: components.into(),
+        }
+    }
+
+    #[test]
+    fn test_join_onto_empty_template() {
+        let joined = Template::default().join(template([literal("a"), resource("b")]));
+        assert_eq!(joined, template([literal("a"), resource("b")]));
+
+        let joined = Template::default().join(template([literal(""), resource("b")]));
+        assert_eq!(joined, template([resource("b")]));
+
+        let joined = template([resource("a")]).join(Template::default());
+        assert_eq!(joined, template([resource("a")]));
+    }
+
+    #[test]
+    fn test_join_merges_literals() {
+        let joined = template([resource("a"), literal("b")]).join(template([
+            literal("c"),
+            literal(""),
+            literal("d"),
+            resource("e"),
+        ]));
+        assert_eq!(
+            joined,
+            template([resource("a"), literal("bcd"), resource("e")])
+        );
+    }
+
+    #[test]
+    fn test_join_path_after_separator() {
+        let joined = template([literal("a/")]).join_path(literal("b"));
+        assert_eq!(joined, template([literal("a/b")]));
+
+        let joined = template([literal("a/")]).join_path(literal("//b"));
+        assert_eq!(joined, template([literal("a/b")]));
+
+        let joined = template([literal("a/")]).join_path(resource("b"));
+        assert_eq!(joined, template([literal("a/"), resource("b")]));
+    }
+
+    #[test]
+    fn test_join_path_without_separator() {
+        let joined = template([literal("a")]).join_path(literal("b"));
+        assert_eq!(joined, template([literal("a/b")]));
+
+        let joined = template([literal("a")]).join_path(literal("/b"));
+        assert_eq!(joined, template([literal("a/b")]));
+    }
+
+    #[test]
+    fn test_join_path_onto_empty_template() {
+        let joined = Template::default().join_path(literal("b"));
+        assert_eq!(joined, template([literal("b")]));
+
+        let joined = Template::default().join_path(resource("b"));
+        assert_eq!(joined, template([resource("b")]));
+    }
+
+    #[test]
+    fn test_join_path_after_non_literal() {
+        let joined = template([TemplateComponent::ProgramDir]).join_path(literal("bin"));
+        assert_eq!(
+            joined,
+            template([TemplateComponent::ProgramDir, literal("/bin")])
+        );
+
+        let joined = template([resource("a")]).join_path(TemplateComponent::EnvVar {
+            name: "NAME".to_string(),
+            fallback: None,
+        });
+        assert_eq!(
+            joined,
+            template([
+                resource("a"),
+                literal("/"),
+                TemplateComponent::EnvVar {
+                    name: "NAME".to_string(),
+                    fallback: None,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_is_newer_runnable_format() {
+        assert!(!is_newer_runnable_format(FORMAT));
+        assert!(!is_newer_runnable_format(REFERENCE_FORMAT));
+        assert!(!is_newer_runnable_format(BINCODE_FORMAT));
+        assert!(!is_newer_runnable_format(FORMAT_V0_1_0));
+        assert!(is_newer_runnable_format(
+            "application/vnd.brioche.runnable-v0.3.0+json"
+        ));
+        assert!(is_newer_runnable_format(
+            "application/vnd.brioche.runnable-reference-v1.0.0+json"
+        ));
+        assert!(is_newer_runnable_format(
+            "application/vnd.brioche.runnable-v0.2.1+bincode"
+        ));
+        assert!(!is_newer_runnable_format(
+            "application/vnd.brioche.runnable-v0.3+json"
+        ));
+        assert!(!is_newer_runnable_format(
+            "application/vnd.brioche.windows-dll-dirs-v1.0.0+json"
+        ));
+    }
+
+    #[cfg(feature = "resources")]
+    #[test]
+    fn test_load_runnable_versions() {
+        let metadata = br#"{"command":{"components":[]},"clearEnv":false}"#;
+        assert!(load_runnable(FORMAT, metadata, &[]).is_ok());
+        assert!(load_runnable(FORMAT_V0_1_0, metadata, &[]).is_ok());
+
+        let newer_format = "application/vnd.brioche.runnable-v0.3.0+json";
+        assert!(matches!(
+            load_runnable(newer_format, metadata, &[]),
+            Err(LoadRunnableError::UnsupportedVersion(format)) if format == newer_format
+        ));
+        assert!(matches!(
+            load_runnable("application/x-unknown", metadata, &[]),
+            Err(LoadRunnableError::UnknownFormat(_))
+        ));
+    }
+}
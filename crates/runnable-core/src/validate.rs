@@ -0,0 +1,251 @@
+use bstr::ByteSlice as _;
+
+use crate::{ArgValue, Runnable, RunnablePath, Template, TemplateComponent};
+
+/// A problem found by [`Runnable::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunnableDiagnostic {
+    /// The part of the runnable with the problem, such as `command` or
+    /// `arg 2`.
+    pub location: String,
+
+    pub message: String,
+}
+
+impl std::fmt::Display for RunnableDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+impl Runnable {
+    /// Check the runnable for problems that would stop it from running,
+    /// without rendering any templates. `resource_paths` are the resource
+    /// paths of the pack the runnable is stored in: every resource
+    /// referenced by the runnable should be one of them (or inside one of
+    /// them), so it gets copied along with the packed program.
+    ///
+    /// Returns an empty list if no problems were found.
+    pub fn validate(&self, resource_paths: &[Vec<u8>]) -> Vec<RunnableDiagnostic> {
+        let mut validator = Validator {
+            resource_paths,
+            diagnostics: vec![],
+        };
+
+        validator.command("command", &self.command);
+        validator.args("", &self.args);
+
+        let mut env_names = std::collections::HashSet::new();
+        for (env_name, env_value) in &self.env {
+            let location = format!("env var ${env_name}");
+            if !is_valid_env_var_name(env_name) {
+                validator.push(&location, "invalid env var name");
+            }
+            if !env_names.insert(env_name) {
+                validator.push(&location, "env var is set more than once");
+            }
+            for template in env_value.templates() {
+                validator.template(&location, template);
+            }
+            if let crate::EnvValue::Prepend { separator, .. }
+            | crate::EnvValue::Append { separator, .. } = env_value
+            {
+                if separator.to_os_str().is_err() {
+                    validator.push(&location, "separator is not valid UTF-8");
+                }
+            }
+            if let crate::EnvValue::PrependPath { path, subpath } = env_value {
+                validator.runnable_path(&location, path);
+                validator.path(&location, subpath);
+            }
+        }
+
+        for pattern in &self.clear_env_except {
+            let location = format!("clear env except {pattern:?}");
+            if pattern.is_empty() {
+                validator.push(&location, "pattern is empty");
+            }
+            if !self.clear_env {
+                validator.push(&location, "pattern has no effect without clear env");
+            }
+        }
+
+        let mut rlimit_resources = std::collections::HashSet::new();
+        for (resource, rlimit) in &self.rlimits {
+            let location = format!("rlimit {}", resource.name());
+            if !rlimit_resources.insert(resource) {
+                validator.push(&location, "limit is set more than once");
+            }
+            if let (Some(soft), Some(hard)) = (rlimit.soft, rlimit.hard) {
+                if soft > hard {
+                    validator.push(
+                        &location,
+                        format!("soft limit {soft} is greater than hard limit {hard}"),
+                    );
+                }
+            }
+        }
+
+        if let Some(cwd) = &self.cwd {
+            validator.template("cwd", cwd);
+        }
+
+        for (n, pre_command) in self.pre_commands.iter().enumerate() {
+            let location = format!("pre-command {n}");
+            validator.command(&location, &pre_command.command);
+            validator.args(&format!("{location} "), &pre_command.args);
+        }
+
+        for (platform, variant) in &self.platforms {
+            validator.command(&format!("{platform} command"), &variant.command);
+            if let Some(args) = &variant.args {
+                validator.args(&format!("{platform} "), args);
+            }
+        }
+
+        if let Some(source) = &self.source {
+            validator.runnable_path("source", &source.path);
+        }
+
+        validator.diagnostics
+    }
+}
+
+struct Validator<'a> {
+    resource_paths: &'a [Vec<u8>],
+    diagnostics: Vec<RunnableDiagnostic>,
+}
+
+impl Validator<'_> {
+    fn push(&mut self, location: &str, message: impl Into<String>) {
+        self.diagnostics.push(RunnableDiagnostic {
+            location: location.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn command(&mut self, location: &str, command: &Template) {
+        let is_empty = command.components.iter().all(|component| match component {
+            TemplateComponent::Literal { value } => value.is_empty(),
+            TemplateComponent::RelativePath { .. }
+            | TemplateComponent::Resource { .. }
+            | TemplateComponent::EnvVar { .. }
+            | TemplateComponent::ProgramDir => false,
+        });
+        if is_empty {
+            self.push(location, "command is empty");
+        }
+
+        self.template(location, command);
+    }
+
+    fn args(&mut self, location_prefix: &str, args: &[ArgValue]) {
+        let mut rest_args = 0;
+        for (n, arg) in args.iter().enumerate() {
+            let location = format!("{location_prefix}arg {n}");
+            match arg {
+                ArgValue::Arg { value } | ArgValue::Optional { value } => {
+                    self.template(&location, value);
+                }
+                ArgValue::Spread { value, separator } => {
+                    self.template(&location, value);
+                    if separator.to_os_str().is_err() {
+                        self.push(&location, "separator is not valid UTF-8");
+                    }
+                }
+                ArgValue::IfEnvSet { name, value } => {
+                    if !is_valid_env_var_name(name) {
+                        self.push(&location, "invalid env var name");
+                    }
+                    self.template(&location, value);
+                }
+                ArgValue::Rest => {
+                    rest_args += 1;
+                }
+            }
+        }
+
+        if rest_args > 1 {
+            self.push(
+                &format!("{location_prefix}args"),
+                format!("remaining arguments are passed {rest_args} times, expected at most once"),
+            );
+        }
+    }
+
+    fn template(&mut self, location: &str, template: &Template) {
+        for component in &template.components {
+            match component {
+                TemplateComponent::Literal { value } => {
+                    if value.to_os_str().is_err() {
+                        self.push(location, "literal is not valid UTF-8");
+                    }
+                }
+                TemplateComponent::RelativePath { path } => {
+                    self.path(location, path);
+                }
+                TemplateComponent::Resource { resource } => {
+                    self.resource(location, resource);
+                }
+                TemplateComponent::ProgramDir => {}
+                TemplateComponent::EnvVar { name, fallback } => {
+                    if !is_valid_env_var_name(name) {
+                        self.push(location, "invalid env var name");
+                    }
+                    if fallback
+                        .as_ref()
+                        .is_some_and(|fallback| fallback.to_os_str().is_err())
+                    {
+                        self.push(location, "env var fallback is not valid UTF-8");
+                    }
+                }
+            }
+        }
+    }
+
+    fn runnable_path(&mut self, location: &str, path: &RunnablePath) {
+        match path {
+            RunnablePath::RelativePath { path } => {
+                self.path(location, path);
+            }
+            RunnablePath::Resource { resource } => {
+                self.resource(location, resource);
+            }
+        }
+    }
+
+    fn path(&mut self, location: &str, path: &[u8]) {
+        if path.to_path().is_err() {
+            self.push(location, format!("invalid path: {}", bstr::BStr::new(path)));
+        }
+    }
+
+    fn resource(&mut self, location: &str, resource: &[u8]) {
+        let Ok(resource_path) = resource.to_path() else {
+            self.push(
+                location,
+                format!("invalid resource path: {}", bstr::BStr::new(resource)),
+            );
+            return;
+        };
+
+        let is_listed = self.resource_paths.iter().any(|resource_path_bytes| {
+            resource_path_bytes
+                .to_path()
+                .is_ok_and(|listed_path| resource_path.starts_with(listed_path))
+        });
+        if !is_listed {
+            self.push(
+                location,
+                format!(
+                    "resource is not in the pack's resource paths: {}",
+                    resource_path.display()
+                ),
+            );
+        }
+    }
+}
+
+fn is_valid_env_var_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['=', '\0'])
+}
@@ -0,0 +1,1066 @@
+//! Build the command for a runnable, shared by the packed launchers.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use bstr::ByteSlice as _;
+
+/// The env vars a command is built from, normally the current process's.
+type EnvVars = BTreeMap<OsString, OsString>;
+
+/// Build the command to run for a runnable: the rendered command, args,
+/// env vars, and working directory. `program_path` is the path of the
+/// packed program, which relative paths in templates are resolved from.
+/// The remaining args are taken from the current process's args.
+///
+/// This doesn't apply the runnable's process settings or run its
+/// pre-commands; see [`apply_process_settings`] and [`run_pre_commands`].
+pub fn build_command(
+    runnable: &runnable_core::Runnable,
+    program_path: &Path,
+    resource_dirs: &[PathBuf],
+) -> Result<Command, RunnableExecError> {
+    build_command_with_args(runnable, program_path, resource_dirs, &original_args())
+}
+
+/// Build the command to run for a runnable like [`build_command`], but
+/// use `remaining_args` (not including argv0) as the remaining args
+/// instead of the current process's args.
+pub fn build_command_with_args(
+    runnable: &runnable_core::Runnable,
+    program_path: &Path,
+    resource_dirs: &[PathBuf],
+    remaining_args: &[OsString],
+) -> Result<Command, RunnableExecError> {
+    build_command_with_env(
+        runnable,
+        program_path,
+        resource_dirs,
+        remaining_args,
+        &std::env::vars_os().collect(),
+    )
+}
+
+/// Build the command to run for a runnable like
+/// [`build_command_with_args`], reading env vars from `env_vars` instead
+/// of the current process's env vars.
+fn build_command_with_env(
+    runnable: &runnable_core::Runnable,
+    program_path: &Path,
+    resource_dirs: &[PathBuf],
+    remaining_args: &[OsString],
+    env_vars: &EnvVars,
+) -> Result<Command, RunnableExecError> {
+    let program = runnable.command.to_os_string(program_path, resource_dirs)?;
+
+    let mut command = Command::new(program);
+    add_runnable_args(
+        &mut command,
+        &runnable.args,
+        remaining_args,
+        env_vars,
+        program_path,
+        resource_dirs,
+    )?;
+
+    if let Some(cwd) = &runnable.cwd {
+        let cwd = cwd.to_os_string(program_path, resource_dirs)?;
+        command.current_dir(cwd);
+    }
+
+    if runnable.clear_env {
+        command.env_clear();
+
+        // Keep env vars matching one of the patterns, if any. These are
+        // set explicitly, so pre-commands inherit them too
+        if !runnable.clear_env_except.is_empty() {
+            for (env_name, env_value) in env_vars {
+                let Some(env_name_str) = env_name.to_str() else {
+                    continue;
+                };
+                let is_kept = runnable.clear_env_except.iter().any(|pattern| {
+                    matches_env_pattern(pattern.as_bytes(), env_name_str.as_bytes())
+                });
+                if is_kept {
+                    command.env(env_name, env_value);
+                }
+            }
+        }
+    }
+
+    for (env_name, env_value) in &runnable.env {
+        match env_value {
+            runnable_core::EnvValue::Clear => {
+                command.env_remove(env_name);
+            }
+            runnable_core::EnvValue::Inherit => {
+                let value = env_vars.get(OsStr::new(env_name));
+                if let Some(value) = value {
+                    command.env(env_name, value);
+                }
+            }
+            runnable_core::EnvValue::Set { value } => {
+                let value = value.to_os_string(program_path, resource_dirs)?;
+                command.env(env_name, value);
+            }
+            runnable_core::EnvValue::Fallback { value } => {
+                let current_value = env_vars.get(OsStr::new(env_name)).cloned();
+                let current_value = current_value.filter(|value| !value.is_empty());
+                let value = match current_value {
+                    Some(current_value) => current_value,
+                    None => value.to_os_string(program_path, resource_dirs)?,
+                };
+                command.env(env_name, value);
+            }
+            runnable_core::EnvValue::Prepend {
+                value,
+                separator,
+                if_exists,
+            } => {
+                let value = value.to_os_string(program_path, resource_dirs)?;
+                let Some(mut value) = existing_env_components(value, separator, *if_exists) else {
+                    continue;
+                };
+                let separator =
+                    separator
+                        .to_os_str()
+                        .map_err(|_| RunnableExecError::InvalidUtf8 {
+                            bytes: separator.clone().into(),
+                        })?;
+
+                let current_value = env_vars.get(OsStr::new(env_name)).cloned();
+                let new_value = match current_value {
+                    Some(current_value) if !current_value.is_empty() => {
+                        value.push(separator);
+                        value.push(current_value);
+
+                        value
+                    }
+                    _ => value,
+                };
+                command.env(env_name, new_value);
+            }
+            runnable_core::EnvValue::Append {
+                value,
+                separator,
+                if_exists,
+            } => {
+                let value = value.to_os_string(program_path, resource_dirs)?;
+                let Some(value) = existing_env_components(value, separator, *if_exists) else {
+                    continue;
+                };
+                let separator =
+                    separator
+                        .to_os_str()
+                        .map_err(|_| RunnableExecError::InvalidUtf8 {
+                            bytes: separator.clone().into(),
+                        })?;
+
+                let current_value = env_vars.get(OsStr::new(env_name)).cloned();
+                let new_value = match current_value {
+                    Some(mut current_value) if !current_value.is_empty() => {
+                        current_value.push(separator);
+                        current_value.push(value);
+
+                        current_value
+                    }
+                    _ => value,
+                };
+                command.env(env_name, new_value);
+            }
+            runnable_core::EnvValue::MergedView { values } => {
+                let dirs = values
+                    .iter()
+                    .map(|value| value.to_os_string(program_path, resource_dirs))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let merged_view = merged_view_dir(&dirs)?;
+                command.env(env_name, merged_view);
+            }
+            runnable_core::EnvValue::PrependPath { path, subpath } => {
+                let mut dir = path.to_path_buf(program_path, resource_dirs)?;
+                if !subpath.is_empty() {
+                    let subpath =
+                        subpath
+                            .to_path()
+                            .map_err(|_| RunnableExecError::InvalidUtf8 {
+                                bytes: subpath.clone().into(),
+                            })?;
+                    dir.push(subpath);
+                }
+
+                let mut new_value = OsString::from(dir);
+                let current_value = env_vars.get(OsStr::new(env_name)).cloned();
+                if let Some(current_value) = current_value.filter(|value| !value.is_empty()) {
+                    new_value.push(":");
+                    new_value.push(current_value);
+                }
+                command.env(env_name, new_value);
+            }
+        }
+    }
+
+    Ok(command)
+}
+
+/// Apply the runnable's umask, niceness, and resource limits to the
+/// current process. These are inherited by child processes and through
+/// exec, so this should be called just before running the command.
+pub fn apply_process_settings(runnable: &runnable_core::Runnable) -> Result<(), RunnableExecError> {
+    // The umask, niceness, and resource limits are inherited through
+    // exec, so they can be set on the current process
+    if let Some(umask) = runnable.umask {
+        unsafe {
+            libc::umask(umask as libc::mode_t);
+        }
+    }
+    if let Some(nice) = runnable.nice {
+        let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+        if result != 0 {
+            return Err(RunnableExecError::IoError(std::io::Error::last_os_error()));
+        }
+    }
+    for (resource, rlimit) in &runnable.rlimits {
+        set_rlimit(*resource, *rlimit)?;
+    }
+
+    Ok(())
+}
+
+fn set_rlimit(
+    resource: runnable_core::RlimitResource,
+    rlimit: runnable_core::Rlimit,
+) -> Result<(), RunnableExecError> {
+    let libc_resource = match resource {
+        runnable_core::RlimitResource::Core => libc::RLIMIT_CORE,
+        runnable_core::RlimitResource::Cpu => libc::RLIMIT_CPU,
+        runnable_core::RlimitResource::Data => libc::RLIMIT_DATA,
+        runnable_core::RlimitResource::Fsize => libc::RLIMIT_FSIZE,
+        runnable_core::RlimitResource::Memlock => libc::RLIMIT_MEMLOCK,
+        runnable_core::RlimitResource::Nofile => libc::RLIMIT_NOFILE,
+        runnable_core::RlimitResource::Nproc => libc::RLIMIT_NPROC,
+        runnable_core::RlimitResource::Stack => libc::RLIMIT_STACK,
+    };
+
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    let result = unsafe { libc::getrlimit(libc_resource, &mut limit) };
+    if result != 0 {
+        return Err(RunnableExecError::IoError(std::io::Error::last_os_error()));
+    }
+
+    apply_rlimit(&mut limit, rlimit);
+
+    let result = unsafe { libc::setrlimit(libc_resource, &limit) };
+    if result != 0 {
+        let error = std::io::Error::last_os_error();
+        return Err(RunnableExecError::RlimitError {
+            resource: resource.name(),
+            source: error,
+        });
+    }
+
+    Ok(())
+}
+
+/// Override the soft and hard limits that are set in `rlimit`, keeping the
+/// current value of the others.
+fn apply_rlimit(limit: &mut libc::rlimit, rlimit: runnable_core::Rlimit) {
+    if let Some(soft) = rlimit.soft {
+        limit.rlim_cur = soft as libc::rlim_t;
+    }
+    if let Some(hard) = rlimit.hard {
+        limit.rlim_max = hard as libc::rlim_t;
+    }
+}
+
+/// Run each of the runnable's pre-commands in order, waiting for each one
+/// to finish. They run with the same env vars and working directory as
+/// `main_command`, which should come from [`build_command`].
+pub fn run_pre_commands(
+    runnable: &runnable_core::Runnable,
+    main_command: &Command,
+    program_path: &Path,
+    resource_dirs: &[PathBuf],
+) -> Result<(), RunnableExecError> {
+    run_pre_commands_with_args(
+        runnable,
+        main_command,
+        program_path,
+        resource_dirs,
+        &original_args(),
+    )
+}
+
+/// Run the runnable's pre-commands like [`run_pre_commands`], but use
+/// `remaining_args` (not including argv0) as the remaining args instead
+/// of the current process's args.
+pub fn run_pre_commands_with_args(
+    runnable: &runnable_core::Runnable,
+    main_command: &Command,
+    program_path: &Path,
+    resource_dirs: &[PathBuf],
+    remaining_args: &[OsString],
+) -> Result<(), RunnableExecError> {
+    let env_vars = std::env::vars_os().collect();
+    for pre_command in &runnable.pre_commands {
+        run_pre_command(
+            pre_command,
+            main_command,
+            runnable.clear_env,
+            remaining_args,
+            &env_vars,
+            program_path,
+            resource_dirs,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The args passed to the current process, without the program name.
+fn original_args() -> Vec<OsString> {
+    std::env::args_os().skip(1).collect()
+}
+
+/// Render a runnable's args and add them to `command`.
+fn add_runnable_args(
+    command: &mut std::process::Command,
+    args: &[runnable_core::ArgValue],
+    original_args: &[OsString],
+    env_vars: &EnvVars,
+    program_path: &Path,
+    resource_dirs: &[PathBuf],
+) -> Result<(), RunnableExecError> {
+    let mut added_original_args = false;
+
+    for arg in args {
+        match arg {
+            runnable_core::ArgValue::Arg { value } => {
+                let value = value.to_os_string(program_path, resource_dirs)?;
+                command.arg(value);
+            }
+            runnable_core::ArgValue::Spread { value, separator } => {
+                let value = value.to_os_string(program_path, resource_dirs)?;
+                command.args(spread_arg(value, separator));
+            }
+            runnable_core::ArgValue::IfEnvSet { name, value } => {
+                let is_set = env_vars
+                    .get(OsStr::new(name))
+                    .is_some_and(|var| !var.is_empty());
+                if is_set {
+                    let value = value.to_os_string(program_path, resource_dirs)?;
+                    command.arg(value);
+                }
+            }
+            runnable_core::ArgValue::Optional { value } => {
+                let value = value.to_os_string(program_path, resource_dirs).ok();
+                if let Some(value) = value.filter(|value| !value.is_empty()) {
+                    command.arg(value);
+                }
+            }
+            runnable_core::ArgValue::Rest => {
+                if added_original_args {
+                    return Err(RunnableExecError::RepeatedArgs);
+                }
+                command.args(original_args);
+                added_original_args = true;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Match an env var name against a pattern from
+/// [`runnable_core::Runnable::clear_env_except`], where `*` matches any
+/// sequence of bytes and `?` matches any single byte.
+fn matches_env_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|n| matches_env_pattern(rest, &name[n..])),
+        Some((b'?', rest)) => !name.is_empty() && matches_env_pattern(rest, &name[1..]),
+        Some((byte, rest)) => name.first() == Some(byte) && matches_env_pattern(rest, &name[1..]),
+    }
+}
+
+/// Run one of a runnable's pre-commands and wait for it to finish. It
+/// runs with the same env vars and working directory as `main_command`.
+fn run_pre_command(
+    pre_command: &runnable_core::RunnableCommand,
+    main_command: &std::process::Command,
+    clear_env: bool,
+    original_args: &[OsString],
+    env_vars: &EnvVars,
+    program_path: &Path,
+    resource_dirs: &[PathBuf],
+) -> Result<(), RunnableExecError> {
+    let program = pre_command
+        .command
+        .to_os_string(program_path, resource_dirs)?;
+    let mut command = std::process::Command::new(&program);
+    add_runnable_args(
+        &mut command,
+        &pre_command.args,
+        original_args,
+        env_vars,
+        program_path,
+        resource_dirs,
+    )?;
+
+    if clear_env {
+        command.env_clear();
+    }
+    for (env_name, env_value) in main_command.get_envs() {
+        match env_value {
+            Some(env_value) => {
+                command.env(env_name, env_value);
+            }
+            None => {
+                command.env_remove(env_name);
+            }
+        }
+    }
+    if let Some(cwd) = main_command.get_current_dir() {
+        command.current_dir(cwd);
+    }
+
+    let status = command.status()?;
+    if !status.success() {
+        return Err(RunnableExecError::PreCommandFailed { program, status });
+    }
+
+    Ok(())
+}
+
+/// Split a rendered spread arg into separate args, skipping empty
+/// components.
+fn spread_arg(value: OsString, separator: &[u8]) -> Vec<OsString> {
+    use std::os::unix::ffi::OsStringExt as _;
+
+    let value = value.into_vec();
+    let components: Vec<&[u8]> = if separator.is_empty() {
+        vec![&value]
+    } else {
+        value.split_str(separator).collect()
+    };
+    components
+        .into_iter()
+        .filter(|component| !component.is_empty())
+        .map(|component| OsString::from_vec(component.to_vec()))
+        .collect()
+}
+
+/// Filter the components of a prepended or appended env var value to the
+/// ones that exist, if `if_exists` is set. Returns `None` if there are no
+/// components left to add.
+fn existing_env_components(value: OsString, separator: &[u8], if_exists: bool) -> Option<OsString> {
+    use std::os::unix::ffi::{OsStrExt as _, OsStringExt as _};
+
+    if !if_exists {
+        return Some(value);
+    }
+
+    let value = value.into_vec();
+    let components: Vec<&[u8]> = if separator.is_empty() {
+        vec![&value]
+    } else {
+        value.split_str(separator).collect()
+    };
+    let existing_components = components
+        .into_iter()
+        .filter(|component| {
+            !component.is_empty()
+                && std::path::Path::new(std::ffi::OsStr::from_bytes(component)).exists()
+        })
+        .collect::<Vec<_>>();
+
+    if existing_components.is_empty() {
+        None
+    } else {
+        Some(OsString::from_vec(bstr::join(
+            separator,
+            existing_components,
+        )))
+    }
+}
+
+/// Get a directory that merges the contents of `dirs` with symlinks,
+/// creating it if it hasn't been created already. Merged views are cached
+/// by the list of dirs, under `$BRIOCHE_VIEW_DIR` if set or otherwise in
+/// the user's cache dir.
+fn merged_view_dir(dirs: &[OsString]) -> Result<PathBuf, RunnableExecError> {
+    let views_dir = match std::env::var_os("BRIOCHE_VIEW_DIR") {
+        Some(views_dir) => PathBuf::from(views_dir),
+        None => {
+            let cache_dir = match std::env::var_os("XDG_CACHE_HOME") {
+                Some(cache_dir) => PathBuf::from(cache_dir),
+                None => {
+                    let home = std::env::var_os("HOME").ok_or(RunnableExecError::NoCacheDir)?;
+                    Path::new(&home).join(".cache")
+                }
+            };
+            cache_dir.join("brioche").join("views")
+        }
+    };
+
+    merged_view_dir_in(&views_dir, dirs)
+}
+
+/// Get a directory within `views_dir` that merges the contents of `dirs`,
+/// like [`merged_view_dir`].
+fn merged_view_dir_in(views_dir: &Path, dirs: &[OsString]) -> Result<PathBuf, RunnableExecError> {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let mut hasher = blake3::Hasher::new();
+    for dir in dirs {
+        let dir = std::path::absolute(dir)?;
+        hasher.update(dir.as_os_str().as_bytes());
+        hasher.update(b"\0");
+    }
+    let hash = hasher.finalize();
+
+    let view_dir = views_dir.join(hash.to_hex().as_str());
+    if view_dir.is_dir() {
+        return Ok(view_dir);
+    }
+
+    // Build the view in a temporary dir, then rename it into place so
+    // concurrent runs never see a partial view
+    std::fs::create_dir_all(views_dir)?;
+    let temp_dir = views_dir.join(format!("{hash}-{}", std::process::id()));
+    std::fs::create_dir(&temp_dir)?;
+    for dir in dirs {
+        merge_dir_into(Path::new(dir), &temp_dir)?;
+    }
+
+    match std::fs::rename(&temp_dir, &view_dir) {
+        Ok(()) => {}
+        Err(_) if view_dir.is_dir() => {
+            // Another process created the view first
+            std::fs::remove_dir_all(&temp_dir)?;
+        }
+        Err(error) => {
+            return Err(error.into());
+        }
+    }
+
+    Ok(view_dir)
+}
+
+/// Add symlinks to each entry in `source` within `target`. Directories
+/// are merged recursively. Existing entries in `target` are kept.
+fn merge_dir_into(source: &Path, target: &Path) -> Result<(), RunnableExecError> {
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let source_path = entry.path();
+        let target_path = target.join(entry.file_name());
+
+        let target_metadata = std::fs::symlink_metadata(&target_path).ok();
+        if source_path.is_dir() {
+            match target_metadata {
+                None => {
+                    std::fs::create_dir(&target_path)?;
+                    merge_dir_into(&source_path, &target_path)?;
+                }
+                Some(metadata) if metadata.is_dir() => {
+                    merge_dir_into(&source_path, &target_path)?;
+                }
+                Some(_) => {}
+            }
+        } else if target_metadata.is_none() {
+            let source_path = std::path::absolute(&source_path)?;
+            std::os::unix::fs::symlink(source_path, &target_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RunnableExecError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    RunnableTemplateError(#[from] runnable_core::RunnableTemplateError),
+    #[error("tried to pass remaining arguments more than once")]
+    RepeatedArgs,
+    #[error("invalid UTF-8: {bytes:?}")]
+    InvalidUtf8 { bytes: bstr::BString },
+    #[error("pre-command {program:?} failed: {status}")]
+    PreCommandFailed {
+        program: OsString,
+        status: std::process::ExitStatus,
+    },
+    #[error("could not find cache dir for merged view, set $BRIOCHE_VIEW_DIR or $HOME")]
+    NoCacheDir,
+    #[error("failed to set {resource} limit: {source}")]
+    RlimitError {
+        resource: &'static str,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use runnable_core::{ArgValue, EnvValue, Runnable, RunnablePath, Template, TemplateComponent};
+
+    use super::*;
+
+    struct TestProgram {
+        _root: tempfile::TempDir,
+        program_dir: PathBuf,
+        program_path: PathBuf,
+        resource_dir: PathBuf,
+    }
+
+    fn test_program() -> TestProgram {
+        let root = tempfile::tempdir().unwrap();
+        let program_dir = root.path().join("bin");
+        let resource_dir = root.path().join("brioche-resources.d");
+        std::fs::create_dir_all(resource_dir.join("tools")).unwrap();
+        std::fs::write(resource_dir.join("tools").join("tool"), "").unwrap();
+
+        TestProgram {
+            program_path: program_dir.join("program"),
+            program_dir,
+            resource_dir,
+            _root: root,
+        }
+    }
+
+    fn runnable(command: Template) -> Runnable {
+        Runnable {
+            command,
+            args: vec![],
+            env: vec![],
+            clear_env: false,
+            clear_env_except: vec![],
+            umask: None,
+            nice: None,
+            rlimits: vec![],
+            cwd: None,
+            platforms: vec![],
+            pre_commands: vec![],
+            source: None,
+        }
+    }
+
+    fn literal(value: &str) -> Template {
+        Template::from_literal(value.as_bytes().to_vec())
+    }
+
+    fn resource(resource: &str) -> Template {
+        Template {
+            components: vec![TemplateComponent::Resource {
+                resource: resource.as_bytes().to_vec(),
+            }],
+        }
+    }
+
+    fn relative_path(path: &str) -> Template {
+        Template {
+            components: vec![TemplateComponent::RelativePath {
+                path: path.as_bytes().to_vec(),
+            }],
+        }
+    }
+
+    fn build(program: &TestProgram, runnable: &Runnable, remaining_args: &[&str]) -> Command {
+        build_with_env(program, runnable, remaining_args, &[])
+    }
+
+    fn build_with_env(
+        program: &TestProgram,
+        runnable: &Runnable,
+        remaining_args: &[&str],
+        env_vars: &[(&str, &str)],
+    ) -> Command {
+        let remaining_args = remaining_args
+            .iter()
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        let env_vars = env_vars
+            .iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        build_command_with_env(
+            runnable,
+            &program.program_path,
+            std::slice::from_ref(&program.resource_dir),
+            &remaining_args,
+            &env_vars,
+        )
+        .unwrap()
+    }
+
+    fn command_envs(command: &Command) -> HashMap<String, Option<OsString>> {
+        command
+            .get_envs()
+            .map(|(name, value)| (name.to_str().unwrap().to_string(), value.map(Into::into)))
+            .collect()
+    }
+
+    #[test]
+    fn test_build_command_renders_templates() {
+        let program = test_program();
+        let mut runnable = runnable(resource("tools/tool"));
+        runnable.cwd = Some(Template {
+            components: vec![
+                TemplateComponent::ProgramDir,
+                TemplateComponent::Literal {
+                    value: b"/work".to_vec(),
+                },
+            ],
+        });
+
+        let command = build(&program, &runnable, &[]);
+        assert_eq!(
+            command.get_program(),
+            program.resource_dir.join("tools").join("tool")
+        );
+        assert_eq!(
+            command.get_current_dir(),
+            Some(&*program.program_dir.join("work"))
+        );
+    }
+
+    #[test]
+    fn test_build_command_args() {
+        let program = test_program();
+        let mut runnable = runnable(literal("/bin/tool"));
+        runnable.args = vec![
+            ArgValue::Arg {
+                value: literal("--flag"),
+            },
+            ArgValue::Arg {
+                value: relative_path("data"),
+            },
+            ArgValue::Spread {
+                value: literal("a::b"),
+                separator: b":".to_vec(),
+            },
+            ArgValue::Optional {
+                value: resource("missing"),
+            },
+            ArgValue::Optional {
+                value: resource("tools"),
+            },
+            ArgValue::Rest,
+            ArgValue::Arg {
+                value: literal("--end"),
+            },
+        ];
+
+        let command = build(&program, &runnable, &["one", "two"]);
+        let args = command.get_args().collect::<Vec<_>>();
+        assert_eq!(
+            args,
+            [
+                "--flag".as_ref(),
+                program.program_dir.join("data").as_os_str(),
+                "a".as_ref(),
+                "b".as_ref(),
+                program.resource_dir.join("tools").as_os_str(),
+                "one".as_ref(),
+                "two".as_ref(),
+                "--end".as_ref(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_command_if_env_set_args() {
+        let program = test_program();
+        let mut runnable = runnable(literal("/bin/tool"));
+        runnable.args = ["SET", "EMPTY", "UNSET"]
+            .into_iter()
+            .map(|name| ArgValue::IfEnvSet {
+                name: format!("RUNNABLE_EXEC_TEST_IF_ENV_{name}"),
+                value: literal(name),
+            })
+            .collect();
+
+        let command = build_with_env(
+            &program,
+            &runnable,
+            &[],
+            &[
+                ("RUNNABLE_EXEC_TEST_IF_ENV_SET", "1"),
+                ("RUNNABLE_EXEC_TEST_IF_ENV_EMPTY", ""),
+            ],
+        );
+        let args = command.get_args().collect::<Vec<_>>();
+        assert_eq!(args, ["SET"]);
+    }
+
+    #[test]
+    fn test_build_command_repeated_rest_args() {
+        let program = test_program();
+        let mut runnable = runnable(literal("/bin/tool"));
+        runnable.args = vec![ArgValue::Rest, ArgValue::Rest];
+
+        let result = build_command_with_args(
+            &runnable,
+            &program.program_path,
+            std::slice::from_ref(&program.resource_dir),
+            &[],
+        );
+        assert!(matches!(result, Err(RunnableExecError::RepeatedArgs)));
+    }
+
+    #[test]
+    fn test_build_command_env() {
+        let program = test_program();
+        let mut runnable = runnable(literal("/bin/tool"));
+        runnable.env = vec![
+            (
+                "RUNNABLE_EXEC_TEST_ENV_SET".to_string(),
+                EnvValue::Set {
+                    value: resource("tools/tool"),
+                },
+            ),
+            ("RUNNABLE_EXEC_TEST_ENV_CLEAR".to_string(), EnvValue::Clear),
+            (
+                "RUNNABLE_EXEC_TEST_ENV_INHERIT".to_string(),
+                EnvValue::Inherit,
+            ),
+            (
+                "RUNNABLE_EXEC_TEST_ENV_FALLBACK_SET".to_string(),
+                EnvValue::Fallback {
+                    value: literal("fallback"),
+                },
+            ),
+            (
+                "RUNNABLE_EXEC_TEST_ENV_FALLBACK_UNSET".to_string(),
+                EnvValue::Fallback {
+                    value: literal("fallback"),
+                },
+            ),
+            (
+                "RUNNABLE_EXEC_TEST_ENV_PREPEND".to_string(),
+                EnvValue::Prepend {
+                    value: literal("new"),
+                    separator: b":".to_vec(),
+                    if_exists: false,
+                },
+            ),
+            (
+                "RUNNABLE_EXEC_TEST_ENV_APPEND".to_string(),
+                EnvValue::Append {
+                    value: literal("new"),
+                    separator: b":".to_vec(),
+                    if_exists: false,
+                },
+            ),
+            (
+                "RUNNABLE_EXEC_TEST_ENV_IF_EXISTS".to_string(),
+                EnvValue::Prepend {
+                    value: relative_path("missing"),
+                    separator: b":".to_vec(),
+                    if_exists: true,
+                },
+            ),
+            (
+                "RUNNABLE_EXEC_TEST_ENV_PREPEND_PATH".to_string(),
+                EnvValue::PrependPath {
+                    path: RunnablePath::Resource {
+                        resource: b"tools".to_vec(),
+                    },
+                    subpath: b"tool".to_vec(),
+                },
+            ),
+        ];
+
+        let command = build_with_env(
+            &program,
+            &runnable,
+            &[],
+            &[
+                ("RUNNABLE_EXEC_TEST_ENV_FALLBACK_SET", "current"),
+                ("RUNNABLE_EXEC_TEST_ENV_PREPEND", "old"),
+                ("RUNNABLE_EXEC_TEST_ENV_APPEND", "old"),
+                ("RUNNABLE_EXEC_TEST_ENV_INHERIT", "inherited"),
+            ],
+        );
+        let envs = command_envs(&command);
+        let tool_path = program.resource_dir.join("tools").join("tool");
+        assert_eq!(
+            envs,
+            HashMap::from([
+                (
+                    "RUNNABLE_EXEC_TEST_ENV_SET".to_string(),
+                    Some(tool_path.clone().into())
+                ),
+                ("RUNNABLE_EXEC_TEST_ENV_CLEAR".to_string(), None),
+                (
+                    "RUNNABLE_EXEC_TEST_ENV_INHERIT".to_string(),
+                    Some("inherited".into())
+                ),
+                (
+                    "RUNNABLE_EXEC_TEST_ENV_FALLBACK_SET".to_string(),
+                    Some("current".into())
+                ),
+                (
+                    "RUNNABLE_EXEC_TEST_ENV_FALLBACK_UNSET".to_string(),
+                    Some("fallback".into())
+                ),
+                (
+                    "RUNNABLE_EXEC_TEST_ENV_PREPEND".to_string(),
+                    Some("new:old".into())
+                ),
+                (
+                    "RUNNABLE_EXEC_TEST_ENV_APPEND".to_string(),
+                    Some("old:new".into())
+                ),
+                (
+                    "RUNNABLE_EXEC_TEST_ENV_PREPEND_PATH".to_string(),
+                    Some(tool_path.into())
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_build_command_clear_env_except() {
+        let program = test_program();
+        let mut runnable = runnable(literal("/bin/tool"));
+        runnable.clear_env = true;
+        runnable.clear_env_except = vec!["RUNNABLE_EXEC_TEST_CLEAR_KEEP_?".to_string()];
+
+        let command = build_with_env(
+            &program,
+            &runnable,
+            &[],
+            &[
+                ("RUNNABLE_EXEC_TEST_CLEAR_KEEP_1", "kept"),
+                ("RUNNABLE_EXEC_TEST_CLEAR_DROP", "dropped"),
+            ],
+        );
+        let envs = command_envs(&command);
+        assert_eq!(
+            envs.get("RUNNABLE_EXEC_TEST_CLEAR_KEEP_1"),
+            Some(&Some("kept".into()))
+        );
+        assert_eq!(envs.get("RUNNABLE_EXEC_TEST_CLEAR_DROP"), None);
+    }
+
+    #[test]
+    fn test_matches_env_pattern() {
+        assert!(matches_env_pattern(b"HOME", b"HOME"));
+        assert!(!matches_env_pattern(b"HOME", b"HOMEDIR"));
+        assert!(matches_env_pattern(b"LC_*", b"LC_ALL"));
+        assert!(matches_env_pattern(b"LC_*", b"LC_"));
+        assert!(!matches_env_pattern(b"LC_*", b"LANG"));
+        assert!(matches_env_pattern(b"*PATH", b"LD_LIBRARY_PATH"));
+        assert!(matches_env_pattern(b"TERM?", b"TERM2"));
+        assert!(!matches_env_pattern(b"TERM?", b"TERM"));
+        assert!(matches_env_pattern(b"*", b""));
+    }
+
+    #[test]
+    fn test_merged_view_dir() {
+        let root = tempfile::tempdir().unwrap();
+        let first = root.path().join("first");
+        let second = root.path().join("second");
+        std::fs::create_dir_all(first.join("share")).unwrap();
+        std::fs::create_dir_all(second.join("share")).unwrap();
+        std::fs::write(first.join("both"), "first").unwrap();
+        std::fs::write(second.join("both"), "second").unwrap();
+        std::fs::write(first.join("share").join("a"), "").unwrap();
+        std::fs::write(second.join("share").join("b"), "").unwrap();
+
+        let views_dir = root.path().join("views");
+        let dirs = [first.clone().into(), second.clone().into()];
+        let view_dir = merged_view_dir_in(&views_dir, &dirs).unwrap();
+
+        assert_eq!(
+            std::fs::read_link(view_dir.join("both")).unwrap(),
+            first.join("both")
+        );
+        assert_eq!(
+            std::fs::read_link(view_dir.join("share").join("a")).unwrap(),
+            first.join("share").join("a")
+        );
+        assert_eq!(
+            std::fs::read_link(view_dir.join("share").join("b")).unwrap(),
+            second.join("share").join("b")
+        );
+
+        // The view is reused for the same dirs
+        assert_eq!(merged_view_dir_in(&views_dir, &dirs).unwrap(), view_dir);
+    }
+
+    #[test]
+    fn test_run_pre_commands() {
+        let program = test_program();
+        let mut runnable = runnable(literal("/bin/tool"));
+        runnable.pre_commands = vec![runnable_core::RunnableCommand {
+            command: literal("/bin/sh"),
+            args: vec![
+                ArgValue::Arg {
+                    value: literal("-c"),
+                },
+                ArgValue::Arg {
+                    value: literal(r#"test "$RUNNABLE_EXEC_TEST_PRE_COMMAND" = "$1""#),
+                },
+                ArgValue::Arg {
+                    value: literal("sh"),
+                },
+                ArgValue::Rest,
+            ],
+        }];
+
+        let mut main_command = build(&program, &runnable, &[]);
+        main_command.env("RUNNABLE_EXEC_TEST_PRE_COMMAND", "expected");
+
+        let run = |remaining_arg: &str| {
+            run_pre_commands_with_args(
+                &runnable,
+                &main_command,
+                &program.program_path,
+                std::slice::from_ref(&program.resource_dir),
+                &[remaining_arg.into()],
+            )
+        };
+        assert!(run("expected").is_ok());
+        assert!(matches!(
+            run("unexpected"),
+            Err(RunnableExecError::PreCommandFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_rlimit() {
+        // Only check the computed limits, since setting them would change
+        // the limits of the whole test process
+        let mut limit = libc::rlimit {
+            rlim_cur: 100,
+            rlim_max: 200,
+        };
+        apply_rlimit(
+            &mut limit,
+            runnable_core::Rlimit {
+                soft: Some(0),
+                hard: None,
+            },
+        );
+        assert_eq!((limit.rlim_cur, limit.rlim_max), (0, 200));
+
+        apply_rlimit(
+            &mut limit,
+            runnable_core::Rlimit {
+                soft: None,
+                hard: Some(50),
+            },
+        );
+        assert_eq!((limit.rlim_cur, limit.rlim_max), (0, 50));
+    }
+}